@@ -0,0 +1,54 @@
+use crate::parser::{Attribute, Program, Stmt};
+
+// Атрибуты, которые понимает компилятор; неизвестные отвергает парсер
+pub struct AttributeSpec {
+    pub name: &'static str,
+    // Наибольшее число строковых аргументов в скобках
+    pub max_args: usize,
+    pub functions_only: bool,
+}
+
+pub const ATTRIBUTES: &[AttributeSpec] = &[
+    // Функция объявляется в C как static inline
+    AttributeSpec { name: "inline", max_args: 0, functions_only: true },
+    // Использование функции или типа вызывает предупреждение с необязательным пояснением
+    AttributeSpec { name: "deprecated", max_args: 1, functions_only: false },
+    // Функция без параметров, которую запускает quark test
+    AttributeSpec { name: "test", max_args: 0, functions_only: true },
+];
+
+pub fn lookup(name: &str) -> Option<&'static AttributeSpec> {
+    ATTRIBUTES.iter().find(|spec| spec.name == name)
+}
+
+pub fn has(attributes: &[Attribute], name: &str) -> bool {
+    attributes.iter().any(|attribute| attribute.name == name)
+}
+
+// Хвост предупреждения: ": пояснение" из #[deprecated("...")] или пустая строка
+pub fn deprecation_note(attributes: &[Attribute]) -> Option<String> {
+    let attribute = attributes.iter().find(|attribute| attribute.name == "deprecated")?;
+    Some(attribute.args.first().map_or(String::new(), |note| format!(": {}", note)))
+}
+
+pub fn attributes_of(stmt: &Stmt) -> &[Attribute] {
+    match stmt {
+        Stmt::Function { attributes, .. }
+        | Stmt::Struct { attributes, .. }
+        | Stmt::Enum { attributes, .. }
+        | Stmt::Trait { attributes, .. } => attributes,
+        _ => &[],
+    }
+}
+
+// Функции #[test] в порядке объявления
+pub fn test_functions(program: &Program) -> Vec<&str> {
+    program
+        .statements
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Function { name, attributes, .. } if has(attributes, "test") => Some(name.as_str()),
+            _ => None,
+        })
+        .collect()
+}
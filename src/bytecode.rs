@@ -0,0 +1,1144 @@
+use crate::compiler::CompileError;
+use crate::parser::{BinOp, Expr, Field, MatchArm, Pattern, Program, Stmt, StructPattern, UnaryOp, Value, VarType, Variant};
+use crate::types::{is_builtin, member_name, split_member, user_type_name, RANGE_CONTEXT_ERROR};
+use crate::vm;
+
+use std::collections::HashMap;
+
+// Байткод .qbc — переносимый вариант программы, который исполняет стековая машина из vm.rs:
+// её не нужно собирать компилятором C под каждую систему.
+// Файл: сигнатура, константы, имена встроенных функций, число глобальных переменных,
+// функции и номер функции, с которой начинается выполнение
+pub const MAGIC: &[u8; 4] = b"QBC\0";
+pub const FORMAT_VERSION: u16 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    String(String),
+    Array(Vec<Constant>),
+    Struct { name: String, fields: Vec<(String, Constant)> },
+    Enum { name: String, variant: String, payload: Vec<Constant> },
+}
+
+// Операнды — номера констант, локальных и глобальных переменных, функций и адреса переходов.
+// Локальные переменные функции лежат на стеке, первыми идут параметры
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Constant(u32),
+    // Результат вызова функции, которая ничего не возвращает
+    Unit,
+    Pop,
+    Load(u32),
+    Store(u32),
+    LoadGlobal(u32),
+    StoreGlobal(u32),
+    Binary(BinOp),
+    Unary(UnaryOp),
+    Jump(u32),
+    JumpIfFalse(u32),
+    Call(u32),
+    // Номер имени в таблице встроенных функций и число аргументов
+    Builtin(u32, u32),
+    // Метод ищется по типу значения под аргументами: имя метода — константа
+    CallMethod(u32, u32),
+    Return,
+    Array(u32),
+    Index,
+    StoreIndex,
+    Field(u32),
+    // Структура и новое значение поля на стеке дают изменённую копию структуры
+    SetField(u32),
+    // Массив или строка, начало и конец (Unit — до конца)
+    Slice,
+    ToInteger,
+    ToFloat,
+    IsVariant(u32),
+    Payload(u32),
+    // Элементы для for-in: массив как есть или символы строки
+    Items,
+    Len,
+    // Обработчик ошибок выполнения; текст ошибки оказывается на стеке
+    TryBegin(u32),
+    TryEnd,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub arity: u32,
+    pub locals: u32,
+    pub code: Vec<Op>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub constants: Vec<Constant>,
+    pub builtins: Vec<String>,
+    pub globals: u32,
+    pub functions: Vec<Function>,
+    pub entry: u32,
+}
+
+// Порядок задаёт коды операторов в файле, менять его можно только вместе с FORMAT_VERSION
+const BINARY_OPS: &[BinOp] = &[
+    BinOp::Add,
+    BinOp::Sub,
+    BinOp::Mul,
+    BinOp::Div,
+    BinOp::Eq,
+    BinOp::Ne,
+    BinOp::Lt,
+    BinOp::Gt,
+    BinOp::Le,
+    BinOp::Ge,
+    BinOp::BitAnd,
+    BinOp::BitOr,
+    BinOp::BitXor,
+    BinOp::Shl,
+    BinOp::Shr,
+    BinOp::In,
+    BinOp::Rem,
+];
+
+impl Module {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Writer(Vec::new());
+        out.0.extend_from_slice(MAGIC);
+        out.0.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        out.u32(self.constants.len() as u32);
+        self.constants.iter().for_each(|constant| out.constant(constant));
+        out.u32(self.builtins.len() as u32);
+        self.builtins.iter().for_each(|name| out.string(name));
+        out.u32(self.globals);
+        out.u32(self.functions.len() as u32);
+        for function in &self.functions {
+            out.string(&function.name);
+            out.u32(function.arity);
+            out.u32(function.locals);
+            out.u32(function.code.len() as u32);
+            function.code.iter().for_each(|op| out.op(op));
+        }
+        out.u32(self.entry);
+        out.0
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Module, String> {
+        if bytes.len() < 6 || &bytes[..4] != MAGIC {
+            return Err("not a Quark bytecode file".to_string());
+        }
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        if version != FORMAT_VERSION {
+            return Err(format!(
+                "bytecode format version {} is not supported (this quark reads version {})",
+                version, FORMAT_VERSION
+            ));
+        }
+        let mut input = Reader { bytes, at: 6 };
+        let constants = (0..input.u32()?).map(|_| input.constant()).collect::<Result<_, _>>()?;
+        let builtins = (0..input.u32()?).map(|_| input.string()).collect::<Result<_, _>>()?;
+        let globals = input.u32()?;
+        let mut functions = Vec::new();
+        for _ in 0..input.u32()? {
+            let name = input.string()?;
+            let arity = input.u32()?;
+            let locals = input.u32()?;
+            let code = (0..input.u32()?).map(|_| input.op()).collect::<Result<_, _>>()?;
+            functions.push(Function { name, arity, locals, code });
+        }
+        let entry = input.u32()?;
+        if input.at != bytes.len() {
+            return Err("unexpected data after the end of the bytecode".to_string());
+        }
+        Ok(Module { constants, builtins, globals, functions, entry })
+    }
+}
+
+// Числа — little-endian, строки и списки начинаются с длины
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.0.extend_from_slice(value.as_bytes());
+    }
+
+    fn constant(&mut self, constant: &Constant) {
+        match constant {
+            Constant::Integer(value) => {
+                self.u8(0);
+                self.0.extend_from_slice(&value.to_le_bytes());
+            }
+            Constant::Float(value) => {
+                self.u8(1);
+                self.0.extend_from_slice(&value.to_le_bytes());
+            }
+            Constant::Boolean(value) => {
+                self.u8(2);
+                self.u8(*value as u8);
+            }
+            Constant::String(value) => {
+                self.u8(3);
+                self.string(value);
+            }
+            Constant::Array(items) => {
+                self.u8(4);
+                self.u32(items.len() as u32);
+                items.iter().for_each(|item| self.constant(item));
+            }
+            Constant::Struct { name, fields } => {
+                self.u8(5);
+                self.string(name);
+                self.u32(fields.len() as u32);
+                for (field, value) in fields {
+                    self.string(field);
+                    self.constant(value);
+                }
+            }
+            Constant::Enum { name, variant, payload } => {
+                self.u8(6);
+                self.string(name);
+                self.string(variant);
+                self.u32(payload.len() as u32);
+                payload.iter().for_each(|item| self.constant(item));
+            }
+        }
+    }
+
+    fn op(&mut self, op: &Op) {
+        let (code, operands): (u8, &[u32]) = match op {
+            Op::Constant(index) => (0, &[*index]),
+            Op::Unit => (1, &[]),
+            Op::Pop => (2, &[]),
+            Op::Load(index) => (3, &[*index]),
+            Op::Store(index) => (4, &[*index]),
+            Op::LoadGlobal(index) => (5, &[*index]),
+            Op::StoreGlobal(index) => (6, &[*index]),
+            Op::Binary(op) => {
+                self.u8(7);
+                self.u8(BINARY_OPS.iter().position(|known| known == op).unwrap() as u8);
+                return;
+            }
+            Op::Unary(op) => {
+                self.u8(8);
+                self.u8(matches!(op, UnaryOp::BitNot) as u8);
+                return;
+            }
+            Op::Jump(address) => (9, &[*address]),
+            Op::JumpIfFalse(address) => (10, &[*address]),
+            Op::Call(index) => (11, &[*index]),
+            Op::Builtin(index, count) => {
+                self.u8(12);
+                self.u32(*index);
+                self.u32(*count);
+                return;
+            }
+            Op::CallMethod(name, count) => {
+                self.u8(13);
+                self.u32(*name);
+                self.u32(*count);
+                return;
+            }
+            Op::Return => (14, &[]),
+            Op::Array(count) => (15, &[*count]),
+            Op::Index => (16, &[]),
+            Op::StoreIndex => (17, &[]),
+            Op::Field(name) => (18, &[*name]),
+            Op::SetField(name) => (19, &[*name]),
+            Op::Slice => (20, &[]),
+            Op::ToInteger => (21, &[]),
+            Op::ToFloat => (22, &[]),
+            Op::IsVariant(name) => (23, &[*name]),
+            Op::Payload(index) => (24, &[*index]),
+            Op::Items => (25, &[]),
+            Op::Len => (26, &[]),
+            Op::TryBegin(address) => (27, &[*address]),
+            Op::TryEnd => (28, &[]),
+        };
+        self.u8(code);
+        operands.iter().for_each(|operand| self.u32(*operand));
+    }
+}
+
+struct Reader<'b> {
+    bytes: &'b [u8],
+    at: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.at..self.at + count)
+            .ok_or_else(|| "the bytecode file is truncated".to_string())?;
+        self.at += count;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<[u8; 8], String> {
+        Ok(self.take(8)?.try_into().unwrap())
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| "invalid UTF-8 in the bytecode file".to_string())
+    }
+
+    fn constant(&mut self) -> Result<Constant, String> {
+        Ok(match self.u8()? {
+            0 => Constant::Integer(i64::from_le_bytes(self.u64()?)),
+            1 => Constant::Float(f64::from_le_bytes(self.u64()?)),
+            2 => Constant::Boolean(self.u8()? != 0),
+            3 => Constant::String(self.string()?),
+            4 => Constant::Array((0..self.u32()?).map(|_| self.constant()).collect::<Result<_, _>>()?),
+            5 => {
+                let name = self.string()?;
+                let fields = (0..self.u32()?)
+                    .map(|_| Ok((self.string()?, self.constant()?)))
+                    .collect::<Result<_, String>>()?;
+                Constant::Struct { name, fields }
+            }
+            6 => {
+                let name = self.string()?;
+                let variant = self.string()?;
+                let payload = (0..self.u32()?).map(|_| self.constant()).collect::<Result<_, _>>()?;
+                Constant::Enum { name, variant, payload }
+            }
+            tag => return Err(format!("unknown constant tag {} in the bytecode file", tag)),
+        })
+    }
+
+    fn op(&mut self) -> Result<Op, String> {
+        Ok(match self.u8()? {
+            0 => Op::Constant(self.u32()?),
+            1 => Op::Unit,
+            2 => Op::Pop,
+            3 => Op::Load(self.u32()?),
+            4 => Op::Store(self.u32()?),
+            5 => Op::LoadGlobal(self.u32()?),
+            6 => Op::StoreGlobal(self.u32()?),
+            7 => {
+                let code = self.u8()?;
+                let op = BINARY_OPS.get(code as usize).ok_or_else(|| format!("unknown operator {}", code))?;
+                Op::Binary(op.clone())
+            }
+            8 => Op::Unary(if self.u8()? == 0 { UnaryOp::Neg } else { UnaryOp::BitNot }),
+            9 => Op::Jump(self.u32()?),
+            10 => Op::JumpIfFalse(self.u32()?),
+            11 => Op::Call(self.u32()?),
+            12 => Op::Builtin(self.u32()?, self.u32()?),
+            13 => Op::CallMethod(self.u32()?, self.u32()?),
+            14 => Op::Return,
+            15 => Op::Array(self.u32()?),
+            16 => Op::Index,
+            17 => Op::StoreIndex,
+            18 => Op::Field(self.u32()?),
+            19 => Op::SetField(self.u32()?),
+            20 => Op::Slice,
+            21 => Op::ToInteger,
+            22 => Op::ToFloat,
+            23 => Op::IsVariant(self.u32()?),
+            24 => Op::Payload(self.u32()?),
+            25 => Op::Items,
+            26 => Op::Len,
+            27 => Op::TryBegin(self.u32()?),
+            28 => Op::TryEnd,
+            code => return Err(format!("unknown instruction {} in the bytecode file", code)),
+        })
+    }
+}
+
+fn unsupported(what: &str) -> CompileError {
+    CompileError::Compilation("E0599", format!("the bytecode backend does not support {} yet", what))
+}
+
+pub fn compile(program: &Program) -> Result<Module, CompileError> {
+    let mut emitter = Emitter {
+        constants: Vec::new(),
+        builtins: Vec::new(),
+        globals: HashMap::new(),
+        functions: HashMap::new(),
+        structs: HashMap::new(),
+        enums: HashMap::new(),
+        module: None,
+        code: Vec::new(),
+        scopes: Vec::new(),
+        locals: 0,
+        loops: Vec::new(),
+        tries: 0,
+    };
+
+    // Функции и глобальные переменные видны отовсюду, поэтому номера раздаются заранее
+    let mut bodies = Vec::new();
+    for stmt in &program.statements {
+        match stmt {
+            Stmt::Function { name, params, return_type, body, .. } => {
+                let params = params.iter().map(|param| param.name.clone()).collect();
+                bodies.push((name.clone(), params, return_type, body));
+            }
+            Stmt::Impl { self_type, methods, .. } => {
+                let type_name = user_type_name(self_type).unwrap();
+                for method in methods {
+                    let mut params = vec!["self".to_string()];
+                    params.extend(method.signature.params.iter().map(|param| param.name.clone()));
+                    let key = method_key(type_name, &method.signature.name);
+                    bodies.push((key, params, &method.signature.return_type, &method.body));
+                }
+            }
+            Stmt::Struct { name, fields, .. } => {
+                emitter.structs.insert(name.clone(), fields.clone());
+            }
+            Stmt::Enum { name, variants, .. } => {
+                emitter.enums.insert(name.clone(), variants.clone());
+            }
+            Stmt::Declaration { name, .. } => emitter.declare_global(name),
+            Stmt::Destructure { pattern, .. } => {
+                for (_, binding) in pattern_fields(pattern) {
+                    emitter.declare_global(binding);
+                }
+            }
+            _ => {}
+        }
+    }
+    for (index, (name, ..)) in bodies.iter().enumerate() {
+        emitter.functions.insert(name.clone(), index as u32);
+    }
+
+    let mut functions = Vec::new();
+    for (name, params, return_type, body) in &bodies {
+        // Методы не принадлежат модулю, а функции модуля видят его члены без префикса
+        emitter.module = split_member(name).filter(|_| !name.contains("::")).map(|(module, _)| module.to_string());
+        functions.push(emitter.function(name, params, return_type.as_ref(), body, false)?);
+    }
+    emitter.module = None;
+    let entry = functions.len() as u32;
+    functions.push(emitter.function("main", &[], None, &program.statements, true)?);
+
+    Ok(Module {
+        constants: emitter.constants,
+        builtins: emitter.builtins,
+        globals: emitter.globals.len() as u32,
+        functions,
+        entry,
+    })
+}
+
+// Методы хранятся под именем Type::method, чтобы не совпасть с членами модулей
+pub fn method_key(type_name: &str, method: &str) -> String {
+    format!("{}::{}", type_name, method)
+}
+
+#[derive(Clone, Copy)]
+enum Slot {
+    Local(u32),
+    Global(u32),
+}
+
+struct LoopContext {
+    label: Option<String>,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+    // Сколько try было открыто снаружи цикла: выход из цикла закрывает остальные
+    tries: usize,
+}
+
+struct Emitter {
+    constants: Vec<Constant>,
+    builtins: Vec<String>,
+    globals: HashMap<String, u32>,
+    functions: HashMap<String, u32>,
+    // Поля структур в порядке объявления
+    structs: HashMap<String, Vec<Field>>,
+    enums: HashMap<String, Vec<Variant>>,
+    module: Option<String>,
+    // Состояние функции, которая сейчас переводится
+    code: Vec<Op>,
+    scopes: Vec<HashMap<String, u32>>,
+    locals: u32,
+    loops: Vec<LoopContext>,
+    tries: usize,
+}
+
+impl Emitter {
+    fn declare_global(&mut self, name: &str) {
+        let index = self.globals.len() as u32;
+        self.globals.entry(name.to_string()).or_insert(index);
+    }
+
+    // Тело main — инструкции верхнего уровня: их объявления попадают в глобальные переменные
+    fn function(
+        &mut self,
+        name: &str,
+        params: &[String],
+        return_type: Option<&VarType>,
+        body: &[Stmt],
+        top_level: bool,
+    ) -> Result<Function, CompileError> {
+        self.code = Vec::new();
+        self.locals = 0;
+        self.loops.clear();
+        self.tries = 0;
+        self.scopes = if top_level { Vec::new() } else { vec![HashMap::new()] };
+        for param in params {
+            self.declare_local(param);
+        }
+        for stmt in body {
+            self.stmt(stmt)?;
+        }
+        // Как и в C, функция, дошедшая до конца без return, возвращает значение по умолчанию
+        match return_type {
+            Some(return_type) => {
+                let value = self.default_value(return_type)?;
+                let index = self.constant(value);
+                self.emit(Op::Constant(index))
+            }
+            None => self.emit(Op::Unit),
+        };
+        self.emit(Op::Return);
+        Ok(Function {
+            name: name.to_string(),
+            arity: params.len() as u32,
+            locals: self.locals,
+            code: std::mem::take(&mut self.code),
+        })
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.code.push(op);
+        self.code.len() - 1
+    }
+
+    fn here(&self) -> u32 {
+        self.code.len() as u32
+    }
+
+    fn patch(&mut self, at: usize, target: u32) {
+        match &mut self.code[at] {
+            Op::Jump(address) | Op::JumpIfFalse(address) | Op::TryBegin(address) => *address = target,
+            _ => unreachable!(),
+        }
+    }
+
+    fn constant(&mut self, constant: Constant) -> u32 {
+        if let Some(index) = self.constants.iter().position(|known| *known == constant) {
+            return index as u32;
+        }
+        self.constants.push(constant);
+        (self.constants.len() - 1) as u32
+    }
+
+    fn name_constant(&mut self, name: &str) -> u32 {
+        self.constant(Constant::String(name.to_string()))
+    }
+
+    fn builtin(&mut self, name: &str) -> u32 {
+        if let Some(index) = self.builtins.iter().position(|known| known == name) {
+            return index as u32;
+        }
+        self.builtins.push(name.to_string());
+        (self.builtins.len() - 1) as u32
+    }
+
+    fn declare_local(&mut self, name: &str) -> u32 {
+        let index = self.locals;
+        self.locals += 1;
+        self.scopes.last_mut().unwrap().insert(name.to_string(), index);
+        index
+    }
+
+    // Временная переменная без имени
+    fn temp(&mut self) -> u32 {
+        self.locals += 1;
+        self.locals - 1
+    }
+
+    // На верхнем уровне main областей нет: объявление там — глобальная переменная
+    fn declare(&mut self, name: &str) -> Slot {
+        if self.scopes.is_empty() {
+            return Slot::Global(self.globals[name]);
+        }
+        Slot::Local(self.declare_local(name))
+    }
+
+    // Член модуля виден без префикса, если его не закрывает локальная переменная
+    fn lookup(&self, name: &str) -> Option<Slot> {
+        if let Some(index) = self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            return Some(Slot::Local(*index));
+        }
+        if let Some(module) = &self.module {
+            if let Some(index) = self.globals.get(&member_name(module, name)) {
+                return Some(Slot::Global(*index));
+            }
+        }
+        self.globals.get(name).map(|index| Slot::Global(*index))
+    }
+
+    fn resolve_function(&self, name: &str) -> Option<u32> {
+        if let Some(module) = &self.module {
+            if let Some(index) = self.functions.get(&member_name(module, name)) {
+                return Some(*index);
+            }
+        }
+        self.functions.get(name).copied()
+    }
+
+    fn load(&mut self, name: &str) -> Result<(), CompileError> {
+        match self.lookup(name) {
+            Some(Slot::Local(index)) => self.emit(Op::Load(index)),
+            Some(Slot::Global(index)) => self.emit(Op::LoadGlobal(index)),
+            None => return Err(CompileError::Compilation("E0301", format!("undeclared variable '{}'", name))),
+        };
+        Ok(())
+    }
+
+    fn store(&mut self, slot: Slot) {
+        match slot {
+            Slot::Local(index) => self.emit(Op::Store(index)),
+            Slot::Global(index) => self.emit(Op::StoreGlobal(index)),
+        };
+    }
+
+    fn scoped(&mut self, body: impl FnOnce(&mut Self) -> Result<(), CompileError>) -> Result<(), CompileError> {
+        self.scopes.push(HashMap::new());
+        let result = body(self);
+        self.scopes.pop();
+        result
+    }
+
+    fn block(&mut self, statements: &[Stmt]) -> Result<(), CompileError> {
+        self.scoped(|emitter| statements.iter().try_for_each(|stmt| emitter.stmt(stmt)))
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), CompileError> {
+        match stmt {
+            Stmt::Declaration { name, value, .. } => {
+                // Значение глобальной переменной модуля видит члены модуля без префикса
+                let outer_module = self.module.clone();
+                if self.scopes.is_empty() {
+                    if let Some((module, _)) = split_member(name) {
+                        self.module = Some(module.to_string());
+                    }
+                }
+                let result = self.expr(value);
+                self.module = outer_module;
+                result?;
+                let slot = self.declare(name);
+                self.store(slot);
+            }
+            Stmt::Destructure { pattern, value, .. } => {
+                self.expr(value)?;
+                let temp = self.temp();
+                self.emit(Op::Store(temp));
+                self.destructure(temp, pattern)?;
+            }
+            Stmt::Assignment { name, value, .. } => {
+                self.expr(value)?;
+                let slot = self
+                    .lookup(name)
+                    .ok_or_else(|| CompileError::Compilation("E0301", format!("cannot assign to undeclared variable '{}'", name)))?;
+                self.store(slot);
+            }
+            Stmt::IndexAssignment { target, index, value, .. } => {
+                self.expr(target)?;
+                self.expr(index)?;
+                self.expr(value)?;
+                self.emit(Op::StoreIndex);
+            }
+            Stmt::FieldAssignment { target, field, value, .. } => {
+                self.expr(value)?;
+                self.store_field(target, field)?;
+            }
+            Stmt::Match { value, arms, .. } => self.match_stmt(value, arms)?,
+            Stmt::For { label, init, condition, update, body, .. } => self.scoped(|emitter| {
+                emitter.stmt(init)?;
+                let start = emitter.here();
+                emitter.expr(condition)?;
+                let exit = emitter.emit(Op::JumpIfFalse(0));
+                let context = emitter.loop_body(label, body)?;
+                let next = emitter.here();
+                emitter.stmt(update)?;
+                emitter.emit(Op::Jump(start));
+                emitter.close_loop(context, next, exit);
+                Ok(())
+            })?,
+            // Как for-in по массиву, только вместо элемента в переменную попадает сама позиция
+            Stmt::ForIn { label, variable, iterable: Expr::Range { start, end }, body, .. } => self.scoped(|emitter| {
+                emitter.expr(start)?;
+                let position = emitter.temp();
+                emitter.emit(Op::Store(position));
+                emitter.expr(end)?;
+                let limit = emitter.temp();
+                emitter.emit(Op::Store(limit));
+                let one = emitter.constant(Constant::Integer(1));
+
+                let start = emitter.here();
+                emitter.emit(Op::Load(position));
+                emitter.emit(Op::Load(limit));
+                emitter.emit(Op::Binary(BinOp::Lt));
+                let exit = emitter.emit(Op::JumpIfFalse(0));
+                emitter.emit(Op::Load(position));
+                let element = emitter.declare_local(variable);
+                emitter.emit(Op::Store(element));
+                emitter.emit(Op::Load(position));
+                emitter.emit(Op::Constant(one));
+                emitter.emit(Op::Binary(BinOp::Add));
+                emitter.emit(Op::Store(position));
+                let context = emitter.loop_body(label, body)?;
+                emitter.emit(Op::Jump(start));
+                emitter.close_loop(context, start, exit);
+                Ok(())
+            })?,
+            // Позиция сдвигается до тела, поэтому continue сразу проверяет следующий элемент
+            Stmt::ForIn { label, variable, iterable, body, .. } => self.scoped(|emitter| {
+                emitter.expr(iterable)?;
+                emitter.emit(Op::Items);
+                let items = emitter.temp();
+                emitter.emit(Op::Store(items));
+                let position = emitter.temp();
+                let zero = emitter.constant(Constant::Integer(0));
+                let one = emitter.constant(Constant::Integer(1));
+                emitter.emit(Op::Constant(zero));
+                emitter.emit(Op::Store(position));
+
+                let start = emitter.here();
+                emitter.emit(Op::Load(position));
+                emitter.emit(Op::Load(items));
+                emitter.emit(Op::Len);
+                emitter.emit(Op::Binary(BinOp::Lt));
+                let exit = emitter.emit(Op::JumpIfFalse(0));
+                emitter.emit(Op::Load(items));
+                emitter.emit(Op::Load(position));
+                emitter.emit(Op::Index);
+                let element = emitter.declare_local(variable);
+                emitter.emit(Op::Store(element));
+                emitter.emit(Op::Load(position));
+                emitter.emit(Op::Constant(one));
+                emitter.emit(Op::Binary(BinOp::Add));
+                emitter.emit(Op::Store(position));
+                let context = emitter.loop_body(label, body)?;
+                emitter.emit(Op::Jump(start));
+                emitter.close_loop(context, start, exit);
+                Ok(())
+            })?,
+            Stmt::While { label, condition, body, .. } => {
+                let start = self.here();
+                self.expr(condition)?;
+                let exit = self.emit(Op::JumpIfFalse(0));
+                let context = self.loop_body(label, body)?;
+                self.emit(Op::Jump(start));
+                self.close_loop(context, start, exit);
+            }
+            Stmt::Loop { label, body } => {
+                let start = self.here();
+                let context = self.loop_body(label, body)?;
+                self.emit(Op::Jump(start));
+                let end = self.here();
+                for at in context.breaks {
+                    self.patch(at, end);
+                }
+                for at in context.continues {
+                    self.patch(at, start);
+                }
+            }
+            Stmt::Break(label) => self.jump(true, label)?,
+            Stmt::Continue(label) => self.jump(false, label)?,
+            Stmt::Return(value) => {
+                match value {
+                    Some(value) => self.expr(value)?,
+                    None => {
+                        self.emit(Op::Unit);
+                    }
+                }
+                // Обработчики этой функции машина снимает сама при выходе из неё
+                self.emit(Op::Return);
+            }
+            Stmt::Block(body) => self.block(body)?,
+            Stmt::Try { body, error, handler } => {
+                let begin = self.emit(Op::TryBegin(0));
+                self.tries += 1;
+                let result = self.block(body);
+                self.tries -= 1;
+                result?;
+                self.emit(Op::TryEnd);
+                let skip = self.emit(Op::Jump(0));
+                let target = self.here();
+                self.patch(begin, target);
+                self.scoped(|emitter| {
+                    let slot = emitter.declare_local(error);
+                    emitter.emit(Op::Store(slot));
+                    handler.iter().try_for_each(|stmt| emitter.stmt(stmt))
+                })?;
+                let end = self.here();
+                self.patch(skip, end);
+            }
+            Stmt::Expression(expr) => {
+                self.expr(expr)?;
+                self.emit(Op::Pop);
+            }
+            Stmt::Defer(_) => return Err(unsupported("defer")),
+            // Объявления разобраны заранее, импорты уже склеены загрузчиком
+            Stmt::Function { .. }
+            | Stmt::Struct { .. }
+            | Stmt::Enum { .. }
+            | Stmt::Trait { .. }
+            | Stmt::Impl { .. }
+            | Stmt::Import(_) => {}
+        }
+        Ok(())
+    }
+
+    fn loop_body(&mut self, label: &Option<String>, body: &[Stmt]) -> Result<LoopContext, CompileError> {
+        self.loops.push(LoopContext { label: label.clone(), breaks: Vec::new(), continues: Vec::new(), tries: self.tries });
+        let result = self.block(body);
+        let context = self.loops.pop().unwrap();
+        result.map(|_| context)
+    }
+
+    fn close_loop(&mut self, context: LoopContext, next: u32, exit: usize) {
+        let end = self.here();
+        self.patch(exit, end);
+        for at in context.breaks {
+            self.patch(at, end);
+        }
+        for at in context.continues {
+            self.patch(at, next);
+        }
+    }
+
+    fn jump(&mut self, is_break: bool, label: &Option<String>) -> Result<(), CompileError> {
+        let index = match label {
+            Some(label) => self.loops.iter().rposition(|l| l.label.as_deref() == Some(label.as_str())),
+            None => self.loops.len().checked_sub(1),
+        };
+        let index = index.ok_or_else(|| {
+            CompileError::Compilation("E0402", match label {
+                Some(label) => format!("unknown loop label '{}'", label),
+                None => "break/continue outside of a loop".to_string(),
+            })
+        })?;
+        for _ in self.loops[index].tries..self.tries {
+            self.emit(Op::TryEnd);
+        }
+        let at = self.emit(Op::Jump(0));
+        let context = &mut self.loops[index];
+        if is_break {
+            context.breaks.push(at);
+        } else {
+            context.continues.push(at);
+        }
+        Ok(())
+    }
+
+    // Структуры копируются по значению, поэтому изменённая копия записывается обратно
+    // туда, откуда её взяли; новое значение поля уже лежит на стеке
+    fn store_field(&mut self, target: &Expr, field: &str) -> Result<(), CompileError> {
+        let value = self.temp();
+        self.emit(Op::Store(value));
+        self.expr(target)?;
+        self.emit(Op::Load(value));
+        let name = self.name_constant(field);
+        self.emit(Op::SetField(name));
+        match target {
+            Expr::Variable(name) => {
+                let slot = self
+                    .lookup(name)
+                    .ok_or_else(|| CompileError::Compilation("E0301", format!("cannot assign to undeclared variable '{}'", name)))?;
+                self.store(slot);
+            }
+            Expr::Field { target, field } => self.store_field(target, field)?,
+            Expr::Index { target, index } => {
+                let updated = self.temp();
+                self.emit(Op::Store(updated));
+                self.expr(target)?;
+                self.expr(index)?;
+                self.emit(Op::Load(updated));
+                self.emit(Op::StoreIndex);
+            }
+            _ => return Err(CompileError::Compilation("E0401", "cannot assign to a field of this expression".to_string())),
+        }
+        Ok(())
+    }
+
+    // Поля по имени или, в (x, y) = p, по порядку объявления: в нём машина и хранит поля
+    fn destructure(&mut self, source: u32, pattern: &StructPattern) -> Result<(), CompileError> {
+        for (field, binding) in pattern_fields(pattern) {
+            if binding == "_" {
+                continue;
+            }
+            self.emit(Op::Load(source));
+            match field {
+                Ok(name) => {
+                    let name = self.name_constant(name);
+                    self.emit(Op::Field(name))
+                }
+                Err(position) => self.emit(Op::Payload(position as u32)),
+            };
+            let slot = self.declare(binding);
+            self.store(slot);
+        }
+        Ok(())
+    }
+
+    fn match_stmt(&mut self, value: &Expr, arms: &[MatchArm]) -> Result<(), CompileError> {
+        self.expr(value)?;
+        let temp = self.temp();
+        self.emit(Op::Store(temp));
+        let mut ends = Vec::new();
+        for arm in arms {
+            let skip = match &arm.pattern {
+                Pattern::Wildcard | Pattern::Struct(_) => None,
+                Pattern::Literal(pattern) => {
+                    self.emit(Op::Load(temp));
+                    let constant = self.literal(pattern)?;
+                    let index = self.constant(constant);
+                    self.emit(Op::Constant(index));
+                    self.emit(Op::Binary(BinOp::Eq));
+                    Some(self.emit(Op::JumpIfFalse(0)))
+                }
+                Pattern::Variant { variant, .. } => {
+                    self.emit(Op::Load(temp));
+                    let name = self.name_constant(variant);
+                    self.emit(Op::IsVariant(name));
+                    Some(self.emit(Op::JumpIfFalse(0)))
+                }
+            };
+            self.scoped(|emitter| {
+                match &arm.pattern {
+                    Pattern::Variant { bindings, .. } => {
+                        for (index, binding) in bindings.iter().enumerate() {
+                            if binding == "_" {
+                                continue;
+                            }
+                            emitter.emit(Op::Load(temp));
+                            emitter.emit(Op::Payload(index as u32));
+                            let slot = emitter.declare_local(binding);
+                            emitter.emit(Op::Store(slot));
+                        }
+                    }
+                    Pattern::Struct(pattern) => emitter.destructure(temp, pattern)?,
+                    Pattern::Literal(_) | Pattern::Wildcard => {}
+                }
+                arm.body.iter().try_for_each(|stmt| emitter.stmt(stmt))
+            })?;
+            match skip {
+                Some(skip) => {
+                    ends.push(self.emit(Op::Jump(0)));
+                    let next = self.here();
+                    self.patch(skip, next);
+                }
+                // Ветки после _ и разбора структуры недостижимы
+                None => break,
+            }
+        }
+        let end = self.here();
+        for at in ends {
+            self.patch(at, end);
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<(), CompileError> {
+        match expr {
+            Expr::Literal(value) => {
+                let constant = self.literal(value)?;
+                let index = self.constant(constant);
+                self.emit(Op::Constant(index));
+            }
+            Expr::Variable(name) => self.load(name)?,
+            Expr::BinaryOp { op: BinOp::Coalesce, .. } => return Err(unsupported("'??'")),
+            Expr::BinaryOp { left, op, right, .. } => {
+                self.expr(left)?;
+                self.expr(right)?;
+                self.emit(Op::Binary(op.clone()));
+            }
+            Expr::UnaryOp { op, operand, .. } => {
+                self.expr(operand)?;
+                self.emit(Op::Unary(op.clone()));
+            }
+            Expr::Call { name, args, .. } => self.call(name, args)?,
+            Expr::MethodCall { target, method, args, .. } => {
+                self.expr(target)?;
+                self.arguments(args)?;
+                let name = self.name_constant(method);
+                self.emit(Op::CallMethod(name, args.len() as u32));
+            }
+            Expr::Index { target, index } => {
+                self.expr(target)?;
+                self.expr(index)?;
+                self.emit(Op::Index);
+            }
+            Expr::Slice { target, start, end } => {
+                self.expr(target)?;
+                match start {
+                    Some(start) => self.expr(start)?,
+                    None => {
+                        let zero = self.constant(Constant::Integer(0));
+                        self.emit(Op::Constant(zero));
+                    }
+                }
+                match end {
+                    Some(end) => self.expr(end)?,
+                    None => {
+                        self.emit(Op::Unit);
+                    }
+                }
+                self.emit(Op::Slice);
+            }
+            Expr::Field { target, field } => {
+                self.expr(target)?;
+                let name = self.name_constant(field);
+                self.emit(Op::Field(name));
+            }
+            Expr::Cast { value, target } => {
+                self.expr(value)?;
+                // Остальные приведения проверка типов пропускает, только если тип не меняется
+                match target {
+                    VarType::Integer => {
+                        self.emit(Op::ToInteger);
+                    }
+                    VarType::Float => {
+                        self.emit(Op::ToFloat);
+                    }
+                    _ => {}
+                }
+            }
+            Expr::Array(elements) => {
+                self.arguments(elements)?;
+                self.emit(Op::Array(elements.len() as u32));
+            }
+            // Поля по одному записываются в структуру со значениями по умолчанию
+            Expr::Struct { name, fields } => {
+                let empty = self.default_value(&VarType::Struct(name.clone()))?;
+                let index = self.constant(empty);
+                self.emit(Op::Constant(index));
+                for (field, value) in fields {
+                    self.expr(value)?;
+                    let name = self.name_constant(field);
+                    self.emit(Op::SetField(name));
+                }
+            }
+            Expr::Enum { .. } => return Err(unsupported("enum values computed at run time")),
+            Expr::Map(_) => return Err(unsupported("maps")),
+            Expr::Spread(_) => return Err(unsupported("spread arguments")),
+            Expr::Range { .. } => return Err(CompileError::Type("E0499", RANGE_CONTEXT_ERROR.to_string())),
+            Expr::Try(_) => return Err(unsupported("the '?' operator")),
+            Expr::Lambda { .. } => return Err(unsupported("lambdas")),
+            Expr::Comprehension { .. } => return Err(unsupported("array comprehensions")),
+            Expr::JsonParse { .. } => return Err(unsupported("'json_parse'")),
+        }
+        Ok(())
+    }
+
+    // Поля литерала структуры записаны в любом порядке, а хранятся в порядке объявления
+    fn literal(&self, value: &Value) -> Result<Constant, CompileError> {
+        Ok(match value {
+            Value::Integer(value) => Constant::Integer(*value),
+            Value::Float(value) => Constant::Float(*value),
+            Value::Boolean(value) => Constant::Boolean(*value),
+            Value::String(value) => Constant::String(value.clone()),
+            Value::Array { items, .. } => {
+                Constant::Array(items.iter().map(|item| self.literal(item)).collect::<Result<_, _>>()?)
+            }
+            Value::Struct { name, fields } => {
+                let mut ordered = Vec::new();
+                for field in &self.structs[name] {
+                    let (_, value) = fields.iter().find(|(known, _)| *known == field.name).unwrap();
+                    ordered.push((field.name.clone(), self.literal(value)?));
+                }
+                Constant::Struct { name: name.clone(), fields: ordered }
+            }
+            Value::Enum { name, variant, payload } => Constant::Enum {
+                name: name.clone(),
+                variant: variant.clone(),
+                payload: payload.iter().map(|item| self.literal(item)).collect::<Result<_, _>>()?,
+            },
+            Value::BigInt(_) => return Err(unsupported("BigInt values")),
+            Value::Bytes(_) => return Err(unsupported("Bytes values")),
+            Value::Map { .. } => return Err(unsupported("maps")),
+            Value::Some(_) | Value::None => return Err(unsupported("optional values")),
+            Value::Result { .. } => return Err(unsupported("Result values")),
+        })
+    }
+
+    // Первый вариант перечисления, поля структуры — тоже значения по умолчанию
+    fn default_value(&self, var_type: &VarType) -> Result<Constant, CompileError> {
+        Ok(match var_type {
+            VarType::String => Constant::String(String::new()),
+            VarType::Integer => Constant::Integer(0),
+            VarType::Float => Constant::Float(0.0),
+            VarType::Boolean => Constant::Boolean(false),
+            VarType::Array(_) => Constant::Array(Vec::new()),
+            VarType::Struct(name) => Constant::Struct {
+                name: name.clone(),
+                fields: self.structs[name]
+                    .iter()
+                    .map(|field| Ok((field.name.clone(), self.default_value(&field.var_type)?)))
+                    .collect::<Result<_, CompileError>>()?,
+            },
+            VarType::Enum(name) => {
+                let variant = &self.enums[name][0];
+                Constant::Enum {
+                    name: name.clone(),
+                    variant: variant.name.clone(),
+                    payload: variant.payload.iter().map(|t| self.default_value(t)).collect::<Result<_, _>>()?,
+                }
+            }
+            other => return Err(unsupported(&format!("values of type {:?}", other))),
+        })
+    }
+
+    fn arguments(&mut self, args: &[Expr]) -> Result<(), CompileError> {
+        args.iter().try_for_each(|arg| self.expr(arg))
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr]) -> Result<(), CompileError> {
+        if is_builtin(name) {
+            if !vm::is_builtin(name) {
+                return Err(unsupported(&format!("the builtin '{}'", name)));
+            }
+            self.arguments(args)?;
+            let index = self.builtin(name);
+            self.emit(Op::Builtin(index, args.len() as u32));
+            return Ok(());
+        }
+        if self.lookup(name).is_some() {
+            return Err(unsupported("calls of function values"));
+        }
+        let index = self
+            .resolve_function(name)
+            .ok_or_else(|| CompileError::Compilation("E0302", format!("unknown function '{}'", name)))?;
+        self.arguments(args)?;
+        self.emit(Op::Call(index));
+        Ok(())
+    }
+}
+
+// Поля по имени (Ok) или по позиции (Err) и переменные, в которые они попадают
+fn pattern_fields(pattern: &StructPattern) -> Vec<(Result<&str, usize>, &str)> {
+    match pattern {
+        StructPattern::Named { fields, .. } => {
+            fields.iter().map(|(field, binding)| (Ok(field.as_str()), binding.as_str())).collect()
+        }
+        StructPattern::Positional { bindings, .. } => {
+            bindings.iter().enumerate().map(|(position, binding)| (Err(position), binding.as_str())).collect()
+        }
+    }
+}
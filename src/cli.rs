@@ -1,7 +1,20 @@
 use crate::lexer;
 use crate::parser;
+use crate::diagnostics::{self, Diagnostic, Note};
+use crate::semantic;
+use crate::lints;
+use crate::locale::{self, translate, Locale};
+use crate::optimize;
+use crate::warnings::{self, Level, WarningLevels};
+use crate::error_codes;
 use crate::compiler;
+use crate::bytecode;
+use crate::vm;
+use crate::sarif;
+use crate::teach;
+use crate::source::SourceText;
 
+use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -15,19 +28,69 @@ fn show_help() {
     println!();
     println!("Commands:");
     println!("  build <file.qrk>    Compile a program");
-    println!("  run <file.qrk>      Compile and run");
-    println!("  check <file.qrk>    Check syntax");
+    println!("  run <file.qrk>      Compile and run (a .qbc file runs in the bytecode VM)");
+    println!("  check <file.qrk>    Check syntax and types, run lints");
+    println!("  test <file.qrk>     Run the #[test] functions");
+    println!("  explain [code]      Describe an error code such as E0101, or list all codes");
     println!("  help                Show this help");
     println!("  version             Show version");
     println!();
     println!("Options for build/run:");
     println!("  -o, --output <name>  Output file name");
+    println!("  --emit <exe|bytecode|asm|obj|c>  What build produces: an executable, .qbc bytecode, assembly,");
+    println!("                       an object file or portable C source to build with any C compiler");
+    println!("  -S                   Write the assembly of the program to <name>.s instead of linking it");
+    println!("  -c                   Write an object file <name>.o to link yourself (with -lm)");
+    println!("  --target <triple>    Build an executable for another system, e.g. x86_64-pc-windows-gnu");
+    println!("  --verbose            Show progress while processing large files");
+    println!("  -- <args>            Pass the remaining arguments to the program (run only)");
+    println!();
+    println!("Options for check:");
+    println!("  --message-format=sarif  Print the results as a SARIF 2.1.0 document on stdout");
+    println!();
+    println!("Common options:");
+    println!("  --locale <en|ru>     Language of compiler messages (default: QUARK_LOCALE or LANG)");
+    println!("  --teach              Explain the language concept behind each error in plain words");
+    println!();
+    println!("Warning options (all commands):");
+    println!("  -W <name>            Report the named warning");
+    println!("  -A <name>            Silence the named warning");
+    println!("  --deny-warnings      Fail if any warning is reported");
+    for spec in warnings::WARNINGS {
+        println!("  {:<20} {}", spec.name, spec.description);
+    }
     println!();
     println!("Examples:");
     println!("  quark build hello.qrk");
     println!("  quark build hello.qrk -o program.exe");
+    println!("  quark build hello.qrk --emit bytecode");
+    println!("  quark build -S hello.qrk");
+    println!("  quark build -c hello.qrk && gcc hello.o other.o -lm -o hello");
+    println!("  quark build hello.qrk --emit c && cc hello.c -lm -o hello");
+    println!("  quark build hello.qrk --target x86_64-pc-windows-gnu");
     println!("  quark run hello.qrk");
+    println!("  quark run hello.qbc");
+    println!("  quark run tool.qrk -- input.txt");
     println!("  quark check test.qrk");
+    println!("  quark check test.qrk --message-format=sarif > quark.sarif");
+    println!("  quark test tests.qrk");
+    println!("  quark check lesson.qrk --teach");
+    println!("  quark explain E0101");
+}
+
+fn explain(code: Option<&str>) -> Result<(), String> {
+    let Some(code) = code else {
+        for entry in error_codes::CODES {
+            println!("{}  {}", entry.code, entry.title);
+        }
+        return Ok(());
+    };
+    let entry = error_codes::lookup(code)
+        .ok_or_else(|| format!("unknown error code '{}'; run 'quark explain' to list all codes", code))?;
+    println!("{}: {}", entry.code, entry.title);
+    println!();
+    println!("{}", entry.explanation);
+    Ok(())
 }
 
 fn show_version() {
@@ -35,9 +98,8 @@ fn show_version() {
     println!("License: Apache 2.0");
 }
 
-fn read_source_file(path: &str) -> Result<String, String> {
-    let path = Path::new(path);
-    
+fn read_source_file(path: &Path) -> Result<SourceText, String> {
+
     if !path.exists() {
         return Err(format!("File not found: {}", path.display()));
     }
@@ -46,26 +108,421 @@ fn read_source_file(path: &str) -> Result<String, String> {
         return Err("File must have .qrk extension".to_string());
     }
     
-    fs::read_to_string(path).map_err(|e| format!("Error reading file: {}", e))
+    SourceText::read(path).map_err(|e| format!("Error reading file: {}", e))
+}
+
+fn tokenize_source(
+    path: &Path,
+    source: &SourceText,
+    verbose: bool,
+    reporter: &mut Reporter,
+) -> Result<Vec<lexer::SpannedToken>, String> {
+    let mut lexer = lexer::Lexer::new(source);
+    
+    let result = if verbose {
+        if source.is_mapped() {
+            reporter.progress(format!("Memory-mapped {} bytes", source.len()));
+        }
+        let result = lexer.tokenize_with_progress(|percent| eprint!("\rLexing: {}%", percent));
+        eprintln!();
+        result
+    } else {
+        lexer.tokenize()
+    };
+    
+    result.map_err(|errors| {
+        let diagnostics = errors.iter().map(|e| {
+            let (line, column) = line_and_column(source, e.position);
+            let span = lexer::Span {
+                start: e.position,
+                end: e.position + 1,
+                line,
+                column,
+                end_line: line,
+                end_column: column + 1,
+            };
+            Diagnostic { code: e.code, message: &e.message, path, source, span, notes: &[] }
+        });
+        reporter.errors(path, diagnostics)
+    })
+}
+
+// Строка и столбец (с единицы) для байтового смещения в исходнике
+fn line_and_column(source: &str, position: usize) -> (usize, usize) {
+    let before = &source[..position.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, column)
+}
+
+fn report_parse_errors<'e>(
+    path: &Path,
+    source: &str,
+    errors: impl IntoIterator<Item = &'e parser::ParseError>,
+    reporter: &mut Reporter,
+) -> String {
+    let diagnostics = errors.into_iter().map(|e| {
+        Diagnostic { code: e.code, message: &e.message, path, source, span: e.span, notes: &e.notes }
+    });
+    reporter.errors(path, diagnostics)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MessageFormat {
+    Human,
+    Sarif,
+}
+
+// Куда уходят диагностики: в stderr с фрагментами исходника или в документ SARIF,
+// который выводится одним куском в конце проверки
+struct Reporter {
+    format: MessageFormat,
+    findings: Vec<sarif::Finding>,
+}
+
+impl Reporter {
+    fn new(format: MessageFormat) -> Self {
+        Reporter { format, findings: Vec::new() }
+    }
+
+    // Для SARIF stdout занят документом, поэтому сообщения о ходе работы не выводятся
+    fn progress(&self, text: impl std::fmt::Display) {
+        if self.format == MessageFormat::Human {
+            println!("{}", translate(&text.to_string()));
+        }
+    }
+
+    // Ошибки выводятся сразу, наверх уходит только итог
+    fn errors<'d>(&mut self, path: &Path, diagnostics: impl Iterator<Item = Diagnostic<'d>>) -> String {
+        let color = diagnostics::use_color();
+        let mut count = 0;
+        for diagnostic in diagnostics {
+            match self.format {
+                MessageFormat::Human => eprintln!("{}\n", with_lesson(diagnostic.render(color), diagnostic.code)),
+                MessageFormat::Sarif => self.findings.push(sarif::Finding {
+                    rule: diagnostic.code.to_string(),
+                    warning: false,
+                    message: translate(diagnostic.message),
+                    path: path.to_path_buf(),
+                    span: Some(diagnostic.span),
+                    notes: translated_notes(diagnostic.notes),
+                }),
+            }
+            count += 1;
+        }
+        translate(&format!("could not compile {} because of {} previous error(s)", path.display(), count))
+    }
+
+    // Семантическая ошибка без места (или в программе из нескольких файлов) относится ко всему файлу
+    fn semantic_error(&mut self, input_path: &str, program: &parser::Program, error: &semantic::SemanticError) -> String {
+        let path = Path::new(input_path);
+        let source = single_file_source(path, program);
+        let code = error.code;
+        let span = error.span.filter(|_| source.is_some());
+        match (self.format, span, &source) {
+            (MessageFormat::Human, Some(span), Some(source)) => {
+                let diagnostic = Diagnostic { code, message: &error.message, path, source, span, notes: &error.notes };
+                eprintln!("{}\n", with_lesson(diagnostic.render(diagnostics::use_color()), code));
+                return translate(&format!("could not compile {} because of {} previous error(s)", path.display(), 1));
+            }
+            (MessageFormat::Human, ..) => {}
+            (MessageFormat::Sarif, ..) => self.findings.push(sarif::Finding {
+                rule: code.to_string(),
+                warning: false,
+                message: translate(&error.message),
+                path: path.to_path_buf(),
+                span,
+                notes: translated_notes(&error.notes),
+            }),
+        }
+        // Без фрагмента исходника заметки идут строками под сообщением
+        let notes: String = error
+            .notes
+            .iter()
+            .map(|note| format!("\n  = {}: {}", translate(note.kind.label()), translate(&note.message)))
+            .collect();
+        with_lesson(format!("{} [{}]: {}{}", translate("Semantic error"), code, translate(&error.message), notes), code)
+    }
+
+    // Место ошибки компилятора показывается фрагментом исходника, только если программа из одного файла:
+    // в программе, склеенной из модулей, не узнать, к какому файлу относится место
+    fn compile_errors(&mut self, input_path: &str, program: &parser::Program, errors: &[compiler::CompileError]) -> String {
+        let path = Path::new(input_path);
+        let source = single_file_source(path, program);
+        let color = diagnostics::use_color();
+        for error in errors {
+            let message = error.message();
+            let code = error.code();
+            let span = error.span().filter(|_| source.is_some());
+            match (self.format, span, &source) {
+                (MessageFormat::Human, Some(span), Some(source)) => {
+                    let diagnostic = Diagnostic { code, message: &message, path, source, span, notes: &[] };
+                    eprintln!("{}\n", with_lesson(diagnostic.render(color), code));
+                }
+                (MessageFormat::Human, ..) => eprintln!(
+                    "{}\n",
+                    with_lesson(format!("{} [{}]: {}", translate("Compilation error"), code, translate(&error.to_string())), code)
+                ),
+                (MessageFormat::Sarif, ..) => self.findings.push(sarif::Finding {
+                    rule: code.to_string(),
+                    warning: false,
+                    message: translate(&message),
+                    path: path.to_path_buf(),
+                    span,
+                    notes: Vec::new(),
+                }),
+            }
+        }
+        translate(&format!("could not compile {} because of {} previous error(s)", path.display(), errors.len()))
+    }
+
+    // span уже отброшен вызывающим, если программа склеена из нескольких файлов
+    fn warning(&mut self, path: &Path, warning: &semantic::Warning, span: Option<lexer::Span>) {
+        match (self.format, span) {
+            (MessageFormat::Human, Some(span)) => eprintln!(
+                "{}: {} [{}]\n  --> {}:{}:{}",
+                translate("Warning"),
+                translate(&warning.message),
+                warning.name,
+                path.display(),
+                span.line,
+                span.column
+            ),
+            (MessageFormat::Human, None) => {
+                eprintln!("{}: {} [{}]", translate("Warning"), translate(&warning.message), warning.name)
+            }
+            (MessageFormat::Sarif, _) => self.findings.push(sarif::Finding {
+                rule: warning.name.to_string(),
+                warning: true,
+                message: translate(&warning.message),
+                path: path.to_path_buf(),
+                span,
+                notes: Vec::new(),
+            }),
+        }
+    }
+}
+
+fn translated_notes(notes: &[Note]) -> Vec<Note> {
+    notes.iter().map(|note| Note { message: translate(&note.message), ..note.clone() }).collect()
+}
+
+// Места в программе, склеенной из модулей, не привязаны к файлу, поэтому исходник читается
+// только для программы без импортов
+fn single_file_source(path: &Path, program: &parser::Program) -> Option<SourceText> {
+    let single_file = !program.statements.iter().any(|stmt| matches!(stmt, parser::Stmt::Import(_)));
+    if single_file { read_source_file(path).ok() } else { None }
+}
+
+// С --teach под сообщением об ошибке идёт пояснение понятия, к которому относится её код
+fn with_lesson(message: String, code: &str) -> String {
+    match teach::render(code) {
+        Some(lesson) if teach::enabled() => format!("{}\n\n{}", message, lesson),
+        _ => message,
+    }
+}
+
+// Загружает файл и всё, что он импортирует, и склеивает модули в одну программу:
+// каждый модуль идёт после своих зависимостей
+fn load_program(input_path: &str, verbose: bool, reporter: &mut Reporter) -> Result<parser::Program, String> {
+    let mut loader = ModuleLoader { verbose, reporter, loaded: Vec::new(), visiting: Vec::new(), modules: Vec::new() };
+    loader.load(Path::new(input_path))?;
+    
+    let mut parser = parser::Parser::new(Vec::new());
+    let mut statements = Vec::new();
+    let mut symbols: HashMap<(&str, String), PathBuf> = HashMap::new();
+    for (path, source, tokens) in loader.modules {
+        let module = parser
+            .parse_module(tokens)
+            .map_err(|errors| report_parse_errors(&path, &source, &errors, loader.reporter))?;
+        
+        for stmt in &module.statements {
+            let symbol = match stmt {
+                parser::Stmt::Function { name, .. } => ("function", name),
+                parser::Stmt::Declaration { name, .. } => ("variable", name),
+                parser::Stmt::Struct { name, .. } | parser::Stmt::Enum { name, .. } | parser::Stmt::Trait { name, .. } => {
+                    ("type", name)
+                }
+                _ => continue,
+            };
+            match symbols.get(&(symbol.0, symbol.1.clone())) {
+                // Повторы внутри одного файла сообщает семантический анализ
+                Some(other) if *other != path => {
+                    return Err(format!(
+                        "Duplicate symbol: {} '{}' is defined in both {} and {}",
+                        symbol.0, symbol.1, other.display(), path.display()
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    symbols.insert((symbol.0, symbol.1.clone()), path.clone());
+                }
+            }
+        }
+        statements.extend(module.statements);
+    }
+    
+    Ok(parser::Program { statements })
+}
+
+struct ModuleLoader<'r> {
+    verbose: bool,
+    reporter: &'r mut Reporter,
+    // Канонические пути уже загруженных файлов
+    loaded: Vec<PathBuf>,
+    // Цепочка файлов, импорт которых сейчас разбирается, — для поиска циклов
+    visiting: Vec<PathBuf>,
+    modules: Vec<(PathBuf, SourceText, Vec<lexer::SpannedToken>)>,
+}
+
+impl ModuleLoader<'_> {
+    fn load(&mut self, path: &Path) -> Result<(), String> {
+        let source = read_source_file(path)?;
+        let canonical = fs::canonicalize(path).map_err(|e| format!("Error reading file: {}", e))?;
+        if self.loaded.contains(&canonical) {
+            return Ok(());
+        }
+        if let Some(start) = self.visiting.iter().position(|p| *p == canonical) {
+            let mut cycle: Vec<String> = self.visiting[start..].iter().map(|p| p.display().to_string()).collect();
+            cycle.push(canonical.display().to_string());
+            return Err(format!("Import cycle: {}", cycle.join(" -> ")));
+        }
+        
+        self.reporter.progress(format!("Reading: {}", path.display()));
+        let tokens = tokenize_source(path, &source, self.verbose, self.reporter)?;
+        self.reporter.progress(format!("Tokens: {}", tokens.len()));
+        let imports = parser::Parser::new(tokens.clone())
+            .parse_imports()
+            .map_err(|e| report_parse_errors(path, &source, [&e], self.reporter))?;
+        
+        self.visiting.push(canonical.clone());
+        let base = path.parent().unwrap_or(Path::new(""));
+        for import in imports {
+            let import_path = base.join(import);
+            if !import_path.exists() {
+                return Err(format!("File not found: {} (imported from {})", import_path.display(), path.display()));
+            }
+            self.load(&import_path)?;
+        }
+        self.visiting.pop();
+        
+        self.loaded.push(canonical);
+        self.modules.push((path.to_path_buf(), source, tokens));
+        Ok(())
+    }
+}
+
+// Проверка заканчивается генерацией C без сборки: деление на ноль и переполнение констант
+// видны только после свёртки. Программа остаётся свёрнутой, сборке это и нужно
+fn analyze_program(
+    input_path: &str,
+    program: &mut parser::Program,
+    levels: &WarningLevels,
+    lint: bool,
+    reporter: &mut Reporter,
+) -> Result<(), String> {
+    let path = Path::new(input_path);
+    let mut analyzer = semantic::Analyzer::new();
+    let mut warnings = analyzer.analyze(program).map_err(|e| reporter.semantic_error(input_path, program, &e))?;
+    if lint {
+        warnings.extend(lints::run(program));
+    }
+    
+    let single_file = !program.statements.iter().any(|stmt| matches!(stmt, parser::Stmt::Import(_)));
+    let mut reported = 0;
+    for warning in warnings.iter().filter(|w| levels.level(w.name) == Level::Warn) {
+        reporter.warning(path, warning, warning.span.filter(|_| single_file));
+        reported += 1;
+    }
+    if levels.deny && reported > 0 {
+        return Err(translate(&format!("{} warning(s) treated as errors because of --deny-warnings", reported)));
+    }
+    
+    optimize::optimize(program);
+    compiler::Compiler::new().check(program).map_err(|errors| reporter.compile_errors(input_path, program, &errors))
+}
+
+// Что строит quark build: исполняемый файл через C, сам код на C, его ассемблер,
+// объектный файл или байткод для встроенной машины
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Emit {
+    Executable,
+    Bytecode,
+    Assembly,
+    Object,
+    C,
+}
+
+const EMIT_KINDS: &[(&str, Emit)] = &[
+    ("exe", Emit::Executable),
+    ("bytecode", Emit::Bytecode),
+    ("asm", Emit::Assembly),
+    ("obj", Emit::Object),
+    ("c", Emit::C),
+];
+
+impl Emit {
+    fn parse(name: &str) -> Result<Emit, String> {
+        EMIT_KINDS.iter().find(|(known, _)| *known == name).map(|(_, emit)| *emit).ok_or_else(|| {
+            format!("unknown --emit kind '{}'; expected {}", name, emit_kind_names())
+        })
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Emit::Executable if cfg!(target_os = "windows") => ".exe",
+            Emit::Executable => "",
+            Emit::Bytecode => ".qbc",
+            Emit::Assembly => ".s",
+            Emit::Object => ".o",
+            Emit::C => ".c",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Emit::Executable => "Executable",
+            Emit::Bytecode => "Bytecode",
+            Emit::Assembly => "Assembly",
+            Emit::Object => "Object file",
+            Emit::C => "C source",
+        }
+    }
+}
+
+fn emit_kind_names() -> String {
+    EMIT_KINDS.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ")
+}
+
+// Параметры quark build, которых нет у run и test
+#[derive(Clone, Copy)]
+struct BuildOptions {
+    emit: Emit,
+    target: Option<&'static compiler::Target>,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        BuildOptions { emit: Emit::Executable, target: None }
+    }
 }
 
 fn compile_file(
     input_path: &str,
     output_path: Option<&str>,
+    verbose: bool,
+    tests: bool,
+    levels: &WarningLevels,
+    options: BuildOptions,
 ) -> Result<PathBuf, String> {
     let start_time = Instant::now();
     
-    let source = read_source_file(input_path)?;
-    println!("Reading: {}", input_path);
-    
-    let mut lexer = lexer::Lexer::new(&source);
-    let tokens = lexer.tokenize().map_err(|e| format!("Lexer error: {}", e.message))?;
-    println!("Tokens: {}", tokens.len());
-    
-    let mut parser = parser::Parser::new(tokens);
-    let program = parser.parse().map_err(|e| format!("Parser error: {}", e))?;
+    let mut reporter = Reporter::new(MessageFormat::Human);
+    let mut program = load_program(input_path, verbose, &mut reporter)?;
     println!("Statements: {}", program.statements.len());
     
+    analyze_program(input_path, &mut program, levels, false, &mut reporter)?;
+    
     let output_path = if let Some(path) = output_path {
         PathBuf::from(path)
     } else {
@@ -75,13 +532,29 @@ fn compile_file(
             .to_str()
             .unwrap_or("output");
         
-        let ext = if cfg!(target_os = "windows") { ".exe" } else { "" };
-        PathBuf::from(format!("{}{}", stem, ext))
+        let extension = match (options.emit, options.target) {
+            (Emit::Executable, Some(target)) => target.exe_suffix(),
+            (emit, _) => emit.extension(),
+        };
+        PathBuf::from(format!("{}{}", stem, extension))
     };
     
     let mut compiler = compiler::Compiler::new();
-    compiler.compile_to_exe(&program, output_path.to_str().unwrap())
-        .map_err(|e| format!("Compilation error: {:?}", e))?;
+    if let Some(target) = options.target {
+        compiler.set_target(target);
+    }
+    let result = match options.emit {
+        Emit::Bytecode => bytecode::compile(&program).and_then(|module| {
+            fs::write(&output_path, module.encode())
+                .map_err(|e| compiler::CompileError::Io(format!("cannot write '{}': {}", output_path.display(), e)))
+        }),
+        Emit::Assembly => compiler.compile_to_assembly(&program, output_path.to_str().unwrap()),
+        Emit::Object => compiler.compile_to_object(&program, output_path.to_str().unwrap()),
+        Emit::C => compiler.compile_to_c(&program, output_path.to_str().unwrap()),
+        Emit::Executable if tests => compiler.compile_tests_to_exe(&program, output_path.to_str().unwrap()),
+        Emit::Executable => compiler.compile_to_exe(&program, output_path.to_str().unwrap()),
+    };
+    result.map_err(|e| reporter.compile_errors(input_path, &program, &[e]))?;
     
     let duration = start_time.elapsed();
     println!("Compilation time: {:.2?}", duration);
@@ -89,7 +562,8 @@ fn compile_file(
     Ok(output_path)
 }
 
-fn run_file(input_path: &str) -> Result<(), String> {
+fn run_file(input_path: &str, verbose: bool, tests: bool, levels: &WarningLevels,
+            program_args: &[String]) -> Result<(), String> {
     let temp_dir = env::temp_dir();
     let timestamp = std::time::SystemTime::now()
         .duration_since(std::time::SystemTime::UNIX_EPOCH)
@@ -104,12 +578,13 @@ fn run_file(input_path: &str) -> Result<(), String> {
     
     let exe_path = temp_dir.join(exe_name);
     
-    let output = compile_file(input_path, exe_path.to_str())?;
+    let output = compile_file(input_path, exe_path.to_str(), verbose, tests, levels, BuildOptions::default())?;
     
-    println!("Running program...");
+    println!("{}", if tests { "Running tests..." } else { "Running program..." });
     println!("------------------");
     
     let status = std::process::Command::new(&output)
+        .args(program_args)
         .status()
         .map_err(|e| format!("Execution error: {}", e))?;
     
@@ -118,29 +593,172 @@ fn run_file(input_path: &str) -> Result<(), String> {
     let _ = std::fs::remove_file(&output);
     
     if !status.success() {
+        if tests {
+            return Err("Some tests failed".to_string());
+        }
         return Err(format!("Program exited with code: {}", status));
     }
     
     Ok(())
 }
 
-fn check_syntax(input_path: &str) -> Result<(), String> {
-    let source = read_source_file(input_path)?;
+fn is_bytecode(input_path: &str) -> bool {
+    Path::new(input_path).extension().is_some_and(|ext| ext == "qbc")
+}
+
+// Байткод уже проверен при сборке, поэтому сразу исполняется, без компилятора C
+fn run_bytecode(input_path: &str, program_args: &[String]) -> Result<(), String> {
+    let bytes = fs::read(input_path).map_err(|e| format!("cannot read '{}': {}", input_path, e))?;
+    let module = bytecode::Module::decode(&bytes).map_err(|e| format!("{}: {}", input_path, e))?;
+    match vm::run(&module, program_args.to_vec()) {
+        Ok(0) => Ok(()),
+        Ok(code) => std::process::exit(code),
+        Err(message) => {
+            eprintln!("Runtime error: {}", message);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn check_syntax(input_path: &str, verbose: bool, levels: &WarningLevels, format: MessageFormat) -> Result<(), String> {
+    let mut reporter = Reporter::new(format);
+    reporter.progress(format!("Checking syntax: {}", input_path));
     
-    println!("Checking syntax: {}", input_path);
+    let result = load_program(input_path, verbose, &mut reporter)
+        .and_then(|mut program| analyze_program(input_path, &mut program, levels, true, &mut reporter));
     
-    let mut lexer = lexer::Lexer::new(&source);
-    let tokens = lexer.tokenize().map_err(|e| format!("Lexer error: {}", e.message))?;
+    match format {
+        MessageFormat::Human if result.is_ok() => println!("{}", translate("Syntax is correct")),
+        MessageFormat::Human => {}
+        MessageFormat::Sarif => println!("{}", sarif::document(&reporter.findings, VERSION)),
+    }
+    result
+}
+
+fn parse_file_args(args: &[String]) -> Result<(&str, bool, WarningLevels), String> {
+    let mut input_file = args[0].as_str();
+    let mut verbose = false;
+    let mut levels = WarningLevels::default();
+    let mut i = 0;
     
-    let mut parser = parser::Parser::new(tokens);
-    parser.parse().map_err(|e| format!("Parser error: {}", e))?;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--verbose" => {
+                verbose = true;
+                i += 1;
+            }
+            _ => match parse_warning_option(args, i, &mut levels)? {
+                0 => {
+                    input_file = &args[i];
+                    i += 1;
+                }
+                used => i += used,
+            },
+        }
+    }
     
-    println!("Syntax is correct");
-    Ok(())
+    Ok((input_file, verbose, levels))
+}
+
+// --message-format=human|sarif убирается из аргументов, остальное разбирает parse_file_args
+fn parse_message_format(args: &[String]) -> Result<(MessageFormat, Vec<String>), String> {
+    let mut format = MessageFormat::Human;
+    let mut rest = Vec::new();
+    for arg in args {
+        match arg.strip_prefix("--message-format=") {
+            Some("human") => format = MessageFormat::Human,
+            Some("sarif") => format = MessageFormat::Sarif,
+            Some(other) => return Err(format!("unknown message format '{}'; expected human or sarif", other)),
+            None => rest.push(arg.clone()),
+        }
+    }
+    if rest.is_empty() {
+        return Err("No file specified".to_string());
+    }
+    Ok((format, rest))
+}
+
+// --locale <en|ru> и --teach действуют на все команды, поэтому снимаются до их разбора;
+// аргументы после -- принадлежат программе и не трогаются
+fn take_common_options(args: Vec<String>) -> Result<Vec<String>, String> {
+    let mut rest = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--teach" {
+            teach::enable();
+            continue;
+        }
+        let name = match arg.strip_prefix("--locale=") {
+            Some(name) => name.to_string(),
+            None if arg == "--locale" => args.next().ok_or("--locale requires a language name")?,
+            None => {
+                let end = arg == "--";
+                rest.push(arg);
+                if end {
+                    break;
+                }
+                continue;
+            }
+        };
+        let locale = Locale::parse(&name).ok_or_else(|| format!("unknown locale '{}'; expected en or ru", name))?;
+        locale::set(locale);
+    }
+    rest.extend(args);
+    Ok(rest)
+}
+
+// -W <name>, -A <name> и --deny-warnings; возвращает число разобранных аргументов
+fn parse_warning_option(args: &[String], i: usize, levels: &mut WarningLevels) -> Result<usize, String> {
+    match args[i].as_str() {
+        "--deny-warnings" => {
+            levels.deny = true;
+            Ok(1)
+        }
+        flag @ ("-W" | "-A") => {
+            let name = args.get(i + 1).ok_or_else(|| format!("{} requires a warning name", flag))?;
+            levels.set(name, if flag == "-W" { Level::Warn } else { Level::Allow })?;
+            Ok(2)
+        }
+        _ => Ok(0),
+    }
+}
+
+// -S, -c, --emit и --target, через пробел или =; возвращает число разобранных аргументов
+fn parse_build_option(args: &[String], i: usize, options: &mut BuildOptions) -> Result<usize, String> {
+    let short = match args[i].as_str() {
+        "-S" => Some(Emit::Assembly),
+        "-c" => Some(Emit::Object),
+        _ => None,
+    };
+    if let Some(emit) = short {
+        options.emit = emit;
+        return Ok(1);
+    }
+    for name in ["--emit", "--target"] {
+        let (value, used) = match args[i].strip_prefix(name) {
+            Some("") => (args.get(i + 1).map(String::as_str), 2),
+            Some(rest) if rest.starts_with('=') => (Some(&rest[1..]), 1),
+            _ => continue,
+        };
+        match (name, value) {
+            ("--emit", Some(kind)) => options.emit = Emit::parse(kind)?,
+            ("--emit", None) => return Err(format!("--emit requires one of: {}", emit_kind_names())),
+            (_, Some(triple)) => options.target = Some(compiler::target(triple)?),
+            (_, None) => return Err("--target requires a target triple such as x86_64-pc-windows-gnu".to_string()),
+        }
+        return Ok(used);
+    }
+    Ok(0)
 }
 
 pub fn main() {
-    let args: Vec<String> = env::args().collect();
+    let args = match take_common_options(env::args().collect()) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    };
     
     if args.len() < 2 {
         show_help();
@@ -160,10 +778,32 @@ pub fn main() {
             
             let mut input_file = &args[2];
             let mut output_file = None;
-            let mut i = 3;
+            let mut options = BuildOptions::default();
+            let mut verbose = false;
+            let mut levels = WarningLevels::default();
+            let mut i = 2;
             
             while i < args.len() {
+                let used = match parse_warning_option(&args, i, &mut levels) {
+                    Ok(0) => parse_build_option(&args, i, &mut options),
+                    used => used,
+                };
+                match used {
+                    Ok(0) => {}
+                    Ok(used) => {
+                        i += used;
+                        continue;
+                    }
+                    Err(err) => {
+                        eprintln!("{}: {}", translate("Error"), err);
+                        std::process::exit(1);
+                    }
+                }
                 match args[i].as_str() {
+                    "--verbose" => {
+                        verbose = true;
+                        i += 1;
+                    }
                     "-o" | "--output" => {
                         if i + 1 < args.len() {
                             output_file = Some(args[i + 1].as_str());
@@ -180,12 +820,12 @@ pub fn main() {
                 }
             }
             
-            match compile_file(input_file, output_file) {
+            match compile_file(input_file, output_file, verbose, false, &levels, options) {
                 Ok(output) => {
-                    println!("Done. Executable: {}", output.display());
+                    println!("Done. {}: {}", options.emit.label(), output.display());
                 }
                 Err(err) => {
-                    eprintln!("Error: {}", err);
+                    eprintln!("{}: {}", translate("Error"), err);
                     std::process::exit(1);
                 }
             }
@@ -194,12 +834,44 @@ pub fn main() {
         "run" => {
             if args.len() < 3 {
                 eprintln!("Error: No file specified");
-                eprintln!("Usage: quark run <file.qrk>");
+                eprintln!("Usage: quark run <file.qrk> [-- <args>]");
                 return;
             }
             
-            if let Err(err) = run_file(&args[2]) {
-                eprintln!("Error: {}", err);
+            // Всё после -- передаётся запущенной программе
+            let (own_args, program_args) = match args[2..].iter().position(|arg| arg == "--") {
+                Some(at) => (&args[2..2 + at], &args[3 + at..]),
+                None => (&args[2..], &args[args.len()..]),
+            };
+            if own_args.is_empty() {
+                eprintln!("Error: No file specified");
+                eprintln!("Usage: quark run <file.qrk> [-- <args>]");
+                return;
+            }
+            let result = parse_file_args(own_args).and_then(|(input_file, verbose, levels)| {
+                if is_bytecode(input_file) {
+                    run_bytecode(input_file, program_args)
+                } else {
+                    run_file(input_file, verbose, false, &levels, program_args)
+                }
+            });
+            if let Err(err) = result {
+                eprintln!("{}: {}", translate("Error"), err);
+                std::process::exit(1);
+            }
+        }
+        
+        "test" => {
+            if args.len() < 3 {
+                eprintln!("Error: No file specified");
+                eprintln!("Usage: quark test <file.qrk>");
+                return;
+            }
+            
+            let result = parse_file_args(&args[2..])
+                .and_then(|(input_file, verbose, levels)| run_file(input_file, verbose, true, &levels, &[]));
+            if let Err(err) = result {
+                eprintln!("{}: {}", translate("Error"), err);
                 std::process::exit(1);
             }
         }
@@ -211,12 +883,23 @@ pub fn main() {
                 return;
             }
             
-            if let Err(err) = check_syntax(&args[2]) {
+            let result = parse_message_format(&args[2..]).and_then(|(format, rest)| {
+                parse_file_args(&rest)
+                    .and_then(|(input_file, verbose, levels)| check_syntax(input_file, verbose, &levels, format))
+            });
+            if let Err(err) = result {
                 eprintln!("{}", err);
                 std::process::exit(1);
             }
         }
         
+        "explain" => {
+            if let Err(err) = explain(args.get(2).map(String::as_str)) {
+                eprintln!("{}: {}", translate("Error"), err);
+                std::process::exit(1);
+            }
+        }
+        
         cmd => {
             eprintln!("Unknown command: '{}'", cmd);
             eprintln!("Use 'quark help' for command list");
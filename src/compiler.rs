@@ -1,4 +1,19 @@
-use crate::parser::{Program, Stmt, Expr, Value, VarType, BinOp};
+use crate::attributes;
+use crate::lexer::Span;
+use crate::parser::{
+    Program, Stmt, Expr, Value, VarType, BinOp, UnaryOp, MatchArm, Pattern, Param, Field, Variant, MethodSignature, LambdaParam,
+    StructPattern, Attribute,
+};
+use crate::types::{
+    argument_count_matches, array_result_type, assignable, binary_result_type, builtin_result_type,
+    cast_result_type, check_json_type, comprehension_result_type, field_result_type, index_result_type,
+    inferred_type, is_builtin, is_scalar, iteration_element_type, lambda_param_types, map_result_type,
+    member_name, method_signature, operator_call, operator_method, pattern_bindings, range_result_type, slice_result_type,
+    split_member, spread_element_type, try_result_type, type_name, unary_result_type, user_type_name, value_type,
+    with_expected_type, Impls, TypeError, RANGE_CONTEXT_ERROR, SPREAD_CONTEXT_ERROR,
+};
+use std::collections::HashMap;
+use std::fmt;
 use std::fs;
 use std::process::{Command, Stdio};
 
@@ -6,214 +21,4074 @@ use std::process::{Command, Stdio};
 pub enum CompileError {
     Parse(String),
     Io(String),
-    Compilation(String),
+    // Первое поле — код из error_codes::CODES
+    Compilation(&'static str, String),
+    // Ошибка в конкретном выражении исходника
+    Located(&'static str, String, Span),
+    Type(&'static str, String),
     NoCompiler,
 }
 
+impl CompileError {
+    // У выражений, которые собрал сам парсер (интерполяция, x++), места нет
+    fn at(code: &'static str, message: String, span: Span) -> CompileError {
+        if span == Span::default() {
+            CompileError::Compilation(code, message)
+        } else {
+            CompileError::Located(code, message, span)
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            CompileError::Compilation(code, _) | CompileError::Located(code, ..) | CompileError::Type(code, _) => code,
+            CompileError::NoCompiler => "E0504",
+            CompileError::Parse(_) | CompileError::Io(_) => "E0599",
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            CompileError::Located(_, _, span) => Some(*span),
+            _ => None,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            CompileError::Located(_, msg, _) => msg.clone(),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl From<TypeError> for CompileError {
+    fn from(error: TypeError) -> Self {
+        CompileError::Type(error.code, error.message)
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::Parse(msg) => write!(f, "parse error: {}", msg),
+            CompileError::Io(msg) => write!(f, "I/O error: {}", msg),
+            CompileError::Compilation(_, msg) => write!(f, "{}", msg),
+            CompileError::Located(_, msg, span) => write!(f, "{} at line {}:{}", msg, span.line, span.column),
+            CompileError::Type(_, msg) => write!(f, "type error: {}", msg),
+            CompileError::NoCompiler => write!(f, "no C compiler found (tried gcc, clang, cc/cl)"),
+        }
+    }
+}
+
+// Система, под которую собирается программа. Сгенерированный C один для всех систем,
+// различия рантайма закрыты #ifdef _WIN32, поэтому цель выбирает только компилятор C
+// и параметры компоновки
+pub struct Target {
+    pub triple: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+    // Компиляторы в порядке предпочтения: при сборке на той же системе и с другой
+    pub native_compilers: &'static [&'static str],
+    pub cross_compilers: &'static [&'static str],
+}
+
+pub const TARGETS: &[Target] = &[
+    Target {
+        triple: "x86_64-unknown-linux-gnu",
+        os: "linux",
+        arch: "x86_64",
+        native_compilers: &["gcc", "clang", "cc"],
+        cross_compilers: &["x86_64-linux-gnu-gcc"],
+    },
+    Target {
+        triple: "aarch64-unknown-linux-gnu",
+        os: "linux",
+        arch: "aarch64",
+        native_compilers: &["gcc", "clang", "cc"],
+        cross_compilers: &["aarch64-linux-gnu-gcc"],
+    },
+    Target {
+        triple: "x86_64-pc-windows-gnu",
+        os: "windows",
+        arch: "x86_64",
+        native_compilers: &["gcc", "clang"],
+        cross_compilers: &["x86_64-w64-mingw32-gcc"],
+    },
+    Target {
+        triple: "i686-pc-windows-gnu",
+        os: "windows",
+        arch: "x86",
+        native_compilers: &["gcc"],
+        cross_compilers: &["i686-w64-mingw32-gcc"],
+    },
+    Target {
+        triple: "x86_64-pc-windows-msvc",
+        os: "windows",
+        arch: "x86_64",
+        native_compilers: &["cl"],
+        cross_compilers: &[],
+    },
+];
+
+pub fn target(triple: &str) -> Result<&'static Target, String> {
+    TARGETS.iter().find(|target| target.triple == triple).ok_or_else(|| {
+        let known: Vec<&str> = TARGETS.iter().map(|target| target.triple).collect();
+        format!("unknown target '{}'; expected one of: {}", triple, known.join(", "))
+    })
+}
+
+impl Target {
+    pub fn is_windows(&self) -> bool {
+        self.os == "windows"
+    }
+
+    pub fn exe_suffix(&self) -> &'static str {
+        if self.is_windows() { ".exe" } else { "" }
+    }
+
+    fn compilers(&self) -> &'static [&'static str] {
+        if self.os == std::env::consts::OS && self.arch == std::env::consts::ARCH {
+            self.native_compilers
+        } else {
+            self.cross_compilers
+        }
+    }
+}
+
+// Докуда доводит сборку компилятор C
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Output {
+    Executable,
+    Assembly,
+    Object,
+}
+
+// Аргумент print: кусок строки формата со значением или литерал с нулевым байтом и его длина
+enum EchoPiece {
+    Format(String, Option<String>),
+    Raw(String, usize),
+}
+
 pub struct Compiler {
     temp_counter: usize,
+    scopes: Vec<HashMap<String, VarType>>,
+    loops: Vec<LoopContext>,
+    functions: HashMap<String, FunctionSignature>,
+    structs: HashMap<String, Vec<Field>>,
+    enums: HashMap<String, Vec<Variant>>,
+    traits: HashMap<String, Vec<MethodSignature>>,
+    impls: Impls,
+    // Тип результата функции, тело которой сейчас генерируется
+    return_type: Option<Option<VarType>>,
+    // Для каждого охватывающего try в текущей функции — переменная с внешним обработчиком
+    tries: Vec<String>,
+    // Код отложенных инструкций текущей функции в порядке defer
+    defers: Vec<String>,
+    // Модуль генерируемой функции и индекс её первой области видимости, как в семантическом анализе
+    module: Option<String>,
+    function_scope: usize,
+    // Определения функций C, в которые превращаются лямбды
+    lambdas: String,
+    // Сборка для quark test: main вместо программы запускает функции #[test]
+    tests: bool,
+    // Без цели программа собирается для системы, на которой запущен quark
+    target: Option<&'static Target>,
+    // Функции C для json_parse и json_stringify: имя и определение, по одной на тип
+    json_helpers: Vec<(String, String)>,
+    // Ошибки функций и инструкций верхнего уровня, после которых генерация продолжилась
+    errors: Vec<CompileError>,
+}
+
+struct FunctionSignature {
+    params: Vec<VarType>,
+    return_type: Option<VarType>,
+}
+
+// Цикл, в котором сейчас генерируется код: нужен для break/continue
+struct LoopContext {
+    id: usize,
+    label: Option<String>,
+    // У for перед следующей итерацией выполняется шаг, поэтому continue — это goto
+    has_update: bool,
+    break_used: bool,
+    continue_used: bool,
+    // Сколько try было открыто снаружи цикла: выход из цикла закрывает остальные
+    tries: usize,
+    // Столько же для defer: выход из цикла выполняет отложенные внутри него
+    defers: usize,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn escape_c_string(s: &str) -> String {
+    let mut escaped = String::new();
+    for ch in s.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            // Остальные управляющие символы — восьмеричным кодом; UTF-8 остаётся как есть
+            ch if ch.is_ascii_control() => escaped.push_str(&format!("\\{:03o}", ch as u32)),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+// Пустая строка формата ничего не печатает, вызов не нужен
+fn printf_call(stream: &str, format: &str, values: &[String]) -> String {
+    match (format.is_empty(), stream) {
+        (true, _) => String::new(),
+        (false, "stdout") if values.is_empty() => format!("    printf(\"{}\");\n", format),
+        (false, "stdout") => format!("    printf(\"{}\", {});\n", format, values.join(", ")),
+        (false, _) if values.is_empty() => format!("    fprintf({}, \"{}\");\n", stream, format),
+        (false, _) => format!("    fprintf({}, \"{}\", {});\n", stream, format, values.join(", ")),
+    }
+}
+
+fn c_literal(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("\"{}\"", escape_c_string(s)),
+        // -9223372036854775808 в C не является литералом long long
+        Value::Integer(i) if *i == i64::MIN => format!("({}LL - 1)", i64::MIN + 1),
+        // Суффикс LL: без него C считает литерал int и арифметика переполняется
+        Value::Integer(i) => format!("{}LL", i),
+        // {:?} всегда сохраняет точку, иначе C воспримет 3.0 как int
+        Value::Float(f) => format!("{:?}", f),
+        Value::Boolean(true) => "true".to_string(),
+        Value::Boolean(false) => "false".to_string(),
+        Value::BigInt(digits) => format!("\"{}\"", digits),
+        // Восьмеричные escape-последовательности: у \x в C нет ограничения на число цифр
+        Value::Bytes(bytes) => {
+            let escaped: String = bytes.iter().map(|byte| format!("\\{:03o}", byte)).collect();
+            format!("qk_bytes_from(\"{}\", {})", escaped, bytes.len())
+        }
+        Value::Array { element_type, items } if items.is_empty() => {
+            format!("qk_array_new(sizeof({}))", c_type(element_type))
+        }
+        // Элементы передаются составным литералом C99
+        Value::Array { element_type, items } => {
+            let items: Vec<String> = items.iter().map(c_literal).collect();
+            format!(
+                "qk_array_from(sizeof({}), {}, ({}[]){{{}}})",
+                c_type(element_type), items.len(), c_type(element_type), items.join(", ")
+            )
+        }
+        Value::Map { key_type, value_type, entries } if entries.is_empty() => {
+            format!("qk_map_new({}, sizeof({}))", *key_type == VarType::String, c_type(value_type))
+        }
+        Value::Map { key_type, value_type, entries } => {
+            let keys: Vec<String> = entries.iter().map(|(key, _)| c_literal(key)).collect();
+            let values: Vec<String> = entries.iter().map(|(_, value)| c_literal(value)).collect();
+            format!(
+                "qk_map_from({}, sizeof({}), {}, ({}[]){{{}}}, ({}[]){{{}}})",
+                *key_type == VarType::String, c_type(value_type), entries.len(),
+                c_type(key_type), keys.join(", "), c_type(value_type), values.join(", ")
+            )
+        }
+        // У пустой структуры есть только поле-заглушка
+        Value::Struct { name, fields } if fields.is_empty() => format!("(qs_{}){{0}}", name),
+        Value::Struct { name, fields } => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(field, value)| format!(".{} = {}", field, c_literal(value)))
+                .collect();
+            format!("(qs_{}){{{}}}", name, fields.join(", "))
+        }
+        // Тег варианта и его значения в объединении
+        Value::Enum { name, variant, payload } if payload.is_empty() => {
+            format!("(qe_{}){{.tag = qe_{}_{}}}", name, name, variant)
+        }
+        Value::Enum { name, variant, payload } => {
+            let payload: Vec<String> = payload.iter().map(c_literal).collect();
+            format!("(qe_{}){{.tag = qe_{}_{}, .as.{} = {{{}}}}}", name, name, variant, variant, payload.join(", "))
+        }
+        // Значение T? хранится в куче, none — нулевой указатель
+        Value::Some(value) => {
+            format!("qk_option_some({}, sizeof({}))", c_literal_address(value), c_type(&value_type(value)))
+        }
+        Value::None => "NULL".to_string(),
+        Value::Result { ok, value, .. } => {
+            format!("qk_result_new({}, {}, sizeof({}))", ok, c_literal_address(value), c_type(&value_type(value)))
+        }
+    }
+}
+
+// Адрес литерала для копирования в кучу
+fn c_literal_address(value: &Value) -> String {
+    match value {
+        // Литерал структуры уже составной, у него можно взять адрес
+        Value::Struct { .. } | Value::Enum { .. } => format!("&{}", c_literal(value)),
+        _ => format!("&({}){{{}}}", c_type(&value_type(value)), c_literal(value)),
+    }
+}
+
+fn c_type(var_type: &VarType) -> String {
+    match var_type {
+        VarType::String => "const char*".to_string(),
+        VarType::Integer => "long long".to_string(),
+        VarType::Float => "double".to_string(),
+        VarType::Boolean => "bool".to_string(),
+        // BigInt хранится как десятичная строка
+        VarType::BigInt => "const char*".to_string(),
+        VarType::Bytes => "qk_bytes*".to_string(),
+        // Массивы и словари передаются по ссылке
+        VarType::Array(_) => "qk_array*".to_string(),
+        VarType::Map(..) => "qk_map*".to_string(),
+        // Структуры, в отличие от коллекций, копируются по значению
+        VarType::Struct(name) => format!("qs_{}", name),
+        VarType::Enum(name) => format!("qe_{}", name),
+        // Указатель на значение и таблица его методов
+        VarType::Trait(name) => format!("qt_{}", name),
+        VarType::Option(inner) => format!("{}*", c_type(inner)),
+        VarType::None => "void*".to_string(),
+        // Обе ветки Result хранятся в куче, поэтому тип в C один для всех T и E
+        VarType::Result(..) | VarType::Ok(_) | VarType::Err(_) => "qk_result".to_string(),
+        // Указатель на функцию и захваченное ею окружение
+        VarType::Function(..) => "qk_closure".to_string(),
+    }
+}
+
+// Коллекции создаются вызовом рантайма, а не константой. Структура или
+// перечисление может содержать коллекцию, поэтому их литерал тоже не всегда константа
+fn needs_runtime_init(var_type: &VarType) -> bool {
+    matches!(
+        var_type,
+        VarType::Bytes | VarType::Array(_) | VarType::Map(..) | VarType::Struct(_) | VarType::Enum(_)
+            | VarType::Option(_) | VarType::Result(..)
+    )
+}
+
+// Часть имени помощника JSON: типы, которые можно записать в JSON, различаются ею однозначно
+fn json_helper_suffix(var_type: &VarType) -> String {
+    match var_type {
+        VarType::Array(element) => format!("Array_{}", json_helper_suffix(element)),
+        VarType::Map(_, value_type) => format!("Map_{}", json_helper_suffix(value_type)),
+        VarType::Option(inner) => format!("Option_{}", json_helper_suffix(inner)),
+        VarType::Struct(name) => format!("Struct_{}", name),
+        other => type_name(other),
+    }
+}
+
+// Указатель на элемент массива или на значение в словаре по ключу
+fn c_element_pointer(target_type: &VarType, target: &str, index: &str, for_write: bool) -> String {
+    match target_type {
+        VarType::Map(key_type, _) => format!(
+            "qk_map_{}({}, &({}){{{}}})",
+            if for_write { "set" } else { "get" }, target, c_type(key_type), index
+        ),
+        _ => format!("qk_array_at({}, {})", target, index),
+    }
+}
+
+// Указатель на элемент массива, приведённый к типу элемента
+fn c_element(var_type: &VarType, pointer: &str) -> String {
+    format!("(*({}*){})", c_type(var_type), pointer)
+}
+
+fn c_declaration(var_type: &VarType, name: &str, value: &str, constant: bool) -> String {
+    match (constant, var_type) {
+        // У строк константным должен быть сам указатель
+        (true, VarType::String | VarType::BigInt | VarType::Bytes | VarType::Array(_) | VarType::Map(..) | VarType::Option(_)) => {
+            format!("{} const {} = {}", c_type(var_type), name, value)
+        }
+        (true, _) => format!("const {} {} = {}", c_type(var_type), name, value),
+        (false, _) => format!("{} {} = {}", c_type(var_type), name, value),
+    }
+}
+
+// Таблица методов трейта и сам трейт-объект
+fn trait_definition(name: &str, methods: &[MethodSignature]) -> String {
+    let mut code = format!("struct qt_{}_vtable {{\n", name);
+    for method in methods {
+        let mut params = vec!["void*".to_string()];
+        params.extend(method.params.iter().map(|p| c_type(&p.var_type)));
+        code.push_str(&format!(
+            "    {} (*{})({});\n", c_return_type(&method.return_type), c_method_member(&method.name), params.join(", ")
+        ));
+    }
+    code.push_str("};\n");
+    code.push_str(&format!("struct qt_{} {{\n    void* self;\n    const qt_{}_vtable* vtable;\n}};\n", name, name));
+    code
+}
+
+// Обёртка для таблицы методов: разыменовывает self и вызывает сам метод
+fn method_thunk(self_type: &VarType, c_name: &str, signature: &MethodSignature) -> String {
+    let mut params = vec!["void* self".to_string()];
+    params.extend(signature.params.iter().map(|p| format!("{} {}", c_type(&p.var_type), p.name)));
+    let mut args = vec![format!("*({}*)self", c_type(self_type))];
+    args.extend(signature.params.iter().map(|p| p.name.clone()));
+    let call = format!("{}({})", c_name, args.join(", "));
+    let body = if signature.return_type.is_some() { format!("return {};", call) } else { format!("{};", call) };
+    format!(
+        "static {} {}_dyn({}) {{ {} }}\n",
+        c_return_type(&signature.return_type), c_name, params.join(", "), body
+    )
+}
+
+// Префикс не даёт функциям пользователя совпасть с main или функциями libc
+fn c_function_name(name: &str) -> String {
+    format!("qf_{}", c_member_name(name))
+}
+
+// math.pi становится qn_4math_pi: длина имени модуля исключает совпадения вроде a_b.c и a.b_c
+fn c_variable_name(name: &str) -> String {
+    match split_member(name) {
+        Some(_) => format!("qn_{}", c_member_name(name)),
+        None => name.to_string(),
+    }
+}
+
+fn c_member_name(name: &str) -> String {
+    match split_member(name) {
+        Some((module, member)) => format!("{}{}_{}", module.len(), module, member),
+        None => name.to_string(),
+    }
+}
+
+fn c_return_type(return_type: &Option<VarType>) -> String {
+    match return_type {
+        Some(var_type) => c_type(var_type),
+        None => "void".to_string(),
+    }
+}
+
+// Метод Point.show становится функцией qm_Point_show(qs_Point self, ...)
+fn c_method_name(type_name: &str, method: &str) -> String {
+    format!("qm_{}_{}", type_name, c_method_member(method))
+}
+
+// Имя метода в C: operator+ становится operator_add, остальные имена не меняются
+fn c_method_member(method: &str) -> String {
+    let suffix = match method.strip_prefix("operator") {
+        Some("+") => "add",
+        Some("-") => "sub",
+        Some("*") => "mul",
+        Some("/") => "div",
+        Some("==") => "eq",
+        Some("!=") => "ne",
+        Some("<") => "lt",
+        Some(">") => "gt",
+        Some("<=") => "le",
+        Some(">=") => "ge",
+        _ => return method.to_string(),
+    };
+    format!("operator_{}", suffix)
+}
+
+// #[inline] превращается в static inline и у прототипа, и у определения
+fn inline_prefix(attributes: &[Attribute]) -> &'static str {
+    if attributes::has(attributes, "inline") {
+        "static inline "
+    } else {
+        ""
+    }
+}
+
+fn c_function_header(c_name: &str, params: &[Param], return_type: &Option<VarType>) -> String {
+    let c_params: Vec<String> = params
+        .iter()
+        .map(|p| format!("{} {}", c_type(&p.var_type), p.name))
+        .collect();
+    let c_params = if c_params.is_empty() { "void".to_string() } else { c_params.join(", ") };
+    format!("{} {}({})", c_return_type(return_type), c_name, c_params)
+}
+
+// Параметры метода вместе с self
+fn method_params(self_type: &VarType, signature: &MethodSignature) -> Vec<Param> {
+    let mut params = vec![Param { name: "self".to_string(), var_type: self_type.clone() }];
+    params.extend(signature.params.iter().cloned());
+    params
+}
+
+// Бесконечная рекурсия завершается понятной ошибкой, а не падением по переполнению стека.
+// Предел можно переопределить при сборке C кода через -DQK_MAX_CALL_DEPTH=N
+const CALL_DEPTH_GUARD: &str = r#"#ifndef QK_MAX_CALL_DEPTH
+#define QK_MAX_CALL_DEPTH 5000
+#endif
+
+static void qk_enter(const char* name) {
+    if (++qk_call_depth > QK_MAX_CALL_DEPTH) {
+        qk_fail("call depth limit of %d exceeded in function '%s' (infinite recursion?)", QK_MAX_CALL_DEPTH, name);
+    }
+}
+
+"#;
+
+// Динамический массив с элементами произвольного размера
+const ARRAY_RUNTIME: &str = r#"typedef struct {
+    char* data;
+    long long len;
+    long long cap;
+    size_t elem_size;
+} qk_array;
+
+static qk_array* qk_array_new(size_t elem_size) {
+    qk_array* array = malloc(sizeof(qk_array));
+    array->data = NULL;
+    array->len = 0;
+    array->cap = 0;
+    array->elem_size = elem_size;
+    return array;
+}
+
+static void qk_array_push(qk_array* array, const void* value) {
+    if (array->len == array->cap) {
+        array->cap = array->cap ? array->cap * 2 : 4;
+        array->data = realloc(array->data, array->cap * array->elem_size);
+    }
+    memcpy(array->data + array->len * array->elem_size, value, array->elem_size);
+    array->len++;
+}
+
+static qk_array* qk_array_from(size_t elem_size, long long len, const void* items) {
+    qk_array* array = qk_array_new(elem_size);
+    for (long long i = 0; i < len; i++) {
+        qk_array_push(array, (const char*)items + i * elem_size);
+    }
+    return array;
+}
+
+static void* qk_array_at(qk_array* array, long long index) {
+    if (index < 0 || index >= array->len) {
+        qk_fail("index %lld is out of bounds for an array of length %lld", index, array->len);
+    }
+    return array->data + index * array->elem_size;
+}
+
+static void qk_array_extend(qk_array* array, qk_array* other) {
+    for (long long i = 0; i < other->len; i++) {
+        qk_array_push(array, other->data + i * other->elem_size);
+    }
+}
+
+// f(...xs): у функции фиксированное число параметров, массив должен заполнить их ровно
+static void qk_spread_check(qk_array* array, long long count) {
+    if (array->len != count) {
+        qk_fail("cannot spread an array of length %lld into %lld argument(s)", array->len, count);
+    }
+}
+
+// Числа с плавающей точкой сравниваются через ==, остальные скаляры побайтно
+static bool qk_array_contains(qk_array* array, const void* value) {
+    for (long long i = 0; i < array->len; i++) {
+        if (memcmp(array->data + i * array->elem_size, value, array->elem_size) == 0) {
+            return true;
+        }
+    }
+    return false;
+}
+
+static bool qk_array_contains_float(qk_array* array, double value) {
+    for (long long i = 0; i < array->len; i++) {
+        if (((double*)array->data)[i] == value) {
+            return true;
+        }
+    }
+    return false;
+}
+
+static bool qk_array_contains_string(qk_array* array, const char* value) {
+    for (long long i = 0; i < array->len; i++) {
+        if (strcmp(((const char**)array->data)[i], value) == 0) {
+            return true;
+        }
+    }
+    return false;
+}
+
+// Копия элементов [start, end); при to_end срез идёт до конца массива
+static qk_array* qk_array_slice(qk_array* array, long long start, long long end, bool to_end) {
+    if (to_end) {
+        end = array->len;
+    }
+    if (start < 0 || start > end || end > array->len) {
+        qk_fail("slice %lld..%lld is out of bounds for an array of length %lld", start, end, array->len);
+    }
+    return qk_array_from(array->elem_size, end - start, array->data + start * array->elem_size);
+}
+
+// Строки массива через разделитель; размер результата известен заранее
+static const char* qk_array_join(qk_array* array, const char* separator) {
+    const char** items = (const char**)array->data;
+    size_t separator_len = strlen(separator);
+    size_t size = 1;
+    for (long long i = 0; i < array->len; i++) {
+        size += strlen(items[i]) + (i > 0 ? separator_len : 0);
+    }
+    char* result = malloc(size);
+    size_t at = 0;
+    for (long long i = 0; i < array->len; i++) {
+        if (i > 0) {
+            memcpy(result + at, separator, separator_len);
+            at += separator_len;
+        }
+        size_t len = strlen(items[i]);
+        memcpy(result + at, items[i], len);
+        at += len;
+    }
+    result[at] = '\0';
+    return result;
+}
+
+static void* qk_array_pop(qk_array* array) {
+    if (array->len == 0) {
+        qk_fail("pop from an empty array");
+    }
+    array->len--;
+    return array->data + array->len * array->elem_size;
+}
+
+"#;
+
+fn contains_call(expr: &Expr) -> bool {
+    match expr {
+        Expr::Call { .. } => true,
+        Expr::BinaryOp { left, right, .. } => contains_call(left) || contains_call(right),
+        Expr::UnaryOp { operand, .. } => contains_call(operand),
+        Expr::Index { target, index } => contains_call(target) || contains_call(index),
+        Expr::Cast { value, .. } => contains_call(value),
+        Expr::Slice { target, start, end } => {
+            contains_call(target) || start.iter().chain(end).any(|bound| contains_call(bound))
+        }
+        Expr::Range { start, end } => contains_call(start) || contains_call(end),
+        Expr::Field { target, .. } => contains_call(target),
+        Expr::MethodCall { .. } => true,
+        Expr::Try(value) | Expr::Spread(value) => contains_call(value),
+        // Тело лямбды при её создании не выполняется, а массив и разобранный JSON
+        // собираются заранее во временной переменной
+        Expr::Variable(_) | Expr::Literal(_) | Expr::Lambda { .. } | Expr::Array(_) | Expr::Struct { .. }
+        | Expr::Enum { .. } | Expr::Map(_) | Expr::Comprehension { .. } | Expr::JsonParse { .. } => false,
+    }
+}
+
+// Имена, которые тело лямбды берёт снаружи, в порядке первого упоминания
+fn free_variables(expr: &Expr, bound: &[String], found: &mut Vec<String>) {
+    let mention = |name: &String, found: &mut Vec<String>| {
+        if !bound.contains(name) && !found.contains(name) {
+            found.push(name.clone());
+        }
+    };
+    match expr {
+        Expr::Variable(name) => mention(name, found),
+        // Вызываемое имя тоже может быть переменной с функцией
+        Expr::Call { name, args, .. } => {
+            mention(name, found);
+            args.iter().for_each(|arg| free_variables(arg, bound, found));
+        }
+        Expr::BinaryOp { left, right, .. } => {
+            free_variables(left, bound, found);
+            free_variables(right, bound, found);
+        }
+        Expr::UnaryOp { operand, .. } => free_variables(operand, bound, found),
+        Expr::Index { target, index } => {
+            free_variables(target, bound, found);
+            free_variables(index, bound, found);
+        }
+        Expr::Cast { value, .. } => free_variables(value, bound, found),
+        Expr::Slice { target, start, end } => {
+            free_variables(target, bound, found);
+            start.iter().chain(end).for_each(|value| free_variables(value, bound, found));
+        }
+        Expr::Range { start, end } => {
+            free_variables(start, bound, found);
+            free_variables(end, bound, found);
+        }
+        Expr::Field { target, .. } => free_variables(target, bound, found),
+        Expr::MethodCall { target, args, .. } => {
+            free_variables(target, bound, found);
+            args.iter().for_each(|arg| free_variables(arg, bound, found));
+        }
+        Expr::Try(value) | Expr::Spread(value) => free_variables(value, bound, found),
+        Expr::JsonParse { text, .. } => free_variables(text, bound, found),
+        Expr::Array(elements) => elements.iter().for_each(|element| free_variables(element, bound, found)),
+        Expr::Struct { fields, .. } => fields.iter().for_each(|(_, value)| free_variables(value, bound, found)),
+        Expr::Enum { payload, .. } => payload.iter().for_each(|value| free_variables(value, bound, found)),
+        Expr::Map(entries) => entries.iter().for_each(|(key, value)| {
+            free_variables(key, bound, found);
+            free_variables(value, bound, found);
+        }),
+        Expr::Comprehension { element, variable, iterable, condition } => {
+            free_variables(iterable, bound, found);
+            let mut inner = bound.to_vec();
+            inner.push(variable.clone());
+            std::iter::once(element).chain(condition).for_each(|value| free_variables(value, &inner, found));
+        }
+        Expr::Lambda { params, body } => {
+            let mut inner = bound.to_vec();
+            inner.extend(params.iter().map(|p| p.name.clone()));
+            free_variables(body, &inner, found);
+        }
+        Expr::Literal(_) => {}
+    }
+}
+
+// Функция хранится без типа, к нужной сигнатуре она приводится в месте вызова
+const CLOSURE_RUNTIME: &str = r#"typedef void (*qk_function)(void);
+typedef struct {
+    qk_function fn;
+    void* env;
+} qk_closure;
+
+static qk_function qk_closure_fn(qk_closure closure) {
+    if (closure.fn == NULL) {
+        qk_fail("call of an empty function value");
+    }
+    return closure.fn;
+}
+
+"#;
+
+// Хэш-таблица с открытой адресацией. Ключи — строки или long long,
+// строковые ключи копируются при вставке
+const MAP_RUNTIME: &str = r#"typedef struct {
+    char* keys;
+    char* values;
+    bool* used;
+    long long len;
+    long long cap;
+    bool string_keys;
+    size_t value_size;
+} qk_map;
+
+static qk_map* qk_map_new(bool string_keys, size_t value_size) {
+    qk_map* map = malloc(sizeof(qk_map));
+    map->keys = NULL;
+    map->values = NULL;
+    map->used = NULL;
+    map->len = 0;
+    map->cap = 0;
+    map->string_keys = string_keys;
+    map->value_size = value_size;
+    return map;
+}
+
+static size_t qk_map_key_size(const qk_map* map) {
+    return map->string_keys ? sizeof(const char*) : sizeof(long long);
+}
+
+static unsigned long long qk_map_hash(const qk_map* map, const void* key) {
+    const unsigned char* bytes = key;
+    size_t len = sizeof(long long);
+    if (map->string_keys) {
+        bytes = *(const unsigned char* const*)key;
+        len = strlen((const char*)bytes);
+    }
+    unsigned long long hash = 14695981039346656037ULL;
+    for (size_t i = 0; i < len; i++) {
+        hash ^= bytes[i];
+        hash *= 1099511628211ULL;
+    }
+    return hash;
+}
+
+static bool qk_map_key_equals(const qk_map* map, const void* a, const void* b) {
+    if (map->string_keys) {
+        return strcmp(*(const char* const*)a, *(const char* const*)b) == 0;
+    }
+    return *(const long long*)a == *(const long long*)b;
+}
+
+// Слот с этим ключом или первый свободный слот на пути поиска
+static long long qk_map_slot(const qk_map* map, const void* key) {
+    long long slot = (long long)(qk_map_hash(map, key) % (unsigned long long)map->cap);
+    while (map->used[slot] && !qk_map_key_equals(map, map->keys + slot * qk_map_key_size(map), key)) {
+        slot = (slot + 1) % map->cap;
+    }
+    return slot;
+}
+
+static void qk_map_grow(qk_map* map) {
+    qk_map old = *map;
+    map->cap = old.cap ? old.cap * 2 : 8;
+    map->keys = calloc(map->cap, qk_map_key_size(map));
+    map->values = calloc(map->cap, map->value_size);
+    map->used = calloc(map->cap, sizeof(bool));
+    for (long long i = 0; i < old.cap; i++) {
+        if (old.used[i]) {
+            long long slot = qk_map_slot(map, old.keys + i * qk_map_key_size(map));
+            map->used[slot] = true;
+            memcpy(map->keys + slot * qk_map_key_size(map), old.keys + i * qk_map_key_size(map), qk_map_key_size(map));
+            memcpy(map->values + slot * map->value_size, old.values + i * map->value_size, map->value_size);
+        }
+    }
+    free(old.keys);
+    free(old.values);
+    free(old.used);
+}
+
+static void* qk_map_set(qk_map* map, const void* key) {
+    if ((map->len + 1) * 4 > map->cap * 3) {
+        qk_map_grow(map);
+    }
+    long long slot = qk_map_slot(map, key);
+    if (!map->used[slot]) {
+        map->used[slot] = true;
+        map->len++;
+        if (map->string_keys) {
+            char* copy = qk_strdup(*(const char* const*)key);
+            memcpy(map->keys + slot * sizeof(char*), &copy, sizeof(char*));
+        } else {
+            memcpy(map->keys + slot * sizeof(long long), key, sizeof(long long));
+        }
+    }
+    return map->values + slot * map->value_size;
+}
+
+static void* qk_map_get(qk_map* map, const void* key) {
+    long long slot = map->cap ? qk_map_slot(map, key) : 0;
+    if (!map->cap || !map->used[slot]) {
+        if (map->string_keys) {
+            qk_fail("key \"%s\" is not in the map", *(const char* const*)key);
+        }
+        qk_fail("key %lld is not in the map", *(const long long*)key);
+    }
+    return map->values + slot * map->value_size;
+}
+
+static bool qk_map_contains(const qk_map* map, const void* key) {
+    return map->cap && map->used[qk_map_slot(map, key)];
+}
+
+static qk_map* qk_map_from(bool string_keys, size_t value_size, long long len, const void* keys, const void* values) {
+    qk_map* map = qk_map_new(string_keys, value_size);
+    for (long long i = 0; i < len; i++) {
+        void* value = qk_map_set(map, (const char*)keys + i * qk_map_key_size(map));
+        memcpy(value, (const char*)values + i * value_size, value_size);
+    }
+    return map;
+}
+
+"#;
+
+const OPTION_RUNTIME: &str = r#"static void* qk_option_some(const void* value, size_t size) {
+    void* box = malloc(size);
+    memcpy(box, value, size);
+    return box;
+}
+
+static void* qk_option_unwrap(void* option) {
+    if (!option) {
+        qk_fail("unwrap of a none value");
+    }
+    return option;
+}
+
+"#;
+
+// Result<T, E>: признак успеха и копия значения T или ошибки E в куче
+const RESULT_RUNTIME: &str = r#"typedef struct {
+    bool ok;
+    void* value;
+} qk_result;
+
+static qk_result qk_result_new(bool ok, const void* value, size_t size) {
+    qk_result result = { ok, malloc(size) };
+    memcpy(result.value, value, size);
+    return result;
+}
+
+static void* qk_result_unwrap(qk_result result, bool ok) {
+    if (result.ok != ok) {
+        qk_fail(ok ? "unwrap of an err value" : "unwrap_err of an ok value");
+    }
+    return result.value;
+}
+
+"#;
+
+// Все ошибки выполнения проходят через qk_fail: внутри try она переходит в catch,
+// иначе печатает сообщение и завершает программу
+const ERROR_RUNTIME: &str = r#"#include <setjmp.h>
+#include <stdarg.h>
+
+static jmp_buf* qk_catch_target = NULL;
+static char qk_error_message[256];
+// Глубина вызовов восстанавливается при переходе в catch
+static int qk_call_depth = 0;
+
+static void qk_fail(const char* format, ...) {
+    va_list args;
+    va_start(args, format);
+    vsnprintf(qk_error_message, sizeof(qk_error_message), format, args);
+    va_end(args);
+    if (qk_catch_target) {
+        longjmp(*qk_catch_target, 1);
+    }
+    fprintf(stderr, "Runtime error: %s\n", qk_error_message);
+    exit(1);
+}
+
+"#;
+
+// strdup есть в POSIX, но не в стандарте C, поэтому копия строки своя
+const COPY_RUNTIME: &str = r#"static char* qk_strdup(const char* s) {
+    size_t size = strlen(s) + 1;
+    char* copy = malloc(size);
+    memcpy(copy, s, size);
+    return copy;
+}
+
+"#;
+
+const CAST_RUNTIME: &str = r#"static long long qk_float_to_int(double value) {
+    // NaN не проходит ни одно сравнение
+    if (!(value >= -9223372036854775808.0 && value < 9223372036854775808.0)) {
+        qk_fail("cannot convert %f to Integer: the value is out of range", value);
+    }
+    return (long long)value;
+}
+
+"#;
+
+// Байты хранятся вместе с длиной: нулевой байт не означает конец данных
+const BYTES_RUNTIME: &str = r#"#include <errno.h>
+
+typedef struct {
+    unsigned char* data;
+    long long len;
+} qk_bytes;
+
+static qk_bytes* qk_bytes_from(const char* data, long long len) {
+    qk_bytes* bytes = malloc(sizeof(qk_bytes));
+    bytes->data = malloc(len + 1);
+    memcpy(bytes->data, data, len);
+    bytes->len = len;
+    return bytes;
+}
+
+static long long qk_bytes_at(const qk_bytes* bytes, long long index) {
+    if (index < 0 || index >= bytes->len) {
+        qk_fail("index %lld is out of bounds for bytes of length %lld", index, bytes->len);
+    }
+    return bytes->data[index];
+}
+
+// Запись в виде литерала: печатные символы ASCII как есть, остальные байты как \xNN
+static const char* qk_bytes_repr(const qk_bytes* bytes) {
+    char* result = malloc(bytes->len * 4 + 4);
+    size_t at = 0;
+    result[at++] = 'b';
+    result[at++] = '"';
+    for (long long i = 0; i < bytes->len; i++) {
+        unsigned char byte = bytes->data[i];
+        if (byte == '"' || byte == '\\') {
+            result[at++] = '\\';
+            result[at++] = byte;
+        } else if (byte >= 0x20 && byte < 0x7f) {
+            result[at++] = byte;
+        } else {
+            at += sprintf(result + at, "\\x%02x", byte);
+        }
+    }
+    result[at++] = '"';
+    result[at] = '\0';
+    return result;
 }
 
-impl Compiler {
-    pub fn new() -> Self {
-        Self { temp_counter: 0 }
+static qk_bytes* qk_read_bytes(const char* path) {
+    FILE* file = fopen(path, "rb");
+    if (!file) {
+        qk_fail("cannot open '%s' for reading: %s", path, strerror(errno));
+    }
+    size_t cap = 4096;
+    qk_bytes* bytes = malloc(sizeof(qk_bytes));
+    bytes->data = malloc(cap);
+    bytes->len = 0;
+    size_t read;
+    while ((read = fread(bytes->data + bytes->len, 1, cap - bytes->len, file)) > 0) {
+        bytes->len += read;
+        if ((size_t)bytes->len == cap) {
+            cap *= 2;
+            bytes->data = realloc(bytes->data, cap);
+        }
+    }
+    bool failed = ferror(file);
+    fclose(file);
+    if (failed) {
+        qk_fail("cannot read '%s'", path);
+    }
+    return bytes;
+}
+
+static void qk_write_bytes(const char* path, const qk_bytes* bytes) {
+    FILE* file = fopen(path, "wb");
+    if (!file) {
+        qk_fail("cannot open '%s' for writing: %s", path, strerror(errno));
+    }
+    size_t written = fwrite(bytes->data, 1, bytes->len, file);
+    if (fclose(file) != 0 || written != (size_t)bytes->len) {
+        qk_fail("cannot write '%s'", path);
+    }
+}
+
+"#;
+
+// Деление на ноль и LLONG_MIN / -1 в C — неопределённое поведение
+const DIVISION_RUNTIME: &str = r#"static long long qk_div(long long a, long long b) {
+    if (b == 0) {
+        qk_fail("division by zero");
+    }
+    if (b == -1 && a == -9223372036854775807LL - 1) {
+        qk_fail("integer overflow in division");
+    }
+    return a / b;
+}
+
+static long long qk_rem(long long a, long long b) {
+    if (b == 0) {
+        qk_fail("division by zero");
+    }
+    if (b == -1 && a == -9223372036854775807LL - 1) {
+        qk_fail("integer overflow in remainder");
+    }
+    return a % b;
+}
+
+"#;
+
+// Целочисленные варианты проверяют переполнение, как qk_div
+const MATH_RUNTIME: &str = r#"#include <math.h>
+
+static long long qk_math_abs_int(long long x) {
+    if (x == -9223372036854775807LL - 1) {
+        qk_fail("integer overflow in abs");
+    }
+    return x < 0 ? -x : x;
+}
+
+static double qk_math_sqrt(double x) {
+    if (x < 0) {
+        qk_fail("cannot take the square root of a negative number %f", x);
+    }
+    return sqrt(x);
+}
+
+static long long qk_math_pow_int(long long base, long long exponent) {
+    if (exponent < 0) {
+        qk_fail("negative exponent %lld in integer pow", exponent);
+    }
+    long long result = 1;
+    while (exponent > 0) {
+        if ((exponent & 1) && __builtin_mul_overflow(result, base, &result)) {
+            qk_fail("integer overflow in pow");
+        }
+        exponent >>= 1;
+        if (exponent > 0 && __builtin_mul_overflow(base, base, &base)) {
+            qk_fail("integer overflow in pow");
+        }
+    }
+    return result;
+}
+
+static double qk_math_pow_float(double base, double exponent) {
+    return pow(base, exponent);
+}
+
+// floor, ceil и round возвращают Integer
+static long long qk_math_floor(double x) { return qk_float_to_int(floor(x)); }
+static long long qk_math_ceil(double x) { return qk_float_to_int(ceil(x)); }
+static long long qk_math_round(double x) { return qk_float_to_int(round(x)); }
+
+static double qk_math_abs_float(double x) { return fabs(x); }
+static long long qk_math_min_int(long long a, long long b) { return a < b ? a : b; }
+static long long qk_math_max_int(long long a, long long b) { return a > b ? a : b; }
+static double qk_math_min_float(double a, double b) { return a < b ? a : b; }
+static double qk_math_max_float(double a, double b) { return a > b ? a : b; }
+
+"#;
+
+// main сохраняет argc и argv, массив строится при первом вызове args()
+const ARGS_RUNTIME: &str = r#"static int qk_argc = 0;
+static char** qk_argv = NULL;
+
+static qk_array* qk_args(void) {
+    qk_array* args = qk_array_new(sizeof(const char*));
+    for (int i = 1; i < qk_argc; i++) {
+        const char* arg = qk_argv[i];
+        qk_array_push(args, &arg);
+    }
+    return args;
+}
+
+"#;
+
+// Число должно занимать всю строку, без пробелов по краям
+const PARSE_RUNTIME: &str = r#"#include <errno.h>
+#include <ctype.h>
+
+static bool qk_parse_int(const char* s, long long* value) {
+    if (*s == '\0' || isspace((unsigned char)*s)) {
+        return false;
+    }
+    char* end;
+    errno = 0;
+    *value = strtoll(s, &end, 10);
+    return errno == 0 && *end == '\0';
+}
+
+static bool qk_parse_float(const char* s, double* value) {
+    if (*s == '\0' || isspace((unsigned char)*s)) {
+        return false;
+    }
+    char* end;
+    errno = 0;
+    *value = strtod(s, &end);
+    return errno == 0 && *end == '\0';
+}
+
+static const char* qk_parse_error(const char* s, const char* type) {
+    size_t size = strlen(s) + strlen(type) + 24;
+    char* message = malloc(size);
+    snprintf(message, size, "cannot convert '%s' to %s", s, type);
+    return message;
+}
+
+static long long qk_parse_int_or_fail(const char* s) {
+    long long value;
+    if (!qk_parse_int(s, &value)) {
+        qk_fail("%s", qk_parse_error(s, "Integer"));
+    }
+    return value;
+}
+
+static double qk_parse_float_or_fail(const char* s) {
+    double value;
+    if (!qk_parse_float(s, &value)) {
+        qk_fail("%s", qk_parse_error(s, "Float"));
+    }
+    return value;
+}
+
+"#;
+
+// Процесс наследует stdin, stdout и stderr. Завершение по сигналу даёт 128 + номер,
+// как в shell
+const EXEC_RUNTIME: &str = r#"#ifdef _WIN32
+#include <process.h>
+#else
+#include <errno.h>
+#include <spawn.h>
+#include <sys/wait.h>
+extern char** environ;
+#endif
+
+static long long qk_exec(const char* command, qk_array* args) {
+    const char** argv = malloc((args->len + 2) * sizeof(const char*));
+    argv[0] = command;
+    memcpy(argv + 1, args->data, args->len * sizeof(const char*));
+    argv[args->len + 1] = NULL;
+    fflush(stdout);
+    fflush(stderr);
+#ifdef _WIN32
+    intptr_t status = _spawnvp(_P_WAIT, command, argv);
+    free(argv);
+    if (status == -1) {
+        qk_fail("cannot run '%s'", command);
+    }
+    return status;
+#else
+    pid_t pid;
+    int error = posix_spawnp(&pid, command, NULL, NULL, (char* const*)argv, environ);
+    free(argv);
+    if (error != 0) {
+        qk_fail("cannot run '%s': %s", command, strerror(error));
+    }
+    int status;
+    while (waitpid(pid, &status, 0) == -1) {
+        if (errno != EINTR) {
+            qk_fail("cannot wait for '%s': %s", command, strerror(errno));
+        }
+    }
+    return WIFEXITED(status) ? WEXITSTATUS(status) : 128 + WTERMSIG(status);
+#endif
+}
+
+"#;
+
+// Разобранный JSON хранится деревом, а генерируемые для каждого типа функции
+// qk_json_decode_* и qk_json_encode_* переводят его в значения Quark и обратно
+const JSON_RUNTIME: &str = r#"#include <errno.h>
+
+enum { QK_JSON_NULL, QK_JSON_BOOLEAN, QK_JSON_NUMBER, QK_JSON_STRING, QK_JSON_ARRAY, QK_JSON_OBJECT };
+
+typedef struct qk_json {
+    int kind;
+    bool boolean;
+    // Текст строки или число как оно записано: Integer разбирается из него без потери точности
+    const char* text;
+    struct qk_json** items;
+    const char** keys;
+    long long len;
+} qk_json;
+
+typedef struct {
+    const char* start;
+    const char* at;
+    const char* error;
+    int depth;
+} qk_json_parser;
+
+static qk_json* qk_json_fail(qk_json_parser* parser, const char* expected) {
+    if (!parser->error) {
+        char* message = malloc(strlen(expected) + 48);
+        sprintf(message, "invalid JSON at offset %lld: %s", (long long)(parser->at - parser->start), expected);
+        parser->error = message;
+    }
+    return NULL;
+}
+
+static void qk_json_skip(qk_json_parser* parser) {
+    while (*parser->at == ' ' || *parser->at == '\t' || *parser->at == '\n' || *parser->at == '\r') {
+        parser->at++;
+    }
+}
+
+static qk_json* qk_json_node(int kind) {
+    qk_json* node = calloc(1, sizeof(qk_json));
+    node->kind = kind;
+    return node;
+}
+
+static bool qk_json_hex4(const char* s, unsigned* value) {
+    *value = 0;
+    for (int i = 0; i < 4; i++) {
+        char c = s[i];
+        unsigned digit;
+        if (c >= '0' && c <= '9') {
+            digit = c - '0';
+        } else if (c >= 'a' && c <= 'f') {
+            digit = c - 'a' + 10;
+        } else if (c >= 'A' && c <= 'F') {
+            digit = c - 'A' + 10;
+        } else {
+            return false;
+        }
+        *value = *value * 16 + digit;
+    }
+    return true;
+}
+
+// Экранированная строка после открывающей кавычки; результат не длиннее записи
+static const char* qk_json_string(qk_json_parser* parser) {
+    const char* end = parser->at;
+    while (*end && *end != '"') {
+        end += (*end == '\\' && end[1]) ? 2 : 1;
+    }
+    char* result = malloc(end - parser->at + 1);
+    size_t len = 0;
+    while (*parser->at != '"') {
+        unsigned char c = (unsigned char)*parser->at;
+        if (c == '\0') {
+            qk_json_fail(parser, "unterminated string");
+            return NULL;
+        }
+        if (c < 0x20) {
+            qk_json_fail(parser, "control character in a string");
+            return NULL;
+        }
+        parser->at++;
+        if (c != '\\') {
+            result[len++] = c;
+            continue;
+        }
+        char escape = *parser->at++;
+        unsigned code;
+        switch (escape) {
+            case '"': result[len++] = '"'; break;
+            case '\\': result[len++] = '\\'; break;
+            case '/': result[len++] = '/'; break;
+            case 'b': result[len++] = '\b'; break;
+            case 'f': result[len++] = '\f'; break;
+            case 'n': result[len++] = '\n'; break;
+            case 'r': result[len++] = '\r'; break;
+            case 't': result[len++] = '\t'; break;
+            case 'u':
+                if (!qk_json_hex4(parser->at, &code)) {
+                    qk_json_fail(parser, "expected four hex digits after \\u");
+                    return NULL;
+                }
+                parser->at += 4;
+                // Символ вне BMP записывается суррогатной парой
+                if (code >= 0xD800 && code <= 0xDBFF) {
+                    unsigned low;
+                    if (parser->at[0] != '\\' || parser->at[1] != 'u' || !qk_json_hex4(parser->at + 2, &low)
+                        || low < 0xDC00 || low > 0xDFFF) {
+                        qk_json_fail(parser, "unpaired surrogate in \\u escape");
+                        return NULL;
+                    }
+                    parser->at += 6;
+                    code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                } else if (code >= 0xDC00 && code <= 0xDFFF) {
+                    qk_json_fail(parser, "unpaired surrogate in \\u escape");
+                    return NULL;
+                }
+                if (code < 0x80) {
+                    result[len++] = code;
+                } else if (code < 0x800) {
+                    result[len++] = 0xC0 | (code >> 6);
+                    result[len++] = 0x80 | (code & 0x3F);
+                } else if (code < 0x10000) {
+                    result[len++] = 0xE0 | (code >> 12);
+                    result[len++] = 0x80 | ((code >> 6) & 0x3F);
+                    result[len++] = 0x80 | (code & 0x3F);
+                } else {
+                    result[len++] = 0xF0 | (code >> 18);
+                    result[len++] = 0x80 | ((code >> 12) & 0x3F);
+                    result[len++] = 0x80 | ((code >> 6) & 0x3F);
+                    result[len++] = 0x80 | (code & 0x3F);
+                }
+                break;
+            default:
+                parser->at--;
+                qk_json_fail(parser, "invalid escape in a string");
+                return NULL;
+        }
+    }
+    parser->at++;
+    result[len] = '\0';
+    return result;
+}
+
+static bool qk_json_digits(qk_json_parser* parser) {
+    if (*parser->at < '0' || *parser->at > '9') {
+        return false;
+    }
+    while (*parser->at >= '0' && *parser->at <= '9') {
+        parser->at++;
+    }
+    return true;
+}
+
+static qk_json* qk_json_value(qk_json_parser* parser);
+
+static qk_json* qk_json_list(qk_json_parser* parser, bool object) {
+    qk_json* node = qk_json_node(object ? QK_JSON_OBJECT : QK_JSON_ARRAY);
+    long long cap = 0;
+    char close = object ? '}' : ']';
+    parser->at++;
+    qk_json_skip(parser);
+    if (*parser->at == close) {
+        parser->at++;
+        return node;
+    }
+    while (true) {
+        if (node->len == cap) {
+            cap = cap ? cap * 2 : 4;
+            node->items = realloc(node->items, cap * sizeof(qk_json*));
+            if (object) {
+                node->keys = realloc(node->keys, cap * sizeof(const char*));
+            }
+        }
+        qk_json_skip(parser);
+        if (object) {
+            if (*parser->at != '"') {
+                return qk_json_fail(parser, "expected a string key");
+            }
+            parser->at++;
+            const char* key = qk_json_string(parser);
+            if (!key) {
+                return NULL;
+            }
+            node->keys[node->len] = key;
+            qk_json_skip(parser);
+            if (*parser->at != ':') {
+                return qk_json_fail(parser, "expected ':' after a key");
+            }
+            parser->at++;
+        }
+        qk_json* item = qk_json_value(parser);
+        if (!item) {
+            return NULL;
+        }
+        node->items[node->len++] = item;
+        qk_json_skip(parser);
+        if (*parser->at == close) {
+            parser->at++;
+            return node;
+        }
+        if (*parser->at != ',') {
+            return qk_json_fail(parser, object ? "expected ',' or '}'" : "expected ',' or ']'");
+        }
+        parser->at++;
+    }
+}
+
+static qk_json* qk_json_value(qk_json_parser* parser) {
+    qk_json_skip(parser);
+    const char* start = parser->at;
+    qk_json* node;
+    switch (*parser->at) {
+        case '{':
+        case '[':
+            // Глубокая вложенность не должна переполнять стек
+            if (++parser->depth > 512) {
+                return qk_json_fail(parser, "nesting is too deep");
+            }
+            node = qk_json_list(parser, *parser->at == '{');
+            parser->depth--;
+            return node;
+        case '"':
+            parser->at++;
+            node = qk_json_node(QK_JSON_STRING);
+            node->text = qk_json_string(parser);
+            return node->text ? node : NULL;
+        case 't':
+        case 'f':
+        case 'n': {
+            const char* words[] = { "true", "false", "null" };
+            for (int i = 0; i < 3; i++) {
+                size_t len = strlen(words[i]);
+                if (strncmp(parser->at, words[i], len) == 0) {
+                    parser->at += len;
+                    node = qk_json_node(i == 2 ? QK_JSON_NULL : QK_JSON_BOOLEAN);
+                    node->boolean = i == 0;
+                    return node;
+                }
+            }
+            return qk_json_fail(parser, "expected a value");
+        }
+        default:
+            if (*parser->at == '-') {
+                parser->at++;
+            }
+            if (*parser->at == '0') {
+                parser->at++;
+            } else if (!qk_json_digits(parser)) {
+                parser->at = start;
+                return qk_json_fail(parser, "expected a value");
+            }
+            if (*parser->at == '.') {
+                parser->at++;
+                if (!qk_json_digits(parser)) {
+                    return qk_json_fail(parser, "expected digits after '.'");
+                }
+            }
+            if (*parser->at == 'e' || *parser->at == 'E') {
+                parser->at++;
+                if (*parser->at == '+' || *parser->at == '-') {
+                    parser->at++;
+                }
+                if (!qk_json_digits(parser)) {
+                    return qk_json_fail(parser, "expected digits in the exponent");
+                }
+            }
+            node = qk_json_node(QK_JSON_NUMBER);
+            char* text = malloc(parser->at - start + 1);
+            memcpy(text, start, parser->at - start);
+            text[parser->at - start] = '\0';
+            node->text = text;
+            return node;
+    }
+}
+
+static qk_json* qk_json_parse(const char* s, const char** error) {
+    qk_json_parser parser = { s, s, NULL, 0 };
+    qk_json* node = qk_json_value(&parser);
+    if (node) {
+        qk_json_skip(&parser);
+        if (*parser.at != '\0') {
+            node = qk_json_fail(&parser, "unexpected characters after the value");
+        }
+    }
+    *error = parser.error;
+    return node;
+}
+
+static const char* qk_json_kind_name(const qk_json* node) {
+    const char* names[] = { "null", "a boolean", "a number", "a string", "an array", "an object" };
+    return names[node->kind];
+}
+
+static bool qk_json_expected(const char* expected, const qk_json* node, const char** error) {
+    const char* kind = qk_json_kind_name(node);
+    char* message = malloc(strlen(expected) + strlen(kind) + 16);
+    sprintf(message, "expected %s, got %s", expected, kind);
+    *error = message;
+    return false;
+}
+
+// Ошибка внутри поля или элемента получает путь к нему: [2].name: expected ...
+static bool qk_json_nest(const char* segment, const char** error) {
+    bool nested = (*error)[0] == '[' || (*error)[0] == '.';
+    char* message = malloc(strlen(segment) + strlen(*error) + 3);
+    sprintf(message, nested ? "%s%s" : "%s: %s", segment, *error);
+    *error = message;
+    return false;
+}
+
+static bool qk_json_in_index(long long index, const char** error) {
+    char segment[32];
+    snprintf(segment, sizeof(segment), "[%lld]", index);
+    return qk_json_nest(segment, error);
+}
+
+static bool qk_json_in_field(const char* name, const char** error) {
+    char* segment = malloc(strlen(name) + 2);
+    sprintf(segment, ".%s", name);
+    return qk_json_nest(segment, error);
+}
+
+static const qk_json* qk_json_member(const qk_json* node, const char* name) {
+    for (long long i = 0; i < node->len; i++) {
+        if (strcmp(node->keys[i], name) == 0) {
+            return node->items[i];
+        }
+    }
+    return NULL;
+}
+
+static bool qk_json_to_int(const qk_json* node, long long* value, const char** error) {
+    if (node->kind != QK_JSON_NUMBER) {
+        return qk_json_expected("an Integer", node, error);
+    }
+    char* end;
+    errno = 0;
+    *value = strtoll(node->text, &end, 10);
+    if (errno != 0 || *end != '\0') {
+        char* message = malloc(strlen(node->text) + 24);
+        sprintf(message, "%s is not an Integer", node->text);
+        *error = message;
+        return false;
+    }
+    return true;
+}
+
+static bool qk_json_to_float(const qk_json* node, double* value, const char** error) {
+    if (node->kind != QK_JSON_NUMBER) {
+        return qk_json_expected("a Float", node, error);
+    }
+    *value = strtod(node->text, NULL);
+    return true;
+}
+
+static bool qk_json_to_bigint(const qk_json* node, const char** value, const char** error) {
+    if (node->kind != QK_JSON_NUMBER || strpbrk(node->text, ".eE")) {
+        return qk_json_expected("a whole number", node, error);
+    }
+    *value = node->text;
+    return true;
+}
+
+static bool qk_json_to_bool(const qk_json* node, bool* value, const char** error) {
+    if (node->kind != QK_JSON_BOOLEAN) {
+        return qk_json_expected("a Boolean", node, error);
+    }
+    *value = node->boolean;
+    return true;
+}
+
+static bool qk_json_to_string(const qk_json* node, const char** value, const char** error) {
+    if (node->kind != QK_JSON_STRING) {
+        return qk_json_expected("a String", node, error);
+    }
+    *value = node->text;
+    return true;
+}
+
+typedef struct {
+    char* data;
+    size_t len;
+    size_t cap;
+} qk_json_buffer;
+
+static qk_json_buffer qk_json_buffer_new(void) {
+    qk_json_buffer buffer = { malloc(64), 0, 64 };
+    buffer.data[0] = '\0';
+    return buffer;
+}
+
+static void qk_json_append(qk_json_buffer* out, const char* s) {
+    size_t len = strlen(s);
+    if (out->len + len + 1 > out->cap) {
+        while (out->len + len + 1 > out->cap) {
+            out->cap *= 2;
+        }
+        out->data = realloc(out->data, out->cap);
+    }
+    memcpy(out->data + out->len, s, len + 1);
+    out->len += len;
+}
+
+static void qk_json_append_int(qk_json_buffer* out, long long value) {
+    char buffer[32];
+    snprintf(buffer, sizeof(buffer), "%lld", value);
+    qk_json_append(out, buffer);
+}
+
+// Кратчайшая запись, из которой читается то же число
+static void qk_json_append_float(qk_json_buffer* out, double value) {
+    // Для бесконечности и NaN разность не равна нулю
+    if (value - value != 0) {
+        qk_fail("cannot write %f to JSON", value);
+    }
+    char buffer[32];
+    snprintf(buffer, sizeof(buffer), "%.15g", value);
+    if (strtod(buffer, NULL) != value) {
+        snprintf(buffer, sizeof(buffer), "%.17g", value);
+    }
+    qk_json_append(out, buffer);
+}
+
+static void qk_json_append_string(qk_json_buffer* out, const char* s) {
+    qk_json_append(out, "\"");
+    for (; *s; s++) {
+        unsigned char c = (unsigned char)*s;
+        char escaped[8] = { (char)c, '\0' };
+        switch (c) {
+            case '"': strcpy(escaped, "\\\""); break;
+            case '\\': strcpy(escaped, "\\\\"); break;
+            case '\n': strcpy(escaped, "\\n"); break;
+            case '\r': strcpy(escaped, "\\r"); break;
+            case '\t': strcpy(escaped, "\\t"); break;
+            default:
+                if (c < 0x20) {
+                    snprintf(escaped, sizeof(escaped), "\\u%04x", c);
+                }
+        }
+        qk_json_append(out, escaped);
+    }
+    qk_json_append(out, "\"");
+}
+
+"#;
+
+// Только HTTP без TLS; запрос HTTP/1.0, чтобы ответ пришёл целиком, без chunked
+const NET_RUNTIME: &str = r#"#include <stdarg.h>
+#ifdef _WIN32
+#include <winsock2.h>
+#include <ws2tcpip.h>
+#pragma comment(lib, "ws2_32.lib")
+typedef SOCKET qk_socket;
+#define qk_socket_close closesocket
+#define QK_SHUT_WR SD_SEND
+#else
+#include <netdb.h>
+#include <sys/socket.h>
+#include <unistd.h>
+typedef int qk_socket;
+#define INVALID_SOCKET (-1)
+#define qk_socket_close close
+#define QK_SHUT_WR SHUT_WR
+#endif
+#ifndef MSG_NOSIGNAL
+#define MSG_NOSIGNAL 0
+#endif
+
+static bool qk_net_fail(const char** out, const char* format, ...) {
+    char* message = malloc(256);
+    va_list args;
+    va_start(args, format);
+    vsnprintf(message, 256, format, args);
+    va_end(args);
+    *out = message;
+    return false;
+}
+
+static bool qk_net_connect(const char* host, long long port, qk_socket* out, const char** error) {
+#ifdef _WIN32
+    static bool started = false;
+    if (!started) {
+        WSADATA data;
+        WSAStartup(MAKEWORD(2, 2), &data);
+        started = true;
+    }
+#endif
+    if (port < 1 || port > 65535) {
+        return qk_net_fail(error, "port %lld is out of range", port);
+    }
+    char service[8];
+    snprintf(service, sizeof(service), "%lld", port);
+    struct addrinfo hints;
+    memset(&hints, 0, sizeof(hints));
+    hints.ai_family = AF_UNSPEC;
+    hints.ai_socktype = SOCK_STREAM;
+    struct addrinfo* addresses;
+    int status = getaddrinfo(host, service, &hints, &addresses);
+    if (status != 0) {
+        return qk_net_fail(error, "cannot resolve '%s': %s", host, gai_strerror(status));
+    }
+    qk_socket sock = INVALID_SOCKET;
+    for (struct addrinfo* address = addresses; address; address = address->ai_next) {
+        sock = socket(address->ai_family, address->ai_socktype, address->ai_protocol);
+        if (sock == INVALID_SOCKET) {
+            continue;
+        }
+        if (connect(sock, address->ai_addr, address->ai_addrlen) == 0) {
+            break;
+        }
+        qk_socket_close(sock);
+        sock = INVALID_SOCKET;
+    }
+    freeaddrinfo(addresses);
+    if (sock == INVALID_SOCKET) {
+        return qk_net_fail(error, "cannot connect to %s:%lld", host, port);
+    }
+    *out = sock;
+    return true;
+}
+
+// Отправляет всё, закрывает запись и читает ответ, пока собеседник не закроет соединение
+static bool qk_net_exchange(const char* host, long long port, const char* data, char** reply, const char** error) {
+    qk_socket sock;
+    if (!qk_net_connect(host, port, &sock, error)) {
+        return false;
+    }
+    size_t len = strlen(data);
+    for (size_t sent = 0; sent < len;) {
+        int written = send(sock, data + sent, (int)(len - sent), MSG_NOSIGNAL);
+        if (written <= 0) {
+            qk_socket_close(sock);
+            return qk_net_fail(error, "cannot send to %s:%lld", host, port);
+        }
+        sent += written;
+    }
+    shutdown(sock, QK_SHUT_WR);
+    size_t cap = 4096;
+    size_t received = 0;
+    char* buffer = malloc(cap);
+    while (true) {
+        if (received + 1 == cap) {
+            cap *= 2;
+            buffer = realloc(buffer, cap);
+        }
+        int read = recv(sock, buffer + received, (int)(cap - received - 1), 0);
+        if (read == 0) {
+            break;
+        }
+        if (read < 0) {
+            qk_socket_close(sock);
+            return qk_net_fail(error, "cannot receive from %s:%lld", host, port);
+        }
+        received += read;
+    }
+    qk_socket_close(sock);
+    buffer[received] = '\0';
+    *reply = buffer;
+    return true;
+}
+
+static bool qk_tcp_send(const char* host, long long port, const char* data, const char** out) {
+    char* reply;
+    if (!qk_net_exchange(host, port, data, &reply, out)) {
+        return false;
+    }
+    *out = reply;
+    return true;
+}
+
+static bool qk_http_get(const char* url, const char** out) {
+    if (strncmp(url, "https://", 8) == 0) {
+        return qk_net_fail(out, "https is not supported, only plain http");
+    }
+    if (strncmp(url, "http://", 7) != 0) {
+        return qk_net_fail(out, "invalid URL '%s': expected it to start with http://", url);
+    }
+    const char* rest = url + 7;
+    size_t host_len = strcspn(rest, ":/");
+    if (host_len == 0) {
+        return qk_net_fail(out, "invalid URL '%s': no host", url);
+    }
+    char* host = malloc(host_len + 1);
+    memcpy(host, rest, host_len);
+    host[host_len] = '\0';
+    const char* at = rest + host_len;
+    long long port = 80;
+    if (*at == ':') {
+        char* end;
+        port = strtoll(at + 1, &end, 10);
+        if (end == at + 1) {
+            return qk_net_fail(out, "invalid URL '%s': bad port", url);
+        }
+        at = end;
+    }
+    const char* path = *at ? at : "/";
+    if (*path != '/') {
+        return qk_net_fail(out, "invalid URL '%s'", url);
+    }
+    
+    size_t size = strlen(path) + strlen(host) + 96;
+    char* request = malloc(size);
+    snprintf(request, size, "GET %s HTTP/1.0\r\nHost: %s\r\nUser-Agent: quark\r\nConnection: close\r\n\r\n", path, host);
+    char* response;
+    if (!qk_net_exchange(host, port, request, &response, out)) {
+        return false;
+    }
+    const char* space = strchr(response, ' ');
+    if (strncmp(response, "HTTP/", 5) != 0 || !space) {
+        return qk_net_fail(out, "invalid HTTP response from %s", host);
+    }
+    int status = atoi(space + 1);
+    if (status < 200 || status > 299) {
+        return qk_net_fail(out, "%s responded with status %d", url, status);
+    }
+    const char* body = strstr(response, "\r\n\r\n");
+    *out = body ? body + 4 : "";
+    return true;
+}
+
+"#;
+
+// Перед паузой выводится всё, что print успел собрать в буфере
+const SLEEP_RUNTIME: &str = r#"#ifdef _WIN32
+#include <windows.h>
+#else
+#include <time.h>
+#endif
+
+static void qk_sleep(long long ms) {
+    if (ms < 0) {
+        qk_fail("cannot sleep for a negative duration of %lld ms", ms);
+    }
+    fflush(stdout);
+#ifdef _WIN32
+    Sleep((DWORD)ms);
+#else
+    struct timespec duration = { ms / 1000, (ms % 1000) * 1000000 };
+    while (nanosleep(&duration, &duration) != 0) {}
+#endif
+}
+
+"#;
+
+// Неявное приведение к строке, конкатенация и символы UTF-8. Результат всегда
+// в куче, поэтому строку можно сохранить куда угодно без копирования
+const STRING_RUNTIME: &str = r#"static const char* qk_string_concat(const char* a, const char* b) {
+    size_t len_a = strlen(a);
+    size_t len_b = strlen(b);
+    char* result = malloc(len_a + len_b + 1);
+    memcpy(result, a, len_a);
+    memcpy(result + len_a, b, len_b + 1);
+    return result;
+}
+
+static const char* qk_string_from_int(long long value) {
+    char buffer[32];
+    snprintf(buffer, sizeof(buffer), "%lld", value);
+    return qk_strdup(buffer);
+}
+
+static const char* qk_string_from_float(double value) {
+    char buffer[64];
+    snprintf(buffer, sizeof(buffer), "%f", value);
+    return qk_strdup(buffer);
+}
+
+static const char* qk_string_from_bool(bool value) {
+    return value ? "true" : "false";
+}
+
+// Конец символа UTF-8, который начинается с offset. Оборванная
+// последовательность заканчивается на первом байте, который её не продолжает
+static size_t qk_utf8_next(const char* s, size_t offset) {
+    unsigned char first = (unsigned char)s[offset];
+    size_t width = 1;
+    if ((first & 0xE0) == 0xC0) {
+        width = 2;
+    } else if ((first & 0xF0) == 0xE0) {
+        width = 3;
+    } else if ((first & 0xF8) == 0xF0) {
+        width = 4;
+    }
+    size_t end = offset + 1;
+    while (end < offset + width && ((unsigned char)s[end] & 0xC0) == 0x80) {
+        end++;
+    }
+    return end;
+}
+
+static long long qk_string_len(const char* s) {
+    long long len = 0;
+    for (size_t offset = 0; s[offset]; offset = qk_utf8_next(s, offset)) {
+        len++;
+    }
+    return len;
+}
+
+// Копия байтов [start, end)
+static const char* qk_string_bytes(const char* s, size_t start, size_t end) {
+    char* result = malloc(end - start + 1);
+    memcpy(result, s + start, end - start);
+    result[end - start] = '\0';
+    return result;
+}
+
+static const char* qk_string_at(const char* s, long long index) {
+    size_t offset = 0;
+    for (long long i = 0; i < index && s[offset]; i++) {
+        offset = qk_utf8_next(s, offset);
+    }
+    if (index < 0 || !s[offset]) {
+        qk_fail("index %lld is out of bounds for a string of length %lld", index, qk_string_len(s));
+    }
+    return qk_string_bytes(s, offset, qk_utf8_next(s, offset));
+}
+
+// Символы [start, end), как и индексы, считаются в символах, а не в байтах
+static const char* qk_string_slice(const char* s, long long start, long long end, bool to_end) {
+    long long len = qk_string_len(s);
+    if (to_end) {
+        end = len;
+    }
+    if (start < 0 || start > end || end > len) {
+        qk_fail("slice %lld..%lld is out of bounds for a string of length %lld", start, end, len);
+    }
+    size_t from = 0;
+    for (long long i = 0; i < start; i++) {
+        from = qk_utf8_next(s, from);
+    }
+    size_t to = from;
+    for (long long i = start; i < end; i++) {
+        to = qk_utf8_next(s, to);
+    }
+    return qk_string_bytes(s, from, to);
+}
+
+// Символ, который начинается с *offset; offset сдвигается на следующий
+static const char* qk_string_next(const char* s, size_t* offset) {
+    size_t start = *offset;
+    *offset = qk_utf8_next(s, start);
+    return qk_string_bytes(s, start, *offset);
+}
+
+"#;
+
+fn indent(code: &str) -> String {
+    code.lines()
+        .map(|line| if line.is_empty() { "\n".to_string() } else { format!("    {}\n", line) })
+        .collect()
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self {
+            temp_counter: 0,
+            scopes: vec![HashMap::new()],
+            loops: Vec::new(),
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            traits: HashMap::new(),
+            impls: Impls::new(),
+            return_type: None,
+            tries: Vec::new(),
+            defers: Vec::new(),
+            module: None,
+            function_scope: 0,
+            lambdas: String::new(),
+            tests: false,
+            target: None,
+            json_helpers: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn default_value(&self, var_type: &VarType) -> Value {
+        match var_type {
+            VarType::String => Value::String(String::new()),
+            VarType::Integer => Value::Integer(0),
+            VarType::Float => Value::Float(0.0),
+            VarType::Boolean => Value::Boolean(false),
+            VarType::BigInt => Value::BigInt("0".to_string()),
+            VarType::Bytes => Value::Bytes(Vec::new()),
+            VarType::Array(element_type) => Value::Array {
+                element_type: (**element_type).clone(),
+                items: Vec::new(),
+            },
+            VarType::Map(key_type, value_type) => Value::Map {
+                key_type: (**key_type).clone(),
+                value_type: (**value_type).clone(),
+                entries: Vec::new(),
+            },
+            VarType::Struct(name) => Value::Struct {
+                name: name.clone(),
+                fields: self.structs[name]
+                    .iter()
+                    .map(|f| (f.name.clone(), self.default_value(&f.var_type)))
+                    .collect(),
+            },
+            // Трейт не может быть типом результата, значение по умолчанию не нужно.
+            // У функции литерала нет, пустое значение подставляет generate_function
+            VarType::Trait(_) | VarType::Ok(_) | VarType::Err(_) | VarType::Function(..) => unreachable!(),
+            VarType::Option(_) | VarType::None => Value::None,
+            VarType::Result(ok_type, err_type) => Value::Result {
+                ok_type: (**ok_type).clone(),
+                err_type: (**err_type).clone(),
+                ok: true,
+                value: Box::new(self.default_value(ok_type)),
+            },
+            // Первый вариант со значениями по умолчанию
+            VarType::Enum(name) => {
+                let variant = &self.enums[name][0];
+                Value::Enum {
+                    name: name.clone(),
+                    variant: variant.name.clone(),
+                    payload: variant.payload.iter().map(|t| self.default_value(t)).collect(),
+                }
+            }
+        }
+    }
+
+    // Определение структуры или перечисления на C: тип, вложенный по значению,
+    // должен быть определён раньше, поэтому зависимости выводятся первыми
+    fn emit_type(&self, name: &str, visiting: &mut Vec<String>, emitted: &mut Vec<String>,
+                 code: &mut String) -> Result<(), CompileError> {
+        if emitted.iter().any(|n| n == name) {
+            return Ok(());
+        }
+        let members: Vec<(&str, &VarType)> = match self.structs.get(name) {
+            Some(fields) => fields.iter().map(|f| (f.name.as_str(), &f.var_type)).collect(),
+            None => self.enums[name]
+                .iter()
+                .flat_map(|v| v.payload.iter().map(move |t| (v.name.as_str(), t)))
+                .collect(),
+        };
+        
+        visiting.push(name.to_string());
+        for (member, var_type) in members {
+            if let VarType::Struct(inner) | VarType::Enum(inner) = var_type {
+                if visiting.contains(inner) {
+                    let kind = if self.structs.contains_key(inner) { "struct" } else { "enum" };
+                    return Err(CompileError::Type("E0499", format!(
+                        "{} '{}' cannot contain itself by value (through '{}')",
+                        kind, inner, member
+                    )));
+                }
+                self.emit_type(inner, visiting, emitted, code)?;
+            }
+        }
+        visiting.pop();
+        
+        match self.structs.get(name) {
+            Some(fields) => {
+                code.push_str(&format!("struct qs_{} {{\n", name));
+                if fields.is_empty() {
+                    // Пустая структура в C недопустима
+                    code.push_str("    char qk_empty;\n");
+                }
+                for field in fields {
+                    code.push_str(&format!("    {} {};\n", c_type(&field.var_type), field.name));
+                }
+                code.push_str("};\n");
+            }
+            None => {
+                let variants = &self.enums[name];
+                let tags: Vec<String> = variants.iter().map(|v| format!("qe_{}_{}", name, v.name)).collect();
+                code.push_str(&format!("enum {{ {} }};\n", tags.join(", ")));
+                code.push_str(&format!("struct qe_{} {{\n    int tag;\n", name));
+                // Значения всех вариантов делят одну память
+                if variants.iter().any(|v| !v.payload.is_empty()) {
+                    code.push_str("    union {\n");
+                    for variant in variants.iter().filter(|v| !v.payload.is_empty()) {
+                        let members: Vec<String> = variant
+                            .payload
+                            .iter()
+                            .enumerate()
+                            .map(|(index, t)| format!("{} _{};", c_type(t), index))
+                            .collect();
+                        code.push_str(&format!("        struct {{ {} }} {};\n", members.join(" "), variant.name));
+                    }
+                    code.push_str("    } as;\n");
+                }
+                code.push_str("};\n");
+            }
+        }
+        emitted.push(name.to_string());
+        Ok(())
+    }
+
+    fn next_id(&mut self) -> usize {
+        let id = self.temp_counter;
+        self.temp_counter += 1;
+        id
+    }
+
+    fn lookup(&self, name: &str) -> Option<&VarType> {
+        let name = self.resolve_variable(name);
+        self.scopes.iter().rev().find_map(|scope| scope.get(&name))
+    }
+
+    // Внутри функции модуля имя без префикса сначала ищется среди её переменных, затем среди членов модуля
+    fn resolve_variable(&self, name: &str) -> String {
+        if let Some(module) = &self.module {
+            let (outer, locals) = self.scopes.split_at(self.function_scope);
+            let member = member_name(module, name);
+            if !locals.iter().any(|scope| scope.contains_key(name)) && outer.iter().any(|scope| scope.contains_key(&member)) {
+                return member;
+            }
+        }
+        name.to_string()
+    }
+
+    fn resolve_function(&self, name: &str) -> String {
+        if let Some(module) = &self.module {
+            let member = member_name(module, name);
+            if self.functions.contains_key(&member) {
+                return member;
+            }
+        }
+        name.to_string()
+    }
+
+    // Сборка останавливается на первой ошибке, проверка сообщает все
+    fn generate_c_code(&mut self, program: &Program) -> Result<String, CompileError> {
+        self.generate_all(program).map_err(|mut errors| errors.remove(0))
+    }
+
+    fn generate_all(&mut self, program: &Program) -> Result<String, Vec<CompileError>> {
+        self.errors.clear();
+        let result = self.generate_program(program);
+        let mut errors = std::mem::take(&mut self.errors);
+        match result {
+            Ok(c_code) if errors.is_empty() => Ok(c_code),
+            Ok(_) => Err(errors),
+            Err(error) => {
+                errors.push(error);
+                Err(errors)
+            }
+        }
+    }
+
+    // Ошибка в одной функции или инструкции не мешает проверить остальные: она запоминается,
+    // а состояние генератора возвращается к тому, что было до неё
+    fn recover<T>(&mut self, generate: impl FnOnce(&mut Self) -> Result<T, CompileError>) -> Option<T> {
+        let (scopes, loops, tries, defers) = (self.scopes.len(), self.loops.len(), self.tries.len(), self.defers.len());
+        let (return_type, module, function_scope) = (self.return_type.clone(), self.module.clone(), self.function_scope);
+        match generate(self) {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.scopes.truncate(scopes);
+                self.loops.truncate(loops);
+                self.tries.truncate(tries);
+                self.defers.truncate(defers);
+                self.return_type = return_type;
+                self.module = module;
+                self.function_scope = function_scope;
+                self.errors.push(error);
+                None
+            }
+        }
+    }
+
+    fn generate_program(&mut self, program: &Program) -> Result<String, CompileError> {
+        let mut c_code = String::new();
+        
+        c_code.push_str("/* Generated by Quark Compiler */\n");
+        // В строгом режиме C99 функции POSIX (сокеты, nanosleep) видны только с этим макросом
+        c_code.push_str("#if !defined(_WIN32) && !defined(_POSIX_C_SOURCE)\n#define _POSIX_C_SOURCE 200809L\n#endif\n");
+        c_code.push_str("#include <stdio.h>\n");
+        c_code.push_str("#include <stdlib.h>\n");
+        c_code.push_str("#include <string.h>\n");
+        c_code.push_str("#include <stdbool.h>\n\n");
+        let headers_len = c_code.len();
+        
+        self.structs.clear();
+        self.enums.clear();
+        self.traits.clear();
+        self.impls.clear();
+        let mut typedefs = String::new();
+        let mut type_names = Vec::new();
+        for stmt in &program.statements {
+            match stmt {
+                Stmt::Struct { name, fields, .. } => {
+                    self.structs.insert(name.clone(), fields.clone());
+                    typedefs.push_str(&format!("typedef struct qs_{} qs_{};\n", name, name));
+                    type_names.push(name.clone());
+                }
+                Stmt::Enum { name, variants, .. } => {
+                    self.enums.insert(name.clone(), variants.clone());
+                    typedefs.push_str(&format!("typedef struct qe_{} qe_{};\n", name, name));
+                    type_names.push(name.clone());
+                }
+                Stmt::Trait { name, methods, .. } => {
+                    self.traits.insert(name.clone(), methods.clone());
+                    typedefs.push_str(&format!("typedef struct qt_{} qt_{};\n", name, name));
+                    typedefs.push_str(&format!("typedef struct qt_{}_vtable qt_{}_vtable;\n", name, name));
+                }
+                Stmt::Impl { trait_name, self_type, .. } => {
+                    self.impls.insert((user_type_name(self_type).unwrap().to_string(), trait_name.clone()));
+                }
+                _ => {}
+            }
+        }
+        if !typedefs.is_empty() {
+            // Предварительные typedef позволяют полям ссылаться на любой тип
+            c_code.push_str("// Types\n");
+            c_code.push_str(&typedefs);
+            let mut emitted = Vec::new();
+            for name in &type_names {
+                self.emit_type(name, &mut Vec::new(), &mut emitted, &mut c_code)?;
+            }
+            for stmt in &program.statements {
+                if let Stmt::Trait { name, methods, .. } = stmt {
+                    c_code.push_str(&trait_definition(name, methods));
+                }
+            }
+            c_code.push('\n');
+        }
+        
+        // Объявления верхнего уровня становятся глобальными переменными
+        self.scopes = vec![HashMap::new()];
+        self.functions.clear();
+        c_code.push_str("// Variables\n");
+        // Коллекция создаётся вызовом, а глобальная переменная C требует константы,
+        // поэтому такие переменные (и структуры) инициализируются в начале main.
+        // Значение-выражение вычисляется в main там же, где стоит объявление
+        let mut global_init = String::new();
+        for stmt in &program.statements {
+            if let Stmt::Declaration { var_type, name, value, constant, .. } = stmt {
+                // Тип let со значением-выражением выводится ниже, когда известны функции
+                let var_type = match (var_type, value) {
+                    (Some(var_type), _) => var_type.clone(),
+                    (None, Expr::Literal(value)) => value_type(value),
+                    (None, _) => continue,
+                };
+                self.scopes[0].insert(name.clone(), var_type.clone());
+                let c_name = c_variable_name(name);
+                match value {
+                    Expr::Literal(value) if !needs_runtime_init(&var_type) => {
+                        c_code.push_str(&format!("{};\n", c_declaration(&var_type, &c_name, &c_literal(value), *constant)));
+                    }
+                    Expr::Literal(value) => {
+                        c_code.push_str(&format!("{} {};\n", c_type(&var_type), c_name));
+                        global_init.push_str(&format!("    {} = {};\n", c_name, c_literal(value)));
+                    }
+                    _ => c_code.push_str(&format!("{} {};\n", c_type(&var_type), c_name)),
+                }
+            }
+        }
+        
+        // Сначала собираем сигнатуры всех функций: прототипы позволяют вызывать
+        // функцию до её определения, в том числе при взаимной рекурсии
+        let mut prototypes = String::new();
+        for stmt in &program.statements {
+            if let Stmt::Function { name, params, return_type, attributes, .. } = stmt {
+                self.functions.insert(name.clone(), FunctionSignature {
+                    params: params.iter().map(|p| p.var_type.clone()).collect(),
+                    return_type: return_type.clone(),
+                });
+                prototypes.push_str(&format!(
+                    "{}{};\n", inline_prefix(attributes), c_function_header(&c_function_name(name), params, return_type)
+                ));
+            }
+        }
+        // Переменные из разбора структуры и из let с выражением на верхнем уровне тоже
+        // глобальные, но значение присваивается в main, когда до него доходит выполнение
+        for stmt in &program.statements {
+            match stmt {
+                Stmt::Destructure { pattern, value, .. } => {
+                    for (binding, _, var_type) in self.pattern_bindings(pattern, value)? {
+                        c_code.push_str(&format!("{} {};\n", c_type(&var_type), c_variable_name(&binding)));
+                        self.scopes[0].insert(binding, var_type);
+                    }
+                }
+                Stmt::Declaration { var_type: None, name, value, .. } if !matches!(value, Expr::Literal(_)) => {
+                    let var_type = self.in_member_scope(name, true, |compiler| compiler.expr_type(value))?;
+                    let var_type = inferred_type(name, &var_type)?;
+                    c_code.push_str(&format!("{} {};\n", c_type(&var_type), c_variable_name(name)));
+                    self.scopes[0].insert(name.clone(), var_type);
+                }
+                _ => {}
+            }
+        }
+        // Методы вызываются напрямую, а через трейт — по таблице с функциями-обёртками,
+        // которые принимают self как void*
+        let mut vtables = String::new();
+        for stmt in &program.statements {
+            if let Stmt::Impl { trait_name, self_type, methods, .. } = stmt {
+                let type_name = user_type_name(self_type).unwrap();
+                let mut entries = Vec::new();
+                for method in methods {
+                    let signature = &method.signature;
+                    let c_name = c_method_name(type_name, &signature.name);
+                    prototypes.push_str(&format!(
+                        "{};\n", c_function_header(&c_name, &method_params(self_type, signature), &signature.return_type)
+                    ));
+                    vtables.push_str(&method_thunk(self_type, &c_name, signature));
+                    entries.push(format!(".{} = {}_dyn", c_method_member(&signature.name), c_name));
+                }
+                vtables.push_str(&format!(
+                    "static const qt_{}_vtable qv_{}_{} = {{ {} }};\n",
+                    trait_name, trait_name, type_name, entries.join(", ")
+                ));
+            }
+        }
+        
+        self.lambdas.clear();
+        self.json_helpers.clear();
+        let mut functions = Vec::new();
+        for stmt in &program.statements {
+            match stmt {
+                Stmt::Function { name, params, return_type, body, attributes, .. } => {
+                    self.module = split_member(name).map(|(module, _)| module.to_string());
+                    self.function_scope = 1;
+                    let function = self.recover(|compiler| {
+                        compiler.generate_function(&c_function_name(name), name, params, return_type, body)
+                    });
+                    functions.extend(function.map(|function| format!("{}{}", inline_prefix(attributes), function)));
+                    self.module = None;
+                }
+                Stmt::Impl { self_type, methods, .. } => {
+                    let type_name = user_type_name(self_type).unwrap();
+                    for method in methods {
+                        let signature = &method.signature;
+                        functions.extend(self.recover(|compiler| {
+                            compiler.generate_function(
+                                &c_method_name(type_name, &signature.name),
+                                &format!("{}.{}", type_name, signature.name),
+                                &method_params(self_type, signature),
+                                &signature.return_type,
+                                &method.body,
+                            )
+                        }));
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !functions.is_empty() {
+            c_code.push_str("\n// Functions\n");
+            c_code.push_str(CALL_DEPTH_GUARD);
+            c_code.push_str(&prototypes);
+            c_code.push('\n');
+            if !vtables.is_empty() {
+                c_code.push_str(&vtables);
+                c_code.push('\n');
+            }
+        }
+        // Лямбды из функций и из main определяются до них
+        let lambdas_at = c_code.len();
+        c_code.push_str(&functions.join("\n"));
+        
+        // У параметров main служебные имена: пользовательские argc и argv остаются глобальными переменными
+        c_code.push_str("\nint main(int qk_main_argc, char** qk_main_argv) {\n");
+        let main_at = c_code.len();
+        c_code.push_str(&global_init);
+        
+        if self.tests {
+            // Глобальные переменные нужны тестам, остальной код программы не выполняется
+            for stmt in &program.statements {
+                if matches!(stmt, Stmt::Declaration { .. } | Stmt::Destructure { .. }) {
+                    let code = self.recover(|compiler| compiler.generate_statement(stmt));
+                    c_code.push_str(&code.unwrap_or_default());
+                }
+            }
+            c_code.push_str(&self.generate_test_runner(program));
+        } else {
+            // Генерация кода
+            for stmt in &program.statements {
+                let code = self.recover(|compiler| compiler.generate_statement(stmt));
+                c_code.push_str(&code.unwrap_or_default());
+            }
+            c_code.push_str(&self.close_defers(0, &program.statements));
+            c_code.push_str("    return 0;\n");
+        }
+        c_code.push_str("}\n");
+        // args() может встретиться и в функциях, и в лямбдах
+        if c_code[headers_len..].contains("qk_args") || self.lambdas.contains("qk_args") {
+            c_code.insert_str(main_at, "    qk_argc = qk_main_argc;\n    qk_argv = qk_main_argv;\n");
+        }
+        if !self.lambdas.is_empty() {
+            c_code.insert_str(lambdas_at, &format!("\n// Lambdas\n{}", self.lambdas));
+        }
+        // Помощники JSON вызывают друг друга, поэтому сначала идут их прототипы
+        if !self.json_helpers.is_empty() {
+            let mut helpers = String::from("\n// JSON\n");
+            for (_, definition) in &self.json_helpers {
+                helpers.push_str(&format!("{};\n", definition.lines().next().unwrap().trim_end_matches(" {")));
+            }
+            for (_, definition) in &self.json_helpers {
+                helpers.push_str(&format!("\n{}", definition));
+            }
+            c_code.insert_str(lambdas_at, &helpers);
+        }
+        
+        // Рантайм коллекций подключается, только если сгенерированный код им пользуется
+        if c_code[headers_len..].contains("qk_result") {
+            c_code.insert_str(headers_len, RESULT_RUNTIME);
+        }
+        if c_code[headers_len..].contains("qk_option") {
+            c_code.insert_str(headers_len, OPTION_RUNTIME);
+        }
+        if c_code[headers_len..].contains("qk_map") {
+            c_code.insert_str(headers_len, MAP_RUNTIME);
+        }
+        if c_code[headers_len..].contains("qk_args") {
+            c_code.insert_str(headers_len, ARGS_RUNTIME);
+        }
+        if c_code[headers_len..].contains("qk_exec") {
+            c_code.insert_str(headers_len, EXEC_RUNTIME);
+        }
+        if c_code[headers_len..].contains("qk_array") {
+            c_code.insert_str(headers_len, ARRAY_RUNTIME);
+        }
+        if c_code[headers_len..].contains("qk_string") {
+            c_code.insert_str(headers_len, STRING_RUNTIME);
+        }
+        if c_code[headers_len..].contains("qk_bytes") {
+            c_code.insert_str(headers_len, BYTES_RUNTIME);
+        }
+        if c_code[headers_len..].contains("qk_div") || c_code[headers_len..].contains("qk_rem") {
+            c_code.insert_str(headers_len, DIVISION_RUNTIME);
+        }
+        if c_code[headers_len..].contains("qk_http_get") || c_code[headers_len..].contains("qk_tcp_send") {
+            c_code.insert_str(headers_len, NET_RUNTIME);
+        }
+        if c_code[headers_len..].contains("qk_json") {
+            c_code.insert_str(headers_len, JSON_RUNTIME);
+        }
+        if c_code[headers_len..].contains("qk_parse") {
+            c_code.insert_str(headers_len, PARSE_RUNTIME);
+        }
+        if c_code[headers_len..].contains("qk_sleep") {
+            c_code.insert_str(headers_len, SLEEP_RUNTIME);
+        }
+        if c_code[headers_len..].contains("qk_math") {
+            c_code.insert_str(headers_len, MATH_RUNTIME);
+        }
+        if c_code[headers_len..].contains("qk_float_to_int") {
+            c_code.insert_str(headers_len, CAST_RUNTIME);
+        }
+        if c_code[headers_len..].contains("qk_closure") {
+            c_code.insert_str(headers_len, CLOSURE_RUNTIME);
+        }
+        if c_code[headers_len..].contains("qk_strdup") {
+            c_code.insert_str(headers_len, COPY_RUNTIME);
+        }
+        // Остальной рантайм сообщает об ошибках через qk_fail, поэтому он идёт первым
+        if c_code[headers_len..].contains("qk_fail") || c_code[headers_len..].contains("qk_catch_target") {
+            c_code.insert_str(headers_len, ERROR_RUNTIME);
+        }
+        
+        Ok(c_code)
+    }
+
+    fn generate_statement(&mut self, stmt: &Stmt) -> Result<String, CompileError> {
+        match stmt {
+            Stmt::Declaration { var_type, name, value, constant, .. } => {
+                let global = self.scopes.len() == 1;
+                if global && matches!(value, Expr::Literal(_)) {
+                    // Литералы верхнего уровня уже были выше
+                    return Ok(String::new());
+                }
+                // Тип глобального let уже выведен при объявлении переменных
+                let var_type = match var_type {
+                    Some(var_type) => var_type.clone(),
+                    None if global => self.scopes[0][name].clone(),
+                    None => inferred_type(name, &self.expr_type(value)?)?,
+                };
+                let (code, c_value) = self.in_member_scope(name, global, |compiler| {
+                    compiler.generate_initializer(&var_type, name, value)
+                })?;
+                
+                if global {
+                    return Ok(format!("{}    {} = {};\n", code, c_variable_name(name), c_value));
+                }
+                self.scopes.last_mut().unwrap().insert(name.clone(), var_type.clone());
+                Ok(format!("{}    {};\n", code, c_declaration(&var_type, name, &c_value, *constant)))
+            }
+            Stmt::Destructure { pattern, value, .. } => {
+                let bindings = self.pattern_bindings(pattern, value)?;
+                let value_type = self.expr_type(value)?;
+                let (setup, c_value) = self.generate_value(value)?;
+                let temp_name = format!("temp_destructure_{}", self.next_id());
+                let mut code = format!("{}    {} {} = {};\n", setup, c_type(&value_type), temp_name, c_value);
+                let global = self.scopes.len() == 1;
+                for (binding, field, var_type) in bindings {
+                    if global {
+                        code.push_str(&format!("    {} = {}.{};\n", c_variable_name(&binding), temp_name, field));
+                    } else {
+                        code.push_str(&format!("    {} {} = {}.{};\n", c_type(&var_type), binding, temp_name, field));
+                        self.scopes.last_mut().unwrap().insert(binding, var_type);
+                    }
+                }
+                Ok(code)
+            }
+            Stmt::Assignment { name, value, .. } => {
+                let var_type = self.lookup(name).cloned().ok_or_else(|| {
+                    CompileError::Compilation("E0301", format!("Unknown variable: {}", name))
+                })?;
+                let value = &*with_expected_type(value, &var_type);
+                let value_type = self.expr_type(value)?;
+                if !assignable(&self.impls, &var_type, &value_type) {
+                    return Err(CompileError::Type("E0101", format!(
+                        "cannot assign {:?} to variable '{}' of type {:?}",
+                        value_type, name, var_type
+                    )));
+                }
+                
+                let (mut code, c_value) = self.generate_value(value)?;
+                let c_value = self.coerce(c_value, &value_type, &var_type, &mut code);
+                Ok(format!("{}    {} = {};\n", code, c_variable_name(&self.resolve_variable(name)), c_value))
+            }
+            Stmt::IndexAssignment { target, index, value, .. } => {
+                let target_type = self.expr_type(target)?;
+                if target_type == VarType::String {
+                    return Err(CompileError::Type("E0401", "strings cannot be modified by index".to_string()));
+                }
+                if target_type == VarType::Bytes {
+                    return Err(CompileError::Type("E0401", "Bytes values cannot be modified by index".to_string()));
+                }
+                let index_type = self.expr_type(index)?;
+                let element_type = index_result_type(&target_type, &index_type)?;
+                let value_type = self.expr_type(value)?;
+                if !assignable(&self.impls, &element_type, &value_type) {
+                    return Err(CompileError::Type("E0101", format!(
+                        "cannot assign {:?} to an element of type {:?}",
+                        value_type, element_type
+                    )));
+                }
+                
+                let (target_code, c_target) = self.generate_expression(target)?;
+                let (index_code, c_index) = self.generate_value(index)?;
+                let (mut value_code, c_value) = self.generate_value(value)?;
+                let c_value = self.coerce(c_value, &value_type, &element_type, &mut value_code);
+                // Вставка в словарь может перестроить таблицу, поэтому значение
+                // вычисляется до получения указателя на слот
+                let temp_name = format!("temp_value_{}", self.next_id());
+                let slot = c_element(&element_type, &c_element_pointer(&target_type, &c_target, &c_index, true));
+                Ok(format!(
+                    "{}{}{}    {} {} = {};\n    {} = {};\n",
+                    target_code, index_code, value_code, c_type(&element_type), temp_name, c_value, slot, temp_name
+                ))
+            }
+            Stmt::FieldAssignment { target, field, value, .. } => {
+                let target_type = self.expr_type(target)?;
+                let field_type = field_result_type(&self.structs, &target_type, field)?;
+                let value_type = self.expr_type(value)?;
+                if !assignable(&self.impls, &field_type, &value_type) {
+                    return Err(CompileError::Type("E0101", format!(
+                        "cannot assign {:?} to field '{}' of type {:?}",
+                        value_type, field, field_type
+                    )));
+                }
+                
+                let (mut value_code, c_value) = self.generate_value(value)?;
+                let c_value = self.coerce(c_value, &value_type, &field_type, &mut value_code);
+                // Цель может быть элементом словаря, поэтому значение вычисляется первым
+                let temp_name = format!("temp_value_{}", self.next_id());
+                let (target_code, c_target) = self.generate_expression(target)?;
+                Ok(format!(
+                    "{}    {} {} = {};\n{}    {}.{} = {};\n",
+                    value_code, c_type(&field_type), temp_name, c_value, target_code, c_target, field, temp_name
+                ))
+            }
+            Stmt::Match { value, arms, .. } => self.generate_match(value, arms),
+            Stmt::For { label, init, condition, update, body, .. } => {
+                self.generate_for(label, init, condition, update, body)
+            }
+            Stmt::ForIn { label, variable, iterable, body, .. } => self.generate_for_in(label, variable, iterable, body),
+            Stmt::While { label, condition, body, .. } => self.generate_loop(label, Some(condition), "", None, body),
+            Stmt::Loop { label, body } => self.generate_loop(label, None, "", None, body),
+            // Функции и типы генерируются отдельно, до main
+            // Модули из import уже слиты CLI в одну программу
+            Stmt::Function { .. } | Stmt::Struct { .. } | Stmt::Enum { .. } | Stmt::Trait { .. } | Stmt::Impl { .. }
+            | Stmt::Import(_) => Ok(String::new()),
+            Stmt::Return(value) => self.generate_return(value.as_ref()),
+            Stmt::Block(statements) => Ok(format!("    {{\n{}    }}\n", self.generate_block(statements)?)),
+            // Код генерируется сразу и вставляется при каждом выходе из блока, поэтому
+            // значения переменных берутся на момент выхода. Скобки разделяют временные
+            // переменные копий. Ошибка выполнения уходит в catch, минуя отложенное
+            Stmt::Defer(deferred) => {
+                let code = self.generate_statement(deferred)?;
+                self.defers.push(format!("    {{\n{}    }}\n", indent(&code)));
+                Ok(String::new())
+            }
+            Stmt::Try { body, error, handler } => self.generate_try_catch(body, error, handler),
+            Stmt::Break(label) => self.generate_jump(true, label),
+            Stmt::Continue(label) => self.generate_jump(false, label),
+            Stmt::Expression(expr) => {
+                let mut code = String::new();
+                if let Expr::Call { name, args, .. } = expr {
+                    if matches!(name.as_str(), "echo" | "print" | "println" | "eprint" | "eprintln") {
+                        code.push_str(&self.generate_echo(name, args)?);
+                    } else {
+                        let (setup, call) = self.generate_call(name, args)?;
+                        code.push_str(&format!("{}    {};\n", setup, call));
+                    }
+                } else if let Expr::MethodCall { target, method, args, .. } = expr {
+                    let (setup, call) = self.generate_method_call(target, method, args)?;
+                    code.push_str(&format!("{}    {};\n", setup, call));
+                } else if let Expr::Try(_) = expr {
+                    // Значение ok не нужно, важен только досрочный возврат ошибки
+                    let (setup, _) = self.generate_expression(expr)?;
+                    code.push_str(&setup);
+                }
+                Ok(code)
+            }
+        }
+    }
+
+    fn generate_function(&mut self, c_name: &str, name: &str, params: &[Param], return_type: &Option<VarType>,
+                         body: &[Stmt]) -> Result<String, CompileError> {
+        // Параметры живут в собственной области видимости поверх глобальных переменных
+        self.scopes.push(params.iter().map(|p| (p.name.clone(), p.var_type.clone())).collect());
+        self.return_type = Some(return_type.clone());
+        let outer_tries = std::mem::take(&mut self.tries);
+        let outer_defers = std::mem::take(&mut self.defers);
+        let code: Result<String, CompileError> = body
+            .iter()
+            .map(|stmt| self.generate_statement(stmt))
+            .collect();
+        let deferred = self.close_defers(0, body);
+        self.tries = outer_tries;
+        self.defers = outer_defers;
+        self.return_type = None;
+        self.scopes.pop();
+        
+        let mut function = format!(
+            "{} {{\n    qk_enter(\"{}\");\n{}{}",
+            c_function_header(c_name, params, return_type), name, code?, deferred
+        );
+        // Если выполнение дошло до конца тела, возвращается значение по умолчанию
+        if !matches!(body.last(), Some(Stmt::Return(_))) {
+            function.push_str("    qk_call_depth--;\n");
+            match return_type {
+                Some(VarType::Function(..)) => function.push_str("    return (qk_closure){NULL, NULL};\n"),
+                Some(var_type) => {
+                    function.push_str(&format!("    return {};\n", c_literal(&self.default_value(var_type))));
+                }
+                None => {}
+            }
+        }
+        function.push_str("}\n");
+        Ok(function)
+    }
+
+    fn generate_return(&mut self, value: Option<&Expr>) -> Result<String, CompileError> {
+        let expected = self.return_type.clone().ok_or_else(|| {
+            CompileError::Compilation("E0402", "'return' outside of a function".to_string())
+        })?;
+        
+        let (value, expected) = match (value, expected) {
+            (None, None) => {
+                return Ok(format!("{}{}    qk_call_depth--;\n    return;\n", self.run_defers(0), self.leave_tries(0)))
+            }
+            (Some(value), Some(expected)) => (with_expected_type(value, &expected), expected),
+            (None, Some(expected)) => return Err(CompileError::Type("E0101", format!(
+                "missing return value: the function returns {:?}", expected
+            ))),
+            (Some(_), None) => return Err(CompileError::Type("E0101",
+                "cannot return a value from a function without a return type".to_string()
+            )),
+        };
+        
+        let value_type = self.expr_type(&value)?;
+        if !assignable(&self.impls, &expected, &value_type) {
+            return Err(CompileError::Type("E0101", format!(
+                "cannot return {:?} from a function returning {:?}",
+                value_type, expected
+            )));
+        }
+        
+        let (mut code, c_value) = self.generate_value(&value)?;
+        let c_value = self.coerce(c_value, &value_type, &expected, &mut code);
+        // Значение вычисляется до выхода из функции, чтобы вложенные вызовы
+        // учитывались в глубине стека
+        let temp_name = format!("temp_return_{}", self.next_id());
+        Ok(format!(
+            "{}    {} {} = {};\n{}{}    qk_call_depth--;\n    return {};\n",
+            code, c_type(&expected), temp_name, c_value, self.run_defers(0), self.leave_tries(0), temp_name
+        ))
+    }
+
+    fn generate_call(&mut self, name: &str, args: &[Expr]) -> Result<(String, String), CompileError> {
+        if is_builtin(name) {
+            return self.generate_builtin_call(name, args);
+        }
+        if let Some(VarType::Function(params, return_type)) = self.closure_type(name) {
+            return self.generate_closure_call(name, &params, &return_type.map(|t| *t), args);
+        }
+        
+        let name = &self.resolve_function(name);
+        let params = match self.functions.get(name) {
+            Some(signature) => signature.params.clone(),
+            None => return Err(CompileError::Compilation("E0302", format!(
+                "unknown function '{}'", name
+            ))),
+        };
+        if !argument_count_matches(params.len(), args) {
+            return Err(CompileError::Type("E0102", format!(
+                "function '{}' takes {} argument(s), got {}",
+                name, params.len(), args.len()
+            )));
+        }
+        
+        let mut setup = String::new();
+        let c_args = self.generate_arguments(name, &params, args, &mut setup)?;
+        Ok((setup, format!("{}({})", c_function_name(name), c_args.join(", "))))
+    }
+
+    fn generate_arguments(&mut self, name: &str, params: &[VarType], args: &[Expr],
+                          setup: &mut String) -> Result<Vec<String>, CompileError> {
+        let (args, spread) = match args.split_last() {
+            Some((Expr::Spread(array), fixed)) => (fixed, Some(array)),
+            _ => (args, None),
+        };
+        let mut c_args = Vec::new();
+        for (index, (arg, param_type)) in args.iter().zip(params).enumerate() {
+            let arg = &*with_expected_type(arg, param_type);
+            let arg_type = self.expr_type(arg)?;
+            if !assignable(&self.impls, param_type, &arg_type) {
+                return Err(CompileError::Type("E0101", format!(
+                    "argument {} of '{}' must be {:?}, got {:?}",
+                    index + 1, name, param_type, arg_type
+                )));
+            }
+            let (code, value) = self.generate_value(arg)?;
+            setup.push_str(&code);
+            
+            let value = self.coerce(value, &arg_type, param_type, setup);
+            c_args.push(self.hoist_argument(arg, param_type, value, setup));
+        }
+        
+        // Длина массива известна только при выполнении, она проверяется перед вызовом
+        if let Some(array) = spread {
+            let rest = &params[args.len()..];
+            let element_type = spread_element_type(&self.expr_type(array)?)?;
+            if let Some(index) = rest.iter().position(|param| !assignable(&self.impls, param, &element_type)) {
+                return Err(CompileError::Type("E0101", format!(
+                    "cannot spread {:?} elements into argument {} of '{}', which must be {:?}",
+                    element_type, args.len() + index + 1, name, rest[index]
+                )));
+            }
+            let (code, value) = self.generate_value(array)?;
+            setup.push_str(&code);
+            let temp_name = format!("temp_spread_{}", self.next_id());
+            setup.push_str(&format!(
+                "    qk_array* {} = {};\n    qk_spread_check({}, {});\n",
+                temp_name, value, temp_name, rest.len()
+            ));
+            for (index, param_type) in rest.iter().enumerate() {
+                let element = c_element(&element_type, &format!("qk_array_at({}, {})", temp_name, index));
+                c_args.push(self.coerce(element, &element_type, param_type, setup));
+            }
+        }
+        Ok(c_args)
+    }
+
+    fn pattern_bindings(&mut self, pattern: &StructPattern,
+                        value: &Expr) -> Result<Vec<(String, String, VarType)>, CompileError> {
+        let value_type = self.expr_type(value)?;
+        pattern_bindings(&self.structs, pattern, &value_type).map_err(CompileError::from)
+    }
+
+    // f(x) для переменной f: функция из значения приводится к своей сигнатуре, первым
+    // аргументом идёт окружение
+    fn generate_closure_call(&mut self, name: &str, params: &[VarType], return_type: &Option<VarType>,
+                             args: &[Expr]) -> Result<(String, String), CompileError> {
+        if !argument_count_matches(params.len(), args) {
+            return Err(CompileError::Type("E0102", format!(
+                "function value '{}' takes {} argument(s), got {}",
+                name, params.len(), args.len()
+            )));
+        }
+        
+        let mut setup = String::new();
+        let c_name = c_variable_name(&self.resolve_variable(name));
+        let mut c_args = vec![format!("{}.env", c_name)];
+        c_args.extend(self.generate_arguments(name, params, args, &mut setup)?);
+        let mut c_params = vec!["void*".to_string()];
+        c_params.extend(params.iter().map(c_type));
+        Ok((setup, format!(
+            "(({} (*)({}))qk_closure_fn({}))({})",
+            c_return_type(return_type), c_params.join(", "), c_name, c_args.join(", ")
+        )))
+    }
+
+    // Лямбда становится static функцией ql_N. Захваченные локальные переменные копируются
+    // в окружение в куче; глобальные переменные и функции доступны ей напрямую
+    fn generate_lambda(&mut self, params: &[LambdaParam], body: &Expr) -> Result<(String, String), CompileError> {
+        let param_types = lambda_param_types(params)?;
+        let names: Vec<String> = params.iter().map(|p| p.name.clone()).collect();
+        let mut free = Vec::new();
+        free_variables(body, &names, &mut free);
+        let captures: Vec<(String, VarType)> = free
+            .into_iter()
+            .filter_map(|name| {
+                let var_type = self.scopes[1..].iter().rev().find_map(|scope| scope.get(&name))?.clone();
+                Some((name, var_type))
+            })
+            .collect();
+        
+        let id = self.next_id();
+        let mut scope: HashMap<String, VarType> = captures.iter().cloned().collect();
+        scope.extend(names.iter().cloned().zip(param_types.iter().cloned()));
+        let globals = self.scopes[0].clone();
+        let outer_scopes = std::mem::replace(&mut self.scopes, vec![globals, scope]);
+        let outer_return = self.return_type.take();
+        let outer_function_scope = std::mem::replace(&mut self.function_scope, 1);
+        let result = self.generate_lambda_body(body);
+        self.scopes = outer_scopes;
+        self.return_type = outer_return;
+        self.function_scope = outer_function_scope;
+        let (return_type, code) = result?;
+        
+        let mut c_params = vec!["void* qk_env".to_string()];
+        c_params.extend(names.iter().zip(&param_types).map(|(name, t)| format!("{} {}", c_type(t), name)));
+        let mut definition = String::new();
+        if !captures.is_empty() {
+            definition.push_str(&format!("struct ql_{}_env {{\n", id));
+            for (name, var_type) in &captures {
+                definition.push_str(&format!("    {} {};\n", c_type(var_type), name));
+            }
+            definition.push_str("};\n");
+        }
+        definition.push_str(&format!(
+            "static {} ql_{}({}) {{\n", c_return_type(&return_type), id, c_params.join(", ")
+        ));
+        if !captures.is_empty() {
+            definition.push_str(&format!("    struct ql_{}_env* qk_captured = qk_env;\n", id));
+            for (name, var_type) in &captures {
+                definition.push_str(&format!("    {} {} = qk_captured->{};\n", c_type(var_type), name, name));
+            }
+        }
+        definition.push_str(&code);
+        definition.push_str("}\n");
+        self.lambdas.push_str(&definition);
+        
+        if captures.is_empty() {
+            return Ok((String::new(), format!("(qk_closure){{(qk_function)ql_{}, NULL}}", id)));
+        }
+        let env_name = format!("temp_env_{}", self.next_id());
+        let mut setup = format!(
+            "    struct ql_{}_env* {} = malloc(sizeof(struct ql_{}_env));\n", id, env_name, id
+        );
+        for (name, _) in &captures {
+            setup.push_str(&format!("    {}->{} = {};\n", env_name, name, name));
+        }
+        Ok((setup, format!("(qk_closure){{(qk_function)ql_{}, {}}}", id, env_name)))
+    }
+    
+    // Тело лямбды без результата — вызов, он генерируется как оператор
+    fn generate_lambda_body(&mut self, body: &Expr) -> Result<(Option<VarType>, String), CompileError> {
+        let return_type = self.call_result_type(body)?;
+        let code = match return_type {
+            Some(_) => {
+                let (setup, value) = self.generate_value(body)?;
+                format!("{}    return {};\n", setup, value)
+            }
+            None => self.generate_statement(&Stmt::Expression(body.clone()))?,
+        };
+        Ok((return_type, code))
+    }
+
+    // Переменная модуля видит другие его члены без префикса
+    fn in_member_scope<T>(&mut self, name: &str, global: bool, generate: impl FnOnce(&mut Self) -> T) -> T {
+        let outer_module = self.module.clone();
+        let outer_function_scope = self.function_scope;
+        if let Some((module, _)) = split_member(name).filter(|_| global) {
+            self.module = Some(module.to_string());
+            self.function_scope = 1;
+        }
+        let result = generate(self);
+        self.module = outer_module;
+        self.function_scope = outer_function_scope;
+        result
+    }
+
+    fn generate_initializer(&mut self, var_type: &VarType, name: &str, value: &Expr) -> Result<(String, String), CompileError> {
+        let value = &*with_expected_type(value, var_type);
+        let value_type = self.expr_type(value)?;
+        if !assignable(&self.impls, var_type, &value_type) {
+            return Err(CompileError::Type("E0101", format!(
+                "cannot initialize '{}' of type {:?} with a value of type {:?}",
+                name, var_type, value_type
+            )));
+        }
+        let (mut code, c_value) = self.generate_value(value)?;
+        let c_value = self.coerce(c_value, &value_type, var_type, &mut code);
+        Ok((code, c_value))
+    }
+
+    // Преобразует значение типа from к типу to; допустимость проверяется через assignable
+    fn coerce(&mut self, value: String, from: &VarType, to: &VarType, setup: &mut String) -> String {
+        match to {
+            _ if from == to => value,
+            VarType::Option(_) if *from == VarType::None => "NULL".to_string(),
+            // Значение копируется в кучу, сама переменная T? хранит указатель
+            VarType::Option(inner) => {
+                let temp_name = format!("temp_some_{}", self.next_id());
+                setup.push_str(&format!("    {} {} = {};\n", c_type(inner), temp_name, value));
+                format!("qk_option_some(&{}, sizeof({}))", temp_name, c_type(inner))
+            }
+            // Значение копируется во временную переменную, трейт хранит указатель на неё
+            VarType::Trait(trait_name) => {
+                let temp_name = format!("temp_self_{}", self.next_id());
+                setup.push_str(&format!("    {} {} = {};\n", c_type(from), temp_name, value));
+                format!(
+                    "(qt_{}){{&{}, &qv_{}_{}}}",
+                    trait_name, temp_name, trait_name, user_type_name(from).unwrap()
+                )
+            }
+            _ => value,
+        }
+    }
+
+    fn generate_method_call(&mut self, target: &Expr, method: &str, args: &[Expr]) -> Result<(String, String), CompileError> {
+        let target_type = self.expr_type(target)?;
+        let signature = method_signature(&self.traits, &self.impls, &target_type, method)?
+            .clone();
+        if !argument_count_matches(signature.params.len(), args) {
+            return Err(CompileError::Type("E0102", format!(
+                "method '{}' takes {} argument(s), got {}",
+                method, signature.params.len(), args.len()
+            )));
+        }
+        
+        let (mut setup, c_target) = self.generate_expression(target)?;
+        let c_target = self.hoist_argument(target, &target_type, c_target, &mut setup);
+        let params: Vec<VarType> = signature.params.iter().map(|p| p.var_type.clone()).collect();
+        let mut c_args = self.generate_arguments(method, &params, args, &mut setup)?;
+        
+        let call = match &target_type {
+            VarType::Trait(_) => {
+                c_args.insert(0, format!("{}.self", c_target));
+                format!("{}.vtable->{}({})", c_target, c_method_member(method), c_args.join(", "))
+            }
+            _ => {
+                c_args.insert(0, c_target);
+                format!("{}({})", c_method_name(user_type_name(&target_type).unwrap(), method), c_args.join(", "))
+            }
+        };
+        Ok((setup, call))
     }
 
-    fn generate_c_code(&mut self, program: &Program) -> String {
-        let mut c_code = String::new();
+    // Порядок вычисления аргументов в C не определён, поэтому аргументы с вызовами
+    // вычисляются заранее, слева направо
+    fn hoist_argument(&mut self, arg: &Expr, arg_type: &VarType, value: String, setup: &mut String) -> String {
+        if !contains_call(arg) {
+            return value;
+        }
+        let temp_name = format!("temp_arg_{}", self.next_id());
+        setup.push_str(&format!("    {} {} = {};\n", c_type(arg_type), temp_name, value));
+        temp_name
+    }
+
+    fn generate_builtin_call(&mut self, name: &str, args: &[Expr]) -> Result<(String, String), CompileError> {
+        let arg_types = args.iter().map(|arg| self.expr_type(arg)).collect::<Result<Vec<_>, _>>()?;
+        let result_type = builtin_result_type(name, &arg_types)?;
+        // Тип известен при компиляции, аргумент не вычисляется
+        if let ("type_of", [arg_type]) = (name, arg_types.as_slice()) {
+            return Ok((String::new(), format!("\"{}\"", escape_c_string(&type_name(arg_type)))));
+        }
         
-        c_code.push_str("/* Generated by Quark Compiler */\n");
-        c_code.push_str("#include <stdio.h>\n");
-        c_code.push_str("#include <stdlib.h>\n");
-        c_code.push_str("#include <string.h>\n");
-        c_code.push_str("#include <stdbool.h>\n\n");
+        let mut setup = String::new();
+        let mut c_args = Vec::new();
+        for (arg, arg_type) in args.iter().zip(&arg_types) {
+            let (code, value) = self.generate_value(arg)?;
+            setup.push_str(&code);
+            c_args.push(self.hoist_argument(arg, arg_type, value, &mut setup));
+        }
         
-        // Собираем все объявленные переменные
-        let mut variables = std::collections::HashMap::new();
+        let call = match (name, arg_types.as_slice()) {
+            ("len", [VarType::String]) => format!("qk_string_len({})", c_args[0]),
+            ("len", _) => format!("({}->len)", c_args[0]),
+            ("read_bytes", _) => format!("qk_read_bytes({})", c_args[0]),
+            ("write_bytes", _) => format!("qk_write_bytes({}, {})", c_args[0], c_args[1]),
+            ("push", [_, element_type]) => {
+                format!("qk_array_push({}, &({}){{{}}})", c_args[0], c_type(element_type), c_args[1])
+            }
+            ("pop", _) => c_element(result_type.as_ref().unwrap(), &format!("qk_array_pop({})", c_args[0])),
+            ("is_some", _) => format!("({} != NULL)", c_args[0]),
+            ("is_none", _) => format!("({} == NULL)", c_args[0]),
+            ("unwrap", [VarType::Result(..)]) => {
+                c_element(result_type.as_ref().unwrap(), &format!("qk_result_unwrap({}, true)", c_args[0]))
+            }
+            ("unwrap", _) => c_element(result_type.as_ref().unwrap(), &format!("qk_option_unwrap({})", c_args[0])),
+            ("unwrap_err", _) => {
+                c_element(result_type.as_ref().unwrap(), &format!("qk_result_unwrap({}, false)", c_args[0]))
+            }
+            // Составной литерал не инициализируется структурой, поэтому значение идёт через переменную
+            ("ok" | "err", [value_type]) => {
+                let temp_name = format!("temp_result_{}", self.next_id());
+                setup.push_str(&format!("    {} {} = {};\n", c_type(value_type), temp_name, c_args[0]));
+                format!("qk_result_new({}, &{}, sizeof({}))", name == "ok", temp_name, c_type(value_type))
+            }
+            ("abs", [VarType::Integer]) => format!("qk_math_abs_int({})", c_args[0]),
+            ("abs", _) => format!("qk_math_abs_float({})", c_args[0]),
+            ("sqrt", _) => format!("qk_math_sqrt({})", c_args[0]),
+            ("floor" | "ceil" | "round", [VarType::Integer]) => c_args[0].clone(),
+            ("floor" | "ceil" | "round", _) => format!("qk_math_{}({})", name, c_args[0]),
+            ("pow", [VarType::Integer, VarType::Integer]) => format!("qk_math_pow_int({}, {})", c_args[0], c_args[1]),
+            ("pow", _) => format!("qk_math_pow_float({}, {})", c_args[0], c_args[1]),
+            ("min" | "max", [VarType::Integer, VarType::Integer]) => {
+                format!("qk_math_{}_int({}, {})", name, c_args[0], c_args[1])
+            }
+            ("min" | "max", _) => format!("qk_math_{}_float({}, {})", name, c_args[0], c_args[1]),
+            ("to_string", [VarType::Integer]) => format!("qk_string_from_int({})", c_args[0]),
+            ("to_string", [VarType::Float]) => format!("qk_string_from_float({})", c_args[0]),
+            ("to_string", [VarType::Boolean]) => format!("qk_string_from_bool({})", c_args[0]),
+            ("to_string", _) => c_args[0].clone(),
+            ("parse_int" | "parse_float", _) => {
+                let (value_type, type_label) = if name == "parse_int" {
+                    (VarType::Integer, "Integer")
+                } else {
+                    (VarType::Float, "Float")
+                };
+                let id = self.next_id();
+                setup.push_str(&format!(
+                    "    const char* temp_input_{id} = {input};\n    {c_type} temp_parse_{id};\n    \
+                     bool temp_parsed_{id} = qk_{name}(temp_input_{id}, &temp_parse_{id});\n    \
+                     const char* temp_error_{id} = temp_parsed_{id} ? NULL : qk_parse_error(temp_input_{id}, \"{type_label}\");\n    \
+                     qk_result temp_result_{id} = temp_parsed_{id}\n        \
+                     ? qk_result_new(true, &temp_parse_{id}, sizeof({c_type}))\n        \
+                     : qk_result_new(false, &temp_error_{id}, sizeof(const char*));\n",
+                    id = id, input = c_args[0], c_type = c_type(&value_type), name = name, type_label = type_label
+                ));
+                format!("temp_result_{}", id)
+            }
+            ("json_stringify", [value_type]) => {
+                let encoder = self.json_encoder(value_type)?;
+                let temp_name = format!("temp_json_{}", self.next_id());
+                setup.push_str(&format!(
+                    "    qk_json_buffer {} = qk_json_buffer_new();\n    {}(&{}, {});\n",
+                    temp_name, encoder, temp_name, c_args[0]
+                ));
+                format!("{}.data", temp_name)
+            }
+            // Ответ и сообщение об ошибке — строки, поэтому Result строится из одной переменной
+            ("http_get" | "tcp_send", _) => {
+                let id = self.next_id();
+                setup.push_str(&format!(
+                    "    const char* temp_net_{id};\n    bool temp_ok_{id} = qk_{name}({args}, &temp_net_{id});\n    \
+                     qk_result temp_result_{id} = qk_result_new(temp_ok_{id}, &temp_net_{id}, sizeof(const char*));\n",
+                    id = id, name = name, args = c_args.join(", ")
+                ));
+                format!("temp_result_{}", id)
+            }
+            ("to_int", _) => format!("qk_parse_int_or_fail({})", c_args[0]),
+            ("to_float", _) => format!("qk_parse_float_or_fail({})", c_args[0]),
+            ("args", _) => "qk_args()".to_string(),
+            ("exec", _) => format!("qk_exec({}, {})", c_args[0], c_args[1]),
+            ("join", _) => format!("qk_array_join({}, {})", c_args[0], c_args[1]),
+            ("sleep", _) => format!("qk_sleep({})", c_args[0]),
+            // Отложенные инструкции при выходе не выполняются, буферы вывода сбрасывает exit
+            ("exit", _) => format!("exit((int)({}))", c_args[0]),
+            // Внутри try проваленная проверка, как и другие ошибки выполнения, переходит в catch
+            ("assert", _) => format!("(({}) ? (void)0 : qk_fail(\"assertion failed\"))", c_args[0]),
+            ("is_ok", _) => format!("({}.ok)", c_args[0]),
+            ("is_err", _) => format!("(!{}.ok)", c_args[0]),
+            _ => return Err(CompileError::Compilation("E0403", format!(
+                "'{}' cannot be used as a value", name
+            ))),
+        };
+        Ok((setup, call))
+    }
+
+    // static void qk_json_encode_T(qk_json_buffer* out, T value)
+    fn json_encoder(&mut self, var_type: &VarType) -> Result<String, CompileError> {
+        check_json_type(&self.structs, var_type)?;
+        let name = format!("qk_json_encode_{}", json_helper_suffix(var_type));
+        if self.json_helpers.iter().any(|(helper, _)| *helper == name) {
+            return Ok(name);
+        }
+        // Место занимается до генерации тела, чтобы рекурсивный тип не зациклился
+        let index = self.json_helpers.len();
+        self.json_helpers.push((name.clone(), String::new()));
         
-        for stmt in &program.statements {
-            if let Stmt::Declaration { var_type, name, .. } = stmt {
-                variables.insert(name.clone(), var_type.clone());
+        let body = match var_type {
+            VarType::Integer => "    qk_json_append_int(out, value);\n".to_string(),
+            VarType::Float => "    qk_json_append_float(out, value);\n".to_string(),
+            VarType::Boolean => "    qk_json_append(out, value ? \"true\" : \"false\");\n".to_string(),
+            VarType::String => "    qk_json_append_string(out, value);\n".to_string(),
+            VarType::BigInt => "    qk_json_append(out, value);\n".to_string(),
+            VarType::Array(element) => format!(
+                "    qk_json_append(out, \"[\");\n    for (long long i = 0; i < value->len; i++) {{\n        \
+                 if (i > 0) {{\n            qk_json_append(out, \",\");\n        }}\n        \
+                 {}(out, {});\n    }}\n    qk_json_append(out, \"]\");\n",
+                self.json_encoder(element)?, c_element(element, "qk_array_at(value, i)")
+            ),
+            VarType::Map(_, value_type) => format!(
+                "    qk_json_append(out, \"{{\");\n    bool first = true;\n    \
+                 for (long long i = 0; i < value->cap; i++) {{\n        if (!value->used[i]) {{\n            continue;\n        }}\n        \
+                 if (!first) {{\n            qk_json_append(out, \",\");\n        }}\n        first = false;\n        \
+                 qk_json_append_string(out, *(const char**)(value->keys + i * sizeof(const char*)));\n        \
+                 qk_json_append(out, \":\");\n        {}(out, {});\n    }}\n    qk_json_append(out, \"}}\");\n",
+                self.json_encoder(value_type)?, c_element(value_type, "(value->values + i * value->value_size)")
+            ),
+            VarType::Option(inner) => format!(
+                "    if (!value) {{\n        qk_json_append(out, \"null\");\n    }} else {{\n        {}(out, *value);\n    }}\n",
+                self.json_encoder(inner)?
+            ),
+            VarType::Struct(struct_name) => {
+                let mut body = String::new();
+                for (i, field) in self.structs[struct_name].clone().iter().enumerate() {
+                    let separator = if i == 0 { "{" } else { "," };
+                    body.push_str(&format!(
+                        "    qk_json_append(out, \"{}\\\"{}\\\":\");\n    {}(out, value.{});\n",
+                        separator, field.name, self.json_encoder(&field.var_type)?, field.name
+                    ));
+                }
+                if body.is_empty() {
+                    body.push_str("    qk_json_append(out, \"{\");\n");
+                }
+                body + "    qk_json_append(out, \"}\");\n"
             }
+            _ => unreachable!(),
+        };
+        self.json_helpers[index].1 = format!(
+            "static void {}(qk_json_buffer* out, {} value) {{\n{}}}\n",
+            name, c_type(var_type), body
+        );
+        Ok(name)
+    }
+
+    // static bool qk_json_decode_T(const qk_json* node, T* out, const char** error)
+    fn json_decoder(&mut self, var_type: &VarType) -> Result<String, CompileError> {
+        check_json_type(&self.structs, var_type)?;
+        let name = format!("qk_json_decode_{}", json_helper_suffix(var_type));
+        if self.json_helpers.iter().any(|(helper, _)| *helper == name) {
+            return Ok(name);
         }
+        let index = self.json_helpers.len();
+        self.json_helpers.push((name.clone(), String::new()));
         
-        // Объявления переменных
-        c_code.push_str("// Variables\n");
-        for stmt in &program.statements {
-            if let Stmt::Declaration { var_type, name, value } = stmt {
-                let c_type = match var_type {
-                    VarType::String => "const char*",
-                    VarType::Integer => "long long",
-                    VarType::Float => "double",
-                    VarType::Boolean => "bool",
-                };
-                
-                let c_value = match value {
-                    Value::String(s) => {
-                        let escaped = s
-                            .replace('\\', "\\\\")
-                            .replace('\"', "\\\"")
-                            .replace('\n', "\\n")
-                            .replace('\t', "\\t")
-                            .replace('\r', "\\r");
-                        format!("\"{}\"", escaped)
-                    }
-                    Value::Integer(i) => i.to_string(),
-                    Value::Float(f) => f.to_string(),
-                    Value::Boolean(true) => "true".to_string(),
-                    Value::Boolean(false) => "false".to_string(),
-                };
-                
-                c_code.push_str(&format!("{} {} = {};\n", c_type, name, c_value));
+        let body = match var_type {
+            VarType::Integer => "    return qk_json_to_int(node, out, error);\n".to_string(),
+            VarType::Float => "    return qk_json_to_float(node, out, error);\n".to_string(),
+            VarType::Boolean => "    return qk_json_to_bool(node, out, error);\n".to_string(),
+            VarType::String => "    return qk_json_to_string(node, out, error);\n".to_string(),
+            VarType::BigInt => "    return qk_json_to_bigint(node, out, error);\n".to_string(),
+            VarType::Array(element) => format!(
+                "    if (node->kind != QK_JSON_ARRAY) {{\n        return qk_json_expected(\"an array\", node, error);\n    }}\n    \
+                 *out = qk_array_new(sizeof({c_type}));\n    for (long long i = 0; i < node->len; i++) {{\n        \
+                 {c_type} item;\n        if (!{decoder}(node->items[i], &item, error)) {{\n            \
+                 return qk_json_in_index(i, error);\n        }}\n        qk_array_push(*out, &item);\n    }}\n    return true;\n",
+                c_type = c_type(element), decoder = self.json_decoder(element)?
+            ),
+            VarType::Map(_, value_type) => format!(
+                "    if (node->kind != QK_JSON_OBJECT) {{\n        return qk_json_expected(\"an object\", node, error);\n    }}\n    \
+                 *out = qk_map_new(true, sizeof({c_type}));\n    for (long long i = 0; i < node->len; i++) {{\n        \
+                 {c_type} item;\n        if (!{decoder}(node->items[i], &item, error)) {{\n            \
+                 return qk_json_in_field(node->keys[i], error);\n        }}\n        \
+                 memcpy(qk_map_set(*out, &node->keys[i]), &item, sizeof({c_type}));\n    }}\n    return true;\n",
+                c_type = c_type(value_type), decoder = self.json_decoder(value_type)?
+            ),
+            VarType::Option(inner) => format!(
+                "    if (node->kind == QK_JSON_NULL) {{\n        *out = NULL;\n        return true;\n    }}\n    \
+                 {c_type} item;\n    if (!{decoder}(node, &item, error)) {{\n        return false;\n    }}\n    \
+                 *out = qk_option_some(&item, sizeof({c_type}));\n    return true;\n",
+                c_type = c_type(inner), decoder = self.json_decoder(inner)?
+            ),
+            // Незнакомые ключи пропускаются, отсутствующее поле T? становится none
+            VarType::Struct(struct_name) => {
+                let mut body = String::from(
+                    "    if (node->kind != QK_JSON_OBJECT) {\n        return qk_json_expected(\"an object\", node, error);\n    }\n    \
+                     const qk_json* field;\n"
+                );
+                for field in self.structs[struct_name].clone() {
+                    let missing = match field.var_type {
+                        VarType::Option(_) => format!("out->{} = NULL;", field.name),
+                        _ => format!("*error = \"missing field '{}'\";\n        return false;", field.name),
+                    };
+                    body.push_str(&format!(
+                        "    field = qk_json_member(node, \"{name}\");\n    if (!field) {{\n        {missing}\n    }} \
+                         else if (!{decoder}(field, &out->{name}, error)) {{\n        return qk_json_in_field(\"{name}\", error);\n    }}\n",
+                        name = field.name, missing = missing, decoder = self.json_decoder(&field.var_type)?
+                    ));
+                }
+                body + "    return true;\n"
             }
+            _ => unreachable!(),
+        };
+        self.json_helpers[index].1 = format!(
+            "static bool {}(const qk_json* node, {}* out, const char** error) {{\n{}}}\n",
+            name, c_type(var_type), body
+        );
+        Ok(name)
+    }
+
+    fn generate_block(&mut self, statements: &[Stmt]) -> Result<String, CompileError> {
+        self.scopes.push(HashMap::new());
+        let mark = self.defers.len();
+        let code: Result<String, CompileError> = statements
+            .iter()
+            .map(|stmt| self.generate_statement(stmt))
+            .collect();
+        let deferred = self.close_defers(mark, statements);
+        self.scopes.pop();
+        Ok(indent(&(code? + &deferred)))
+    }
+
+    // Отложенные в блоке инструкции выполняются в обратном порядке. Перед return,
+    // break и continue они уже выполнены, повторять их в конце блока не нужно
+    fn close_defers(&mut self, mark: usize, statements: &[Stmt]) -> String {
+        let code = match statements.last() {
+            Some(Stmt::Return(_) | Stmt::Break(_) | Stmt::Continue(_)) => String::new(),
+            _ => self.run_defers(mark),
+        };
+        self.defers.truncate(mark);
+        code
+    }
+
+    fn run_defers(&self, mark: usize) -> String {
+        self.defers[mark..].iter().rev().map(String::as_str).collect()
+    }
+
+    fn generate_match(&mut self, value: &Expr, arms: &[MatchArm]) -> Result<String, CompileError> {
+        let match_type = self.expr_type(value)?;
+        // Структуру можно только разобрать образцом, сравнивать её не с чем
+        let matchable = match match_type {
+            VarType::Integer | VarType::String | VarType::Boolean | VarType::Enum(_) => true,
+            VarType::Struct(_) => arms.iter().any(|arm| matches!(arm.pattern, Pattern::Struct(_))),
+            _ => false,
+        };
+        if !matchable {
+            return Err(CompileError::Type("E0104", format!(
+                "cannot match on {:?} values (expected Integer, String, Boolean or an enum)",
+                match_type
+            )));
         }
         
-        c_code.push_str("\nint main() {\n");
+        let (setup, c_value) = self.generate_value(value)?;
+        let temp_name = format!("temp_match_{}", self.temp_counter);
+        self.temp_counter += 1;
         
-        // Генерация кода
-        for stmt in &program.statements {
-            match stmt {
-                Stmt::Declaration { .. } => {
-                    // Объявления уже были выше
-                }
-                Stmt::Expression(expr) => {
-                    if let Expr::Call { name, args } = expr {
-                        if name == "echo" {
-                            for arg in args {
-                                let code = self.generate_echo_expression(arg, &variables);
-                                c_code.push_str(&code);
-                            }
-                        }
+        let mut body = format!("{}    {} {} = {};\n", setup, c_type(&match_type), temp_name, c_value);
+        let mut has_conditions = false;
+        let mut default = None;
+        
+        for arm in arms {
+            let condition = match &arm.pattern {
+                // Ветки после _ и разбора структуры недостижимы
+                Pattern::Wildcard | Pattern::Struct(_) => {
+                    default = Some(arm);
+                    break;
+                }
+                Pattern::Literal(pattern) => {
+                    if value_type(pattern) != match_type {
+                        return Err(CompileError::Type("E0101", format!(
+                            "match pattern {} is {:?}, but the matched value is {:?}",
+                            c_literal(pattern), value_type(pattern), match_type
+                        )));
+                    }
+                    match match_type {
+                        VarType::String => format!("strcmp({}, {}) == 0", temp_name, c_literal(pattern)),
+                        _ => format!("{} == {}", temp_name, c_literal(pattern)),
+                    }
+                }
+                Pattern::Variant { enum_name, variant, .. } => {
+                    if match_type != VarType::Enum(enum_name.clone()) {
+                        return Err(CompileError::Type("E0101", format!(
+                            "pattern {}::{} cannot match a value of type {:?}",
+                            enum_name, variant, match_type
+                        )));
                     }
+                    format!("{}.tag == qe_{}_{}", temp_name, enum_name, variant)
                 }
+            };
+            let block = self.generate_arm(&temp_name, &match_type, arm)?;
+            
+            if has_conditions {
+                body.push_str(&format!("    }} else if ({}) {{\n{}", condition, block));
+            } else {
+                body.push_str(&format!("    if ({}) {{\n{}", condition, block));
+                has_conditions = true;
             }
         }
         
-        c_code.push_str("    return 0;\n");
-        c_code.push_str("}\n");
+        match (default, has_conditions) {
+            (Some(arm), true) => {
+                let block = self.generate_arm(&temp_name, &match_type, arm)?;
+                body.push_str(&format!("    }} else {{\n{}    }}\n", block));
+            }
+            (Some(arm), false) => {
+                let block = self.generate_arm(&temp_name, &match_type, arm)?;
+                body.push_str(&format!("    {{\n{}    }}\n", block));
+            }
+            (None, true) => body.push_str("    }\n"),
+            (None, false) => {}
+        }
         
-        c_code
+        Ok(format!("    {{\n{}    }}\n", indent(&body)))
     }
-    
-    fn generate_echo_expression(&mut self, expr: &Expr, 
-                               variables: &std::collections::HashMap<String, VarType>) -> String {
+
+    // Тело ветки; значения варианта или поля структуры копируются в переменные образца
+    fn generate_arm(&mut self, temp_name: &str, match_type: &VarType, arm: &MatchArm) -> Result<String, CompileError> {
+        let mut code = String::new();
+        let mut scope = HashMap::new();
+        if let Pattern::Struct(pattern) = &arm.pattern {
+            for (binding, field, var_type) in pattern_bindings(&self.structs, pattern, match_type)? {
+                code.push_str(&format!("    {} {} = {}.{};\n", c_type(&var_type), binding, temp_name, field));
+                scope.insert(binding, var_type);
+            }
+        }
+        if let Pattern::Variant { enum_name, variant, bindings } = &arm.pattern {
+            let payload = self.enums[enum_name].iter().find(|v| v.name == *variant).unwrap().payload.clone();
+            for (index, (binding, var_type)) in bindings.iter().zip(payload).enumerate() {
+                if binding == "_" {
+                    continue;
+                }
+                code.push_str(&format!(
+                    "    {} {} = {}.as.{}._{};\n",
+                    c_type(&var_type), binding, temp_name, variant, index
+                ));
+                scope.insert(binding.clone(), var_type);
+            }
+        }
+        
+        self.scopes.push(scope);
+        let mark = self.defers.len();
+        let statements: Result<String, CompileError> = arm.body
+            .iter()
+            .map(|stmt| self.generate_statement(stmt))
+            .collect();
+        let deferred = self.close_defers(mark, &arm.body);
+        self.scopes.pop();
+        Ok(indent(&(code + &statements? + &deferred)))
+    }
+
+    // Обработчик ставится через setjmp, qk_fail возвращается в него по longjmp.
+    // Локальные переменные функции, изменённые внутри try, после ошибки могут
+    // потерять новые значения: C не гарантирует их после longjmp
+    fn generate_try_catch(&mut self, body: &[Stmt], error: &str, handler: &[Stmt]) -> Result<String, CompileError> {
+        let id = self.next_id();
+        let target = format!("temp_catch_{}", id);
+        let outer = format!("temp_catch_outer_{}", id);
+        let depth = format!("temp_catch_depth_{}", id);
+        
+        self.tries.push(outer.clone());
+        let body_code = self.generate_block(body);
+        self.tries.pop();
+        let body_code = body_code?;
+        
+        self.scopes.push(HashMap::from([(error.to_string(), VarType::String)]));
+        let handler_code = self.generate_block(handler);
+        self.scopes.pop();
+        let handler_code = handler_code?;
+        
+        let mut code = format!(
+            "    jmp_buf {};\n    jmp_buf* {} = qk_catch_target;\n    int {} = qk_call_depth;\n",
+            target, outer, depth
+        );
+        code.push_str(&format!("    qk_catch_target = &{};\n    if (setjmp({}) == 0) {{\n{}", target, target, body_code));
+        code.push_str(&format!("        qk_catch_target = {};\n    }} else {{\n", outer));
+        code.push_str(&format!("        qk_catch_target = {};\n        qk_call_depth = {};\n", outer, depth));
+        code.push_str(&format!("        const char* {} = qk_strdup(qk_error_message);\n{}    }}\n", error, handler_code));
+        Ok(format!("    {{\n{}    }}\n", indent(&code)))
+    }
+
+    // Выход из всех try глубже depth: действовать снова должен обработчик, бывший до них
+    fn leave_tries(&self, depth: usize) -> String {
+        match self.tries.get(depth) {
+            Some(outer) => format!("    qk_catch_target = {};\n", outer),
+            None => String::new(),
+        }
+    }
+
+    // for сводится к while (1) с проверкой условия в начале итерации
+    fn generate_for(&mut self, label: &Option<String>, init: &Stmt, condition: &Expr,
+                    update: &Stmt, body: &[Stmt]) -> Result<String, CompileError> {
+        self.scopes.push(HashMap::new());
+        let result = self.generate_statement(init).and_then(|init_code| {
+            let loop_code = self.generate_loop(label, Some(condition), "", Some(update), body)?;
+            Ok(init_code + &loop_code)
+        });
+        self.scopes.pop();
+        Ok(format!("    {{\n{}    }}\n", indent(&result?)))
+    }
+
+    // Перебор сводится к while (1): header берёт следующий элемент и сдвигает
+    // позицию сразу, поэтому continue не нужен отдельный шаг
+    fn generate_for_in(&mut self, label: &Option<String>, variable: &str, iterable: &Expr,
+                       body: &[Stmt]) -> Result<String, CompileError> {
+        let iterable_type = self.expr_type(iterable)?;
+        let element_type = iteration_element_type(&iterable_type)?;
+        let id = self.next_id();
+        let items = format!("temp_items_{}", id);
+        let position = format!("temp_position_{}", id);
+        let (mut code, header) = match (iterable, iterable_type) {
+            // Диапазон не превращается в массив: границы вычисляются один раз, и цикл считает
+            (Expr::Range { start, end }, _) => {
+                let (mut code, start) = self.generate_value(start)?;
+                code.push_str(&format!("    long long {} = {};\n", position, start));
+                let (end_code, end) = self.generate_value(end)?;
+                code.push_str(&end_code);
+                code.push_str(&format!("    long long {} = {};\n", items, end));
+                let header = format!("    if ({} >= {}) break;\n    long long {} = {}++;\n", position, items, variable, position);
+                (code, header)
+            }
+            (_, VarType::String) => {
+                let (mut code, c_iterable) = self.generate_value(iterable)?;
+                code.push_str(&format!("    const char* {} = {};\n    size_t {} = 0;\n", items, c_iterable, position));
+                let header = format!(
+                    "    if (!{}[{}]) break;\n    const char* {} = qk_string_next({}, &{});\n",
+                    items, position, variable, items, position
+                );
+                (code, header)
+            }
+            _ => {
+                let (mut code, c_iterable) = self.generate_value(iterable)?;
+                code.push_str(&format!("    qk_array* {} = {};\n    long long {} = 0;\n", items, c_iterable, position));
+                let element = c_element(&element_type, &format!("qk_array_at({}, {}++)", items, position));
+                let header = format!(
+                    "    if ({} >= {}->len) break;\n    {} {} = {};\n",
+                    position, items, c_type(&element_type), variable, element
+                );
+                (code, header)
+            }
+        };        
+        self.scopes.push(HashMap::from([(variable.to_string(), element_type)]));
+        let loop_code = self.generate_loop(label, None, &header, None, body);
+        self.scopes.pop();
+        code.push_str(&loop_code?);
+        Ok(format!("    {{\n{}    }}\n", indent(&code)))
+    }
+
+    // Все циклы сводятся к while (1): условие проверяется в начале итерации,
+    // затем выполняется header, шаг for выполняется в конце, после метки для continue
+    fn generate_loop(&mut self, label: &Option<String>, condition: Option<&Expr>, header: &str,
+                     update: Option<&Stmt>, body: &[Stmt]) -> Result<String, CompileError> {
+        let mut loop_body = String::new();
+        
+        if let Some(condition) = condition {
+            let condition_type = self.expr_type(condition)?;
+            if condition_type != VarType::Boolean {
+                return Err(CompileError::Type("E0101", format!(
+                    "loop condition must be Boolean, got {:?}",
+                    condition_type
+                )));
+            }
+            let (condition_code, condition_value) = self.generate_expression(condition)?;
+            loop_body.push_str(&condition_code);
+            loop_body.push_str(&format!("    if (!{}) break;\n", condition_value));
+        }
+        loop_body.push_str(header);
+        
+        let id = self.next_id();
+        self.loops.push(LoopContext {
+            id,
+            label: label.clone(),
+            has_update: update.is_some(),
+            break_used: false,
+            continue_used: false,
+            tries: self.tries.len(),
+            defers: self.defers.len(),
+        });
+        let block = self.generate_block(body);
+        let context = self.loops.pop().unwrap();
+        loop_body.push_str(&block?);
+        
+        if context.continue_used {
+            loop_body.push_str(&format!("loop_continue_{}:;\n", id));
+        }
+        if let Some(update) = update {
+            loop_body.push_str(&self.generate_statement(update)?);
+        }
+        
+        let mut code = format!("    while (1) {{\n{}    }}\n", indent(&loop_body));
+        if context.break_used {
+            code.push_str(&format!("loop_break_{}:;\n", id));
+        }
+        Ok(code)
+    }
+
+    // Переход к внешнему циклу по метке делается через goto
+    fn generate_jump(&mut self, is_break: bool, label: &Option<String>) -> Result<String, CompileError> {
+        let index = match label {
+            Some(label) => self.loops.iter().rposition(|l| l.label.as_deref() == Some(label.as_str())),
+            None => self.loops.len().checked_sub(1),
+        };
+        let index = index.ok_or_else(|| {
+            CompileError::Compilation("E0402", match label {
+                Some(label) => format!("unknown loop label '{}'", label),
+                None => "break/continue outside of a loop".to_string(),
+            })
+        })?;
+        
+        let innermost = index + 1 == self.loops.len();
+        let leave = self.run_defers(self.loops[index].defers) + &self.leave_tries(self.loops[index].tries);
+        let context = &mut self.loops[index];
+        
+        if is_break {
+            if innermost {
+                return Ok(format!("{}    break;\n", leave));
+            }
+            context.break_used = true;
+            Ok(format!("{}    goto loop_break_{};\n", leave, context.id))
+        } else {
+            if innermost && !context.has_update {
+                return Ok(format!("{}    continue;\n", leave));
+            }
+            context.continue_used = true;
+            Ok(format!("{}    goto loop_continue_{};\n", leave, context.id))
+        }
+    }
+
+    // x ?? y сводится к if: запасное значение вычисляется, только если x — none
+    fn generate_coalesce(&mut self, option: &Expr, fallback: &Expr) -> Result<(String, String), CompileError> {
+        let option_type = self.expr_type(option)?;
+        let fallback_type = self.expr_type(fallback)?;
+        let result_type = binary_result_type(&BinOp::Coalesce, &option_type, &fallback_type)?;
+        let (mut code, c_option) = self.generate_value(option)?;
+        let (fallback_code, c_fallback) = self.generate_value(fallback)?;
+        
+        let id = self.next_id();
+        let option_name = format!("temp_option_{}", id);
+        let result_name = format!("temp_coalesce_{}", id);
+        let some_value = match result_type {
+            VarType::Option(_) => option_name.clone(),
+            _ => format!("*{}", option_name),
+        };
+        code.push_str(&format!("    {} {} = {};\n", c_type(&option_type), option_name, c_option));
+        code.push_str(&format!(
+            "    {} {};\n    if ({}) {{\n        {} = {};\n    }} else {{\n{}        {} = {};\n    }}\n",
+            c_type(&result_type), result_name, option_name, result_name, some_value,
+            indent(&fallback_code), result_name, c_fallback
+        ));
+        Ok((code, result_name))
+    }
+
+    fn generate_value(&mut self, expr: &Expr) -> Result<(String, String), CompileError> {
+        match self.expr_type(expr)? {
+            VarType::String => self.generate_string_value(expr),
+            _ => self.generate_expression(expr),
+        }
+    }
+
+    fn expr_type(&mut self, expr: &Expr) -> Result<VarType, CompileError> {
         match expr {
-            Expr::Literal(value) => {
-                match value {
-                    Value::String(s) => {
-                        let escaped = s
-                            .replace('\\', "\\\\")
-                            .replace('\"', "\\\"")
-                            .replace('\n', "\\n")
-                            .replace('\t', "\\t")
-                            .replace('\r', "\\r")
-                            .replace('%', "%%");
-                        format!("    printf(\"{}\\n\");\n", escaped)
-                    }
-                    Value::Integer(i) => {
-                        format!("    printf(\"%lld\\n\", {});\n", i)
+            Expr::Array(elements) => {
+                let elements = self.element_types(elements)?;
+                array_result_type(&elements).map_err(CompileError::from)
+            }
+            Expr::Struct { name, .. } => Ok(VarType::Struct(name.clone())),
+            Expr::Enum { name, .. } => Ok(VarType::Enum(name.clone())),
+            Expr::Map(entries) => {
+                let mut entry_types = Vec::new();
+                for (key, value) in entries {
+                    entry_types.push((self.expr_type(key)?, self.expr_type(value)?));
+                }
+                map_result_type(&entry_types).map_err(CompileError::from)
+            }
+            Expr::Spread(_) => Err(CompileError::Type("E0499", SPREAD_CONTEXT_ERROR.to_string())),
+            Expr::Comprehension { element, variable, iterable, condition } => {
+                let iterable_type = self.expr_type(iterable)?;
+                let item_type = iteration_element_type(&iterable_type)?;
+                self.scopes.push(HashMap::from([(variable.clone(), item_type)]));
+                let element_type = self.expr_type(element);
+                let condition_type = condition.as_deref().map(|condition| self.expr_type(condition)).transpose();
+                self.scopes.pop();
+                comprehension_result_type(&element_type?, condition_type?.as_ref()).map_err(CompileError::from)
+            }
+            Expr::JsonParse { target, .. } => Ok(VarType::Result(Box::new(target.clone()), Box::new(VarType::String))),
+            Expr::Literal(value) => Ok(value_type(value)),
+            // Если переменная не найдена, предполагаем строку
+            Expr::Variable(name) => Ok(self.lookup(name).cloned().unwrap_or(VarType::String)),
+            Expr::BinaryOp { left, op, right, span } => {
+                let left_type = self.expr_type(left)?;
+                if let Some((method, _)) = operator_method(&self.traits, &self.impls, op, &left_type) {
+                    return self.expr_type(&operator_call(left, method, right, *span));
+                }
+                let right_type = self.expr_type(right)?;
+                binary_result_type(op, &left_type, &right_type).map_err(CompileError::from)
+            }
+            Expr::UnaryOp { op, operand, .. } => {
+                let operand_type = self.expr_type(operand)?;
+                unary_result_type(op, &operand_type).map_err(CompileError::from)
+            }
+            Expr::Index { target, index } => {
+                let target_type = self.expr_type(target)?;
+                let index_type = self.expr_type(index)?;
+                index_result_type(&target_type, &index_type).map_err(CompileError::from)
+            }
+            Expr::Cast { value, target } => {
+                let value_type = self.expr_type(value)?;
+                cast_result_type(&value_type, target).map_err(CompileError::from)
+            }
+            Expr::Slice { target, start, end } => {
+                let target_type = self.expr_type(target)?;
+                let bounds = start.iter().chain(end).map(|bound| self.expr_type(bound)).collect::<Result<Vec<_>, _>>()?;
+                slice_result_type(&target_type, &bounds).map_err(CompileError::from)
+            }
+            Expr::Range { start, end } => {
+                let start_type = self.expr_type(start)?;
+                let end_type = self.expr_type(end)?;
+                range_result_type(&start_type, &end_type).map_err(CompileError::from)
+            }
+            Expr::Field { target, field } => {
+                let target_type = self.expr_type(target)?;
+                field_result_type(&self.structs, &target_type, field).map_err(CompileError::from)
+            }
+            Expr::MethodCall { method, .. } => self
+                .call_result_type(expr)?
+                .ok_or_else(|| CompileError::Type("E0403", format!("method '{}' does not return a value", method))),
+            Expr::Try(value) => {
+                let value_type = self.expr_type(value)?;
+                try_result_type(&value_type).map(|(ok_type, _)| ok_type).map_err(CompileError::from)
+            }
+            Expr::Call { name, .. } => self
+                .call_result_type(expr)?
+                .ok_or_else(|| CompileError::Type("E0403", format!("'{}' does not return a value", name))),
+            // Параметры видны в теле поверх переменных вокруг лямбды
+            Expr::Lambda { params, body } => {
+                let param_types = lambda_param_types(params)?;
+                self.scopes.push(params.iter().map(|p| p.name.clone()).zip(param_types.iter().cloned()).collect());
+                let result = self.call_result_type(body);
+                self.scopes.pop();
+                Ok(VarType::Function(param_types, result?.map(Box::new)))
+            }
+        }
+    }
+
+    // Результат вызова, если он есть; любое другое выражение всегда даёт значение
+    fn call_result_type(&mut self, expr: &Expr) -> Result<Option<VarType>, CompileError> {
+        match expr {
+            Expr::Call { name, args, .. } if is_builtin(name) => {
+                let arg_types = args.iter().map(|arg| self.expr_type(arg)).collect::<Result<Vec<_>, _>>()?;
+                builtin_result_type(name, &arg_types).map_err(CompileError::from)
+            }
+            Expr::Call { name, .. } => {
+                if let Some(VarType::Function(_, return_type)) = self.closure_type(name) {
+                    return Ok(return_type.map(|t| *t));
+                }
+                match self.functions.get(&self.resolve_function(name)) {
+                    Some(signature) => Ok(signature.return_type.clone()),
+                    None => Err(CompileError::Compilation("E0302", format!("unknown function '{}'", name))),
+                }
+            }
+            Expr::MethodCall { target, method, .. } => {
+                let target_type = self.expr_type(target)?;
+                Ok(method_signature(&self.traits, &self.impls, &target_type, method)?
+                    .return_type
+                    .clone())
+            }
+            _ => self.expr_type(expr).map(Some),
+        }
+    }
+
+    fn element_types(&mut self, elements: &[Expr]) -> Result<Vec<(bool, VarType)>, CompileError> {
+        elements
+            .iter()
+            .map(|element| match element {
+                Expr::Spread(array) => Ok((true, self.expr_type(array)?)),
+                _ => Ok((false, self.expr_type(element)?)),
+            })
+            .collect()
+    }
+
+    // Переменная с функцией закрывает функцию с тем же именем
+    fn closure_type(&self, name: &str) -> Option<VarType> {
+        self.lookup(name).filter(|var_type| matches!(var_type, VarType::Function(..))).cloned()
+    }
+
+    fn generate_expression(&mut self, expr: &Expr) -> Result<(String, String), CompileError> {
+        match expr {
+            Expr::Literal(value) => Ok((String::new(), c_literal(value))),
+            Expr::Variable(name) => Ok((String::new(), c_variable_name(&self.resolve_variable(name)))),
+            Expr::Range { .. } => Err(CompileError::Type("E0499", RANGE_CONTEXT_ERROR.to_string())),
+            // Тот же for-in, тело которого добавляет элемент в массив, если выполнено условие
+            Expr::Comprehension { element, variable, iterable, condition } => {
+                let list_type = self.expr_type(expr)?;
+                let element_type = match &list_type {
+                    VarType::Array(element_type) => c_type(element_type),
+                    _ => unreachable!(),
+                };
+                let temp_name = format!("temp_list_{}", self.next_id());
+                let mut code = format!("    qk_array* {} = qk_array_new(sizeof({}));\n", temp_name, element_type);
+                let push = Stmt::Expression(Expr::Call {
+                    name: "push".to_string(),
+                    args: vec![Expr::Variable(temp_name.clone()), (**element).clone()],
+                    span: Span::default(),
+                });
+                let body = match condition {
+                    Some(condition) => vec![Stmt::Match {
+                        value: (**condition).clone(),
+                        arms: vec![
+                            MatchArm { pattern: Pattern::Literal(Value::Boolean(true)), body: vec![push] },
+                            MatchArm { pattern: Pattern::Wildcard, body: Vec::new() },
+                        ],
+                        span: Span::default(),
+                    }],
+                    None => vec![push],
+                };
+                self.scopes.push(HashMap::from([(temp_name.clone(), list_type)]));
+                let loop_code = self.generate_for_in(&None, variable, iterable, &body);
+                self.scopes.pop();
+                code.push_str(&loop_code?);
+                Ok((code, temp_name))
+            }
+            // Ошибка разбора и несовпадение с типом одинаково дают err с сообщением
+            Expr::JsonParse { text, target } => {
+                let (mut code, text) = self.generate_value(text)?;
+                let decoder = self.json_decoder(target)?;
+                let id = self.next_id();
+                code.push_str(&format!(
+                    "    const char* temp_error_{id} = NULL;\n    {c_type} temp_value_{id};\n    \
+                     qk_json* temp_json_{id} = qk_json_parse({text}, &temp_error_{id});\n    \
+                     qk_result temp_result_{id} = temp_json_{id} && {decoder}(temp_json_{id}, &temp_value_{id}, &temp_error_{id})\n        \
+                     ? qk_result_new(true, &temp_value_{id}, sizeof({c_type}))\n        \
+                     : qk_result_new(false, &temp_error_{id}, sizeof(const char*));\n",
+                    id = id, c_type = c_type(target), text = text, decoder = decoder
+                ));
+                Ok((code, format!("temp_result_{}", id)))
+            }
+            // Элементы добавляются по порядку, раскрытый массив — целиком
+            Expr::Array(elements) => {
+                let element_type = match self.expr_type(expr)? {
+                    VarType::Array(element_type) => *element_type,
+                    _ => unreachable!(),
+                };
+                let temp_name = format!("temp_array_{}", self.next_id());
+                let mut code = format!("    qk_array* {} = qk_array_new(sizeof({}));\n", temp_name, c_type(&element_type));
+                for element in elements {
+                    match element {
+                        Expr::Spread(array) => {
+                            let (setup, value) = self.generate_value(array)?;
+                            code.push_str(&format!("{}    qk_array_extend({}, {});\n", setup, temp_name, value));
+                        }
+                        _ => {
+                            let (setup, value) = self.generate_value(element)?;
+                            code.push_str(&format!(
+                                "{}    qk_array_push({}, &({}){{{}}});\n",
+                                setup, temp_name, c_type(&element_type), value
+                            ));
+                        }
                     }
-                    Value::Float(f) => {
-                        format!("    printf(\"%f\\n\", {});\n", f)
+                }
+                Ok((code, temp_name))
+            }
+            // Поля вычисляются в порядке записи и сохраняются во временную структуру
+            Expr::Struct { name, fields } => {
+                let struct_type = VarType::Struct(name.clone());
+                let temp_name = format!("temp_struct_{}", self.next_id());
+                let mut code = format!("    {} {};\n", c_type(&struct_type), temp_name);
+                for (field, value) in fields {
+                    let field_type = field_result_type(&self.structs, &struct_type, field)?;
+                    let (setup, value) = self.generate_initializer(&field_type, &format!("{}.{}", name, field), value)?;
+                    code.push_str(&format!("{}    {}.{} = {};\n", setup, temp_name, field, value));
+                }
+                Ok((code, temp_name))
+            }
+            Expr::Enum { name, variant, payload } => {
+                let types = self.enums[name].iter().find(|v| v.name == *variant).unwrap().payload.clone();
+                let temp_name = format!("temp_enum_{}", self.next_id());
+                let mut code = format!("    qe_{} {} = {{.tag = qe_{}_{}}};\n", name, temp_name, name, variant);
+                for (index, (value, value_type)) in payload.iter().zip(&types).enumerate() {
+                    let (setup, value) = self.generate_initializer(value_type, &format!("{}::{}", name, variant), value)?;
+                    code.push_str(&format!("{}    {}.as.{}._{} = {};\n", setup, temp_name, variant, index, value));
+                }
+                Ok((code, temp_name))
+            }
+            // Записи добавляются по порядку: значение с повторным ключом заменяет прежнее
+            Expr::Map(entries) => {
+                let map_type = self.expr_type(expr)?;
+                let (key_type, value_type) = match &map_type {
+                    VarType::Map(key_type, value_type) => (key_type, value_type),
+                    _ => unreachable!(),
+                };
+                let temp_name = format!("temp_map_{}", self.next_id());
+                let mut code = format!(
+                    "    qk_map* {} = qk_map_new({}, sizeof({}));\n",
+                    temp_name, **key_type == VarType::String, c_type(value_type)
+                );
+                for (key, value) in entries {
+                    let (key_setup, key) = self.generate_value(key)?;
+                    let (value_setup, value) = self.generate_value(value)?;
+                    code.push_str(&format!(
+                        "{}{}    {} = {};\n",
+                        key_setup, value_setup,
+                        c_element(value_type, &c_element_pointer(&map_type, &temp_name, &key, true)), value
+                    ));
+                }
+                Ok((code, temp_name))
+            }
+            Expr::Spread(_) => Err(CompileError::Type("E0499", SPREAD_CONTEXT_ERROR.to_string())),
+            Expr::BinaryOp { left, op: BinOp::Coalesce, right, .. } => self.generate_coalesce(left, right),
+            Expr::BinaryOp { left, op, right, span } => {
+                let left_type = self.expr_type(left)?;
+                if let Some((method, negate)) = operator_method(&self.traits, &self.impls, op, &left_type) {
+                    let (code, call) = self.generate_expression(&operator_call(left, method, right, *span))?;
+                    return Ok((code, if negate { format!("(!{})", call) } else { call }));
+                }
+                if self.expr_type(expr)? == VarType::String {
+                    return self.generate_string_value(expr);
+                }
+                
+                if let (BinOp::Shl | BinOp::Shr, Expr::Literal(Value::Integer(amount))) = (op, right.as_ref()) {
+                    if !(0..64).contains(amount) {
+                        return Err(CompileError::Compilation("E0503", format!(
+                            "shift amount {} is out of range for Integer (must be 0..=63)", amount
+                        )));
                     }
-                    Value::Boolean(b) => {
-                        format!("    printf(\"{}\\n\");\n", if *b { "true" } else { "false" })
+                }
+
+                // Свёртка оставляет два литерала рядом, только если результат не помещается в Integer
+                if let (Expr::Literal(Value::Integer(a)), Expr::Literal(Value::Integer(b))) = (left.as_ref(), right.as_ref()) {
+                    let result = match op {
+                        BinOp::Add => a.checked_add(*b),
+                        BinOp::Sub => a.checked_sub(*b),
+                        BinOp::Mul => a.checked_mul(*b),
+                        BinOp::Div if *b != 0 => a.checked_div(*b),
+                        BinOp::Rem if *b != 0 => a.checked_rem(*b),
+                        _ => Some(0),
+                    };
+                    if result.is_none() {
+                        return Err(CompileError::at("E0502",
+                            format!(
+                                "integer overflow in constant expression: {} {} {} does not fit in Integer",
+                                a, op.symbol(), b
+                            ),
+                            *span,
+                        ));
                     }
                 }
-            }
-            Expr::Variable(var_name) => {
-                if let Some(var_type) = variables.get(var_name) {
-                    match var_type {
-                        VarType::String => {
-                            format!("    printf(\"%s\\n\", {});\n", var_name)
-                        }
-                        VarType::Integer => {
-                            format!("    printf(\"%lld\\n\", {});\n", var_name)
+                
+                let (left_code, left_var) = self.generate_expression(left)?;
+                let (right_code, right_var) = self.generate_expression(right)?;
+                // Повышение явное, чтобы не зависеть от правил C; целые больше 2^53 теряют точность
+                let (left_var, right_var) = match (self.expr_type(left)?, self.expr_type(right)?) {
+                    (VarType::Integer, VarType::Float) => (format!("((double){})", left_var), right_var),
+                    (VarType::Float, VarType::Integer) => (left_var, format!("((double){})", right_var)),
+                    _ => (left_var, right_var),
+                };
+                
+                let value = match op {
+                    // Сдвиг отрицательного числа влево в C — неопределённое поведение
+                    BinOp::Shl => format!("((long long)((unsigned long long){} << {}))", left_var, right_var),
+                    // Делитель уже свёрнут: константный 0 означал бы гарантированное падение
+                    BinOp::Div | BinOp::Rem if self.expr_type(expr)? == VarType::Integer => {
+                        if let Expr::Literal(Value::Integer(0)) = right.as_ref() {
+                            return Err(CompileError::at("E0501",
+                                "integer division by zero: the divisor is always 0".to_string(),
+                                *span,
+                            ));
                         }
-                        VarType::Float => {
-                            format!("    printf(\"%f\\n\", {});\n", var_name)
+                        let runtime = if *op == BinOp::Div { "qk_div" } else { "qk_rem" };
+                        format!("{}({}, {})", runtime, left_var, right_var)
+                    }
+                    // Указатели на строки сравнивать нельзя: равные строки лежат в разных местах
+                    _ if op.is_comparison() && self.expr_type(left)? == VarType::String => {
+                        format!("(strcmp({}, {}) {} 0)", left_var, right_var, op.symbol())
+                    }
+                    BinOp::In => {
+                        let item_type = self.expr_type(left)?;
+                        match self.expr_type(right)? {
+                            VarType::String => format!("(strstr({}, {}) != NULL)", right_var, left_var),
+                            VarType::Map(key_type, _) => {
+                                format!("qk_map_contains({}, &({}){{{}}})", right_var, c_type(&key_type), left_var)
+                            }
+                            _ => match item_type {
+                                VarType::String | VarType::BigInt => {
+                                    format!("qk_array_contains_string({}, {})", right_var, left_var)
+                                }
+                                VarType::Float => format!("qk_array_contains_float({}, {})", right_var, left_var),
+                                _ => format!(
+                                    "qk_array_contains({}, &({}){{{}}})", right_var, c_type(&item_type), left_var
+                                ),
+                            },
                         }
-                        VarType::Boolean => {
-                            format!("    printf(\"%s\\n\", {} ? \"true\" : \"false\");\n", var_name)
+                    }
+                    _ => format!("({} {} {})", left_var, op.symbol(), right_var),
+                };
+                
+                Ok((left_code + &right_code, value))
+            }
+            Expr::UnaryOp { op, operand, span } => {
+                self.expr_type(expr)?;
+                // Свёртка не сворачивает -(-9223372036854775808): в C это переполнение
+                if let (UnaryOp::Neg, Expr::Literal(Value::Integer(value))) = (op, operand.as_ref()) {
+                    if value.checked_neg().is_none() {
+                        return Err(CompileError::at("E0502",
+                            format!("integer overflow in constant expression: -({}) does not fit in Integer", value),
+                            *span,
+                        ));
+                    }
+                }
+                let (code, value) = self.generate_expression(operand)?;
+                Ok((code, format!("({}{})", op.symbol(), value)))
+            }
+            Expr::Call { name, args, .. } => {
+                self.expr_type(expr)?;
+                self.generate_call(name, args)
+            }
+            Expr::Index { target, index } => {
+                let element_type = self.expr_type(expr)?;
+                let target_type = self.expr_type(target)?;
+                let (target_code, c_target) = self.generate_value(target)?;
+                let (index_code, c_index) = self.generate_value(index)?;
+                if target_type == VarType::String {
+                    return Ok((target_code + &index_code, format!("qk_string_at({}, {})", c_target, c_index)));
+                }
+                if target_type == VarType::Bytes {
+                    return Ok((target_code + &index_code, format!("qk_bytes_at({}, {})", c_target, c_index)));
+                }
+                let slot = c_element(&element_type, &c_element_pointer(&target_type, &c_target, &c_index, false));
+                Ok((target_code + &index_code, slot))
+            }
+            // Integer в Float округляется к ближайшему представимому, Float в Integer —
+            // к нулю с проверкой диапазона, true и false становятся 1 и 0
+            Expr::Cast { value, target } => {
+                let value_type = self.expr_type(value)?;
+                cast_result_type(&value_type, target)?;
+                let (code, c_value) = self.generate_value(value)?;
+                let c_value = match (&value_type, target) {
+                    _ if value_type == *target => c_value,
+                    (VarType::Float, VarType::Integer) => format!("qk_float_to_int({})", c_value),
+                    _ => format!("(({}){})", c_type(target), c_value),
+                };
+                Ok((code, c_value))
+            }
+            // Открытое начало — 0, открытый конец рантайм берёт из длины
+            Expr::Slice { target, start, end } => {
+                let slice_type = self.expr_type(expr)?;
+                let (mut code, c_target) = self.generate_value(target)?;
+                let c_target = self.hoist_argument(target, &slice_type, c_target, &mut code);
+                let mut bounds = Vec::new();
+                for bound in [start, end] {
+                    match bound {
+                        Some(bound) => {
+                            let (setup, value) = self.generate_value(bound)?;
+                            code.push_str(&setup);
+                            bounds.push(self.hoist_argument(bound, &VarType::Integer, value, &mut code));
                         }
+                        None => bounds.push("0".to_string()),
                     }
+                }
+                let helper = if slice_type == VarType::String { "qk_string_slice" } else { "qk_array_slice" };
+                Ok((code, format!("{}({}, {}, {}, {})", helper, c_target, bounds[0], bounds[1], end.is_none())))
+            }
+            Expr::Field { target, field } => {
+                self.expr_type(expr)?;
+                let (code, c_target) = self.generate_expression(target)?;
+                Ok((code, format!("{}.{}", c_target, field)))
+            }
+            Expr::MethodCall { target, method, args, .. } => {
+                self.expr_type(expr)?;
+                self.generate_method_call(target, method, args)
+            }
+            Expr::Try(value) => self.generate_try(value),
+            Expr::Lambda { params, body } => self.generate_lambda(params, body),
+        }
+    }
+    
+    // value? при ошибке сразу выходит из функции. Ошибка возвращается как есть:
+    // Result с любым типом значения устроен в C одинаково
+    fn generate_try(&mut self, value: &Expr) -> Result<(String, String), CompileError> {
+        let value_type = self.expr_type(value)?;
+        let (ok_type, error_type) = try_result_type(&value_type)?;
+        match &self.return_type {
+            Some(Some(VarType::Result(_, expected))) if **expected == error_type => {}
+            Some(Some(VarType::Result(_, expected))) => return Err(CompileError::Type("E0101", format!(
+                "'?' cannot return an error of type {:?} from a function whose errors are {:?}",
+                error_type, expected
+            ))),
+            _ => return Err(CompileError::Compilation("E0499",
+                "'?' can only be used inside a function that returns a Result".to_string()
+            )),
+        }
+        
+        let (mut code, c_value) = self.generate_expression(value)?;
+        let temp_name = format!("temp_try_{}", self.next_id());
+        code.push_str(&format!(
+            "    qk_result {} = {};\n    if (!{}.ok) {{\n{}        qk_call_depth--;\n        return {};\n    }}\n",
+            temp_name, c_value, temp_name, indent(&(self.run_defers(0) + &self.leave_tries(0))), temp_name
+        ));
+        Ok((code, c_element(&ok_type, &format!("{}.value", temp_name))))
+    }
+    
+    // Все аргументы печатаются одним printf через пробел, в конце — перевод строки,
+    // кроме print и eprint
+    fn generate_echo(&mut self, name: &str, args: &[Expr]) -> Result<String, CompileError> {
+        let mut code = String::new();
+        let mut pieces = Vec::new();
+        
+        for arg in args {
+            if let Expr::Literal(Value::String(s)) = arg {
+                // Нулевой байт оборвал бы строку формата, такой литерал пишется fwrite с явной длиной
+                if s.contains('\0') {
+                    pieces.push(EchoPiece::Raw(format!("\"{}\"", escape_c_string(s)), s.len()));
                 } else {
-                    // Если переменная не найдена, предполагаем строку
-                    format!("    printf(\"%s\\n\", {});\n", var_name)
+                    pieces.push(EchoPiece::Format(escape_c_string(s).replace('%', "%%"), None));
                 }
+                continue;
             }
-            Expr::BinaryOp { left, op: BinOp::Add, right } => {
-                // Генерация кода для сложения строк
-                let (left_code, left_var) = self.generate_string_value(left, variables);
-                let (right_code, right_var) = self.generate_string_value(right, variables);
-                
-                let temp_name = format!("temp_concat_{}", self.temp_counter);
-                self.temp_counter += 1;
-                
-                format!("{}{}    char {}[256];\n    snprintf({}, sizeof({}), \"%s%s\", {}, {});\n    printf(\"%s\\n\", {});\n",
-                       left_code, right_code, temp_name, temp_name, temp_name, left_var, right_var, temp_name)
+            
+            let expr_type = self.expr_type(arg)?;
+            if !is_scalar(&expr_type) && expr_type != VarType::Bytes {
+                return Err(CompileError::Type("E0104", format!("cannot print {:?} values with {}", expr_type, name)));
             }
-            _ => {
-                String::from("    // Unsupported expression in echo\n")
+            let (setup, value) = self.generate_value(arg)?;
+            code.push_str(&setup);
+            let value = self.hoist_argument(arg, &expr_type, value, &mut code);
+            
+            let (spec, value) = match expr_type {
+                VarType::String | VarType::BigInt => ("%s", value),
+                VarType::Integer => ("%lld", value),
+                VarType::Float => ("%f", value),
+                VarType::Boolean => ("%s", format!("{} ? \"true\" : \"false\"", value)),
+                VarType::Bytes => ("%s", format!("qk_bytes_repr({})", value)),
+                VarType::Array(_) | VarType::Map(..) | VarType::Struct(_) | VarType::Enum(_) | VarType::Trait(_)
+                | VarType::Option(_) | VarType::None | VarType::Result(..) | VarType::Ok(_) | VarType::Err(_)
+                | VarType::Function(..) => {
+                    unreachable!()
+                }
+            };
+            pieces.push(EchoPiece::Format(spec.to_string(), Some(value)));
+        }
+        
+        let newline = if matches!(name, "print" | "eprint") { "" } else { "\\n" };
+        let stream = if matches!(name, "eprint" | "eprintln") { "stderr" } else { "stdout" };
+        let mut format = String::new();
+        let mut values = Vec::new();
+        for (i, piece) in pieces.into_iter().enumerate() {
+            if i > 0 {
+                format.push(' ');
+            }
+            match piece {
+                EchoPiece::Format(spec, value) => {
+                    format.push_str(&spec);
+                    values.extend(value);
+                }
+                EchoPiece::Raw(literal, length) => {
+                    code.push_str(&printf_call(stream, &format, &values));
+                    code.push_str(&format!("    fwrite({}, 1, {}, {});\n", literal, length, stream));
+                    format.clear();
+                    values.clear();
+                }
             }
         }
+        format.push_str(newline);
+        code.push_str(&printf_call(stream, &format, &values));
+        Ok(code)
     }
     
-    fn generate_string_value(&mut self, expr: &Expr, 
-                           variables: &std::collections::HashMap<String, VarType>) -> (String, String) {
+    fn generate_string_value(&mut self, expr: &Expr) -> Result<(String, String), CompileError> {
         match expr {
             Expr::Literal(Value::String(s)) => {
-                let escaped = s
-                    .replace('\\', "\\\\")
-                    .replace('\"', "\\\"")
-                    .replace('\n', "\\n")
-                    .replace('\t', "\\t")
-                    .replace('\r', "\\r");
-                (String::new(), format!("\"{}\"", escaped))
-            }
-            Expr::Variable(var_name) => {
-                if let Some(var_type) = variables.get(var_name) {
-                    match var_type {
-                        VarType::String => (String::new(), var_name.clone()),
-                        VarType::Integer => {
-                            let temp_name = format!("temp_int_{}", self.temp_counter);
-                            self.temp_counter += 1;
-                            (format!("    char {}[32];\n    snprintf({}, sizeof({}), \"%lld\", {});\n", 
-                                   temp_name, temp_name, temp_name, var_name),
-                             temp_name)
-                        }
-                        VarType::Float => {
-                            let temp_name = format!("temp_float_{}", self.temp_counter);
-                            self.temp_counter += 1;
-                            (format!("    char {}[32];\n    snprintf({}, sizeof({}), \"%f\", {});\n", 
-                                   temp_name, temp_name, temp_name, var_name),
-                             temp_name)
-                        }
-                        VarType::Boolean => {
-                            let temp_name = format!("temp_bool_{}", self.temp_counter);
-                            self.temp_counter += 1;
-                            (format!("    const char* {} = {} ? \"true\" : \"false\";\n", temp_name, var_name),
-                             temp_name)
-                        }
-                    }
-                } else {
-                    (String::new(), var_name.clone())
-                }
+                Ok((String::new(), format!("\"{}\"", escape_c_string(s))))
             }
-            Expr::BinaryOp { left, op: BinOp::Add, right } => {
-                // Генерация кода для сложения строк
-                let (left_code, left_var) = self.generate_string_value(left, variables);
-                let (right_code, right_var) = self.generate_string_value(right, variables);
-                
-                let temp_name = format!("temp_expr_{}", self.temp_counter);
-                self.temp_counter += 1;
-                
-                (format!("{}{}    char {}[256];\n    snprintf({}, sizeof({}), \"%s%s\", {}, {});\n",
-                       left_code, right_code, temp_name, temp_name, temp_name, left_var, right_var),
-                 temp_name)
+            Expr::BinaryOp { left, op: BinOp::Add, right, .. } if self.expr_type(expr)? == VarType::String => {
+                // Операнды вычисляются слева направо, нестроковый операнд приводится к строке
+                let (mut code, left_var) = self.generate_string_value(left)?;
+                let left_var = self.hoist_argument(left, &VarType::String, left_var, &mut code);
+                let (right_code, right_var) = self.generate_string_value(right)?;
+                code.push_str(&right_code);
+                Ok((code, format!("qk_string_concat({}, {})", left_var, right_var)))
             }
             _ => {
-                (String::from("    // Unsupported value\n"), String::from("\"\""))
+                let expr_type = self.expr_type(expr)?;
+                let (code, value) = self.generate_expression(expr)?;
+                
+                match expr_type {
+                    VarType::String | VarType::BigInt => Ok((code, value)),
+                    VarType::Bytes | VarType::Array(_) | VarType::Map(..) | VarType::Struct(_) | VarType::Enum(_) | VarType::Trait(_)
+                    | VarType::Option(_) | VarType::None | VarType::Result(..) | VarType::Ok(_) | VarType::Err(_)
+                    | VarType::Function(..) => {
+                        Err(CompileError::Type("E0104", format!("cannot convert {:?} to String", expr_type)))
+                    }
+                    VarType::Integer => Ok((code, format!("qk_string_from_int({})", value))),
+                    VarType::Float => Ok((code, format!("qk_string_from_float({})", value))),
+                    VarType::Boolean => Ok((code, format!("qk_string_from_bool({})", value))),
+                }
             }
         }
     }
@@ -230,28 +4105,102 @@ impl Compiler {
             Ok(())
         } else {
             let stderr = String::from_utf8_lossy(&output.stderr);
-            Err(CompileError::Compilation(format!("{}: {}", compiler, stderr)))
+            Err(CompileError::Compilation("E0599", format!("{}: {}", compiler, stderr)))
         }
     }
 
     fn detect_c_compiler(&self) -> Result<&'static str, CompileError> {
-        let compilers = if cfg!(target_os = "windows") {
-            ["gcc", "clang", "cl"]
-        } else {
-            ["gcc", "clang", "cc"]
+        let compilers: &[&'static str] = match self.target {
+            Some(target) => target.compilers(),
+            None if cfg!(target_os = "windows") => &["gcc", "clang", "cl"],
+            None => &["gcc", "clang", "cc"],
         };
 
-        for &compiler in &compilers {
+        for &compiler in compilers {
             if self.try_compiler(compiler, &["--version"]).is_ok() {
                 return Ok(compiler);
             }
         }
 
-        Err(CompileError::NoCompiler)
+        match self.target {
+            Some(target) if compilers.is_empty() => Err(CompileError::Compilation("E0504", format!(
+                "no C compiler can build for target {} from this system", target.triple
+            ))),
+            Some(target) => Err(CompileError::Compilation("E0504", format!(
+                "no C compiler for target {} found (tried {})", target.triple, compilers.join(", ")
+            ))),
+            None => Err(CompileError::NoCompiler),
+        }
+    }
+
+    fn builds_for_windows(&self) -> bool {
+        match self.target {
+            Some(target) => target.is_windows(),
+            None => cfg!(target_os = "windows"),
+        }
+    }
+
+    // Каждый тест выполняется со своим обработчиком ошибок: упавший тест не останавливает остальные
+    fn generate_test_runner(&mut self, program: &Program) -> String {
+        let mut code = String::from("    int qk_tests_failed = 0;\n");
+        let tests = attributes::test_functions(program);
+        for name in &tests {
+            let target = format!("temp_test_{}", self.next_id());
+            code.push_str(&format!("    jmp_buf {};\n    qk_catch_target = &{};\n", target, target));
+            code.push_str(&format!("    if (setjmp({}) == 0) {{\n        {}();\n", target, c_function_name(name)));
+            code.push_str(&format!("        printf(\"test {} ... ok\\n\");\n", name));
+            code.push_str("    } else {\n        qk_call_depth = 0;\n        qk_tests_failed++;\n");
+            code.push_str(&format!("        printf(\"test {} ... FAILED: %s\\n\", qk_error_message);\n", name));
+            code.push_str("    }\n    qk_catch_target = NULL;\n");
+        }
+        code.push_str(&format!(
+            "    printf(\"\\n%d passed, %d failed\\n\", {} - qk_tests_failed, qk_tests_failed);\n", tests.len()
+        ));
+        code.push_str("    return qk_tests_failed == 0 ? 0 : 1;\n");
+        code
+    }
+
+    pub fn set_target(&mut self, target: &'static Target) {
+        self.target = Some(target);
+    }
+
+    // quark test: та же программа, но main запускает функции #[test]
+    pub fn compile_tests_to_exe(&mut self, program: &Program, output_path: &str) -> Result<(), CompileError> {
+        self.tests = true;
+        let result = self.compile_to_exe(program, output_path);
+        self.tests = false;
+        result
+    }
+
+    // Типы выражений проверяются по ходу генерации C, поэтому другие бэкенды
+    // проходят ту же генерацию и отбрасывают результат
+    pub fn check(&mut self, program: &Program) -> Result<(), Vec<CompileError>> {
+        self.generate_all(program).map(|_| ())
     }
 
     pub fn compile_to_exe(&mut self, program: &Program, output_path: &str) -> Result<(), CompileError> {
-        let c_code = self.generate_c_code(program);
+        self.build(program, output_path, Output::Executable)
+    }
+
+    // quark build -S: ассемблер, который компилятор C получает из сгенерированного кода
+    pub fn compile_to_assembly(&mut self, program: &Program, output_path: &str) -> Result<(), CompileError> {
+        self.build(program, output_path, Output::Assembly)
+    }
+
+    // --emit c: сгенерированный C без вызова компилятора, для систем, где quark не может
+    // его найти или выбрать сам; собирается любым компилятором C99 с -lm
+    pub fn compile_to_c(&mut self, program: &Program, output_path: &str) -> Result<(), CompileError> {
+        let c_code = self.generate_c_code(program)?;
+        fs::write(output_path, c_code).map_err(|e| CompileError::Io(format!("cannot write '{}': {}", output_path, e)))
+    }
+
+    // quark build -c: объектный файл без компоновки, чтобы собрать его вместе с другими
+    pub fn compile_to_object(&mut self, program: &Program, output_path: &str) -> Result<(), CompileError> {
+        self.build(program, output_path, Output::Object)
+    }
+
+    fn build(&mut self, program: &Program, output_path: &str, output: Output) -> Result<(), CompileError> {
+        let c_code = self.generate_c_code(program)?;
         let c_file = "quark_temp.c";
         
         // Отладка: показать сгенерированный C код
@@ -262,14 +4211,27 @@ impl Compiler {
 
         let compiler = self.detect_c_compiler()?;
 
-        let args: Vec<&str> = match compiler {
-            "cl" => vec![c_file, "/Fe:", output_path, "/nologo", "/O2"],
-            _ => vec![c_file, "-o", output_path, "-O2", "-s"],
+        let assembly_file = format!("/Fa{}", output_path);
+        let mut args: Vec<&str> = match (compiler, output) {
+            ("cl", Output::Executable) => vec![c_file, "/Fe:", output_path, "/nologo", "/O2"],
+            // /c не даёт компоновать; объектный файл cl всё равно пишет рядом и удаляется ниже
+            ("cl", Output::Assembly) => vec![c_file, "/c", "/FA", &assembly_file, "/nologo", "/O2"],
+            ("cl", Output::Object) => vec![c_file, "/c", "/Fo:", output_path, "/nologo", "/O2"],
+            (_, Output::Executable) => vec![c_file, "-o", output_path, "-O2", "-s", "-lm"],
+            (_, Output::Assembly) => vec![c_file, "-S", "-o", output_path, "-O2"],
+            (_, Output::Object) => vec![c_file, "-c", "-o", output_path, "-O2"],
         };
+        // Сокетам на Windows нужна отдельная библиотека; cl подключает её через #pragma
+        if output == Output::Executable && self.builds_for_windows() && compiler != "cl" && c_code.contains("qk_socket") {
+            args.push("-lws2_32");
+        }
 
         self.try_compiler(compiler, &args)?;
 
         let _ = fs::remove_file(c_file);
+        if compiler == "cl" && output == Output::Assembly {
+            let _ = fs::remove_file("quark_temp.obj");
+        }
         
         Ok(())
     }
@@ -0,0 +1,142 @@
+use crate::lexer::Span;
+use crate::locale::translate;
+
+use std::env;
+use std::io::IsTerminal;
+use std::path::Path;
+
+// Сообщение об ошибке в стиле rustc: заголовок, место в файле и строка исходника,
+// в которой ошибочный участок подчёркнут, а под ней — заметки и советы
+pub struct Diagnostic<'a> {
+    pub code: &'a str,
+    pub message: &'a str,
+    pub path: &'a Path,
+    pub source: &'a str,
+    pub span: Span,
+    pub notes: &'a [Note],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoteKind {
+    Note,
+    Help,
+}
+
+impl NoteKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            NoteKind::Note => "note",
+            NoteKind::Help => "help",
+        }
+    }
+}
+
+// Заметка со своим местом (например, первое объявление имени) или совет;
+// совет с replacement показывает строку, в которой участок span заменён этим текстом
+#[derive(Debug, Clone)]
+pub struct Note {
+    pub kind: NoteKind,
+    pub message: String,
+    pub span: Option<Span>,
+    pub replacement: Option<String>,
+}
+
+impl Note {
+    pub fn located(message: String, span: Span) -> Note {
+        Note { kind: NoteKind::Note, message, span: Some(span), replacement: None }
+    }
+
+    pub fn help(message: String) -> Note {
+        Note { kind: NoteKind::Help, message, span: None, replacement: None }
+    }
+
+    // Пустой span — вставка перед ним
+    pub fn suggestion(message: String, span: Span, replacement: String) -> Note {
+        Note { kind: NoteKind::Help, message, span: Some(span), replacement: Some(replacement) }
+    }
+}
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const GREEN: &str = "\x1b[1;32m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+// Цвета только для терминала; NO_COLOR отключает их и там
+pub fn use_color() -> bool {
+    env::var_os("NO_COLOR").is_none() && std::io::stderr().is_terminal()
+}
+
+impl Diagnostic<'_> {
+    pub fn render(&self, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("{}{}{}", style, text, RESET)
+            } else {
+                text.to_string()
+            }
+        };
+        // Номера строк всех фрагментов выравниваются по самому длинному
+        let widest = self.notes.iter().filter_map(|note| note.span).map(|span| span.line).fold(self.span.line, usize::max);
+        let gutter = " ".repeat(widest.to_string().len());
+        let bar = paint(BLUE, "|");
+        let location = |span: Span| format!("\n{}{} {}:{}:{}", gutter, paint(BLUE, "-->"), self.path.display(), span.line, span.column);
+        let snippet = |span: Span, line: &str, marks: String| {
+            let number = paint(BLUE, &format!("{:>width$}", span.line, width = gutter.len()));
+            format!("\n{} {}\n{} {} {}\n{} {} {}{}", gutter, bar, number, bar, line, gutter, bar, indent(line, span.column), marks)
+        };
+
+        let mut rendered = format!(
+            "{}{}",
+            paint(RED, &format!("{}[{}]", translate("error"), self.code)),
+            paint(BOLD, &format!(": {}", translate(self.message)))
+        );
+        match self.line(self.span) {
+            Some(line) => {
+                rendered += &location(self.span);
+                rendered += &snippet(self.span, line, paint(RED, &"^".repeat(width(line, self.span))));
+            }
+            // Строки нет — место неизвестно, выводим только имя файла
+            None => rendered += &format!("\n {} {}", paint(BLUE, "-->"), self.path.display()),
+        }
+
+        for note in self.notes {
+            let header = paint(BOLD, &format!("{}: {}", translate(note.kind.label()), translate(&note.message)));
+            let place = note.span.and_then(|span| self.line(span).map(|line| (span, line)));
+            match (place, &note.replacement) {
+                // Замена показывается только в пределах одной строки
+                (Some((span, line)), Some(replacement)) if span.end_line == span.line => {
+                    let before: String = line.chars().take(span.column - 1).collect();
+                    let after: String = line.chars().skip(span.end_column - 1).collect();
+                    let mark = if span.start == span.end { "+" } else { "~" };
+                    let marks = paint(GREEN, &mark.repeat(replacement.chars().count().max(1)));
+                    rendered += &format!("\n{}", header);
+                    rendered += &snippet(span, &format!("{}{}{}", before, replacement, after), marks);
+                }
+                (Some((span, line)), _) => {
+                    rendered += &format!("\n{}", header);
+                    rendered += &location(span);
+                    rendered += &snippet(span, line, paint(BLUE, &"-".repeat(width(line, span))));
+                }
+                (None, _) => rendered += &format!("\n{} {} {}", gutter, paint(BLUE, "="), header),
+            }
+        }
+        rendered
+    }
+
+    // split, а не lines: после последнего \n тоже есть строка, на ней стоит EOF
+    fn line(&self, span: Span) -> Option<&str> {
+        self.source.split('\n').nth(span.line.wrapping_sub(1)).map(|line| line.trim_end_matches('\r'))
+    }
+}
+
+// Отступ под подчёркиванием повторяет табуляции строки, чтобы знаки встали под нужный символ
+fn indent(line: &str, column: usize) -> String {
+    line.chars().take(column.saturating_sub(1)).map(|ch| if ch == '\t' { '\t' } else { ' ' }).collect()
+}
+
+// Участок на нескольких строках подчёркивается до конца первой
+fn width(line: &str, span: Span) -> usize {
+    let end_column = if span.end_line == span.line { span.end_column } else { line.chars().count() + 1 };
+    end_column.saturating_sub(span.column).max(1)
+}
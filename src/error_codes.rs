@@ -0,0 +1,542 @@
+// Постоянные коды ошибок и их подробные описания для quark explain.
+// Код ставится там, где ошибка создаётся, в её поле code
+pub struct ErrorCode {
+    pub code: &'static str,
+    pub title: &'static str,
+    pub explanation: &'static str,
+}
+
+// E00xx — запись токенов, E01xx — типы, E02xx — синтаксис,
+// E03xx — имена и объявления, E04xx — прочие правила программы, E05xx — генерация кода
+pub const CODES: &[ErrorCode] = &[
+    ErrorCode {
+        code: "E0001",
+        title: "unterminated string",
+        explanation: "\
+A string literal reaches the end of the line or file without its closing quote.
+
+Erroneous example:
+
+    String name = \"quark;
+
+Close the string on the same line:
+
+    String name = \"quark\";
+
+Raw strings (r\"...\") and byte strings (b\"...\") must be closed the same way.",
+    },
+    ErrorCode {
+        code: "E0002",
+        title: "unterminated block comment",
+        explanation: "\
+A /* comment is never closed with */, so the rest of the file is part of the comment.
+
+Erroneous example:
+
+    /* helper for the parser
+    func helper() {}
+
+Close the comment:
+
+    /* helper for the parser */
+    func helper() {}",
+    },
+    ErrorCode {
+        code: "E0003",
+        title: "invalid escape sequence",
+        explanation: "\
+A backslash in a string starts an escape sequence, and this one is not known.
+
+Erroneous example:
+
+    println(\"C:\\quark\");
+
+Known escapes are \\n, \\t, \\r, \\0, \\\\, \\\", \\xNN and \\u{NNNN}. Double the
+backslash to write it literally:
+
+    println(\"C:\\\\quark\");",
+    },
+    ErrorCode {
+        code: "E0004",
+        title: "invalid number literal",
+        explanation: "\
+A number literal has a digit that does not belong to its base, a misplaced '_'
+separator, or a value that does not fit in Integer.
+
+Erroneous examples:
+
+    Integer mask = 0b102;
+    Integer big = 1__000;
+    Integer huge = 99999999999999999999;
+
+Separators must stand between digits, and values beyond the Integer range
+need the BigInt type:
+
+    Integer mask = 0b101;
+    Integer big = 1_000;
+    BigInt huge = 99999999999999999999;",
+    },
+    ErrorCode {
+        code: "E0005",
+        title: "invalid string interpolation",
+        explanation: "\
+An interpolated string contains a {...} part that is empty, unclosed or not a
+single expression.
+
+Erroneous example:
+
+    println(\"total: {}\");
+
+Put an expression between the braces:
+
+    println(\"total: {count + 1}\");",
+    },
+    ErrorCode {
+        code: "E0006",
+        title: "invalid character",
+        explanation: "\
+The source contains a character that is not part of any token, such as $ or @
+outside of a string.
+
+Erroneous example:
+
+    Integer price = 10 $ 2;
+
+Remove the character or put it inside a string literal.",
+    },
+    ErrorCode {
+        code: "E0007",
+        title: "non-ASCII character in a byte string",
+        explanation: "\
+Byte strings hold raw bytes, so only ASCII characters can be written in them
+directly.
+
+Erroneous example:
+
+    Bytes data = b\"ключ\";
+
+Write other bytes with \\xNN escapes, or use a String:
+
+    Bytes data = b\"\\xD0\\xBA\";",
+    },
+    ErrorCode {
+        code: "E0099",
+        title: "invalid token",
+        explanation: "\
+The lexer could not turn this part of the source into a token. The message
+describes what was expected at this place.",
+    },
+    ErrorCode {
+        code: "E0101",
+        title: "type mismatch",
+        explanation: "\
+A value of one type is used where another type is required: in a declaration,
+an assignment, a function argument or an operator.
+
+Erroneous example:
+
+    Integer count = \"three\";
+
+Use a value of the declared type, or convert it explicitly:
+
+    Integer count = 3;
+    String label = to_string(count);",
+    },
+    ErrorCode {
+        code: "E0102",
+        title: "wrong number of arguments",
+        explanation: "\
+A function, builtin or macro is called with more or fewer arguments than it
+declares.
+
+Erroneous example:
+
+    func add(Integer a, Integer b) -> Integer {
+        return a + b;
+    }
+
+    Integer sum = add(1);
+
+Pass one argument for every parameter:
+
+    Integer sum = add(1, 2);",
+    },
+    ErrorCode {
+        code: "E0103",
+        title: "cannot infer a type",
+        explanation: "\
+The type of a value cannot be worked out from the code, for example an empty
+array or map literal, or none, declared with let.
+
+Erroneous example:
+
+    let items = [];
+
+Declare the type explicitly:
+
+    Array<Integer> items = [];",
+    },
+    ErrorCode {
+        code: "E0104",
+        title: "operation not supported by this type",
+        explanation: "\
+The operation is not defined for values of this type: indexing a Boolean,
+iterating over an Integer, calling a method the type does not have, and so on.
+
+Erroneous example:
+
+    Integer n = 5;
+    for x in n {}
+
+Iterate over an array, a map or a String instead:
+
+    Array<Integer> items = [1, 2, 3];
+    for x in items {}",
+    },
+    ErrorCode {
+        code: "E0105",
+        title: "invalid cast",
+        explanation: "\
+'as' only converts Integer and Float into each other and Boolean into Integer.
+
+Erroneous example:
+
+    Integer n = \"42\" as Integer;
+
+Text is converted by builtins such as parse_int, which return a Result:
+
+    Result<Integer, String> n = parse_int(\"42\");",
+    },
+    ErrorCode {
+        code: "E0201",
+        title: "unexpected token",
+        explanation: "\
+The parser found a token that cannot appear at this place. The message names
+what was expected instead; most often it is a missing ')', ']' or '}'.
+
+Erroneous example:
+
+    Integer total = (1 + 2;
+
+Close the parenthesis:
+
+    Integer total = (1 + 2);",
+    },
+    ErrorCode {
+        code: "E0202",
+        title: "unexpected end of file",
+        explanation: "\
+The file ends in the middle of a statement, usually because a block is never
+closed.
+
+Erroneous example:
+
+    func main() {
+        println(\"hello\");
+
+Close every opened brace:
+
+    func main() {
+        println(\"hello\");
+    }",
+    },
+    ErrorCode {
+        code: "E0203",
+        title: "declaration not allowed here",
+        explanation: "\
+Functions, structs, enums, traits, impls, modules, macros and imports can only
+be declared at the top level of a file, and imports must come first.
+
+Erroneous example:
+
+    func main() {
+        struct Point { Integer x; Integer y; }
+    }
+
+Move the declaration out of the function:
+
+    struct Point { Integer x; Integer y; }
+
+    func main() {}",
+    },
+    ErrorCode {
+        code: "E0204",
+        title: "invalid attribute",
+        explanation: "\
+An attribute is unknown, repeated, given wrong arguments or attached to an item
+it does not apply to.
+
+Erroneous example:
+
+    #[tset]
+    func check_sum() {}
+
+Use one of the known attributes:
+
+    #[test]
+    func check_sum() {}",
+    },
+    ErrorCode {
+        code: "E0205",
+        title: "invalid macro",
+        explanation: "\
+A macro is defined twice, not closed, or called with a different number of
+arguments than it declares.
+
+Erroneous example:
+
+    macro log_twice(x) {
+        println(x);
+        println(x);
+    }
+
+    log_twice(\"hi\", \"there\");
+
+Pass one argument for every macro parameter:
+
+    log_twice(\"hi\");",
+    },
+    ErrorCode {
+        code: "E0206",
+        title: "program nested too deeply",
+        explanation: "\
+Expressions and blocks are nested deeper than the parser supports. Split the
+expression into several variables or move inner blocks into functions.",
+    },
+    ErrorCode {
+        code: "E0299",
+        title: "invalid syntax",
+        explanation: "\
+The code does not follow the grammar of Quark. The message describes the rule
+that was broken at this place.",
+    },
+    ErrorCode {
+        code: "E0301",
+        title: "undeclared variable",
+        explanation: "\
+A variable is used before it is declared, or it is not declared at all in any
+enclosing scope.
+
+Erroneous example:
+
+    println(total);
+    Integer total = 0;
+
+Declare the variable first:
+
+    Integer total = 0;
+    println(total);",
+    },
+    ErrorCode {
+        code: "E0302",
+        title: "unknown function",
+        explanation: "\
+The called function is neither declared in the program nor a builtin.
+
+Erroneous example:
+
+    printline(\"hello\");
+
+Check the spelling, or declare the function:
+
+    println(\"hello\");",
+    },
+    ErrorCode {
+        code: "E0303",
+        title: "unknown type, field or member",
+        explanation: "\
+A struct, enum or trait name, a field, a variant or a method does not exist.
+
+Erroneous example:
+
+    struct Point { Integer x; Integer y; }
+
+    Point p = Point { x: 1, y: 2 };
+    println(p.z);
+
+Use a member the type declares:
+
+    println(p.x);",
+    },
+    ErrorCode {
+        code: "E0304",
+        title: "duplicate definition",
+        explanation: "\
+The same name is defined twice where names must be unique: in one scope, among
+the fields of a struct, the variants of an enum or the parameters of a function.
+
+Erroneous example:
+
+    Integer count = 0;
+    Integer count = 1;
+
+Give the second definition another name, or assign instead:
+
+    mut Integer count = 0;
+    count = 1;",
+    },
+    ErrorCode {
+        code: "E0305",
+        title: "reserved keyword used as a name",
+        explanation: "\
+Keywords cannot be used as names of variables, functions or types.
+
+Erroneous example:
+
+    Integer match = 1;
+
+Pick another name:
+
+    Integer matches = 1;",
+    },
+    ErrorCode {
+        code: "E0401",
+        title: "assignment to something that cannot change",
+        explanation: "\
+The target of the assignment is a constant, a field of a constant, a character
+of a String, a slice or the result of a call.
+
+Erroneous example:
+
+    const Integer LIMIT = 10;
+    LIMIT = 20;
+
+Declare a mutable variable instead:
+
+    mut Integer limit = 10;
+    limit = 20;",
+    },
+    ErrorCode {
+        code: "E0402",
+        title: "jump outside of a loop",
+        explanation: "\
+break and continue need an enclosing loop, a label must refer to one, and no
+jump can leave a deferred statement.
+
+Erroneous example:
+
+    func main() {
+        break;
+    }
+
+Use break only inside loop, while or for:
+
+    func main() {
+        loop {
+            break;
+        }
+    }",
+    },
+    ErrorCode {
+        code: "E0403",
+        title: "function does not return a value",
+        explanation: "\
+A function or method without a return type is used as a value.
+
+Erroneous example:
+
+    func greet() {
+        println(\"hi\");
+    }
+
+    String text = greet();
+
+Declare the return type and return a value:
+
+    func greet() -> String {
+        return \"hi\";
+    }",
+    },
+    ErrorCode {
+        code: "E0404",
+        title: "invalid trait implementation",
+        explanation: "\
+An impl misses a method of its trait, defines a method the trait does not
+have, or implements the same trait twice for one type.
+
+Erroneous example:
+
+    trait Shape {
+        func area(self) -> Float;
+    }
+
+    struct Square { Float side; }
+
+    impl Shape for Square {}
+
+Implement every method of the trait:
+
+    impl Shape for Square {
+        func area(self) -> Float {
+            return self.side * self.side;
+        }
+    }",
+    },
+    ErrorCode {
+        code: "E0499",
+        title: "invalid program",
+        explanation: "\
+The program breaks a rule of the language that has no more specific code. The
+message describes the rule.",
+    },
+    ErrorCode {
+        code: "E0501",
+        title: "division by zero",
+        explanation: "\
+An Integer is divided by the constant 0, which would fail every time the
+program runs.
+
+Erroneous example:
+
+    Integer half = total / 0;
+
+Divide by a value that cannot be zero, or check it first.",
+    },
+    ErrorCode {
+        code: "E0502",
+        title: "integer overflow in a constant expression",
+        explanation: "\
+An expression of literals produces a value outside the Integer range.
+
+Erroneous example:
+
+    Integer big = 9223372036854775807 + 1;
+
+Use BigInt for values that do not fit in 64 bits.",
+    },
+    ErrorCode {
+        code: "E0503",
+        title: "shift amount out of range",
+        explanation: "\
+An Integer can only be shifted by 0 to 63 bits.
+
+Erroneous example:
+
+    Integer x = 1 << 64;
+
+Use a shift amount in range:
+
+    Integer x = 1 << 63;",
+    },
+    ErrorCode {
+        code: "E0504",
+        title: "no C compiler found",
+        explanation: "\
+Quark translates programs to C and needs gcc, clang or cc (cl on Windows) to
+build them. Install one of them and make sure it is on PATH.
+
+Building for another system with --target needs a cross compiler for that
+system, such as x86_64-w64-mingw32-gcc for x86_64-pc-windows-gnu.",
+    },
+    ErrorCode {
+        code: "E0599",
+        title: "code generation failed",
+        explanation: "\
+The program passed all checks but could not be translated to C or built by the
+C compiler. The message contains the output of the C compiler.",
+    },
+];
+
+pub fn lookup(code: &str) -> Option<&'static ErrorCode> {
+    CODES.iter().find(|entry| entry.code.eq_ignore_ascii_case(code))
+}
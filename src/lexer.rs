@@ -3,43 +3,242 @@ pub enum Token {
     // Базовые токены
     Ident(String),
     StringLiteral(String),
+    // b"..." — произвольные байты, не обязательно UTF-8
+    BytesLiteral(Vec<u8>),
+    // Строка с выражениями в фигурных скобках: "Hello, {name}!"
+    InterpolatedString(Vec<StringPart>),
     NumberLiteral(String),
     LParen,
     RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    FatArrow,
+    Underscore,
     Semicolon,
+    Colon,
+    ColonColon,
+    Comma,
+    Question,
+    QuestionQuestion,
+    Dot,
+    DotDot,
+    Ellipsis,
+    Arrow,
     Equals,
     Plus,
+    Minus,
+    PlusPlus,
+    MinusMinus,
+    Star,
+    Slash,
+    Percent,
+    EqualEqual,
+    NotEqual,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    // # в начале атрибута #[...]
+    Hash,
+    ShiftLeft,
+    ShiftRight,
     EOF,
     Illegal(char),
+    // Перевод строки: парсер считает его концом инструкции вместо ;
+    Newline,
+    // Строка документации /// без самих слэшей; парсер прикрепляет её к следующему объявлению
+    DocComment(String),
     
     // Ключевые слова типов
     StringType,
     IntegerType,
     FloatType,
     BooleanType,
+    BigIntType,
+    BytesType,
+    ArrayType,
+    MapType,
+    ResultType,
     
     // Булевы литералы
     True,
     False,
+    // Отсутствующее значение необязательного типа
+    None,
+    
+    // Ключевые слова
+    Match,
+    For,
+    In,
+    Loop,
+    While,
+    Break,
+    Continue,
+    Func,
+    Return,
+    Const,
+    Let,
+    Struct,
+    Enum,
+    Trait,
+    Impl,
+    Try,
+    Catch,
+    Import,
+    Module,
+    As,
+    Mut,
+    Defer,
+    Macro,
+
+    // Зарезервированные на будущее слова
+    Reserved(String),
+}
+
+pub const RESERVED_WORDS: &[&str] = &["if"];
+
+impl Token {
+    pub fn keyword(&self) -> Option<&str> {
+        match self {
+            Token::StringType => Some("String"),
+            Token::IntegerType => Some("Integer"),
+            Token::FloatType => Some("Float"),
+            Token::BooleanType => Some("Boolean"),
+            Token::BigIntType => Some("BigInt"),
+            Token::BytesType => Some("Bytes"),
+            Token::ArrayType => Some("Array"),
+            Token::MapType => Some("Map"),
+            Token::ResultType => Some("Result"),
+            Token::True => Some("true"),
+            Token::False => Some("false"),
+            Token::None => Some("none"),
+            Token::Match => Some("match"),
+            Token::For => Some("for"),
+            Token::In => Some("in"),
+            Token::Loop => Some("loop"),
+            Token::While => Some("while"),
+            Token::Break => Some("break"),
+            Token::Continue => Some("continue"),
+            Token::Func => Some("func"),
+            Token::Return => Some("return"),
+            Token::Const => Some("const"),
+            Token::Let => Some("let"),
+            Token::Struct => Some("struct"),
+            Token::Enum => Some("enum"),
+            Token::Trait => Some("trait"),
+            Token::Impl => Some("impl"),
+            Token::Try => Some("try"),
+            Token::Catch => Some("catch"),
+            Token::Import => Some("import"),
+            Token::Module => Some("module"),
+            Token::As => Some("as"),
+            Token::Mut => Some("mut"),
+            Token::Defer => Some("defer"),
+            Token::Macro => Some("macro"),
+            Token::Reserved(word) => Some(word),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    Text(String),
+    // Токены выражения внутри {}, заканчиваются EOF
+    Code(Vec<SpannedToken>),
+}
+
+// Место токена в исходнике: байтовые смещения [start, end) и строка со столбцом
+// (с единицы, столбец — в символах) начала и конца
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Span {
+    // Место токена из подстроки, которая в исходнике начинается в base, на той же строке
+    fn within(&self, base: &Span) -> Span {
+        Span {
+            start: base.start + self.start,
+            end: base.start + self.end,
+            line: base.line + self.line - 1,
+            column: base.column + self.column - 1,
+            end_line: base.line + self.end_line - 1,
+            end_column: base.column + self.end_column - 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct LexError {
+    pub code: &'static str,
     pub message: String,
     pub position: usize,
 }
 
-pub struct Lexer {
-    input: Vec<char>,
+// Идентификаторы в духе UAX #31: начинаются с буквы любого алфавита или _,
+// дальше допускаются цифры, комбинируемые диакритические знаки и ZWNJ/ZWJ
+pub fn is_ident_start(ch: char) -> bool {
+    ch.is_alphabetic() || ch == '_'
+}
+
+pub fn is_ident_continue(ch: char) -> bool {
+    ch.is_alphanumeric()
+        || ch == '_'
+        || matches!(ch, '\u{0300}'..='\u{036F}' | '\u{1AB0}'..='\u{1AFF}' | '\u{1DC0}'..='\u{1DFF}'
+            | '\u{20D0}'..='\u{20FF}' | '\u{FE20}'..='\u{FE2F}' | '\u{200C}' | '\u{200D}')
+}
+
+// Запись числа в десятичной системе; длина не ограничена, чтобы большие литералы годились для BigInt
+fn to_decimal(digits: &str, radix: u32) -> String {
+    // Десятичные цифры от младшей к старшей
+    let mut decimal: Vec<u32> = vec![0];
+    for ch in digits.chars() {
+        let mut carry = ch.to_digit(radix).unwrap();
+        for digit in decimal.iter_mut() {
+            let value = *digit * radix + carry;
+            *digit = value % 10;
+            carry = value / 10;
+        }
+        while carry > 0 {
+            decimal.push(carry % 10);
+            carry /= 10;
+        }
+    }
+    while decimal.len() > 1 && decimal.last() == Some(&0) {
+        decimal.pop();
+    }
+    decimal.iter().rev().map(|d| char::from_digit(*d, 10).unwrap()).collect()
+}
+
+pub struct Lexer<'a> {
+    input: &'a str,
     position: usize,
     line: usize,
     column: usize,
 }
 
-impl Lexer {
-    pub fn new(input: &str) -> Self {
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
         Self {
-            input: input.chars().collect(),
+            input,
             position: 0,
             line: 1,
             column: 1,
@@ -47,7 +246,11 @@ impl Lexer {
     }
 
     fn peek(&self) -> Option<char> {
-        self.input.get(self.position).copied()
+        self.input[self.position..].chars().next()
+    }
+
+    fn peek_next(&self) -> Option<char> {
+        self.input[self.position..].chars().nth(1)
     }
 
     fn advance(&mut self) -> Option<char> {
@@ -59,11 +262,17 @@ impl Lexer {
             } else {
                 self.column += 1;
             }
-            self.position += 1;
+            self.position += c.len_utf8();
         }
         ch
     }
 
+    fn advance_by(&mut self, count: usize) {
+        for _ in 0..count {
+            self.advance();
+        }
+    }
+
     fn skip_whitespace(&mut self) {
         while let Some(ch) = self.peek() {
             if ch.is_whitespace() {
@@ -74,89 +283,282 @@ impl Lexer {
         }
     }
 
-    fn skip_comments(&mut self) {
+    fn skip_comments(&mut self) -> Result<(), LexError> {
         while let Some('/') = self.peek() {
-            if let Some('/') = self.input.get(self.position + 1) {
-                // Пропускаем комментарий
-                self.advance(); // /
-                self.advance(); // /
-                
-                while let Some(ch) = self.peek() {
-                    if ch == '\n' {
-                        break;
+            match self.peek_next() {
+                // /// — документация, а не комментарий; //// снова обычный комментарий
+                Some('/') if self.at_doc_comment() => break,
+                Some('/') => {
+                    // Пропускаем комментарий
+                    self.advance(); // /
+                    self.advance(); // /
+
+                    while let Some(ch) = self.peek() {
+                        if ch == '\n' {
+                            break;
+                        }
+                        self.advance();
                     }
-                    self.advance();
                 }
-                
-                // Пропускаем пробелы после комментария
-                self.skip_whitespace();
-            } else {
+                Some('*') => self.skip_block_comment()?,
+                _ => break,
+            }
+
+            // Пропускаем пробелы после комментария
+            self.skip_whitespace();
+        }
+        Ok(())
+    }
+
+    fn at_doc_comment(&self) -> bool {
+        let rest = &self.input[self.position..];
+        rest.starts_with("///") && !rest.starts_with("////")
+    }
+
+    fn read_doc_comment(&mut self) -> Token {
+        self.advance_by(3); // ///
+        let start = self.position;
+        while let Some(ch) = self.peek() {
+            if ch == '\n' {
                 break;
             }
+            self.advance();
+        }
+        let text = self.input[start..self.position].trim_end();
+        Token::DocComment(text.strip_prefix(' ').unwrap_or(text).to_string())
+    }
+
+    // /* ... /* ... */ ... */ — комментарии вкладываются, чтобы можно было закомментировать код с комментариями
+    fn skip_block_comment(&mut self) -> Result<(), LexError> {
+        // Начала ещё не закрытых комментариев, от внешнего к внутреннему
+        let mut open = vec![self.position];
+        self.advance_by(2); // /*
+
+        while let Some(ch) = self.peek() {
+            if ch == '/' && self.peek_next() == Some('*') {
+                open.push(self.position);
+                self.advance_by(2);
+            } else if ch == '*' && self.peek_next() == Some('/') {
+                self.advance_by(2);
+                open.pop();
+                if open.is_empty() {
+                    return Ok(());
+                }
+            } else {
+                self.advance();
+            }
         }
+
+        Err(LexError {
+            code: "E0002",
+            message: "Unterminated block comment".to_string(),
+            position: *open.last().unwrap(),
+        })
     }
 
-    fn read_ident(&mut self) -> String {
-        let mut ident = String::new();
+    fn read_ident(&mut self) -> &'a str {
+        let start = self.position;
         while let Some(ch) = self.peek() {
-            if ch.is_alphanumeric() || ch == '_' {
-                ident.push(ch);
+            if is_ident_continue(ch) {
                 self.advance();
             } else {
                 break;
             }
         }
-        ident
+        &self.input[start..self.position]
     }
 
-    fn read_number(&mut self) -> String {
-        let mut number = String::new();
-        let mut has_dot = false;
+    // Выражение до парной } с учётом вложенных скобок и строк
+    fn read_interpolation(&mut self) -> Result<Vec<SpannedToken>, LexError> {
+        let open = self.position;
+        self.advance(); // {
+        let start = self.position;
+        let base = Span { start, end: start, line: self.line, column: self.column, end_line: self.line, end_column: self.column };
+        let mut depth = 0;
+        let mut in_string = false;
         
+        loop {
+            match self.peek() {
+                None | Some('\n') => {
+                    return Err(LexError {
+                        code: "E0005",
+                        message: "Unclosed '{' in string interpolation".to_string(),
+                        position: open,
+                    });
+                }
+                Some('\\') if in_string => {
+                    self.advance();
+                }
+                Some('"') => in_string = !in_string,
+                Some('{') if !in_string => depth += 1,
+                Some('}') if !in_string && depth == 0 => break,
+                Some('}') if !in_string => depth -= 1,
+                _ => {}
+            }
+            self.advance();
+        }
+        
+        let code = &self.input[start..self.position];
+        self.advance(); // }
+        if code.trim().is_empty() {
+            return Err(LexError {
+                code: "E0005",
+                message: "Empty expression in string interpolation".to_string(),
+                position: open,
+            });
+        }
+        
+        let tokens = Lexer::new(code).tokenize().map_err(|mut errors| {
+            let e = errors.swap_remove(0);
+            LexError {
+                code: e.code,
+                message: e.message,
+                position: start + e.position,
+            }
+        })?;
+        Ok(tokens.into_iter().map(|t| SpannedToken { token: t.token, span: t.span.within(&base) }).collect())
+    }
+
+    // 0xFF, 0o755 и 0b1010 сразу переводятся в десятичную запись, поэтому
+    // парсер, как и для обычных чисел, получает строку десятичных цифр
+    fn read_number(&mut self) -> Result<String, LexError> {
+        let radix = match (self.peek(), self.peek_next()) {
+            (Some('0'), Some('x')) => Some((16, "hexadecimal")),
+            (Some('0'), Some('o')) => Some((8, "octal")),
+            (Some('0'), Some('b')) => Some((2, "binary")),
+            _ => None,
+        };
+        match radix {
+            Some((radix, name)) => self.read_radix_number(radix, name),
+            None => self.read_decimal_number(),
+        }
+    }
+
+    fn read_radix_number(&mut self, radix: u32, name: &str) -> Result<String, LexError> {
+        let start = self.position;
+        self.advance_by(2); // 0x
+        let digits = self.read_digits(radix, name)?;
+        if let Some(ch) = self.peek().filter(|ch| ch.is_ascii_alphanumeric()) {
+            return Err(LexError {
+                code: "E0004",
+                message: format!("Invalid digit '{}' in {} literal", ch, name),
+                position: self.position,
+            });
+        }
+        if digits.is_empty() {
+            return Err(LexError {
+                code: "E0004",
+                message: format!("Missing digits in {} literal", name),
+                position: start,
+            });
+        }
+        Ok(to_decimal(&digits, radix))
+    }
+
+    fn read_decimal_number(&mut self) -> Result<String, LexError> {
+        let mut number = self.read_digits(10, "number")?;
+        if self.peek() == Some('.') && self.peek_next().is_some_and(|ch| ch.is_ascii_digit()) {
+            self.advance(); // .
+            number.push('.');
+            number.push_str(&self.read_digits(10, "number")?);
+        }
+        Ok(number)
+    }
+
+    // Цифры без разделителей: 1_000_000 даёт 1000000. Подчёркивание допустимо
+    // только между двумя цифрами
+    fn read_digits(&mut self, radix: u32, name: &str) -> Result<String, LexError> {
+        let mut digits = String::new();
         while let Some(ch) = self.peek() {
-            if ch.is_ascii_digit() {
-                number.push(ch);
-                self.advance();
-            } else if ch == '.' && !has_dot {
-                if let Some(next) = self.input.get(self.position + 1) {
-                    if next.is_ascii_digit() {
-                        number.push(ch);
-                        self.advance();
-                        has_dot = true;
-                    } else {
-                        break;
-                    }
-                } else {
-                    break;
+            if ch == '_' {
+                if digits.is_empty() || !self.peek_next().is_some_and(|next| next.is_digit(radix)) {
+                    return Err(LexError {
+                        code: "E0004",
+                        message: format!("Misplaced '_' in {} literal: separators must stand between digits", name),
+                        position: self.position,
+                    });
                 }
+            } else if ch.is_digit(radix) {
+                digits.push(ch);
             } else {
                 break;
             }
+            self.advance();
         }
-        
-        number
+        Ok(digits)
     }
 
-    fn read_string(&mut self) -> Result<String, LexError> {
-        self.advance(); // Skip opening quote
+    // {{ и }} дают сами скобки, {выражение} становится отдельной частью строки.
+    // Строка в """ может занимать несколько строк, перевод строки сразу после
+    // открывающих кавычек в неё не входит
+    fn read_string(&mut self) -> Result<Token, LexError> {
+        let multiline = self.input[self.position..].starts_with("\"\"\"");
+        if multiline {
+            self.advance_by(3);
+            if let Some('\n') = self.peek() {
+                self.advance();
+            }
+        } else {
+            self.advance(); // Skip opening quote
+        }
         let mut string = String::new();
+        let mut parts = Vec::new();
         
         while let Some(ch) = self.peek() {
             match ch {
+                '"' if multiline && !self.input[self.position..].starts_with("\"\"\"") => {
+                    string.push(ch);
+                    self.advance();
+                }
                 '"' => {
-                    self.advance(); // Skip closing quote
-                    return Ok(string);
+                    self.advance_by(if multiline { 3 } else { 1 }); // Skip closing quotes
+                    if parts.is_empty() {
+                        return Ok(Token::StringLiteral(string));
+                    }
+                    if !string.is_empty() {
+                        parts.push(StringPart::Text(string));
+                    }
+                    return Ok(Token::InterpolatedString(parts));
+                }
+                '{' | '}' if self.peek_next() == Some(ch) => {
+                    string.push(ch);
+                    self.advance();
+                    self.advance();
+                }
+                '{' => {
+                    if !string.is_empty() {
+                        parts.push(StringPart::Text(std::mem::take(&mut string)));
+                    }
+                    parts.push(StringPart::Code(self.read_interpolation()?));
                 }
                 '\\' => {
                     self.advance(); // Skip backslash
-                    if let Some(escaped) = self.peek() {
+                    if let Some('u') = self.peek() {
+                        string.push(self.read_unicode_escape()?);
+                    } else if let Some('x') = self.peek() {
+                        let escape = self.position - 1;
+                        self.advance();
+                        let byte = self.read_hex_escape()?;
+                        // Один байт UTF-8 — только ASCII, остальные символы записываются через \u{...}
+                        if !byte.is_ascii() {
+                            return Err(LexError {
+                                code: "E0003",
+                                message: format!("\\x{:02X} is not an ASCII character; use \\u{{{:X}}} instead", byte, byte),
+                                position: escape,
+                            });
+                        }
+                        string.push(byte as char);
+                    } else if let Some(escaped) = self.peek() {
                         match escaped {
                             'n' => string.push('\n'),
                             't' => string.push('\t'),
                             'r' => string.push('\r'),
+                            // Строка рантайма заканчивается на первом нулевом байте
+                            '0' => string.push('\0'),
                             '"' => string.push('"'),
                             '\\' => string.push('\\'),
                             _ => return Err(LexError {
+                                code: "E0003",
                                 message: format!("Unknown escape sequence: \\{}", escaped),
                                 position: self.position,
                             }),
@@ -164,13 +566,15 @@ impl Lexer {
                         self.advance();
                     } else {
                         return Err(LexError {
+                            code: "E0003",
                             message: "Incomplete escape sequence".to_string(),
                             position: self.position,
                         });
                     }
                 }
-                '\n' => {
+                '\n' if !multiline => {
                     return Err(LexError {
+                        code: "E0001",
                         message: "Unclosed string".to_string(),
                         position: self.position,
                     });
@@ -183,17 +587,196 @@ impl Lexer {
         }
         
         Err(LexError {
+            code: "E0001",
             message: "Unterminated string constant".to_string(),
             position: self.position,
         })
     }
 
-    pub fn next_token(&mut self) -> Result<Token, LexError> {
+    // Две шестнадцатеричные цифры после \x; ошибка указывает на первую неподходящую
+    fn read_hex_escape(&mut self) -> Result<u8, LexError> {
+        let mut value = 0u8;
+        for _ in 0..2 {
+            match self.peek() {
+                Some(ch) if ch.is_ascii_hexdigit() => {
+                    value = value * 16 + ch.to_digit(16).unwrap() as u8;
+                    self.advance();
+                }
+                found => {
+                    return Err(LexError {
+                        code: "E0003",
+                        message: match found {
+                            Some(ch) => format!("\\x must be followed by two hex digits, found '{}'", ch),
+                            None => "\\x must be followed by two hex digits".to_string(),
+                        },
+                        position: self.position,
+                    })
+                }
+            }
+        }
+        Ok(value)
+    }
+
+    // \u{1F600}: от одной до шести шестнадцатеричных цифр, код символа Unicode
+    fn read_unicode_escape(&mut self) -> Result<char, LexError> {
+        let start = self.position - 1;
+        self.advance(); // u
+        if self.advance() != Some('{') {
+            return Err(LexError {
+                code: "E0003",
+                message: "Expected '{' after \\u".to_string(),
+                position: start,
+            });
+        }
+        
+        let digits_start = self.position;
+        while let Some(ch) = self.peek() {
+            if !ch.is_ascii_hexdigit() {
+                break;
+            }
+            self.advance();
+        }
+        let digits = &self.input[digits_start..self.position];
+        if self.advance() != Some('}') {
+            return Err(LexError {
+                code: "E0003",
+                message: "Unclosed \\u{...} escape".to_string(),
+                position: start,
+            });
+        }
+        if digits.is_empty() || digits.len() > 6 {
+            return Err(LexError {
+                code: "E0003",
+                message: format!("\\u{{{}}} must have from 1 to 6 hex digits", digits),
+                position: start,
+            });
+        }
+        
+        let code = u32::from_str_radix(digits, 16).unwrap();
+        match char::from_u32(code) {
+            // \u{0}, как и \0, обрывает строку рантайма
+            Some(ch) => Ok(ch),
+            None => Err(LexError {
+                code: "E0003",
+                message: format!("\\u{{{}}} is not a valid Unicode code point", digits),
+                position: start,
+            }),
+        }
+    }
+
+    // r"..." — экранирование и подстановки не обрабатываются
+    fn read_raw_string(&mut self) -> Result<Token, LexError> {
+        let start = self.position;
+        self.advance_by(2); // r"
+        let content_start = self.position;
+        while let Some(ch) = self.peek() {
+            match ch {
+                '"' => {
+                    let string = self.input[content_start..self.position].to_string();
+                    self.advance();
+                    return Ok(Token::StringLiteral(string));
+                }
+                '\n' => {
+                    return Err(LexError {
+                        code: "E0001",
+                        message: "Unclosed raw string".to_string(),
+                        position: start,
+                    });
+                }
+                _ => {
+                    self.advance();
+                }
+            }
+        }
+        
+        Err(LexError {
+            code: "E0001",
+            message: "Unterminated raw string".to_string(),
+            position: start,
+        })
+    }
+
+    // b"..." — только ASCII, остальные байты записываются как \xNN
+    fn read_byte_string(&mut self) -> Result<Token, LexError> {
+        let start = self.position;
+        self.advance_by(2); // b"
+        let mut bytes = Vec::new();
+        while let Some(ch) = self.peek() {
+            match ch {
+                '"' => {
+                    self.advance();
+                    return Ok(Token::BytesLiteral(bytes));
+                }
+                '\\' => {
+                    let escape = self.position;
+                    self.advance();
+                    let byte = match self.advance() {
+                        Some('n') => b'\n',
+                        Some('t') => b'\t',
+                        Some('r') => b'\r',
+                        Some('0') => 0,
+                        Some('"') => b'"',
+                        Some('\\') => b'\\',
+                        Some('x') => self.read_hex_escape()?,
+                        Some(other) => return Err(LexError {
+                            code: "E0003",
+                            message: format!("Unknown escape sequence in byte string: \\{}", other),
+                            position: escape,
+                        }),
+                        None => break,
+                    };
+                    bytes.push(byte);
+                }
+                '\n' => {
+                    return Err(LexError {
+                        code: "E0001",
+                        message: "Unclosed byte string".to_string(),
+                        position: start,
+                    });
+                }
+                ch if ch.is_ascii() => {
+                    bytes.push(ch as u8);
+                    self.advance();
+                }
+                ch => {
+                    return Err(LexError {
+                        code: "E0007",
+                        message: format!("Byte strings may only contain ASCII characters; write '{}' as \\xNN escapes", ch),
+                        position: self.position,
+                    });
+                }
+            }
+        }
+        
+        Err(LexError {
+            code: "E0001",
+            message: "Unterminated byte string".to_string(),
+            position: start,
+        })
+    }
+
+    pub fn next_token(&mut self) -> Result<SpannedToken, LexError> {
         // Пропускаем пробелы и комментарии
+        let line = self.line;
         self.skip_whitespace();
-        self.skip_comments();
+        self.skip_comments()?;
+        let (start, start_line, start_column) = (self.position, self.line, self.column);
+        // Несколько пустых строк подряд дают один токен
+        let token = if self.line != line { Token::Newline } else { self.read_token()? };
+        let span = Span {
+            start,
+            end: self.position,
+            line: start_line,
+            column: start_column,
+            end_line: self.line,
+            end_column: self.column,
+        };
+        Ok(SpannedToken { token, span })
+    }
 
+    fn read_token(&mut self) -> Result<Token, LexError> {
         match self.peek() {
+            Some('/') if self.at_doc_comment() => Ok(self.read_doc_comment()),
             Some('(') => {
                 self.advance();
                 Ok(Token::LParen)
@@ -202,36 +785,203 @@ impl Lexer {
                 self.advance();
                 Ok(Token::RParen)
             }
+            Some('[') => {
+                self.advance();
+                Ok(Token::LBracket)
+            }
+            Some(']') => {
+                self.advance();
+                Ok(Token::RBracket)
+            }
+            Some('{') => {
+                self.advance();
+                Ok(Token::LBrace)
+            }
+            Some('}') => {
+                self.advance();
+                Ok(Token::RBrace)
+            }
             Some(';') => {
                 self.advance();
                 Ok(Token::Semicolon)
             }
+            Some(':') => {
+                self.advance();
+                if let Some(':') = self.peek() {
+                    self.advance();
+                    return Ok(Token::ColonColon);
+                }
+                Ok(Token::Colon)
+            }
+            Some(',') => {
+                self.advance();
+                Ok(Token::Comma)
+            }
+            Some('?') => {
+                self.advance();
+                if let Some('?') = self.peek() {
+                    self.advance();
+                    return Ok(Token::QuestionQuestion);
+                }
+                Ok(Token::Question)
+            }
+            Some('.') => {
+                self.advance();
+                if let Some('.') = self.peek() {
+                    self.advance();
+                    if let Some('.') = self.peek() {
+                        self.advance();
+                        return Ok(Token::Ellipsis);
+                    }
+                    return Ok(Token::DotDot);
+                }
+                Ok(Token::Dot)
+            }
             Some('=') => {
                 self.advance();
+                if let Some('=') = self.peek() {
+                    self.advance();
+                    return Ok(Token::EqualEqual);
+                }
+                if let Some('>') = self.peek() {
+                    self.advance();
+                    return Ok(Token::FatArrow);
+                }
                 Ok(Token::Equals)
             }
+            Some('!') if self.peek_next() == Some('=') => {
+                self.advance();
+                self.advance();
+                Ok(Token::NotEqual)
+            }
+            Some('<') => {
+                self.advance();
+                if let Some('<') = self.peek() {
+                    self.advance();
+                    return Ok(Token::ShiftLeft);
+                }
+                if let Some('=') = self.peek() {
+                    self.advance();
+                    return Ok(Token::LessEqual);
+                }
+                Ok(Token::Less)
+            }
+            Some('>') => {
+                self.advance();
+                if let Some('>') = self.peek() {
+                    self.advance();
+                    return Ok(Token::ShiftRight);
+                }
+                if let Some('=') = self.peek() {
+                    self.advance();
+                    return Ok(Token::GreaterEqual);
+                }
+                Ok(Token::Greater)
+            }
             Some('+') => {
                 self.advance();
+                if let Some('+') = self.peek() {
+                    self.advance();
+                    return Ok(Token::PlusPlus);
+                }
                 Ok(Token::Plus)
             }
-            Some('"') => Ok(Token::StringLiteral(self.read_string()?)),
+            Some('-') => {
+                self.advance();
+                if let Some('-') = self.peek() {
+                    self.advance();
+                    return Ok(Token::MinusMinus);
+                }
+                if let Some('>') = self.peek() {
+                    self.advance();
+                    return Ok(Token::Arrow);
+                }
+                Ok(Token::Minus)
+            }
+            Some('*') => {
+                self.advance();
+                Ok(Token::Star)
+            }
+            Some('/') => {
+                self.advance();
+                Ok(Token::Slash)
+            }
+            Some('%') => {
+                self.advance();
+                Ok(Token::Percent)
+            }
+            Some('&') => {
+                self.advance();
+                Ok(Token::Ampersand)
+            }
+            Some('|') => {
+                self.advance();
+                Ok(Token::Pipe)
+            }
+            Some('^') => {
+                self.advance();
+                Ok(Token::Caret)
+            }
+            Some('~') => {
+                self.advance();
+                Ok(Token::Tilde)
+            }
+            Some('#') => {
+                self.advance();
+                Ok(Token::Hash)
+            }
+            Some('"') => self.read_string(),
             
             // Числа
             Some(ch) if ch.is_ascii_digit() => {
-                Ok(Token::NumberLiteral(self.read_number()))
+                Ok(Token::NumberLiteral(self.read_number()?))
             }
             
             // Идентификаторы и ключевые слова
-            Some(ch) if ch.is_alphabetic() || ch == '_' => {
+            Some('r') if self.peek_next() == Some('"') => self.read_raw_string(),
+            Some('b') if self.peek_next() == Some('"') => self.read_byte_string(),
+            
+            Some(ch) if is_ident_start(ch) => {
                 let ident = self.read_ident();
-                Ok(match ident.as_str() {
+                Ok(match ident {
                     "String" => Token::StringType,
                     "Integer" => Token::IntegerType,
                     "Float" => Token::FloatType,
                     "Boolean" => Token::BooleanType,
+                    "BigInt" => Token::BigIntType,
+                    "Bytes" => Token::BytesType,
+                    "Array" => Token::ArrayType,
+                    "Map" => Token::MapType,
+                    "Result" => Token::ResultType,
                     "true" => Token::True,
                     "false" => Token::False,
-                    _ => Token::Ident(ident),
+                    "none" => Token::None,
+                    "match" => Token::Match,
+                    "for" => Token::For,
+                    "in" => Token::In,
+                    "loop" => Token::Loop,
+                    "while" => Token::While,
+                    "break" => Token::Break,
+                    "continue" => Token::Continue,
+                    "func" => Token::Func,
+                    "return" => Token::Return,
+                    "const" => Token::Const,
+                    "let" => Token::Let,
+                    "struct" => Token::Struct,
+                    "enum" => Token::Enum,
+                    "trait" => Token::Trait,
+                    "impl" => Token::Impl,
+                    "try" => Token::Try,
+                    "catch" => Token::Catch,
+                    "import" => Token::Import,
+                    "module" => Token::Module,
+                    "as" => Token::As,
+                    "mut" => Token::Mut,
+                    "defer" => Token::Defer,
+                    "macro" => Token::Macro,
+                    "_" => Token::Underscore,
+                    word if RESERVED_WORDS.contains(&word) => Token::Reserved(word.to_string()),
+                    _ => Token::Ident(ident.to_string()),
                 })
             }
             
@@ -242,8 +992,14 @@ impl Lexer {
             Some(ch) => {
                 let pos = self.position;
                 self.advance();
+                let message = if is_ident_continue(ch) {
+                    format!("Identifier cannot start with '{}' (U+{:04X}); use a letter or '_'", ch, ch as u32)
+                } else {
+                    format!("Invalid character: '{}' (U+{:04X})", ch, ch as u32)
+                };
                 Err(LexError {
-                    message: format!("Invalid character: '{}'", ch),
+                    code: "E0006",
+                    message,
                     position: pos,
                 })
             }
@@ -251,16 +1007,45 @@ impl Lexer {
         }
     }
 
-    pub fn tokenize(&mut self) -> Result<Vec<Token>, LexError> {
+    pub fn tokenize(&mut self) -> Result<Vec<SpannedToken>, Vec<LexError>> {
+        self.tokenize_with_progress(|_| {})
+    }
+
+    // Колбэк получает процент обработанного входа, не чаще раза на процент.
+    // После ошибки разбор продолжается со следующей строки, чтобы найти все ошибки сразу
+    pub fn tokenize_with_progress<F: FnMut(usize)>(&mut self, mut on_progress: F) -> Result<Vec<SpannedToken>, Vec<LexError>> {
         let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        let total = self.input.len().max(1);
+        let mut reported = 0;
         loop {
-            let token = self.next_token()?;
-            let is_eof = matches!(token, Token::EOF);
+            let token = match self.next_token() {
+                Ok(token) => token,
+                Err(error) => {
+                    errors.push(error);
+                    while !matches!(self.peek(), Some('\n') | None) {
+                        self.advance();
+                    }
+                    continue;
+                }
+            };
+            let is_eof = matches!(token.token, Token::EOF);
             tokens.push(token);
+
+            let percent = self.position * 100 / total;
+            if percent > reported {
+                reported = percent;
+                on_progress(percent);
+            }
+
             if is_eof {
                 break;
             }
         }
-        Ok(tokens)
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(errors)
+        }
     }
 }
\ No newline at end of file
@@ -1,4 +1,19 @@
+pub mod source;
 pub mod lexer;
 pub mod parser;
+pub mod locale;
+pub mod diagnostics;
+pub mod attributes;
+pub mod types;
+pub mod warnings;
+pub mod error_codes;
+pub mod teach;
+pub mod semantic;
+pub mod lints;
+pub mod sarif;
+pub mod optimize;
 pub mod compiler;
-pub mod cli;
\ No newline at end of file
+pub mod bytecode;
+pub mod vm;
+pub mod cli;
+pub mod testing;
\ No newline at end of file
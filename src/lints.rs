@@ -0,0 +1,321 @@
+use crate::lexer::Span;
+use crate::parser::{Expr, Pattern, Program, Stmt, StructPattern, Value};
+use crate::semantic::Warning;
+
+use std::collections::HashSet;
+
+// Стилевые проверки для quark check. Обход программы превращает её в поток событий,
+// а каждая проверка смотрит на события по отдельности; имя проверки — имя предупреждения
+pub struct Lint {
+    pub name: &'static str,
+    pub check: fn(&Event) -> Option<String>,
+}
+
+pub const LINTS: &[Lint] = &[
+    Lint { name: "non-snake-case", check: non_snake_case },
+    Lint { name: "shadowed-variable", check: shadowed_variable },
+    Lint { name: "constant-condition", check: constant_condition },
+    Lint { name: "empty-block", check: empty_block },
+];
+
+pub enum Event<'a> {
+    // Новое имя; shadows — такое же имя уже видно из внешней области
+    Binding { name: &'a str, kind: &'static str, constant: bool, shadows: bool },
+    // Условие while и for или значение, которое разбирает match
+    Condition { keyword: &'static str, value: &'a Expr },
+    // Тело функции, цикла или блока; owner описывает его для сообщения
+    Body { owner: String, body: &'a [Stmt] },
+}
+
+pub fn run(program: &Program) -> Vec<Warning> {
+    let mut walker = Walker { scopes: vec![HashSet::new()], events: Vec::new(), span: None };
+    // Глобальные переменные видны и в функциях, объявленных выше них
+    for stmt in &program.statements {
+        match stmt {
+            Stmt::Declaration { name, .. } => {
+                walker.scopes[0].insert(name);
+            }
+            Stmt::Destructure { pattern, .. } => walker.scopes[0].extend(struct_pattern_names(pattern)),
+            _ => {}
+        }
+    }
+    walker.block(&program.statements);
+
+    let mut warnings = Vec::new();
+    for (event, span) in &walker.events {
+        for lint in LINTS {
+            if let Some(message) = (lint.check)(event) {
+                warnings.push(Warning { name: lint.name, message, span: *span });
+            }
+        }
+    }
+    warnings
+}
+
+fn non_snake_case(event: &Event) -> Option<String> {
+    let Event::Binding { name, kind, constant, .. } = event else { return None };
+    // Член модуля проверяется без префикса модуля
+    let name = name.rsplit("::").next().unwrap_or(name);
+    if is_snake_case(name) || (*constant && is_screaming_snake_case(name)) {
+        return None;
+    }
+    Some(format!("{} '{}' should have a snake_case name such as '{}'", kind, name, to_snake_case(name)))
+}
+
+fn shadowed_variable(event: &Event) -> Option<String> {
+    match event {
+        Event::Binding { name, kind, shadows: true, .. } => {
+            Some(format!("{} '{}' shadows a variable of the same name from an outer scope", kind, name))
+        }
+        _ => None,
+    }
+}
+
+fn constant_condition(event: &Event) -> Option<String> {
+    let Event::Condition { keyword, value } = event else { return None };
+    if !is_constant(value) {
+        return None;
+    }
+    Some(match (*keyword, value) {
+        ("while", Expr::Literal(Value::Boolean(true))) => {
+            "'while' condition is always true; use 'loop' for an infinite loop".to_string()
+        }
+        ("match", _) => "'match' on a constant value always takes the same arm".to_string(),
+        _ => format!("'{}' condition does not depend on any variable", keyword),
+    })
+}
+
+fn empty_block(event: &Event) -> Option<String> {
+    match event {
+        Event::Body { owner, body: [] } => Some(format!("empty {}", owner)),
+        _ => None,
+    }
+}
+
+// Выражение из одних литералов вычисляется одинаково при каждом выполнении
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(_) => true,
+        Expr::BinaryOp { left, right, .. } => is_constant(left) && is_constant(right),
+        Expr::UnaryOp { operand, .. } => is_constant(operand),
+        _ => false,
+    }
+}
+
+// _ в начале имени разрешён: так помечают намеренно неиспользуемые значения
+fn is_snake_case(name: &str) -> bool {
+    let name = name.trim_start_matches('_');
+    !name.chars().any(char::is_uppercase) && !name.contains("__")
+}
+
+fn is_screaming_snake_case(name: &str) -> bool {
+    !name.chars().any(char::is_lowercase)
+}
+
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    let mut previous_lower = false;
+    for ch in name.chars() {
+        if ch.is_uppercase() && previous_lower {
+            snake.push('_');
+        }
+        previous_lower = ch.is_lowercase() || ch.is_ascii_digit();
+        snake.extend(ch.to_lowercase());
+    }
+    snake
+}
+
+fn struct_pattern_names(pattern: &StructPattern) -> Vec<&str> {
+    match pattern {
+        StructPattern::Positional { bindings, .. } => bindings.iter().map(String::as_str).collect(),
+        StructPattern::Named { fields, .. } => fields.iter().map(|(_, binding)| binding.as_str()).collect(),
+    }
+}
+
+struct Walker<'a> {
+    scopes: Vec<HashSet<&'a str>>,
+    // Событие и ближайшая к нему инструкция с известным местом
+    events: Vec<(Event<'a>, Option<Span>)>,
+    span: Option<Span>,
+}
+
+impl<'a> Walker<'a> {
+    fn bind(&mut self, name: &'a str, kind: &'static str, constant: bool) {
+        if name == "_" {
+            return;
+        }
+        let (current, outer) = self.scopes.split_last_mut().unwrap();
+        let shadows = outer.iter().any(|scope| scope.contains(name));
+        current.insert(name);
+        self.event(Event::Binding { name, kind, constant, shadows });
+    }
+
+    fn event(&mut self, event: Event<'a>) {
+        self.events.push((event, self.span));
+    }
+
+    fn scoped(&mut self, body: impl FnOnce(&mut Self)) {
+        self.scopes.push(HashSet::new());
+        body(self);
+        self.scopes.pop();
+    }
+
+    fn body(&mut self, owner: String, body: &'a [Stmt]) {
+        self.event(Event::Body { owner, body });
+        self.scoped(|walker| walker.block(body));
+    }
+
+    fn block(&mut self, statements: &'a [Stmt]) {
+        statements.iter().for_each(|stmt| self.stmt(stmt));
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) {
+        let outer = self.span;
+        self.span = stmt.span().filter(|span| *span != Span::default()).or(outer);
+        self.stmt_events(stmt);
+        self.span = outer;
+    }
+
+    fn stmt_events(&mut self, stmt: &'a Stmt) {
+        match stmt {
+            Stmt::Declaration { name, value, constant, .. } => {
+                self.expr(value);
+                self.bind(name, "variable", *constant);
+            }
+            Stmt::Destructure { pattern, value, .. } => {
+                self.expr(value);
+                struct_pattern_names(pattern).into_iter().for_each(|name| self.bind(name, "variable", false));
+            }
+            Stmt::Assignment { value, .. } | Stmt::Expression(value) | Stmt::Return(Some(value)) => self.expr(value),
+            Stmt::IndexAssignment { target, index, value, .. } => {
+                self.expr(target);
+                self.expr(index);
+                self.expr(value);
+            }
+            Stmt::FieldAssignment { target, value, .. } => {
+                self.expr(target);
+                self.expr(value);
+            }
+            Stmt::Match { value, arms, .. } => {
+                self.event(Event::Condition { keyword: "match", value });
+                self.expr(value);
+                for arm in arms {
+                    self.scoped(|walker| {
+                        match &arm.pattern {
+                            Pattern::Variant { bindings, .. } => {
+                                bindings.iter().for_each(|name| walker.bind(name, "variable", false))
+                            }
+                            Pattern::Struct(pattern) => struct_pattern_names(pattern)
+                                .into_iter()
+                                .for_each(|name| walker.bind(name, "variable", false)),
+                            Pattern::Literal(_) | Pattern::Wildcard => {}
+                        }
+                        walker.block(&arm.body);
+                    });
+                }
+            }
+            Stmt::For { init, condition, update, body, .. } => self.scoped(|walker| {
+                walker.stmt(init);
+                walker.event(Event::Condition { keyword: "for", value: condition });
+                walker.expr(condition);
+                walker.stmt(update);
+                walker.body("body of a 'for' loop".to_string(), body);
+            }),
+            Stmt::ForIn { variable, iterable, body, .. } => {
+                self.expr(iterable);
+                self.scoped(|walker| {
+                    walker.bind(variable, "loop variable", false);
+                    walker.body("body of a 'for' loop".to_string(), body);
+                });
+            }
+            Stmt::While { condition, body, .. } => {
+                self.event(Event::Condition { keyword: "while", value: condition });
+                self.expr(condition);
+                self.body("body of a 'while' loop".to_string(), body);
+            }
+            Stmt::Loop { body, .. } => self.body("body of a 'loop'".to_string(), body),
+            Stmt::Block(body) => self.body("block".to_string(), body),
+            Stmt::Defer(stmt) => self.stmt(stmt),
+            Stmt::Try { body, error, handler } => {
+                self.body("body of a 'try'".to_string(), body);
+                self.scoped(|walker| {
+                    walker.bind(error, "variable", false);
+                    walker.block(handler);
+                });
+            }
+            Stmt::Function { name, params, body, .. } => {
+                self.event(Event::Binding { name, kind: "function", constant: false, shadows: false });
+                self.event(Event::Body { owner: format!("body of function '{}'", name), body });
+                self.scoped(|walker| {
+                    params.iter().for_each(|param| walker.bind(&param.name, "parameter", false));
+                    walker.block(body);
+                });
+            }
+            Stmt::Impl { methods, .. } => {
+                for method in methods {
+                    let name = &method.signature.name;
+                    self.event(Event::Binding { name, kind: "method", constant: false, shadows: false });
+                    self.event(Event::Body { owner: format!("body of method '{}'", name), body: &method.body });
+                    self.scoped(|walker| {
+                        method.signature.params.iter().for_each(|param| walker.bind(&param.name, "parameter", false));
+                        walker.block(&method.body);
+                    });
+                }
+            }
+            Stmt::Return(None)
+            | Stmt::Break(_)
+            | Stmt::Continue(_)
+            | Stmt::Struct { .. }
+            | Stmt::Enum { .. }
+            | Stmt::Trait { .. }
+            | Stmt::Import(_) => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &'a Expr) {
+        match expr {
+            Expr::Call { args, .. } | Expr::Array(args) => args.iter().for_each(|arg| self.expr(arg)),
+            Expr::MethodCall { target, args, .. } => {
+                self.expr(target);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            Expr::BinaryOp { left, right, .. } | Expr::Index { target: left, index: right } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Struct { fields, .. } => fields.iter().for_each(|(_, value)| self.expr(value)),
+            Expr::Enum { payload, .. } => payload.iter().for_each(|value| self.expr(value)),
+            Expr::Map(entries) => entries.iter().for_each(|(key, value)| {
+                self.expr(key);
+                self.expr(value);
+            }),
+            Expr::Slice { target, start, end } => {
+                self.expr(target);
+                start.iter().chain(end).for_each(|bound| self.expr(bound));
+            }
+            Expr::Range { start, end } => {
+                self.expr(start);
+                self.expr(end);
+            }
+            Expr::Comprehension { element, variable, iterable, condition } => {
+                self.expr(iterable);
+                self.scoped(|walker| {
+                    walker.bind(variable, "loop variable", false);
+                    walker.expr(element);
+                    condition.iter().for_each(|condition| walker.expr(condition));
+                });
+            }
+            Expr::Lambda { params, body } => self.scoped(|walker| {
+                params.iter().for_each(|param| walker.bind(&param.name, "parameter", false));
+                walker.expr(body);
+            }),
+            Expr::UnaryOp { operand: inner, .. }
+            | Expr::Cast { value: inner, .. }
+            | Expr::Field { target: inner, .. }
+            | Expr::Try(inner)
+            | Expr::Spread(inner)
+            | Expr::JsonParse { text: inner, .. } => self.expr(inner),
+            Expr::Variable(_) | Expr::Literal(_) => {}
+        }
+    }
+}
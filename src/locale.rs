@@ -0,0 +1,345 @@
+use std::env;
+use std::sync::OnceLock;
+
+// Язык сообщений компилятора. Сообщения создаются на английском, а перед выводом
+// переводятся по каталогу; коды ошибок и имена предупреждений не переводятся
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Locale {
+    English,
+    Russian,
+}
+
+static CURRENT: OnceLock<Locale> = OnceLock::new();
+
+impl Locale {
+    pub fn parse(name: &str) -> Option<Locale> {
+        match name.to_lowercase().as_str() {
+            "en" | "english" => Some(Locale::English),
+            "ru" | "russian" => Some(Locale::Russian),
+            _ => None,
+        }
+    }
+}
+
+// --locale задаёт язык один раз при запуске; иначе он берётся из QUARK_LOCALE и LANG
+pub fn set(locale: Locale) {
+    let _ = CURRENT.set(locale);
+}
+
+pub fn current() -> Locale {
+    *CURRENT.get_or_init(|| {
+        let from_env = ["QUARK_LOCALE", "LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty());
+        match from_env {
+            Some(value) if value.to_lowercase().starts_with("ru") => Locale::Russian,
+            _ => Locale::English,
+        }
+    })
+}
+
+// Сообщение каталога: id, английский шаблон и перевод. {name} стоит на месте имён, типов
+// и чисел; при переводе аргумент переносится по имени, так что порядок в переводе свой
+pub const CATALOG: &[(&str, &str, &str)] = &[
+    ("error-heading", "Error", "Ошибка"),
+    ("error-label", "error", "ошибка"),
+    ("note-label", "note", "заметка"),
+    ("help-label", "help", "подсказка"),
+    ("warning-heading", "Warning", "Предупреждение"),
+    ("semantic-error-heading", "Semantic error", "Семантическая ошибка"),
+    ("compilation-error-heading", "Compilation error", "Ошибка компиляции"),
+    (
+        "could-not-compile",
+        "could not compile {path} because of {count} previous error(s)",
+        "не удалось скомпилировать {path}: ошибок — {count}",
+    ),
+    (
+        "denied-warnings",
+        "{count} warning(s) treated as errors because of --deny-warnings",
+        "предупреждений, считающихся ошибками из-за --deny-warnings: {count}",
+    ),
+    ("checking-syntax", "Checking syntax: {path}", "Проверка синтаксиса: {path}"),
+    ("reading", "Reading: {path}", "Чтение: {path}"),
+    ("tokens", "Tokens: {count}", "Токенов: {count}"),
+    ("syntax-correct", "Syntax is correct", "Синтаксис корректен"),
+    // Лексер
+    ("unterminated-string", "Unterminated string constant", "Незакрытая строковая константа"),
+    ("unclosed-string", "Unclosed string", "Незакрытая строка"),
+    ("unterminated-raw-string", "Unterminated raw string", "Незакрытая сырая строка"),
+    ("unclosed-raw-string", "Unclosed raw string", "Незакрытая сырая строка"),
+    ("unterminated-byte-string", "Unterminated byte string", "Незакрытая байтовая строка"),
+    ("unclosed-byte-string", "Unclosed byte string", "Незакрытая байтовая строка"),
+    ("unterminated-block-comment", "Unterminated block comment", "Незакрытый блочный комментарий"),
+    (
+        "unknown-byte-escape",
+        "Unknown escape sequence in byte string: {escape}",
+        "Неизвестная escape-последовательность в байтовой строке: {escape}",
+    ),
+    ("unknown-escape", "Unknown escape sequence: {escape}", "Неизвестная escape-последовательность: {escape}"),
+    ("incomplete-escape", "Incomplete escape sequence", "Незаконченная escape-последовательность"),
+    ("unicode-escape-brace", "Expected '{' after \\u", "После \\u ожидалась '{'"),
+    ("unclosed-unicode-escape", "Unclosed \\u{...} escape", "Незакрытая последовательность \\u{...}"),
+    ("unclosed-interpolation", "Unclosed '{' in string interpolation", "Незакрытая '{' в интерполяции строки"),
+    ("empty-interpolation", "Empty expression in string interpolation", "Пустое выражение в интерполяции строки"),
+    ("missing-digits", "Missing digits in {kind} literal", "В литерале {kind} нет цифр"),
+    ("invalid-digit", "Invalid digit '{digit}' in {kind} literal", "Недопустимая цифра '{digit}' в литерале {kind}"),
+    (
+        "misplaced-separator",
+        "Misplaced '_' in {kind} literal: separators must stand between digits",
+        "'_' не на месте в литерале {kind}: разделители ставятся между цифрами",
+    ),
+    (
+        "non-ascii-byte-string",
+        "Byte strings may only contain ASCII characters; write '{char}' as \\xNN escapes",
+        "Байтовые строки содержат только символы ASCII; запишите '{char}' через \\xNN",
+    ),
+    // Парсер
+    ("expected-token", "Expected {expected}, got {found}", "Ожидалось {expected}, получено {found}"),
+    ("expected-at-end", "Expected {expected}, but no more tokens", "Ожидалось {expected}, но токены закончились"),
+    ("expected-closing-brace", "Expected '}' before end of file", "До конца файла ожидалась '}'"),
+    ("unexpected-end", "Unexpected end of file", "Неожиданный конец файла"),
+    ("unexpected-token", "Unexpected token: {token}", "Неожиданный токен: {token}"),
+    ("expected-expression", "Expected expression", "Ожидалось выражение"),
+    ("expected-type", "Expected type", "Ожидался тип"),
+    ("expected-value", "Expected value", "Ожидалось значение"),
+    ("invalid-character", "Invalid character: '{char}'", "Недопустимый символ: '{char}'"),
+    ("reserved-keyword", "'{word}' is a reserved keyword", "'{word}' — зарезервированное слово"),
+    ("unknown-name", "Unknown function or variable: {name}", "Неизвестная функция или переменная: {name}"),
+    ("unknown-variable", "Unknown variable: {name}", "Неизвестная переменная: {name}"),
+    ("unknown-type", "Unknown type '{name}'", "Неизвестный тип '{name}'"),
+    ("unknown-struct", "Unknown struct '{name}'", "Неизвестная структура '{name}'"),
+    ("unknown-enum", "Unknown enum '{name}'", "Неизвестное перечисление '{name}'"),
+    ("unknown-trait", "Unknown trait '{name}'", "Неизвестный трейт '{name}'"),
+    ("unknown-attribute", "Unknown attribute '{name}'", "Неизвестный атрибут '{name}'"),
+    ("duplicate-type", "Type '{name}' is defined more than once", "Тип '{name}' определён несколько раз"),
+    (
+        "duplicate-field",
+        "Field '{field}' is declared more than once in struct {name}",
+        "Поле '{field}' объявлено в структуре {name} несколько раз",
+    ),
+    (
+        "duplicate-variant",
+        "Variant '{variant}' is declared more than once in enum {name}",
+        "Вариант '{variant}' объявлен в перечислении {name} несколько раз",
+    ),
+    ("duplicate-macro", "Macro '{name}' is defined more than once", "Макрос '{name}' определён несколько раз"),
+    (
+        "macro-argument-count",
+        "Macro '{name}' takes {expected} argument(s), got {found}",
+        "Макрос '{name}' принимает аргументов: {expected}, передано {found}",
+    ),
+    ("struct-has-no-field", "Struct {name} has no field '{field}'", "У структуры {name} нет поля '{field}'"),
+    ("enum-has-no-variant", "Enum {name} has no variant '{variant}'", "У перечисления {name} нет варианта '{variant}'"),
+    ("outside-loop", "'{keyword}' outside of a loop", "'{keyword}' вне цикла"),
+    (
+        "imports-first",
+        "Imports must come before all other declarations",
+        "Импорты должны идти перед всеми остальными объявлениями",
+    ),
+    ("nested-import", "Imports are only allowed at the top level", "Импорт допустим только на верхнем уровне"),
+    ("nested-function", "Functions can only be declared at the top level", "Функции объявляются только на верхнем уровне"),
+    ("nested-struct", "Structs can only be declared at the top level", "Структуры объявляются только на верхнем уровне"),
+    ("nested-enum", "Enums can only be declared at the top level", "Перечисления объявляются только на верхнем уровне"),
+    ("nested-macro", "Macros can only be defined at the top level", "Макросы определяются только на верхнем уровне"),
+    (
+        "literal-type-mismatch",
+        "Type mismatch: cannot assign {value} to {target}",
+        "Несовпадение типов: нельзя присвоить {value} переменной типа {target}",
+    ),
+    (
+        "integer-out-of-range",
+        "Integer literal {literal} is out of range (valid range is {range}); declare it as BigInt to keep the exact value",
+        "Литерал Integer {literal} вне диапазона ({range}); объявите его как BigInt, чтобы сохранить точное значение",
+    ),
+    ("first-defined", "'{name}' is first defined here", "'{name}' впервые определён здесь"),
+    ("first-declared", "'{name}' is first declared here", "'{name}' впервые объявлен здесь"),
+    ("add-terminator", "add '{token}' at the end of the statement", "добавьте '{token}' в конце инструкции"),
+    ("close-parenthesis", "add '{token}' to close the parenthesis", "добавьте '{token}', чтобы закрыть скобку"),
+    ("close-bracket", "add '{token}' to close the bracket", "добавьте '{token}', чтобы закрыть скобку"),
+    ("close-block", "add '{token}' to close the block", "добавьте '{token}', чтобы закрыть блок"),
+    ("choose-another-name", "choose another name", "выберите другое имя"),
+    ("move-import", "move the import to the top of the file", "перенесите импорт в начало файла"),
+    // Семантический анализ
+    ("undeclared-variable", "undeclared variable '{name}'", "необъявленная переменная '{name}'"),
+    (
+        "used-before-declaration",
+        "variable '{name}' is used before its declaration",
+        "переменная '{name}' используется до объявления",
+    ),
+    (
+        "duplicate-variable",
+        "variable '{name}' is already declared in this scope",
+        "переменная '{name}' уже объявлена в этой области",
+    ),
+    // Шаблоны сверяются по порядку, поэтому совет с несколькими именами стоит раньше
+    ("similar-names", "did you mean {names} or {last}?", "может быть, имелось в виду {names} или {last}?"),
+    ("similar-name", "did you mean {name}?", "может быть, имелось в виду {name}?"),
+    ("unknown-function", "unknown function '{name}'", "неизвестная функция '{name}'"),
+    (
+        "argument-count",
+        "function '{name}' takes {expected} argument(s), got {found}",
+        "функция '{name}' принимает аргументов: {expected}, передано {found}",
+    ),
+    ("duplicate-function", "function '{name}' is defined more than once", "функция '{name}' определена несколько раз"),
+    (
+        "argument-type",
+        "argument {index} of '{name}' must be {expected}, got {found}",
+        "аргумент {index} функции '{name}' должен быть {expected}, получен {found}",
+    ),
+    ("assign-to-constant", "cannot assign to constant '{name}'", "нельзя присвоить значение константе '{name}'"),
+    (
+        "assign-to-undeclared",
+        "cannot assign to undeclared variable '{name}'",
+        "нельзя присвоить значение необъявленной переменной '{name}'",
+    ),
+    (
+        "initializer-type",
+        "cannot initialize '{name}' of type {expected} with a value of type {found}",
+        "нельзя инициализировать '{name}' типа {expected} значением типа {found}",
+    ),
+    ("not-iterable", "cannot iterate over a value of type {type}", "нельзя перебрать значение типа {type}"),
+    ("not-indexable", "cannot index into a value of type {type}", "нельзя индексировать значение типа {type}"),
+    (
+        "method-on-type",
+        "cannot call method '{method}' on a value of type {type}",
+        "нельзя вызвать метод '{method}' у значения типа {type}",
+    ),
+    ("no-such-field", "struct {name} has no field '{field}'", "у структуры {name} нет поля '{field}'"),
+    ("no-such-method", "type {type} has no method '{method}'", "у типа {type} нет метода '{method}'"),
+    ("no-return-value", "'{name}' does not return a value", "'{name}' не возвращает значение"),
+    (
+        "impl-missing-method",
+        "impl {trait} for {type} is missing method '{method}'",
+        "в impl {trait} for {type} нет метода '{method}'",
+    ),
+    ("invalid-cast", "cannot cast {from} to {to}: {reason}", "нельзя привести {from} к {to}: {reason}"),
+    // Предупреждения и стилевые проверки
+    ("deprecated-function", "function '{name}' is deprecated{note}", "функция '{name}' устарела{note}"),
+    ("deprecated-type", "type {name} is deprecated{note}", "тип {name} устарел{note}"),
+    (
+        "unreachable-arm",
+        "unreachable match arm: {count} arm(s) after {arm} are never executed",
+        "недостижимые ветви match: после {arm} не выполняется ни одна из ветвей ({count} шт.)",
+    ),
+    (
+        "non-exhaustive-match",
+        "match on {type} has no default arm `_` and does not handle `{missing}`",
+        "в match по {type} нет ветви `_`, и не обработано `{missing}`",
+    ),
+    (
+        "variable-case",
+        "variable '{name}' should have a snake_case name such as '{suggestion}'",
+        "имя переменной '{name}' следует записать в snake_case, например '{suggestion}'",
+    ),
+    (
+        "parameter-case",
+        "parameter '{name}' should have a snake_case name such as '{suggestion}'",
+        "имя параметра '{name}' следует записать в snake_case, например '{suggestion}'",
+    ),
+    (
+        "function-case",
+        "function '{name}' should have a snake_case name such as '{suggestion}'",
+        "имя функции '{name}' следует записать в snake_case, например '{suggestion}'",
+    ),
+    (
+        "shadowed-variable",
+        "variable '{name}' shadows a variable of the same name from an outer scope",
+        "переменная '{name}' скрывает одноимённую переменную из внешней области",
+    ),
+    (
+        "while-always-true",
+        "'while' condition is always true; use 'loop' for an infinite loop",
+        "условие 'while' всегда истинно; для бесконечного цикла есть 'loop'",
+    ),
+    (
+        "constant-match",
+        "'match' on a constant value always takes the same arm",
+        "'match' по константе всегда выбирает одну и ту же ветвь",
+    ),
+    ("empty-body", "empty {owner}", "пустое тело: {owner}"),
+];
+
+// Переводит сообщение на текущий язык; то, чего нет в каталоге, остаётся английским
+pub fn translate(message: &str) -> String {
+    translate_to(current(), message)
+}
+
+// Сообщение узнаётся по английскому шаблону, а перевод берётся по id этого шаблона
+pub fn translate_to(locale: Locale, message: &str) -> String {
+    if locale == Locale::English {
+        return message.to_string();
+    }
+    CATALOG
+        .iter()
+        .find_map(|(id, english, _)| match_template(english, message).map(|args| (id, args)))
+        .and_then(|(id, args)| self::message(locale, id, &args))
+        .unwrap_or_else(|| message.to_string())
+}
+
+// Текст сообщения id на нужном языке; отсутствующие аргументы остаются пустыми
+pub fn message(locale: Locale, id: &str, args: &[(&str, &str)]) -> Option<String> {
+    let (_, english, russian) = CATALOG.iter().find(|(entry, _, _)| *entry == id)?;
+    let template = match locale {
+        Locale::English => english,
+        Locale::Russian => russian,
+    };
+    let (first, pieces) = split_template(template);
+    let mut out = first.to_string();
+    for (name, piece) in pieces {
+        out.push_str(args.iter().find(|(arg, _)| *arg == name).map_or("", |(_, value)| value));
+        out.push_str(piece);
+    }
+    Some(out)
+}
+
+// Имена аргументов шаблона по порядку
+pub fn placeholders(template: &str) -> Vec<&str> {
+    split_template(template).1.into_iter().map(|(name, _)| name).collect()
+}
+
+// Текст до первого аргумента, затем каждый аргумент с текстом после него.
+// Аргумент — {имя} из строчных латинских букв и _, прочие скобки остаются текстом: '{', \u{...}
+fn split_template(template: &str) -> (&str, Vec<(&str, &str)>) {
+    let placeholder = |at: usize| {
+        let name = &template[at + 1..];
+        let end = name.find(|ch: char| !(ch.is_ascii_lowercase() || ch == '_'))?;
+        (end > 0 && name[end..].starts_with('}')).then(|| (&name[..end], at + end + 2))
+    };
+    let mut found = Vec::new();
+    for (at, _) in template.match_indices('{') {
+        if let Some((name, after)) = placeholder(at) {
+            found.push((at, name, after));
+        }
+    }
+    let first = &template[..found.first().map_or(template.len(), |(at, ..)| *at)];
+    let pieces = found
+        .iter()
+        .enumerate()
+        .map(|(i, (_, name, after))| (*name, &template[*after..found.get(i + 1).map_or(template.len(), |(at, ..)| *at)]))
+        .collect();
+    (first, pieces)
+}
+
+// Куски шаблона между аргументами должны встретиться в сообщении по порядку;
+// аргумент — всё между ними, а последний забирает остаток
+fn match_template<'t, 'm>(template: &'t str, message: &'m str) -> Option<Vec<(&'t str, &'m str)>> {
+    let (first, pieces) = split_template(template);
+    let mut rest = message.strip_prefix(first)?;
+    let mut args = Vec::new();
+    for (i, (name, piece)) in pieces.iter().enumerate() {
+        let last = i == pieces.len() - 1;
+        let at = if last {
+            rest.strip_suffix(piece)?.len()
+        } else {
+            rest.find(piece)?
+        };
+        // Пустым может быть только хвост вроде пояснения к deprecated, а не имя
+        if at == 0 && !(last && piece.is_empty()) {
+            return None;
+        }
+        args.push((*name, &rest[..at]));
+        rest = &rest[at + piece.len()..];
+    }
+    rest.is_empty().then_some(args)
+}
@@ -0,0 +1,291 @@
+use crate::parser::{BinOp, Expr, Pattern, Program, Stmt, StructPattern, UnaryOp, Value};
+
+use std::collections::HashMap;
+
+// Проход по AST после семантического анализа: выражения из одних литералов
+// вычисляются заранее и заменяются литералом того же типа, а неизменяемые
+// переменные с таким литералом подставляются в места использования
+pub fn optimize(program: &mut Program) {
+    let mut optimizer = Optimizer { scopes: vec![HashMap::new()] };
+    optimizer.fold_block(&mut program.statements);
+}
+
+struct Optimizer {
+    // None — имя объявлено, но его значение неизвестно; оно закрывает внешнюю константу
+    scopes: Vec<HashMap<String, Option<Value>>>,
+}
+
+impl Optimizer {
+    fn lookup(&self, name: &str) -> Option<&Value> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name)).and_then(|value| value.as_ref())
+    }
+
+    fn bind(&mut self, name: &str, value: Option<Value>) {
+        self.scopes.last_mut().unwrap().insert(name.to_string(), value);
+    }
+
+    fn shadow<'n>(&mut self, names: impl IntoIterator<Item = &'n String>) {
+        names.into_iter().for_each(|name| self.bind(name, None));
+    }
+
+    fn scoped<T>(&mut self, body: impl FnOnce(&mut Self) -> T) -> T {
+        self.scopes.push(HashMap::new());
+        let result = body(self);
+        self.scopes.pop();
+        result
+    }
+
+    fn fold_block(&mut self, statements: &mut [Stmt]) {
+        statements.iter_mut().for_each(|stmt| self.fold_stmt(stmt));
+    }
+
+    fn fold_scope(&mut self, statements: &mut [Stmt]) {
+        self.scoped(|optimizer| optimizer.fold_block(statements));
+    }
+
+    fn fold_stmt(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Declaration { name, value, mutable, .. } => {
+                self.fold_expr(value);
+                // Строки, числа и логические значения не меняются без mut
+                let known = match value {
+                    Expr::Literal(value @ (Value::Integer(_) | Value::Float(_) | Value::Boolean(_) | Value::String(_)))
+                        if !*mutable => Some(value.clone()),
+                    _ => None,
+                };
+                self.bind(name, known);
+            }
+            Stmt::Destructure { pattern, value, .. } => {
+                self.fold_expr(value);
+                self.shadow(&struct_pattern_names(pattern));
+            }
+            Stmt::Assignment { value, .. } | Stmt::Expression(value) | Stmt::Return(Some(value)) => self.fold_expr(value),
+            Stmt::IndexAssignment { target, index, value, .. } => {
+                self.fold_place(target);
+                self.fold_expr(index);
+                self.fold_expr(value);
+            }
+            Stmt::FieldAssignment { target, value, .. } => {
+                self.fold_place(target);
+                self.fold_expr(value);
+            }
+            Stmt::Match { value, arms, .. } => {
+                self.fold_expr(value);
+                for arm in arms {
+                    self.scoped(|optimizer| {
+                        match &arm.pattern {
+                            Pattern::Variant { bindings, .. } => optimizer.shadow(bindings),
+                            Pattern::Struct(pattern) => optimizer.shadow(&struct_pattern_names(pattern)),
+                            Pattern::Literal(_) | Pattern::Wildcard => {}
+                        }
+                        optimizer.fold_block(&mut arm.body);
+                    });
+                }
+            }
+            Stmt::For { init, condition, update, body, .. } => self.scoped(|optimizer| {
+                optimizer.fold_stmt(init);
+                optimizer.fold_expr(condition);
+                optimizer.fold_stmt(update);
+                optimizer.fold_scope(body);
+            }),
+            Stmt::ForIn { variable, iterable, body, .. } => {
+                self.fold_expr(iterable);
+                self.scoped(|optimizer| {
+                    optimizer.bind(variable, None);
+                    optimizer.fold_block(body);
+                });
+            }
+            Stmt::While { condition, body, .. } => {
+                self.fold_expr(condition);
+                self.fold_scope(body);
+            }
+            Stmt::Loop { body, .. } | Stmt::Block(body) => self.fold_scope(body),
+            Stmt::Defer(stmt) => self.fold_stmt(stmt),
+            Stmt::Try { body, error, handler } => {
+                self.fold_scope(body);
+                self.scoped(|optimizer| {
+                    optimizer.bind(error, None);
+                    optimizer.fold_block(handler);
+                });
+            }
+            Stmt::Function { params, body, .. } => self.scoped(|optimizer| {
+                optimizer.shadow(params.iter().map(|param| &param.name));
+                optimizer.fold_block(body);
+            }),
+            Stmt::Impl { methods, .. } => {
+                for method in methods {
+                    self.scoped(|optimizer| {
+                        optimizer.shadow(method.signature.params.iter().map(|param| &param.name));
+                        optimizer.fold_block(&mut method.body);
+                    });
+                }
+            }
+            Stmt::Return(None)
+            | Stmt::Break(_)
+            | Stmt::Continue(_)
+            | Stmt::Struct { .. }
+            | Stmt::Enum { .. }
+            | Stmt::Trait { .. }
+            | Stmt::Import(_) => {}
+        }
+    }
+
+    // Переменная, которой присваивают элемент или поле, остаётся переменной
+    fn fold_place(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Variable(_) => {}
+            Expr::Index { target, index } => {
+                self.fold_place(target);
+                self.fold_expr(index);
+            }
+            Expr::Field { target, .. } => self.fold_place(target),
+            _ => self.fold_expr(expr),
+        }
+    }
+
+    fn fold_expr(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Call { args, .. } | Expr::Array(args) => args.iter_mut().for_each(|arg| self.fold_expr(arg)),
+            Expr::MethodCall { target, args, .. } => {
+                self.fold_place(target);
+                args.iter_mut().for_each(|arg| self.fold_expr(arg));
+            }
+            Expr::BinaryOp { left, right, .. } | Expr::Index { target: left, index: right } => {
+                self.fold_expr(left);
+                self.fold_expr(right);
+            }
+            Expr::Struct { fields, .. } => fields.iter_mut().for_each(|(_, value)| self.fold_expr(value)),
+            Expr::Enum { payload, .. } => payload.iter_mut().for_each(|value| self.fold_expr(value)),
+            Expr::Map(entries) => entries.iter_mut().for_each(|(key, value)| {
+                self.fold_expr(key);
+                self.fold_expr(value);
+            }),
+            Expr::Slice { target, start, end } => {
+                self.fold_expr(target);
+                start.iter_mut().chain(end).for_each(|bound| self.fold_expr(bound));
+            }
+            Expr::Range { start, end } => {
+                self.fold_expr(start);
+                self.fold_expr(end);
+            }
+            Expr::Comprehension { element, variable, iterable, condition } => {
+                self.fold_expr(iterable);
+                self.scoped(|optimizer| {
+                    optimizer.bind(variable, None);
+                    optimizer.fold_expr(element);
+                    condition.iter_mut().for_each(|condition| optimizer.fold_expr(condition));
+                });
+            }
+            Expr::Lambda { params, body } => self.scoped(|optimizer| {
+                optimizer.shadow(params.iter().map(|param| &param.name));
+                optimizer.fold_expr(body);
+            }),
+            Expr::UnaryOp { operand: inner, .. }
+            | Expr::Cast { value: inner, .. }
+            | Expr::Field { target: inner, .. }
+            | Expr::Try(inner)
+            | Expr::Spread(inner)
+            | Expr::JsonParse { text: inner, .. } => self.fold_expr(inner),
+            Expr::Variable(_) | Expr::Literal(_) => {}
+        }
+
+        let value = match expr {
+            Expr::Variable(name) => self.lookup(name).cloned(),
+            Expr::BinaryOp { left, op, right, .. } => match (left.as_ref(), right.as_ref()) {
+                (Expr::Literal(left), Expr::Literal(right)) => fold_binary(op, left, right),
+                _ => None,
+            },
+            Expr::UnaryOp { op, operand, .. } => match operand.as_ref() {
+                Expr::Literal(value) => fold_unary(op, value),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(value) = value {
+            *expr = Expr::Literal(value);
+        }
+    }
+}
+
+fn struct_pattern_names(pattern: &StructPattern) -> Vec<String> {
+    match pattern {
+        StructPattern::Positional { bindings, .. } => bindings.clone(),
+        StructPattern::Named { fields, .. } => fields.iter().map(|(_, binding)| binding.clone()).collect(),
+    }
+}
+
+// Вычисление повторяет сгенерированный C. То, что при выполнении завершилось бы
+// ошибкой (переполнение, деление на ноль) или дало бы inf и NaN, не сворачивается
+fn fold_binary(op: &BinOp, left: &Value, right: &Value) -> Option<Value> {
+    match (left, right) {
+        (Value::Integer(a), Value::Integer(b)) => fold_integers(op, *a, *b),
+        (Value::Float(a), Value::Float(b)) => fold_floats(op, *a, *b),
+        // Integer рядом с Float повышается до Float, как ((double)x) в C
+        (Value::Integer(a), Value::Float(b)) => fold_floats(op, *a as f64, *b),
+        (Value::Float(a), Value::Integer(b)) => fold_floats(op, *a, *b as f64),
+        (Value::Boolean(a), Value::Boolean(b)) => match op {
+            BinOp::Eq => Some(Value::Boolean(a == b)),
+            BinOp::Ne => Some(Value::Boolean(a != b)),
+            _ => None,
+        },
+        // Строки C обрываются на нулевом байте, такие оставляем strcmp и конкатенации
+        (Value::String(a), Value::String(b)) if !a.contains('\0') && !b.contains('\0') => match op {
+            BinOp::Add => Some(Value::String(format!("{}{}", a, b))),
+            _ if op.is_comparison() => Some(Value::Boolean(compare(op, a, b))),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn fold_integers(op: &BinOp, a: i64, b: i64) -> Option<Value> {
+    let value = match op {
+        BinOp::Add => a.checked_add(b)?,
+        BinOp::Sub => a.checked_sub(b)?,
+        BinOp::Mul => a.checked_mul(b)?,
+        BinOp::Div => a.checked_div(b)?,
+        BinOp::Rem => a.checked_rem(b)?,
+        BinOp::BitAnd => a & b,
+        BinOp::BitOr => a | b,
+        BinOp::BitXor => a ^ b,
+        // Сдвиг за пределы 0..=63 компилятор отвергает сам
+        BinOp::Shl if (0..64).contains(&b) => ((a as u64) << b) as i64,
+        BinOp::Shr if (0..64).contains(&b) => a >> b,
+        _ if op.is_comparison() => return Some(Value::Boolean(compare(op, &a, &b))),
+        _ => return None,
+    };
+    Some(Value::Integer(value))
+}
+
+fn fold_floats(op: &BinOp, a: f64, b: f64) -> Option<Value> {
+    let value = match op {
+        BinOp::Add => a + b,
+        BinOp::Sub => a - b,
+        BinOp::Mul => a * b,
+        BinOp::Div => a / b,
+        _ if op.is_comparison() => return Some(Value::Boolean(compare(op, &a, &b))),
+        _ => return None,
+    };
+    value.is_finite().then_some(Value::Float(value))
+}
+
+fn compare<T: PartialOrd + ?Sized>(op: &BinOp, a: &T, b: &T) -> bool {
+    match op {
+        BinOp::Eq => a == b,
+        BinOp::Ne => a != b,
+        BinOp::Lt => a < b,
+        BinOp::Gt => a > b,
+        BinOp::Le => a <= b,
+        BinOp::Ge => a >= b,
+        _ => unreachable!(),
+    }
+}
+
+fn fold_unary(op: &UnaryOp, value: &Value) -> Option<Value> {
+    match (op, value) {
+        (UnaryOp::Neg, Value::Integer(i)) => i.checked_neg().map(Value::Integer),
+        (UnaryOp::Neg, Value::Float(f)) => Some(Value::Float(-f)),
+        (UnaryOp::BitNot, Value::Integer(i)) => Some(Value::Integer(!i)),
+        _ => None,
+    }
+}
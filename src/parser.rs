@@ -1,10 +1,18 @@
-use crate::lexer::Token;
+use crate::lexer::{Span, SpannedToken, StringPart, Token};
+use crate::diagnostics::Note;
+use crate::types::{
+    binary_result_type, builtin_result_type, field_result_type, index_result_type, is_builtin, member_name,
+    unary_result_type, value_type,
+};
+use crate::attributes;
 
 #[derive(Debug, Clone)]
 pub enum Expr {
+    // Место имени функции со списком аргументов
     Call {
         name: String,
         args: Vec<Expr>,
+        span: Span,
     },
     Variable(String),
     Literal(Value),
@@ -12,12 +20,211 @@ pub enum Expr {
         left: Box<Expr>,
         op: BinOp,
         right: Box<Expr>,
+        // От начала левого операнда до конца правого, чтобы компилятор мог указать место ошибки
+        span: Span,
+    },
+    UnaryOp {
+        op: UnaryOp,
+        operand: Box<Expr>,
+        span: Span,
+    },
+    Index {
+        target: Box<Expr>,
+        index: Box<Expr>,
+    },
+    // x as Float — явное преобразование числа
+    Cast {
+        value: Box<Expr>,
+        target: VarType,
+    },
+    // xs[a..b], xs[..b], xs[a..] — копия части массива или строки
+    Slice {
+        target: Box<Expr>,
+        start: Option<Box<Expr>>,
+        end: Option<Box<Expr>>,
+    },
+    Field {
+        target: Box<Expr>,
+        field: String,
+    },
+    // Место имени метода со списком аргументов
+    MethodCall {
+        target: Box<Expr>,
+        method: String,
+        args: Vec<Expr>,
+        span: Span,
+    },
+    // value? — значение ok или досрочный возврат err из функции
+    Try(Box<Expr>),
+    // |x| x + 1 — анонимная функция, захватывает копии используемых переменных
+    Lambda {
+        params: Vec<LambdaParam>,
+        body: Box<Expr>,
+    },
+    // [a, ...rest] — массив из выражений; литерал из одних констант остаётся Value::Array
+    Array(Vec<Expr>),
+    // Point { x: a + 1, y: b } — структура из выражений, типы полей проверяет семантический анализ
+    Struct {
+        name: String,
+        fields: Vec<(String, Expr)>,
+    },
+    // Shape::Circle(r) — вариант перечисления со значениями-выражениями
+    Enum {
+        name: String,
+        variant: String,
+        payload: Vec<Expr>,
+    },
+    // { "a": a } — словарь из выражений; ключи и значения одного типа, как в литерале
+    Map(Vec<(Expr, Expr)>),
+    // ...xs — элементы массива по одному, только в аргументах вызова и в [...]
+    Spread(Box<Expr>),
+    // [x * x for x in xs if x % 2 == 0] — раскрывается в цикл for-in, собирающий массив
+    Comprehension {
+        element: Box<Expr>,
+        variable: String,
+        iterable: Box<Expr>,
+        condition: Option<Box<Expr>>,
+    },
+    // 0..n после in в for-in и в генераторе списка — целые от start до end, не включая end;
+    // по типу это Integer[], но массив не создаётся: цикл просто считает
+    Range {
+        start: Box<Expr>,
+        end: Box<Expr>,
+    },
+    // json_parse(s) там, где ожидается Result<T, String>: T берётся из этого места
+    JsonParse {
+        text: Box<Expr>,
+        target: VarType,
     },
 }
 
+// Тип параметра можно не писать, если его подскажет место, куда передаётся функция
 #[derive(Debug, Clone)]
+pub struct LambdaParam {
+    pub name: String,
+    pub var_type: Option<VarType>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    BitNot,
+}
+
+impl UnaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::BitNot => "~",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum BinOp {
     Add,
+    Sub,
+    Mul,
+    Div,
+    // Остаток от деления целых, знак — как у делимого
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    // x in xs — элемент массива, ключ словаря или подстрока
+    In,
+    // x ?? y — значение необязательного x или y, если x — none
+    Coalesce,
+}
+
+impl BinOp {
+    pub fn from_token(token: &Token) -> Option<BinOp> {
+        match token {
+            Token::Plus => Some(BinOp::Add),
+            Token::Minus => Some(BinOp::Sub),
+            Token::Star => Some(BinOp::Mul),
+            Token::Slash => Some(BinOp::Div),
+            Token::Percent => Some(BinOp::Rem),
+            Token::EqualEqual => Some(BinOp::Eq),
+            Token::NotEqual => Some(BinOp::Ne),
+            Token::Less => Some(BinOp::Lt),
+            Token::Greater => Some(BinOp::Gt),
+            Token::LessEqual => Some(BinOp::Le),
+            Token::GreaterEqual => Some(BinOp::Ge),
+            Token::Ampersand => Some(BinOp::BitAnd),
+            Token::Pipe => Some(BinOp::BitOr),
+            Token::Caret => Some(BinOp::BitXor),
+            Token::ShiftLeft => Some(BinOp::Shl),
+            Token::ShiftRight => Some(BinOp::Shr),
+            Token::In => Some(BinOp::In),
+            Token::QuestionQuestion => Some(BinOp::Coalesce),
+            _ => None,
+        }
+    }
+
+    // Чем больше значение, тем сильнее связывает оператор.
+    // Битовые операции связывают сильнее сравнений, в отличие от C
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinOp::Coalesce => 3,
+            BinOp::Eq | BinOp::Ne => 4,
+            BinOp::Lt | BinOp::Gt | BinOp::Le | BinOp::Ge | BinOp::In => 5,
+            BinOp::BitOr => 6,
+            BinOp::BitXor => 7,
+            BinOp::BitAnd => 8,
+            BinOp::Shl | BinOp::Shr => 9,
+            BinOp::Add | BinOp::Sub => 10,
+            BinOp::Mul | BinOp::Div | BinOp::Rem => 20,
+        }
+    }
+
+    pub fn is_bitwise(&self) -> bool {
+        matches!(self, BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor | BinOp::Shl | BinOp::Shr)
+    }
+
+    pub fn is_comparison(&self) -> bool {
+        matches!(self, BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Gt | BinOp::Le | BinOp::Ge)
+    }
+
+    // Операторы, которые пользовательский тип может определить методом operator+ и т.п.
+    pub fn is_overloadable(&self) -> bool {
+        matches!(self, BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Rem) || self.is_comparison()
+    }
+
+    pub fn method_name(&self) -> String {
+        format!("operator{}", self.symbol())
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Rem => "%",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Lt => "<",
+            BinOp::Gt => ">",
+            BinOp::Le => "<=",
+            BinOp::Ge => ">=",
+            BinOp::BitAnd => "&",
+            BinOp::BitOr => "|",
+            BinOp::BitXor => "^",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+            BinOp::In => "in",
+            BinOp::Coalesce => "??",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -26,24 +233,295 @@ pub enum Value {
     Integer(i64),
     Float(f64),
     Boolean(bool),
+    BigInt(String),
+    Bytes(Vec<u8>),
+    Array {
+        element_type: VarType,
+        items: Vec<Value>,
+    },
+    Map {
+        key_type: VarType,
+        value_type: VarType,
+        entries: Vec<(Value, Value)>,
+    },
+    Struct {
+        name: String,
+        fields: Vec<(String, Value)>,
+    },
+    Enum {
+        name: String,
+        variant: String,
+        payload: Vec<Value>,
+    },
+    // Значение необязательного типа T? — есть или none
+    Some(Box<Value>),
+    None,
+    // ok(value) или err(value) с известными типами обеих веток
+    Result {
+        ok_type: VarType,
+        err_type: VarType,
+        ok: bool,
+        value: Box<Value>,
+    },
 }
 
 #[derive(Debug, Clone)]
 pub enum Stmt {
     Declaration {
-        var_type: VarType,
+        // У let тип не записан, его выводит семантический анализ из значения
+        var_type: Option<VarType>,
+        name: String,
+        value: Expr,
+        constant: bool,
+        // Без mut переменной нельзя присвоить новое значение
+        mutable: bool,
+        doc: Option<String>,
+        // Место имени переменной
+        span: Span,
+    },
+    // let (x, y) = p и let Point { x, y } = p
+    // Место всей инструкции
+    Destructure {
+        pattern: StructPattern,
+        value: Expr,
+        span: Span,
+    },
+    Assignment {
+        name: String,
+        value: Expr,
+        span: Span,
+    },
+    IndexAssignment {
+        target: Expr,
+        index: Expr,
+        value: Expr,
+        span: Span,
+    },
+    FieldAssignment {
+        target: Expr,
+        field: String,
+        value: Expr,
+        span: Span,
+    },
+    // У match и циклов место — заголовок до тела
+    Match {
+        value: Expr,
+        arms: Vec<MatchArm>,
+        span: Span,
+    },
+    For {
+        label: Option<String>,
+        init: Box<Stmt>,
+        condition: Expr,
+        update: Box<Stmt>,
+        body: Vec<Stmt>,
+        span: Span,
+    },
+    // for x in xs — по элементам массива или символам строки
+    ForIn {
+        label: Option<String>,
+        variable: String,
+        iterable: Expr,
+        body: Vec<Stmt>,
+        span: Span,
+    },
+    While {
+        label: Option<String>,
+        condition: Expr,
+        body: Vec<Stmt>,
+        span: Span,
+    },
+    Loop {
+        label: Option<String>,
+        body: Vec<Stmt>,
+    },
+    Break(Option<String>),
+    Continue(Option<String>),
+    Return(Option<Expr>),
+    Block(Vec<Stmt>),
+    // Инструкция выполняется при выходе из блока, в котором стоит defer
+    Defer(Box<Stmt>),
+    // Ошибка выполнения внутри body передаёт управление в handler, её текст — в error
+    Try {
+        body: Vec<Stmt>,
+        error: String,
+        handler: Vec<Stmt>,
+    },
+    Function {
+        name: String,
+        params: Vec<Param>,
+        return_type: Option<VarType>,
+        body: Vec<Stmt>,
+        doc: Option<String>,
+        attributes: Vec<Attribute>,
+        // Место имени функции
+        span: Span,
+    },
+    Struct {
+        name: String,
+        fields: Vec<Field>,
+        doc: Option<String>,
+        attributes: Vec<Attribute>,
+    },
+    Enum {
+        name: String,
+        variants: Vec<Variant>,
+        doc: Option<String>,
+        attributes: Vec<Attribute>,
+    },
+    Trait {
         name: String,
-        value: Value,
+        methods: Vec<MethodSignature>,
+        doc: Option<String>,
+        attributes: Vec<Attribute>,
+    },
+    Impl {
+        trait_name: String,
+        self_type: VarType,
+        methods: Vec<Method>,
+        // Заголовок impl Trait for Type
+        span: Span,
     },
     Expression(Expr),
+    // import "utils.qrk"; — путь относительно импортирующего файла, загрузкой занимается CLI
+    Import(String),
+}
+
+impl Stmt {
+    // Место инструкции для диагностик; у инструкций без собственного участка берётся их выражение
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Stmt::Declaration { span, .. }
+            | Stmt::Destructure { span, .. }
+            | Stmt::Assignment { span, .. }
+            | Stmt::IndexAssignment { span, .. }
+            | Stmt::FieldAssignment { span, .. }
+            | Stmt::Match { span, .. }
+            | Stmt::For { span, .. }
+            | Stmt::ForIn { span, .. }
+            | Stmt::While { span, .. }
+            | Stmt::Function { span, .. }
+            | Stmt::Impl { span, .. } => Some(*span),
+            Stmt::Expression(expr) | Stmt::Return(Some(expr)) => expr.span(),
+            _ => None,
+        }
+    }
+}
+
+impl Expr {
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Expr::BinaryOp { span, .. }
+            | Expr::UnaryOp { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::MethodCall { span, .. } => Some(*span),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: String,
+    pub var_type: VarType,
+}
+
+// #[deprecated("use g")] — имя атрибута и его строковые аргументы
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: String,
+    pub var_type: VarType,
+    pub doc: Option<String>,
+}
+
+// Параметры методов не включают self
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodSignature {
+    pub name: String,
+    pub params: Vec<Param>,
+    pub return_type: Option<VarType>,
+    pub doc: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Method {
+    pub signature: MethodSignature,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub name: String,
+    pub payload: Vec<VarType>,
+    pub doc: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Literal(Value),
+    // Shape::Rect(w, h) — связывает значения варианта с именами, _ пропускает значение
+    Variant {
+        enum_name: String,
+        variant: String,
+        bindings: Vec<String>,
+    },
+    Struct(StructPattern),
+    Wildcard,
 }
 
+// Разбор структуры на переменные: _ пропускает поле, .. в конце — все оставшиеся
 #[derive(Debug, Clone)]
+pub enum StructPattern {
+    // (x, y) — поля по порядку объявления в структуре
+    Positional {
+        bindings: Vec<String>,
+        rest: bool,
+    },
+    // Point { x, y: py } — поле x в переменную x, поле y в py
+    Named {
+        name: String,
+        fields: Vec<(String, String)>,
+        rest: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum VarType {
     String,
     Integer,
     Float,
     Boolean,
+    BigInt,
+    Bytes,
+    Array(Box<VarType>),
+    Map(Box<VarType>, Box<VarType>),
+    Struct(String),
+    Enum(String),
+    // Значение любого типа, реализующего трейт; допускается только в параметрах
+    Trait(String),
+    // T? — значение типа T или none
+    Option(Box<VarType>),
+    // Тип литерала none, подходит любому T?
+    None,
+    // Result<T, E> — значение T или ошибка E
+    Result(Box<VarType>, Box<VarType>),
+    // Типы ok(value) и err(value): вторая ветка берётся из того, куда значение сохраняют
+    Ok(Box<VarType>),
+    Err(Box<VarType>),
+    // func(Integer) -> Integer — функция или замыкание с такими параметрами и результатом
+    Function(Vec<VarType>, Option<Box<VarType>>),
 }
 
 #[derive(Debug, Clone)]
@@ -53,38 +531,137 @@ pub struct Program {
 
 #[derive(Debug, Clone)]
 pub struct ParseError {
+    pub code: &'static str,
     pub message: String,
-    pub line: usize,
-    pub column: usize,
+    // Токен, на котором разбор остановился
+    pub span: Span,
+    pub notes: Vec<Note>,
 }
 
 impl std::fmt::Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} at line {}:{}", self.message, self.line, self.column)
+        write!(f, "{} at line {}:{}", self.message, self.span.line, self.span.column)
     }
 }
 
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+// Раскрытие макроса внутри раскрытия другого — не глубже этого, иначе макрос рекурсивен
+pub const MAX_MACRO_DEPTH: usize = 64;
+
+// macro log(msg) { ... } — тело хранится токенами вместе с переводами строк
+#[derive(Debug, Clone)]
+struct MacroDef {
+    params: Vec<String>,
+    body: Vec<(Token, bool, Span)>,
+}
+
 pub struct Parser {
     tokens: Vec<Token>,
+    // line_breaks[i] — стоял ли перевод строки перед tokens[i]
+    line_breaks: Vec<bool>,
+    // spans[i] — место tokens[i] в исходнике
+    spans: Vec<Span>,
+    // Объявленные переменные и их типы, если парсер их знает
+    declared: std::collections::HashMap<String, Option<VarType>>,
+    // Типы результатов уже разобранных функций — по ним парсер узнаёт тип переменной из let
+    functions: std::collections::HashMap<String, Option<VarType>>,
     position: usize,
-    current_line: usize,
-    current_column: usize,
+    depth: usize,
+    max_depth: usize,
+    loop_depth: usize,
+    in_function: bool,
+    structs: std::collections::HashMap<String, Vec<Field>>,
+    enums: std::collections::HashMap<String, Vec<Variant>>,
+    traits: std::collections::HashMap<String, Vec<MethodSignature>>,
+    modules: std::collections::HashSet<String>,
+    macros: std::collections::HashMap<String, MacroDef>,
+    // Где заканчиваются ещё не разобранные раскрытия макросов, от внешнего к внутреннему
+    expansions: Vec<usize>,
+    // Ошибки, после которых разбор продолжился со следующей инструкции
+    errors: Vec<ParseError>,
+    // Где в текущем модуле объявлены типы и макросы — для заметок к повторным объявлениям
+    definitions: std::collections::HashMap<(&'static str, String), Span>,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+    pub fn new(tokens: Vec<SpannedToken>) -> Self {
+        let (tokens, line_breaks, spans) = split_line_breaks(tokens);
         Self {
             tokens,
+            line_breaks,
+            spans,
+            declared: std::collections::HashMap::new(),
+            functions: std::collections::HashMap::new(),
             position: 0,
-            current_line: 1,
-            current_column: 1,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            loop_depth: 0,
+            in_function: false,
+            structs: std::collections::HashMap::new(),
+            enums: std::collections::HashMap::new(),
+            traits: std::collections::HashMap::new(),
+            modules: std::collections::HashSet::new(),
+            macros: std::collections::HashMap::new(),
+            expansions: Vec::new(),
+            errors: Vec::new(),
+            definitions: std::collections::HashMap::new(),
+        }
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // Ограничиваем глубину рекурсии, чтобы не переполнить стек
+    fn enter_nested(&mut self) -> Result<(), ParseError> {
+        if self.depth >= self.max_depth {
+            return Err(ParseError {
+                code: "E0206",
+                message: format!("Program too deeply nested (limit is {} levels)", self.max_depth),
+                span: self.here(),
+                notes: Vec::new(),
+            });
         }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn leave_nested(&mut self) {
+        self.depth -= 1;
+    }
+
+    // Цепочки a + b + c, a.b().c() и x as T as U строятся циклом, но каждое звено вкладывает
+    // левую часть ещё на уровень, а семантика, свёртка и генерация обходят дерево рекурсивно.
+    // Поэтому звенья тоже входят в глубину, а после цепочки она восстанавливается
+    fn chained(&mut self, parse: impl FnOnce(&mut Self) -> Result<Expr, ParseError>) -> Result<Expr, ParseError> {
+        let depth = self.depth;
+        let result = parse(self);
+        self.depth = depth;
+        result
     }
 
     fn peek(&self) -> Option<&Token> {
         self.tokens.get(self.position)
     }
 
+    // Место следующего токена; за концом потока — место последнего
+    fn here(&self) -> Span {
+        self.spans.get(self.position).or(self.spans.last()).copied().unwrap_or_default()
+    }
+
+    // Место только что прочитанного токена
+    fn previous(&self) -> Span {
+        self.spans.get(self.position.saturating_sub(1)).or(self.spans.last()).copied().unwrap_or_default()
+    }
+
+    // Участок от start до конца только что прочитанного токена
+    fn span_from(&self, start: Span) -> Span {
+        let end = self.previous();
+        Span { end: end.end, end_line: end.end_line, end_column: end.end_column, ..start }
+    }
+
     fn advance(&mut self) -> Option<&Token> {
         let token = self.tokens.get(self.position);
         self.position += 1;
@@ -92,237 +669,2899 @@ impl Parser {
     }
 
     fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
-        match self.advance() {
+        match self.peek() {
             Some(token) if std::mem::discriminant(token) == std::mem::discriminant(&expected) => {
+                self.advance();
                 Ok(())
             }
             Some(token) => Err(ParseError {
+                code: "E0201",
                 message: format!("Expected {:?}, got {:?}", expected, token),
-                line: self.current_line,
-                column: self.current_column,
+                span: self.here(),
+                notes: self.insertion_suggestion(&expected).into_iter().collect(),
             }),
             None => Err(ParseError {
+                code: "E0202",
                 message: format!("Expected {:?}, but no more tokens", expected),
-                line: self.current_line,
-                column: self.current_column,
+                span: self.here(),
+                notes: Vec::new(),
             }),
         }
     }
 
+    // Пропущенную ; или закрывающую скобку предлагается вставить сразу после предыдущего токена
+    fn insertion_suggestion(&self, expected: &Token) -> Option<Note> {
+        let (text, place) = match expected {
+            Token::Semicolon => (";", "at the end of the statement"),
+            Token::RParen => (")", "to close the parenthesis"),
+            Token::RBracket => ("]", "to close the bracket"),
+            Token::RBrace => ("}", "to close the block"),
+            _ => return None,
+        };
+        if self.position == 0 {
+            return None;
+        }
+        let previous = self.previous();
+        let span = Span {
+            start: previous.end,
+            end: previous.end,
+            line: previous.end_line,
+            column: previous.end_column,
+            end_line: previous.end_line,
+            end_column: previous.end_column,
+        };
+        Some(Note::suggestion(format!("add '{}' {}", text, place), span, text.to_string()))
+    }
+
+    // Инструкцию завершает ; или перевод строки, а перед } и в конце файла не нужно ни то ни другое
+    fn at_terminator(&self) -> bool {
+        matches!(self.peek(), Some(Token::Semicolon | Token::RBrace | Token::EOF) | None) || self.at_line_start()
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.line_breaks.get(self.position).copied().unwrap_or(false)
+    }
+
+    fn expect_terminator(&mut self) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(Token::Semicolon) => {
+                self.advance();
+                Ok(())
+            }
+            _ if self.at_terminator() => Ok(()),
+            _ => self.expect(Token::Semicolon),
+        }
+    }
+
+    // В Array<Array<Integer>> лексер отдаёт >> одним токеном, поэтому он делится на два >
+    fn expect_closing_angle(&mut self) -> Result<(), ParseError> {
+        if let Some(Token::ShiftRight) = self.peek() {
+            self.tokens[self.position] = Token::Greater;
+            let span = &mut self.spans[self.position];
+            span.start += 1;
+            span.column += 1;
+            return Ok(());
+        }
+        self.expect(Token::Greater)
+    }
+
+    fn at_type(&self) -> bool {
+        match self.peek() {
+            // Point p = ... — объявление переменной структурного типа или перечисления
+            Some(Token::Ident(name)) => {
+                self.is_type_name(name)
+                    && matches!(self.tokens.get(self.position + 1), Some(Token::Ident(_) | Token::Question))
+            }
+            // func(...) — тип функции; объявление функции начинается с func name(
+            Some(Token::Func) => matches!(self.tokens.get(self.position + 1), Some(Token::LParen)),
+            token => matches!(
+                token,
+                Some(Token::StringType | Token::IntegerType | Token::FloatType | Token::BooleanType
+                    | Token::BigIntType | Token::BytesType | Token::ArrayType | Token::MapType | Token::ResultType)
+            ),
+        }
+    }
+
+    fn is_user_type(&self, name: &str) -> bool {
+        self.structs.contains_key(name) || self.enums.contains_key(name)
+    }
+
+    fn is_type_name(&self, name: &str) -> bool {
+        self.is_user_type(name) || self.traits.contains_key(name)
+    }
+
+    // span — место самого ключевого слова
+    fn reserved_keyword_error(&self, word: &str, span: Span) -> ParseError {
+        ParseError {
+            code: "E0305",
+            message: format!("'{}' is a reserved keyword", word),
+            span,
+            notes: vec![Note::suggestion("choose another name".to_string(), span, format!("{}_", word))],
+        }
+    }
+
+    // Повтор отмечается на имени, а заметка указывает на первое объявление в этом модуле
+    fn duplicate_error(&self, kind: &'static str, name: &str, message: String) -> ParseError {
+        let notes = match self.definitions.get(&(kind, name.to_string())) {
+            Some(first) => vec![Note::located(format!("'{}' is first defined here", name), *first)],
+            None => Vec::new(),
+        };
+        ParseError { code: "E0304", message, span: self.previous(), notes }
+    }
+
     fn parse_type(&mut self) -> Result<VarType, ParseError> {
+        let var_type = self.parse_base_type()?;
+        if !matches!(self.peek(), Some(Token::Question)) {
+            return Ok(var_type);
+        }
+        self.advance();
+        if let Some(Token::Question) = self.peek() {
+            return Err(ParseError {
+                code: "E0299",
+                message: "Nested optional types are not supported".to_string(),
+                span: self.here(),
+                notes: Vec::new(),
+            });
+        }
+        Ok(VarType::Option(Box::new(var_type)))
+    }
+
+    fn parse_base_type(&mut self) -> Result<VarType, ParseError> {
+        let span = self.here();
         match self.advance() {
             Some(Token::StringType) => Ok(VarType::String),
             Some(Token::IntegerType) => Ok(VarType::Integer),
             Some(Token::FloatType) => Ok(VarType::Float),
             Some(Token::BooleanType) => Ok(VarType::Boolean),
+            Some(Token::BigIntType) => Ok(VarType::BigInt),
+            Some(Token::BytesType) => Ok(VarType::Bytes),
+            Some(Token::ArrayType) => {
+                self.expect(Token::Less)?;
+                self.enter_nested()?;
+                let element_type = self.parse_type();
+                self.leave_nested();
+                let element_type = element_type?;
+                self.expect_closing_angle()?;
+                Ok(VarType::Array(Box::new(element_type)))
+            }
+            Some(Token::MapType) => {
+                self.expect(Token::Less)?;
+                self.enter_nested()?;
+                let types = self.parse_map_types();
+                self.leave_nested();
+                let (key_type, value_type) = types?;
+                self.expect_closing_angle()?;
+                Ok(VarType::Map(Box::new(key_type), Box::new(value_type)))
+            }
+            Some(Token::ResultType) => {
+                self.expect(Token::Less)?;
+                self.enter_nested()?;
+                let types = self.parse_result_types();
+                self.leave_nested();
+                let (ok_type, err_type) = types?;
+                self.expect_closing_angle()?;
+                self.check_not_function(&ok_type, "Result values")?;
+                self.check_not_function(&err_type, "Result errors")?;
+                Ok(VarType::Result(Box::new(ok_type), Box::new(err_type)))
+            }
+            Some(Token::Func) => {
+                self.expect(Token::LParen)?;
+                self.enter_nested()?;
+                let types = self.parse_function_type();
+                self.leave_nested();
+                let (params, return_type) = types?;
+                Ok(VarType::Function(params, return_type.map(Box::new)))
+            }
+            Some(Token::Ident(name)) => {
+                let name = name.clone();
+                if self.enums.contains_key(&name) {
+                    return Ok(VarType::Enum(name));
+                }
+                if self.traits.contains_key(&name) {
+                    return Err(ParseError {
+                        code: "E0299",
+                        message: format!("Trait '{}' can only be used as a parameter type", name),
+                        span,
+                        notes: Vec::new(),
+                    });
+                }
+                if !self.structs.contains_key(&name) {
+                    return Err(ParseError {
+                        code: "E0303",
+                        message: format!("Unknown type '{}'", name),
+                        span,
+                        notes: Vec::new(),
+                    });
+                }
+                Ok(VarType::Struct(name))
+            }
             Some(token) => Err(ParseError {
+                code: "E0201",
                 message: format!("Expected type, got {:?}", token),
-                line: self.current_line,
-                column: self.current_column,
+                span,
+                notes: Vec::new(),
             }),
             None => Err(ParseError {
+                code: "E0201",
                 message: "Expected type".to_string(),
-                line: self.current_line,
-                column: self.current_column,
+                span,
+                notes: Vec::new(),
+            }),
+        }
+    }
+
+    fn parse_map_types(&mut self) -> Result<(VarType, VarType), ParseError> {
+        let key_type = self.parse_type()?;
+        self.check_map_key_type(&key_type)?;
+        self.expect(Token::Comma)?;
+        let value_type = self.parse_type()?;
+        Ok((key_type, value_type))
+    }
+
+    fn parse_result_types(&mut self) -> Result<(VarType, VarType), ParseError> {
+        let ok_type = self.parse_type()?;
+        self.expect(Token::Comma)?;
+        let err_type = self.parse_type()?;
+        Ok((ok_type, err_type))
+    }
+
+    fn parse_function_type(&mut self) -> Result<(Vec<VarType>, Option<VarType>), ParseError> {
+        let mut params = Vec::new();
+        if !matches!(self.peek(), Some(Token::RParen)) {
+            params.push(self.parse_type()?);
+            while let Some(Token::Comma) = self.peek() {
+                self.advance();
+                if let Some(Token::RParen) = self.peek() {
+                    break;
+                }
+                params.push(self.parse_type()?);
+            }
+        }
+        self.expect(Token::RParen)?;
+        Ok((params, self.parse_return_type()?))
+    }
+
+    // Значения по умолчанию для полей и Result строятся из литералов, а у функции литерала нет
+    fn check_not_function(&self, var_type: &VarType, place: &str) -> Result<(), ParseError> {
+        match var_type {
+            VarType::Function(..) => Err(ParseError {
+                code: "E0299",
+                message: format!("{} cannot have a function type", place),
+                span: self.here(),
+                notes: Vec::new(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    // Ключи хэшируются рантаймом, он умеет только строки и целые числа
+    fn check_map_key_type(&self, key_type: &VarType) -> Result<(), ParseError> {
+        match key_type {
+            VarType::String | VarType::Integer => Ok(()),
+            _ => Err(ParseError {
+                code: "E0101",
+                message: format!("Map keys must be String or Integer, got {:?}", key_type),
+                span: self.here(),
+                notes: Vec::new(),
+            }),
+        }
+    }
+
+    fn parse_number(&self, num: &str) -> Result<Value, ParseError> {
+        if num.contains('.') {
+            match num.parse::<f64>() {
+                Ok(f) => Ok(Value::Float(f)),
+                Err(_) => Err(ParseError {
+                    code: "E0004",
+                    message: format!("Invalid float literal: {}", num),
+                    span: self.previous(),
+                    notes: Vec::new(),
+                }),
+            }
+        } else {
+            match num.parse::<i64>() {
+                Ok(i) => Ok(Value::Integer(i)),
+                Err(_) => Err(ParseError {
+                    code: "E0004",
+                    message: format!(
+                        "Integer literal {} is out of range (valid range is {}..={}); declare it as BigInt to keep the exact value",
+                        num, i64::MIN, i64::MAX
+                    ),
+                    span: self.previous(),
+                    notes: Vec::new(),
+                }),
+            }
+        }
+    }
+
+    // Знак перед числом становится частью литерала: -9223372036854775808 тоже допустим
+    fn parse_signed_number(&mut self) -> Result<String, ParseError> {
+        let negative = matches!(self.peek(), Some(Token::Minus));
+        if negative {
+            self.advance();
+        }
+        
+        let span = self.here();
+        match self.advance() {
+            Some(Token::NumberLiteral(num)) if negative => Ok(format!("-{}", num)),
+            Some(Token::NumberLiteral(num)) => Ok(num.clone()),
+            Some(token) => Err(ParseError {
+                code: "E0201",
+                message: format!("Expected number, got {:?}", token),
+                span,
+                notes: Vec::new(),
+            }),
+            None => Err(ParseError {
+                code: "E0201",
+                message: "Expected number".to_string(),
+                span,
+                notes: Vec::new(),
             }),
         }
     }
 
     fn parse_value(&mut self) -> Result<Value, ParseError> {
+        if let Some(Token::NumberLiteral(_)) | Some(Token::Minus) = self.peek() {
+            let num = self.parse_signed_number()?;
+            return self.parse_number(&num);
+        }
+        
+        let span = self.here();
         match self.advance() {
             Some(Token::StringLiteral(s)) => Ok(Value::String(s.clone())),
-            Some(Token::NumberLiteral(num)) => {
-                if num.contains('.') {
-                    match num.parse::<f64>() {
-                        Ok(f) => Ok(Value::Float(f)),
-                        Err(_) => Err(ParseError {
-                            message: format!("Invalid float literal: {}", num),
-                            line: self.current_line,
-                            column: self.current_column,
-                        }),
-                    }
-                } else {
-                    match num.parse::<i64>() {
-                        Ok(i) => Ok(Value::Integer(i)),
-                        Err(_) => Err(ParseError {
-                            message: format!("Invalid integer literal: {}", num),
-                            line: self.current_line,
-                            column: self.current_column,
-                        }),
-                    }
-                }
-            }
+            Some(Token::BytesLiteral(bytes)) => Ok(Value::Bytes(bytes.clone())),
             Some(Token::True) => Ok(Value::Boolean(true)),
             Some(Token::False) => Ok(Value::Boolean(false)),
+            Some(Token::None) => Ok(Value::None),
             Some(token) => Err(ParseError {
+                code: "E0201",
                 message: format!("Expected value, got {:?}", token),
-                line: self.current_line,
-                column: self.current_column,
+                span,
+                notes: Vec::new(),
             }),
             None => Err(ParseError {
+                code: "E0201",
                 message: "Expected value".to_string(),
-                line: self.current_line,
-                column: self.current_column,
+                span,
+                notes: Vec::new(),
             }),
         }
     }
 
-    fn parse_primary_expression(&mut self) -> Result<Expr, ParseError> {
-        match self.peek() {
-            Some(Token::StringLiteral(_)) | Some(Token::NumberLiteral(_)) | 
-            Some(Token::True) | Some(Token::False) => {
-                let value = self.parse_value()?;
-                Ok(Expr::Literal(value))
-            }
-            Some(Token::Ident(_)) => {
-                let name = match self.advance() {
-                    Some(Token::Ident(name)) => name.clone(),
-                    _ => unreachable!(),
-                };
-                Ok(Expr::Variable(name))
-            }
-            Some(Token::LParen) => {
-                self.advance(); // пропускаем (
-                let expr = self.parse_expression()?;
-                self.expect(Token::RParen)?;
-                Ok(expr)
+    // Значение, которое должно иметь тип var_type
+    fn parse_typed_value(&mut self, var_type: &VarType) -> Result<Value, ParseError> {
+        let value = match var_type {
+            VarType::BigInt => self.parse_big_int_value()?,
+            VarType::Array(element_type) => self.parse_array_literal(Some(element_type))?,
+            VarType::Map(key_type, value_type) => self.parse_map_literal(Some((key_type, value_type)))?,
+            VarType::Struct(name) => self.parse_struct_literal(Some(name))?,
+            VarType::Enum(name) => self.parse_enum_literal(Some(name))?,
+            VarType::Option(_) if matches!(self.peek(), Some(Token::None)) => self.parse_value()?,
+            VarType::Option(inner) => Value::Some(Box::new(self.parse_typed_value(inner)?)),
+            VarType::Result(ok_type, err_type) => self.parse_result_literal(ok_type, err_type)?,
+            _ => self.parse_value()?,
+        };
+        
+        match (var_type, &value) {
+            (VarType::String, Value::String(_)) => {}
+            (VarType::Integer, Value::Integer(_)) => {}
+            (VarType::Float, Value::Float(_)) => {}
+            (VarType::Boolean, Value::Boolean(_)) => {}
+            (VarType::BigInt, Value::BigInt(_)) => {}
+            (VarType::Bytes, Value::Bytes(_)) => {}
+            (VarType::Array(_), Value::Array { .. }) => {}
+            (VarType::Map(..), Value::Map { .. }) => {}
+            (VarType::Struct(expected), Value::Struct { name, .. }) if expected == name => {}
+            (VarType::Enum(expected), Value::Enum { name, .. }) if expected == name => {}
+            (VarType::Option(_), Value::Some(_) | Value::None) => {}
+            (VarType::Result(..), Value::Result { .. }) => {}
+            _ => {
+                return Err(ParseError {
+                    code: "E0101",
+                    message: format!("Type mismatch: cannot assign {:?} to {:?}", value, var_type),
+                    span: self.here(),
+                    notes: Vec::new(),
+                })
             }
-            _ => Err(ParseError {
-                message: "Expected expression".to_string(),
-                line: self.current_line,
-                column: self.current_column,
-            }),
         }
+        Ok(value)
     }
 
-    fn parse_expression(&mut self) -> Result<Expr, ParseError> {
-        let mut left = self.parse_primary_expression()?;
+    // ok(value) или err(value) в объявлении переменной типа Result<T, E>
+    fn parse_result_literal(&mut self, ok_type: &VarType, err_type: &VarType) -> Result<Value, ParseError> {
+        let span = self.here();
+        let ok = match self.advance() {
+            Some(Token::Ident(name)) if name == "ok" => true,
+            Some(Token::Ident(name)) if name == "err" => false,
+            token => {
+                return Err(ParseError {
+                    code: "E0201",
+                    message: format!("Expected ok(...) or err(...), got {:?}", token),
+                    span,
+                    notes: Vec::new(),
+                })
+            }
+        };
+        self.expect(Token::LParen)?;
+        self.enter_nested()?;
+        let value = self.parse_typed_value(if ok { ok_type } else { err_type });
+        self.leave_nested();
+        let value = value?;
+        self.expect(Token::RParen)?;
         
-        while let Some(Token::Plus) = self.peek() {
-            self.advance(); // пропускаем +
-            let right = self.parse_primary_expression()?;
-            
-            left = Expr::BinaryOp {
-                left: Box::new(left),
+        Ok(Value::Result {
+            ok_type: ok_type.clone(),
+            err_type: err_type.clone(),
+            ok,
+            value: Box::new(value),
+        })
+    }
+
+    // Без известного типа элементов он берётся из первого элемента
+    fn parse_array_literal(&mut self, element_type: Option<&VarType>) -> Result<Value, ParseError> {
+        self.expect(Token::LBracket)?;
+        self.enter_nested()?;
+        let result = self.parse_array_items(element_type);
+        self.leave_nested();
+        result
+    }
+
+    fn parse_array_items(&mut self, element_type: Option<&VarType>) -> Result<Value, ParseError> {
+        let mut element_type = element_type.cloned();
+        let mut items = Vec::new();
+        
+        while !matches!(self.peek(), Some(Token::RBracket)) {
+            let item = match &element_type {
+                Some(element_type) => self.parse_typed_value(element_type)?,
+                None => {
+                    let item = self.parse_literal()?;
+                    element_type = Some(value_type(&item));
+                    item
+                }
+            };
+            items.push(item);
+            
+            match self.peek() {
+                Some(Token::Comma) => {
+                    self.advance();
+                }
+                _ => break,
+            }
+        }
+        self.expect(Token::RBracket)?;
+        
+        let element_type = element_type.ok_or_else(|| ParseError {
+            code: "E0103",
+            message: "Cannot infer the element type of an empty array literal; declare the variable as Array<T>".to_string(),
+            span: self.here(),
+            notes: Vec::new(),
+        })?;
+        Ok(Value::Array { element_type, items })
+    }
+
+    fn parse_map_literal(&mut self, types: Option<(&VarType, &VarType)>) -> Result<Value, ParseError> {
+        self.expect(Token::LBrace)?;
+        self.enter_nested()?;
+        let result = self.parse_map_entries(types);
+        self.leave_nested();
+        result
+    }
+
+    fn parse_map_entries(&mut self, types: Option<(&VarType, &VarType)>) -> Result<Value, ParseError> {
+        let mut types = types.map(|(key, value)| (key.clone(), value.clone()));
+        let mut entries = Vec::new();
+        
+        while !matches!(self.peek(), Some(Token::RBrace)) {
+            let entry = match &types {
+                Some((key_type, value_type)) => {
+                    let key = self.parse_typed_value(key_type)?;
+                    self.expect(Token::Colon)?;
+                    (key, self.parse_typed_value(value_type)?)
+                }
+                None => {
+                    let key = self.parse_value()?;
+                    self.check_map_key_type(&value_type(&key))?;
+                    self.expect(Token::Colon)?;
+                    let value = self.parse_literal()?;
+                    types = Some((value_type(&key), value_type(&value)));
+                    (key, value)
+                }
+            };
+            entries.push(entry);
+            
+            match self.peek() {
+                Some(Token::Comma) => {
+                    self.advance();
+                }
+                _ => break,
+            }
+        }
+        self.expect(Token::RBrace)?;
+        
+        let (key_type, value_type) = types.ok_or_else(|| ParseError {
+            code: "E0103",
+            message: "Cannot infer the types of an empty map literal; declare the variable as Map<K, V>".to_string(),
+            span: self.here(),
+            notes: Vec::new(),
+        })?;
+        Ok(Value::Map { key_type, value_type, entries })
+    }
+
+    // Литерал любого типа, включая массивы, словари, структуры и перечисления
+    fn parse_literal(&mut self) -> Result<Value, ParseError> {
+        match self.peek() {
+            Some(Token::LBracket) => self.parse_array_literal(None),
+            Some(Token::LBrace) => self.parse_map_literal(None),
+            Some(Token::Ident(_)) if self.at_enum_literal() => self.parse_enum_literal(None),
+            Some(Token::Ident(_)) if self.at_struct_literal() => self.parse_struct_literal(None),
+            _ => self.parse_value(),
+        }
+    }
+
+    fn at_struct_literal(&self) -> bool {
+        match (self.peek(), self.tokens.get(self.position + 1)) {
+            (Some(Token::Ident(name)), Some(Token::LBrace)) => self.structs.contains_key(name),
+            _ => false,
+        }
+    }
+
+    fn at_enum_literal(&self) -> bool {
+        match (self.peek(), self.tokens.get(self.position + 1)) {
+            (Some(Token::Ident(name)), Some(Token::ColonColon)) => self.enums.contains_key(name),
+            _ => false,
+        }
+    }
+
+    // Shape::Circle(1.5) или Shape::Empty — вариант и значения по порядку
+    fn parse_enum_literal(&mut self, expected: Option<&str>) -> Result<Value, ParseError> {
+        let (name, variant, payload) = self.parse_enum_body(expected, Self::parse_typed_value)?;
+        Ok(Value::Enum { name, variant, payload })
+    }
+
+    // Shape::Circle(r * 2) — значения вычисляются при выполнении
+    fn parse_enum_expression(&mut self) -> Result<Expr, ParseError> {
+        let (name, variant, payload) = self.parse_enum_body(None, Self::parse_typed_expression)?;
+        Ok(Expr::Enum { name, variant, payload })
+    }
+
+    fn parse_enum_body<T>(
+        &mut self,
+        expected: Option<&str>,
+        parse_value: fn(&mut Self, &VarType) -> Result<T, ParseError>,
+    ) -> Result<(String, String, Vec<T>), ParseError> {
+        let (name, variant) = self.parse_variant_path()?;
+        if let Some(expected) = expected {
+            if expected != name {
+                return Err(ParseError {
+                    code: "E0101",
+                    message: format!("Type mismatch: expected a {} value, got {}", expected, name),
+                    span: self.here(),
+                    notes: Vec::new(),
+                });
+            }
+        }
+        
+        let mut payload = Vec::new();
+        if !variant.payload.is_empty() {
+            self.expect(Token::LParen)?;
+            self.enter_nested()?;
+            let values = variant
+                .payload
+                .iter()
+                .enumerate()
+                .map(|(index, var_type)| {
+                    if index > 0 {
+                        self.expect(Token::Comma)?;
+                    }
+                    parse_value(self, var_type)
+                })
+                .collect::<Result<Vec<_>, _>>();
+            self.leave_nested();
+            payload = values?;
+            if let (Some(Token::Comma), Some(Token::RParen)) = (self.peek(), self.tokens.get(self.position + 1)) {
+                self.advance();
+            }
+            if !matches!(self.peek(), Some(Token::RParen)) {
+                return Err(ParseError {
+                    code: "E0102",
+                    message: format!("{}::{} takes {} value(s)", name, variant.name, variant.payload.len()),
+                    span: self.here(),
+                    notes: Vec::new(),
+                });
+            }
+            self.expect(Token::RParen)?;
+        }
+        Ok((name, variant.name, payload))
+    }
+
+    // Enum::Variant, используется и в литералах, и в образцах match
+    fn parse_variant_path(&mut self) -> Result<(String, Variant), ParseError> {
+        let name = self.parse_name("enum name")?;
+        let variants = match self.enums.get(&name) {
+            Some(variants) => variants.clone(),
+            None => {
+                return Err(ParseError {
+                    code: "E0303",
+                    message: format!("Unknown enum '{}'", name),
+                    span: self.previous(),
+                    notes: Vec::new(),
+                })
+            }
+        };
+        self.expect(Token::ColonColon)?;
+        let variant_name = self.parse_name("variant name")?;
+        let variant = variants.into_iter().find(|v| v.name == variant_name).ok_or_else(|| ParseError {
+            code: "E0303",
+            message: format!("Enum {} has no variant '{}'", name, variant_name),
+            span: self.previous(),
+            notes: Vec::new(),
+        })?;
+        Ok((name, variant))
+    }
+
+    // Point { x: 1, y: 2 } — все поля должны быть заданы, порядок любой
+    fn parse_struct_literal(&mut self, expected: Option<&str>) -> Result<Value, ParseError> {
+        let (name, fields) = self.parse_struct_body(expected, Self::parse_typed_value)?;
+        Ok(Value::Struct { name, fields })
+    }
+
+    // Point { x: a + 1, y: b } — поля вычисляются при выполнении
+    fn parse_struct_expression(&mut self) -> Result<Expr, ParseError> {
+        let (name, fields) = self.parse_struct_body(None, Self::parse_typed_expression)?;
+        Ok(Expr::Struct { name, fields })
+    }
+
+    // Общее у литерала и выражения; значения полей разбирает parse_field
+    fn parse_struct_body<T>(
+        &mut self,
+        expected: Option<&str>,
+        parse_field: fn(&mut Self, &VarType) -> Result<T, ParseError>,
+    ) -> Result<(String, Vec<(String, T)>), ParseError> {
+        let name = self.parse_name("struct name")?;
+        let fields = match self.structs.get(&name) {
+            Some(fields) => fields.clone(),
+            None => {
+                return Err(ParseError {
+                    code: "E0303",
+                    message: format!("Unknown struct '{}'", name),
+                    span: self.previous(),
+                    notes: Vec::new(),
+                })
+            }
+        };
+        if let Some(expected) = expected {
+            if expected != name {
+                return Err(ParseError {
+                    code: "E0101",
+                    message: format!("Type mismatch: expected a {} literal, got {}", expected, name),
+                    span: self.here(),
+                    notes: Vec::new(),
+                });
+            }
+        }
+        
+        self.expect(Token::LBrace)?;
+        self.enter_nested()?;
+        let values = self.parse_struct_fields(&name, &fields, parse_field);
+        self.leave_nested();
+        let values = values?;
+        
+        if let Some(missing) = fields.iter().find(|f| !values.iter().any(|(name, _)| *name == f.name)) {
+            return Err(ParseError {
+                code: "E0303",
+                message: format!("Missing field '{}' in {} literal", missing.name, name),
+                span: self.here(),
+                notes: Vec::new(),
+            });
+        }
+        Ok((name, values))
+    }
+
+    fn parse_struct_fields<T>(
+        &mut self,
+        name: &str,
+        fields: &[Field],
+        parse_field: fn(&mut Self, &VarType) -> Result<T, ParseError>,
+    ) -> Result<Vec<(String, T)>, ParseError> {
+        let mut values: Vec<(String, T)> = Vec::new();
+        
+        while !matches!(self.peek(), Some(Token::RBrace)) {
+            let field_name = self.parse_name("field name")?;
+            let field = fields.iter().find(|f| f.name == field_name).ok_or_else(|| ParseError {
+                code: "E0303",
+                message: format!("Struct {} has no field '{}'", name, field_name),
+                span: self.previous(),
+                notes: Vec::new(),
+            })?;
+            if values.iter().any(|(name, _)| *name == field_name) {
+                return Err(ParseError {
+                    code: "E0304",
+                    message: format!("Field '{}' is set more than once", field_name),
+                    span: self.here(),
+                    notes: Vec::new(),
+                });
+            }
+            self.expect(Token::Colon)?;
+            let value = parse_field(self, &field.var_type)?;
+            values.push((field_name, value));
+            
+            match self.peek() {
+                Some(Token::Comma) => {
+                    self.advance();
+                }
+                _ => break,
+            }
+        }
+        self.expect(Token::RBrace)?;
+        Ok(values)
+    }
+
+    fn parse_big_int_value(&mut self) -> Result<Value, ParseError> {
+        let num = self.parse_signed_number()?;
+        if num.contains('.') {
+            return Err(ParseError {
+                code: "E0004",
+                message: format!("Expected integer literal for BigInt, got {}", num),
+                span: self.previous(),
+                notes: Vec::new(),
+            });
+        }
+        
+        let (sign, digits) = match num.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", num.as_str()),
+        };
+        let digits = digits.trim_start_matches('0');
+        if digits.is_empty() {
+            return Ok(Value::BigInt("0".to_string()));
+        }
+        Ok(Value::BigInt(format!("{}{}", sign, digits)))
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
+        let start = self.here();
+        let op = match self.peek() {
+            Some(Token::Minus) => {
+                // -5 сразу сворачивается в отрицательный литерал
+                if let Some(Token::NumberLiteral(_)) = self.tokens.get(self.position + 1) {
+                    return Ok(Expr::Literal(self.parse_value()?));
+                }
+                UnaryOp::Neg
+            }
+            Some(Token::Tilde) => UnaryOp::BitNot,
+            _ => {
+                let expr = self.parse_primary_expression()?;
+                return self.parse_postfix(expr);
+            }
+        };
+        
+        self.advance(); // пропускаем оператор
+        self.enter_nested()?;
+        let operand = self.parse_unary();
+        self.leave_nested();
+        
+        Ok(Expr::UnaryOp {
+            op,
+            operand: Box::new(operand?),
+            span: self.span_from(start),
+        })
+    }
+
+    // Индексация, срезы, доступ к полям и ?: xs[i], xs[1..3], m["key"], p.x, xs[i].x, read()?
+    fn parse_postfix(&mut self, expr: Expr) -> Result<Expr, ParseError> {
+        self.chained(|parser| parser.parse_postfix_chain(expr))
+    }
+
+    fn parse_postfix_chain(&mut self, mut expr: Expr) -> Result<Expr, ParseError> {
+        loop {
+            if matches!(self.peek(), Some(Token::LBracket | Token::Dot | Token::Question)) {
+                self.enter_nested()?;
+            }
+            match self.peek() {
+                Some(Token::LBracket) => {
+                    self.advance();
+                    let start = match self.peek() {
+                        Some(Token::DotDot) => None,
+                        _ => Some(Box::new(self.parse_expression()?)),
+                    };
+                    expr = match (self.peek(), start) {
+                        (Some(Token::DotDot), start) => {
+                            self.advance();
+                            let end = match self.peek() {
+                                Some(Token::RBracket) => None,
+                                _ => Some(Box::new(self.parse_expression()?)),
+                            };
+                            Expr::Slice { target: Box::new(expr), start, end }
+                        }
+                        (_, Some(index)) => Expr::Index { target: Box::new(expr), index },
+                        (_, None) => unreachable!(),
+                    };
+                    self.expect(Token::RBracket)?;
+                }
+                Some(Token::Dot) => {
+                    self.advance();
+                    let field = self.parse_name("field name")?;
+                    expr = match self.peek() {
+                        Some(Token::LParen) => match self.parse_call(field)? {
+                            Expr::Call { name, args, span } => Expr::MethodCall { target: Box::new(expr), method: name, args, span },
+                            _ => unreachable!(),
+                        },
+                        _ => Expr::Field { target: Box::new(expr), field },
+                    };
+                }
+                Some(Token::Question) => {
+                    self.advance();
+                    expr = Expr::Try(Box::new(expr));
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn parse_primary_expression(&mut self) -> Result<Expr, ParseError> {
+        match self.peek() {
+            Some(Token::Pipe) => self.parse_lambda(),
+            Some(Token::LBracket) => {
+                let start = self.position;
+                match self.parse_literal() {
+                    Ok(value) => Ok(Expr::Literal(value)),
+                    // У пустого [] нет элементов-выражений, важна ошибка самого литерала
+                    Err(error) if matches!(self.tokens.get(start + 1), Some(Token::RBracket)) => Err(error),
+                    Err(_) => {
+                        self.position = start;
+                        self.parse_array_expression()
+                    }
+                }
+            }
+            Some(Token::LBrace) => {
+                let start = self.position;
+                match self.parse_literal() {
+                    Ok(value) => Ok(Expr::Literal(value)),
+                    // Как и у [], у пустого {} важна ошибка самого литерала
+                    Err(error) if matches!(self.tokens.get(start + 1), Some(Token::RBrace)) => Err(error),
+                    Err(_) => {
+                        self.position = start;
+                        self.parse_map_expression()
+                    }
+                }
+            }
+            Some(Token::Ident(_)) if self.at_struct_literal() => {
+                let start = self.position;
+                match self.parse_struct_literal(None) {
+                    Ok(value) => Ok(Expr::Literal(value)),
+                    Err(_) => {
+                        self.position = start;
+                        self.parse_struct_expression()
+                    }
+                }
+            }
+            Some(Token::Ident(_)) if self.at_enum_literal() => {
+                let start = self.position;
+                match self.parse_enum_literal(None) {
+                    Ok(value) => Ok(Expr::Literal(value)),
+                    Err(_) => {
+                        self.position = start;
+                        self.parse_enum_expression()
+                    }
+                }
+            }
+            Some(Token::StringLiteral(_)) | Some(Token::BytesLiteral(_)) | Some(Token::NumberLiteral(_)) | 
+            Some(Token::True) | Some(Token::False) | Some(Token::None) => {
+                let value = self.parse_value()?;
+                Ok(Expr::Literal(value))
+            }
+            Some(Token::Ident(_)) => {
+                let name = match self.advance() {
+                    Some(Token::Ident(name)) => name.clone(),
+                    _ => unreachable!(),
+                };
+                let name = self.parse_qualified_name(name)?;
+                match self.peek() {
+                    Some(Token::LParen) => self.parse_call(name),
+                    _ => Ok(Expr::Variable(name)),
+                }
+            }
+            Some(Token::LParen) => {
+                self.advance(); // пропускаем (
+                let expr = self.parse_expression()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::InterpolatedString(parts)) => {
+                let parts = parts.clone();
+                self.advance();
+                self.parse_interpolation(parts)
+            }
+            Some(token) if token.keyword().is_some() => {
+                Err(self.reserved_keyword_error(token.keyword().unwrap(), self.here()))
+            }
+            _ => Err(ParseError {
+                code: "E0201",
+                message: "Expected expression".to_string(),
+                span: self.here(),
+                notes: Vec::new(),
+            }),
+        }
+    }
+
+    // "a{x}b" превращается в "a" + x + "b": сложение со строкой само приводит значения к строке
+    fn parse_interpolation(&mut self, parts: Vec<StringPart>) -> Result<Expr, ParseError> {
+        let mut result = None;
+        for part in parts {
+            let expr = match part {
+                StringPart::Text(text) => Expr::Literal(Value::String(text)),
+                StringPart::Code(tokens) => self.parse_embedded_expression(tokens)?,
+            };
+            let left = match result {
+                Some(left) => left,
+                None if matches!(expr, Expr::Literal(Value::String(_))) => {
+                    result = Some(expr);
+                    continue;
+                }
+                None => Expr::Literal(Value::String(String::new())),
+            };
+            result = Some(Expr::BinaryOp {
+                left: Box::new(left),
                 op: BinOp::Add,
+                right: Box::new(expr),
+                span: Span::default(),
+            });
+        }
+        Ok(result.unwrap())
+    }
+
+    // |x, Integer y| x + y — тело лямбды одно выражение
+    fn parse_lambda(&mut self) -> Result<Expr, ParseError> {
+        self.expect(Token::Pipe)?;
+        let mut params: Vec<LambdaParam> = Vec::new();
+        while !matches!(self.peek(), Some(Token::Pipe)) {
+            let var_type = if self.at_type() { Some(self.parse_type()?) } else { None };
+            let name = self.parse_name("lambda parameter name")?;
+            if params.iter().any(|p| p.name == name) {
+                return Err(ParseError {
+                    code: "E0304",
+                    message: format!("Lambda parameter '{}' is declared more than once", name),
+                    span: self.here(),
+                    notes: Vec::new(),
+                });
+            }
+            params.push(LambdaParam { name, var_type });
+            match self.peek() {
+                Some(Token::Comma) => {
+                    self.advance();
+                }
+                _ => break,
+            }
+        }
+        self.expect(Token::Pipe)?;
+        
+        let outer_declared = self.declared.clone();
+        for param in &params {
+            self.declared.insert(param.name.clone(), param.var_type.clone());
+        }
+        let body = self.parse_expression();
+        self.declared = outer_declared;
+        Ok(Expr::Lambda { params, body: Box::new(body?) })
+    }
+
+    // Токены из {} разбираются тем же парсером, чтобы были видны объявленные переменные и типы
+    fn parse_embedded_expression(&mut self, tokens: Vec<SpannedToken>) -> Result<Expr, ParseError> {
+        let (tokens, line_breaks, spans) = split_line_breaks(tokens);
+        let outer_tokens = std::mem::replace(&mut self.tokens, tokens);
+        let outer_line_breaks = std::mem::replace(&mut self.line_breaks, line_breaks);
+        let outer_spans = std::mem::replace(&mut self.spans, spans);
+        let outer_position = std::mem::replace(&mut self.position, 0);
+        let result = self.parse_expression().and_then(|expr| match self.peek() {
+            Some(Token::EOF) => Ok(expr),
+            token => Err(ParseError {
+                code: "E0005",
+                message: format!("Unexpected {:?} in string interpolation", token),
+                span: self.here(),
+                notes: Vec::new(),
+            }),
+        });
+        self.tokens = outer_tokens;
+        self.line_breaks = outer_line_breaks;
+        self.spans = outer_spans;
+        self.position = outer_position;
+        result
+    }
+
+    fn parse_expression(&mut self) -> Result<Expr, ParseError> {
+        self.enter_nested()?;
+        let result = self.parse_binary(0);
+        self.leave_nested();
+        result
+    }
+
+    // Разбор бинарных операторов методом подъёма приоритетов
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr, ParseError> {
+        self.chained(|parser| parser.parse_binary_chain(min_precedence))
+    }
+
+    fn parse_binary_chain(&mut self, min_precedence: u8) -> Result<Expr, ParseError> {
+        let start = self.here();
+        let mut left = self.parse_cast()?;
+        
+        while let Some(op) = self.peek().and_then(BinOp::from_token) {
+            let precedence = op.precedence();
+            if precedence < min_precedence {
+                break;
+            }
+            self.advance(); // пропускаем оператор
+            self.enter_nested()?;
+            // a ?? b ?? c — это a ?? (b ?? c): запасные значения перебираются по порядку
+            let right = match op {
+                BinOp::Coalesce => self.parse_binary(precedence)?,
+                _ => self.parse_binary(precedence + 1)?,
+            };
+            
+            left = Expr::BinaryOp {
+                left: Box::new(left),
+                op,
                 right: Box::new(right),
+                span: self.span_from(start),
             };
         }
         
-        Ok(left)
+        Ok(left)
+    }
+
+    // as связывает сильнее бинарных операторов, но слабее унарных: -x as Float — это (-x) as Float
+    fn parse_cast(&mut self) -> Result<Expr, ParseError> {
+        self.chained(|parser| {
+            let mut value = parser.parse_unary()?;
+            while let Some(Token::As) = parser.peek() {
+                parser.advance();
+                parser.enter_nested()?;
+                let target = parser.parse_type()?;
+                value = Expr::Cast { value: Box::new(value), target };
+            }
+            Ok(value)
+        })
+    }
+
+    fn parse_name(&mut self, what: &str) -> Result<String, ParseError> {
+        let span = self.here();
+        match self.advance() {
+            Some(Token::Ident(name)) => Ok(name.clone()),
+            Some(token) if token.keyword().is_some() => {
+                let word = token.keyword().unwrap().to_string();
+                Err(self.reserved_keyword_error(&word, span))
+            }
+            Some(token) => Err(ParseError {
+                code: "E0201",
+                message: format!("Expected {}, got {:?}", what, token),
+                span,
+                notes: Vec::new(),
+            }),
+            None => Err(ParseError {
+                code: "E0201",
+                message: format!("Expected {}", what),
+                span,
+                notes: Vec::new(),
+            }),
+        }
+    }
+
+    fn parse_declaration(&mut self, constant: bool, mutable: bool) -> Result<Stmt, ParseError> {
+        let var_type = self.parse_type()?;
+        
+        let name = self.parse_name("variable name")?;
+        let span = self.previous();
+        
+        self.expect(Token::Equals)?;
+        
+        let value = self.parse_initializer(&var_type)?;
+        
+        self.expect_terminator()?;
+        
+        self.declared.insert(name.clone(), Some(var_type.clone()));
+        
+        Ok(Stmt::Declaration {
+            var_type: Some(var_type),
+            name,
+            value,
+            constant,
+            mutable,
+            doc: None,
+            span,
+        })
+    }
+
+    // Одиночный литерал разбирается с учётом объявленного типа: так [] и {} узнают тип
+    // элементов, а 5 в Integer? становится some(5). Всё остальное — обычное выражение,
+    // его тип проверяет семантический анализ
+    fn parse_initializer(&mut self, var_type: &VarType) -> Result<Expr, ParseError> {
+        let start = self.position;
+        if let Ok(value) = self.parse_typed_value(var_type) {
+            if self.at_terminator() {
+                return Ok(Expr::Literal(value));
+            }
+        }
+        self.position = start;
+        self.parse_expression()
+    }
+
+    // То же для поля структуры или значения варианта, которые заканчиваются запятой или скобкой
+    fn parse_typed_expression(&mut self, var_type: &VarType) -> Result<Expr, ParseError> {
+        let start = self.position;
+        if let Ok(value) = self.parse_typed_value(var_type) {
+            if matches!(self.peek(), Some(Token::Comma | Token::RBrace | Token::RParen)) {
+                return Ok(Expr::Literal(value));
+            }
+        }
+        self.position = start;
+        self.parse_expression()
+    }
+
+    // Тип переменной в let выводится из значения
+    fn parse_let(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.here();
+        self.expect(Token::Let)?;
+        let mutable = matches!(self.peek(), Some(Token::Mut));
+        if mutable {
+            self.advance();
+        }
+        if self.at_struct_pattern() {
+            if mutable {
+                return Err(ParseError {
+                    code: "E0299",
+                    message: "Variables bound by destructuring cannot be declared mut".to_string(),
+                    span: self.here(),
+                    notes: Vec::new(),
+                });
+            }
+            return self.parse_destructure(start);
+        }
+        let name = self.parse_name("variable name")?;
+        let span = self.previous();
+        self.expect(Token::Equals)?;
+        let value = self.parse_expression()?;
+        self.expect_terminator()?;
+        
+        if let Expr::Literal(Value::None) = value {
+            return Err(ParseError {
+                code: "E0103",
+                message: format!("Cannot infer the type of '{}' from none; declare it with an optional type such as Integer?", name),
+                span: self.here(),
+                notes: Vec::new(),
+            });
+        }
+        self.declared.insert(name.clone(), self.expression_type(&value));
+        
+        Ok(Stmt::Declaration {
+            var_type: None,
+            name,
+            value,
+            constant: false,
+            mutable,
+            doc: None,
+            span,
+        })
+    }
+
+    fn parse_destructure(&mut self, start: Span) -> Result<Stmt, ParseError> {
+        let pattern = self.parse_struct_pattern()?;
+        self.expect(Token::Equals)?;
+        let value = self.parse_expression()?;
+        self.expect_terminator()?;
+        self.declare_pattern(&pattern, &value);
+        Ok(Stmt::Destructure { pattern, value, span: self.span_from(start) })
+    }
+
+    fn at_struct_pattern(&self) -> bool {
+        match (self.peek(), self.tokens.get(self.position + 1)) {
+            (Some(Token::LParen), _) => true,
+            (Some(Token::Ident(name)), Some(Token::LBrace)) => self.structs.contains_key(name),
+            _ => false,
+        }
+    }
+
+    fn parse_struct_pattern(&mut self) -> Result<StructPattern, ParseError> {
+        let (name, close) = match self.peek() {
+            Some(Token::LParen) => {
+                self.advance();
+                (None, Token::RParen)
+            }
+            _ => {
+                let name = self.parse_name("struct name")?;
+                self.expect(Token::LBrace)?;
+                (Some(name), Token::RBrace)
+            }
+        };
+        
+        let mut entries = Vec::new();
+        let mut rest = false;
+        while self.peek() != Some(&close) {
+            if rest {
+                return Err(ParseError {
+                    code: "E0299",
+                    message: "'..' must be the last element of a pattern".to_string(),
+                    span: self.here(),
+                    notes: Vec::new(),
+                });
+            }
+            if !entries.is_empty() {
+                self.expect(Token::Comma)?;
+                if self.peek() == Some(&close) {
+                    break;
+                }
+            }
+            match self.peek() {
+                Some(Token::DotDot) => {
+                    self.advance();
+                    rest = true;
+                }
+                Some(Token::Underscore) if name.is_none() => {
+                    self.advance();
+                    entries.push(("_".to_string(), "_".to_string()));
+                }
+                _ => {
+                    let field = self.parse_name(if name.is_some() { "field name" } else { "binding name" })?;
+                    let binding = match (&name, self.peek()) {
+                        (Some(_), Some(Token::Colon)) => {
+                            self.advance();
+                            match self.peek() {
+                                Some(Token::Underscore) => {
+                                    self.advance();
+                                    "_".to_string()
+                                }
+                                _ => self.parse_name("binding name")?,
+                            }
+                        }
+                        _ => field.clone(),
+                    };
+                    entries.push((field, binding));
+                }
+            }
+        }
+        self.expect(close)?;
+        
+        Ok(match name {
+            Some(name) => StructPattern::Named { name, fields: entries, rest },
+            None => StructPattern::Positional {
+                bindings: entries.into_iter().map(|(_, binding)| binding).collect(),
+                rest,
+            },
+        })
+    }
+
+    // Типы полей известны парсеру, только если он знает тип разбираемой структуры
+    fn declare_pattern(&mut self, pattern: &StructPattern, value: &Expr) {
+        let fields = match self.expression_type(value) {
+            Some(VarType::Struct(struct_name)) => self.structs.get(&struct_name).cloned().unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        let bindings: Vec<(String, Option<VarType>)> = match pattern {
+            StructPattern::Positional { bindings, .. } => bindings
+                .iter()
+                .enumerate()
+                .map(|(index, binding)| (binding.clone(), fields.get(index).map(|f| f.var_type.clone())))
+                .collect(),
+            StructPattern::Named { fields: entries, .. } => entries
+                .iter()
+                .map(|(field, binding)| {
+                    (binding.clone(), fields.iter().find(|f| f.name == *field).map(|f| f.var_type.clone()))
+                })
+                .collect(),
+        };
+        for (binding, var_type) in bindings {
+            self.declared.insert(binding, var_type);
+        }
+    }
+
+    // Тип значения, насколько его видно парсеру; нужен для x++, for-in и разбора структур
+    fn expression_type(&self, value: &Expr) -> Option<VarType> {
+        match value {
+            Expr::Literal(value) => Some(value_type(value)),
+            Expr::Variable(name) => self.declared.get(name).cloned().flatten(),
+            // Переменная с функцией закрывает функцию с тем же именем
+            Expr::Call { name, args, .. } => match self.declared.get(name) {
+                Some(Some(VarType::Function(_, return_type))) => return_type.as_deref().cloned(),
+                Some(_) => None,
+                None if is_builtin(name) => {
+                    let args = args.iter().map(|arg| self.expression_type(arg)).collect::<Option<Vec<_>>>()?;
+                    builtin_result_type(name, &args).ok().flatten()
+                }
+                None => self.functions.get(name).cloned().flatten(),
+            },
+            Expr::Index { target, index } => {
+                index_result_type(&self.expression_type(target)?, &self.expression_type(index)?).ok()
+            }
+            Expr::Field { target, field } => field_result_type(&self.structs, &self.expression_type(target)?, field).ok(),
+            Expr::Struct { name, .. } => Some(VarType::Struct(name.clone())),
+            Expr::Enum { name, .. } => Some(VarType::Enum(name.clone())),
+            Expr::Cast { target, .. } => Some(target.clone()),
+            Expr::UnaryOp { op, operand, .. } => unary_result_type(op, &self.expression_type(operand)?).ok(),
+            Expr::BinaryOp { left, op, right, .. } => {
+                binary_result_type(op, &self.expression_type(left)?, &self.expression_type(right)?).ok()
+            }
+            Expr::Range { .. } => Some(VarType::Array(Box::new(VarType::Integer))),
+            _ => None,
+        }
+    }
+
+    // x++ и x-- разворачиваются в x = x + 1 и x = x - 1
+    fn parse_increment(&mut self, name: String, start: Span) -> Result<Stmt, ParseError> {
+        let (op, symbol) = match self.advance() {
+            Some(Token::PlusPlus) => (BinOp::Add, "++"),
+            _ => (BinOp::Sub, "--"),
+        };
+        
+        match self.declared.get(&name) {
+            Some(Some(VarType::Integer)) => {}
+            Some(Some(var_type)) => {
+                return Err(ParseError {
+                    code: "E0104",
+                    message: format!("'{}' requires an Integer variable, '{}' is {:?}", symbol, name, var_type),
+                    span: self.here(),
+                    notes: Vec::new(),
+                })
+            }
+            // Иначе x++ у строки молча стал бы приклеиванием единицы
+            Some(None) => {
+                return Err(ParseError {
+                    code: "E0103",
+                    message: format!(
+                        "'{}' requires an Integer variable, and the type of '{}' is not known here; declare it as Integer {} = ...",
+                        symbol, name, name
+                    ),
+                    span: self.here(),
+                    notes: Vec::new(),
+                })
+            }
+            None => {
+                return Err(ParseError {
+                    code: "E0301",
+                    message: format!("Unknown variable: {}", name),
+                    span: self.here(),
+                    notes: Vec::new(),
+                })
+            }
+        }
+        
+        Ok(Stmt::Assignment {
+            name: name.clone(),
+            value: Expr::BinaryOp {
+                left: Box::new(Expr::Variable(name)),
+                op,
+                right: Box::new(Expr::Literal(Value::Integer(1))),
+                span: Span::default(),
+            },
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_assignment(&mut self, name: String, start: Span) -> Result<Stmt, ParseError> {
+        self.expect(Token::Equals)?;
+        let value = self.parse_expression()?;
+        Ok(Stmt::Assignment { name, value, span: self.span_from(start) })
+    }
+
+    // xs[i] = v; и p.x = v; а также вызов метода p.show();
+    fn parse_place_assignment(&mut self, name: String, start: Span) -> Result<Stmt, ParseError> {
+        let place = self.parse_postfix(Expr::Variable(name))?;
+        if matches!(place, Expr::MethodCall { .. } | Expr::Try(_)) && self.at_terminator() {
+            self.expect_terminator()?;
+            return Ok(Stmt::Expression(place));
+        }
+        self.expect(Token::Equals)?;
+        let value = self.parse_expression()?;
+        self.expect_terminator()?;
+        let span = self.span_from(start);
+        
+        match place {
+            Expr::Index { target, index } => Ok(Stmt::IndexAssignment { target: *target, index: *index, value, span }),
+            Expr::Field { target, field } => Ok(Stmt::FieldAssignment { target: *target, field, value, span }),
+            Expr::Slice { .. } => Err(ParseError {
+                code: "E0401",
+                message: "Cannot assign to a slice; a slice is a copy".to_string(),
+                span: self.here(),
+                notes: Vec::new(),
+            }),
+            _ => Err(ParseError {
+                code: "E0401",
+                message: "Cannot assign to the result of a method call".to_string(),
+                span: self.here(),
+                notes: Vec::new(),
+            }),
+        }
+    }
+
+    // Имя уже прочитано, следующий токен — открывающая скобка
+    fn parse_call(&mut self, name: String) -> Result<Expr, ParseError> {
+        let start = self.previous();
+        self.expect(Token::LParen)?;
+        
+        let mut args = Vec::new();
+        
+        if let Some(Token::RParen) = self.peek() {
+            // Нет аргументов
+        } else {
+            args.push(self.parse_element()?);
+            // Запятая после последнего аргумента допускается и отбрасывается
+            while let Some(Token::Comma) = self.peek() {
+                self.advance();
+                if let Some(Token::RParen) = self.peek() {
+                    break;
+                }
+                args.push(self.parse_element()?);
+            }
+        }
+        // Массив заполняет параметры, оставшиеся после обычных аргументов
+        if args[..args.len().saturating_sub(1)].iter().any(|arg| matches!(arg, Expr::Spread(_))) {
+            return Err(ParseError {
+                code: "E0299",
+                message: "A spread argument must be the last argument of a call".to_string(),
+                span: self.here(),
+                notes: Vec::new(),
+            });
+        }
+        
+        self.expect(Token::RParen)?;
+        
+        Ok(Expr::Call {
+            name,
+            args,
+            span: self.span_from(start),
+        })
+    }
+
+    // Аргумент вызова или элемент массива: выражение или ...массив
+    fn parse_element(&mut self) -> Result<Expr, ParseError> {
+        if let Some(Token::Ellipsis) = self.peek() {
+            self.advance();
+            return Ok(Expr::Spread(Box::new(self.parse_expression()?)));
+        }
+        self.parse_expression()
+    }
+
+    // [a, b * 2, ...rest] — элементы вычисляются при выполнении
+    fn parse_array_expression(&mut self) -> Result<Expr, ParseError> {
+        self.expect(Token::LBracket)?;
+        let mut elements = vec![self.parse_element()?];
+        if let Some(Token::For) = self.peek() {
+            let element = elements.pop().unwrap();
+            if let Expr::Spread(_) = element {
+                return Err(ParseError {
+                    code: "E0299",
+                    message: "A list comprehension cannot spread its element".to_string(),
+                    span: self.here(),
+                    notes: Vec::new(),
+                });
+            }
+            return self.parse_comprehension(element);
+        }
+        while let Some(Token::Comma) = self.peek() {
+            self.advance();
+            if let Some(Token::RBracket) = self.peek() {
+                break;
+            }
+            elements.push(self.parse_element()?);
+        }
+        self.expect(Token::RBracket)?;
+        Ok(Expr::Array(elements))
+    }
+
+    // { "a": a, name: f(x) } — ключи и значения вычисляются при выполнении
+    fn parse_map_expression(&mut self) -> Result<Expr, ParseError> {
+        self.expect(Token::LBrace)?;
+        let mut entries = Vec::new();
+        while !matches!(self.peek(), Some(Token::RBrace)) {
+            let key = self.parse_expression()?;
+            self.expect(Token::Colon)?;
+            entries.push((key, self.parse_expression()?));
+            
+            match self.peek() {
+                Some(Token::Comma) => {
+                    self.advance();
+                }
+                _ => break,
+            }
+        }
+        self.expect(Token::RBrace)?;
+        Ok(Expr::Map(entries))
+    }
+
+    // То, что идёт после in: выражение или диапазон start..end
+    fn parse_iterable(&mut self) -> Result<Expr, ParseError> {
+        let start = self.parse_expression()?;
+        if self.peek() != Some(&Token::DotDot) {
+            return Ok(start);
+        }
+        self.advance();
+        let end = self.parse_expression()?;
+        Ok(Expr::Range { start: Box::new(start), end: Box::new(end) })
+    }
+
+    // После элемента: for x in xs, затем необязательное if условие
+    fn parse_comprehension(&mut self, element: Expr) -> Result<Expr, ParseError> {
+        self.expect(Token::For)?;
+        let variable = self.parse_name("loop variable name")?;
+        self.expect(Token::In)?;
+        let iterable = self.parse_iterable()?;
+        let condition = match self.peek() {
+            Some(Token::Reserved(word)) if word == "if" => {
+                self.advance();
+                Some(Box::new(self.parse_expression()?))
+            }
+            _ => None,
+        };
+        self.expect(Token::RBracket)?;
+        Ok(Expr::Comprehension { element: Box::new(element), variable, iterable: Box::new(iterable), condition })
+    }
+
+    // Объявленные в блоке переменные не видны после закрывающей скобки
+    fn parse_block(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        self.expect(Token::LBrace)?;
+        self.enter_nested()?;
+        let outer_declared = self.declared.clone();
+        let result = self.parse_block_body();
+        self.declared = outer_declared;
+        self.leave_nested();
+        result
+    }
+
+    fn parse_block_body(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        let mut statements = Vec::new();
+        
+        loop {
+            match self.peek() {
+                Some(Token::RBrace) => {
+                    self.advance();
+                    return Ok(statements);
+                }
+                Some(Token::EOF) | None => {
+                    return Err(ParseError {
+                        code: "E0202",
+                        message: "Expected '}' before end of file".to_string(),
+                        span: self.here(),
+                        notes: Vec::new(),
+                    });
+                }
+                _ => {
+                    let (start, depth, loop_depth) = (self.position, self.depth, self.loop_depth);
+                    match self.parse_statement() {
+                        Ok(stmt) => statements.push(stmt),
+                        // После конца файла продолжать нечего: ошибку сообщит внешний уровень
+                        Err(error) if matches!(self.peek(), Some(Token::EOF) | None) => return Err(error),
+                        Err(error) => {
+                            self.errors.push(error);
+                            self.depth = depth;
+                            self.loop_depth = loop_depth;
+                            self.synchronize(start);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn parse_match(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.here();
+        self.expect(Token::Match)?;
+        let value = self.parse_expression()?;
+        let span = self.span_from(start);
+        self.expect(Token::LBrace)?;
+        
+        let mut arms = Vec::new();
+        loop {
+            let pattern = match self.peek() {
+                Some(Token::RBrace) => {
+                    self.advance();
+                    break;
+                }
+                Some(Token::Underscore) => {
+                    self.advance();
+                    Pattern::Wildcard
+                }
+                _ if self.at_struct_pattern() => Pattern::Struct(self.parse_struct_pattern()?),
+                Some(Token::Ident(_)) => self.parse_variant_pattern()?,
+                _ => Pattern::Literal(self.parse_value()?),
+            };
+            
+            self.expect(Token::FatArrow)?;
+            
+            // Связанные образцом имена видны только в теле ветки
+            let outer_declared = self.declared.clone();
+            match &pattern {
+                Pattern::Variant { enum_name, variant, bindings } => {
+                    let payload = self.enums[enum_name].iter().find(|v| v.name == *variant).unwrap().payload.clone();
+                    for (binding, var_type) in bindings.iter().zip(payload) {
+                        self.declared.insert(binding.clone(), Some(var_type));
+                    }
+                }
+                Pattern::Struct(struct_pattern) => self.declare_pattern(struct_pattern, &value),
+                Pattern::Literal(_) | Pattern::Wildcard => {}
+            }
+            
+            // Тело ветки — блок или одна инструкция
+            let body = match self.peek() {
+                Some(Token::LBrace) => self.parse_block(),
+                _ => self.parse_statement().map(|stmt| vec![stmt]),
+            };
+            self.declared = outer_declared;
+            
+            arms.push(MatchArm { pattern, body: body? });
+        }
+        
+        Ok(Stmt::Match { value, arms, span })
+    }
+
+    fn parse_defer(&mut self) -> Result<Stmt, ParseError> {
+        self.expect(Token::Defer)?;
+        if self.at_type() || matches!(self.peek(), Some(Token::Const | Token::Mut | Token::Let)) {
+            return Err(ParseError {
+                code: "E0299",
+                message: "Cannot defer a declaration: the variable would go out of scope immediately".to_string(),
+                span: self.here(),
+                notes: Vec::new(),
+            });
+        }
+        Ok(Stmt::Defer(Box::new(self.parse_statement()?)))
+    }
+
+    fn parse_try(&mut self) -> Result<Stmt, ParseError> {
+        self.expect(Token::Try)?;
+        let body = self.parse_block()?;
+        self.expect(Token::Catch)?;
+        self.expect(Token::LParen)?;
+        let error = self.parse_name("error variable name")?;
+        self.expect(Token::RParen)?;
+        
+        let outer_declared = self.declared.clone();
+        self.declared.insert(error.clone(), Some(VarType::String));
+        let handler = self.parse_block();
+        self.declared = outer_declared;
+        
+        Ok(Stmt::Try { body, error, handler: handler? })
+    }
+
+    fn parse_variant_pattern(&mut self) -> Result<Pattern, ParseError> {
+        let (enum_name, variant) = self.parse_variant_path()?;
+        
+        let mut bindings = Vec::new();
+        if let Some(Token::LParen) = self.peek() {
+            self.advance();
+            while !matches!(self.peek(), Some(Token::RParen)) {
+                if !bindings.is_empty() {
+                    self.expect(Token::Comma)?;
+                    if let Some(Token::RParen) = self.peek() {
+                        break;
+                    }
+                }
+                match self.peek() {
+                    Some(Token::Underscore) => {
+                        self.advance();
+                        bindings.push("_".to_string());
+                    }
+                    _ => bindings.push(self.parse_name("binding name")?),
+                }
+            }
+            self.expect(Token::RParen)?;
+        }
+        
+        if bindings.len() != variant.payload.len() {
+            return Err(ParseError {
+                code: "E0102",
+                message: format!(
+                    "Pattern {}::{} must bind {} value(s), got {}",
+                    enum_name, variant.name, variant.payload.len(), bindings.len()
+                ),
+                span: self.here(),
+                notes: Vec::new(),
+            });
+        }
+        Ok(Pattern::Variant { enum_name, variant: variant.name, bindings })
+    }
+
+    // Шаг цикла for: i = выражение, i++ или i--
+    fn parse_for_update(&mut self) -> Result<Stmt, ParseError> {
+        let span = self.here();
+        let name = match self.advance() {
+            Some(Token::Ident(name)) => name.clone(),
+            Some(token) => {
+                return Err(ParseError {
+                    code: "E0201",
+                    message: format!("Expected loop variable update, got {:?}", token),
+                    span,
+                    notes: Vec::new(),
+                })
+            }
+            None => {
+                return Err(ParseError {
+                    code: "E0201",
+                    message: "Expected loop variable update".to_string(),
+                    span,
+                    notes: Vec::new(),
+                })
+            }
+        };
+        
+        if let Some(Token::PlusPlus) | Some(Token::MinusMinus) = self.peek() {
+            return self.parse_increment(name, span);
+        }
+        
+        self.parse_assignment(name, span)
+    }
+
+    fn parse_for(&mut self, label: Option<String>) -> Result<Stmt, ParseError> {
+        let start = self.here();
+        self.expect(Token::For)?;
+        if let (Some(Token::Ident(_)), Some(Token::In)) = (self.peek(), self.tokens.get(self.position + 1)) {
+            return self.parse_for_in(label, start);
+        }
+        self.expect(Token::LParen)?;
+        
+        // Переменная цикла видна только внутри него
+        let outer_declared = self.declared.clone();
+        
+        // Счётчик цикла меняется шагом for, поэтому он изменяем и без mut
+        let init = match self.peek() {
+            _ if self.at_type() => self.parse_declaration(false, true)?,
+            Some(Token::Let) => match self.parse_let()? {
+                Stmt::Declaration { var_type, name, value, constant, doc, span, .. } => {
+                    Stmt::Declaration { var_type, name, value, constant, mutable: true, doc, span }
+                }
+                stmt => stmt,
+            },
+            _ => {
+                let stmt = self.parse_for_update()?;
+                self.expect(Token::Semicolon)?;
+                stmt
+            }
+        };
+        
+        let condition = self.parse_expression()?;
+        self.expect(Token::Semicolon)?;
+        
+        let update = self.parse_for_update()?;
+        self.expect(Token::RParen)?;
+        let span = self.span_from(start);
+        
+        let body = self.parse_loop_body()?;
+        self.declared = outer_declared;
+        
+        Ok(Stmt::For {
+            label,
+            init: Box::new(init),
+            condition,
+            update: Box::new(update),
+            body,
+            span,
+        })
+    }
+
+    fn parse_for_in(&mut self, label: Option<String>, start: Span) -> Result<Stmt, ParseError> {
+        let variable = self.parse_name("loop variable name")?;
+        self.expect(Token::In)?;
+        let iterable = self.parse_iterable()?;
+        let span = self.span_from(start);
+        
+        // Тип элемента известен парсеру, только если он знает тип массива
+        let element_type = match self.expression_type(&iterable) {
+            Some(VarType::Array(element_type)) => Some(*element_type),
+            Some(VarType::String) => Some(VarType::String),
+            _ => None,
+        };
+        let outer_declared = self.declared.clone();
+        self.declared.insert(variable.clone(), element_type);
+        let body = self.parse_loop_body();
+        self.declared = outer_declared;
+        
+        Ok(Stmt::ForIn { label, variable, iterable, body: body?, span })
+    }
+
+    fn parse_loop_body(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        self.loop_depth += 1;
+        let body = self.parse_block();
+        self.loop_depth -= 1;
+        body
+    }
+
+    fn parse_loop(&mut self, label: Option<String>) -> Result<Stmt, ParseError> {
+        self.expect(Token::Loop)?;
+        let body = self.parse_loop_body()?;
+        Ok(Stmt::Loop { label, body })
+    }
+
+    fn parse_while(&mut self, label: Option<String>) -> Result<Stmt, ParseError> {
+        let start = self.here();
+        self.expect(Token::While)?;
+        let condition = self.parse_expression()?;
+        let span = self.span_from(start);
+        let body = self.parse_loop_body()?;
+        Ok(Stmt::While { label, condition, body, span })
+    }
+
+    // Метка вида `outer:` ставится только перед циклом
+    fn parse_labeled(&mut self, label: String) -> Result<Stmt, ParseError> {
+        self.expect(Token::Colon)?;
+        
+        match self.peek() {
+            Some(Token::For) => self.parse_for(Some(label)),
+            Some(Token::While) => self.parse_while(Some(label)),
+            Some(Token::Loop) => self.parse_loop(Some(label)),
+            _ => Err(ParseError {
+                code: "E0201",
+                message: format!("Label '{}' must be followed by a loop", label),
+                span: self.here(),
+                notes: Vec::new(),
+            }),
+        }
+    }
+
+    // break и continue допустимы только внутри цикла
+    fn parse_jump(&mut self) -> Result<Stmt, ParseError> {
+        let is_break = matches!(self.advance(), Some(Token::Break));
+        
+        if self.loop_depth == 0 {
+            return Err(ParseError {
+                code: "E0402",
+                message: format!("'{}' outside of a loop", if is_break { "break" } else { "continue" }),
+                span: self.here(),
+                notes: Vec::new(),
+            });
+        }
+        
+        let label = match self.peek() {
+            Some(Token::Ident(label)) if !self.at_line_start() => {
+                let label = label.clone();
+                self.advance();
+                Some(label)
+            }
+            _ => None,
+        };
+        
+        self.expect_terminator()?;
+        Ok(if is_break { Stmt::Break(label) } else { Stmt::Continue(label) })
+    }
+
+    fn parse_return(&mut self) -> Result<Stmt, ParseError> {
+        self.expect(Token::Return)?;
+        
+        if !self.in_function {
+            return Err(ParseError {
+                code: "E0402",
+                message: "'return' outside of a function".to_string(),
+                span: self.here(),
+                notes: Vec::new(),
+            });
+        }
+        
+        let value = if self.at_terminator() { None } else { Some(self.parse_expression()?) };
+        
+        self.expect_terminator()?;
+        Ok(Stmt::Return(value))
+    }
+
+    fn parse_struct(&mut self) -> Result<Stmt, ParseError> {
+        self.expect(Token::Struct)?;
+        let name = self.parse_name("struct name")?;
+        if self.is_type_name(&name) {
+            return Err(self.duplicate_error("type", &name, format!("Type '{}' is defined more than once", name)));
+        }
+        self.definitions.insert(("type", name.clone()), self.previous());
+        // Имя видно уже в полях, чтобы можно было объявить Array<Node> внутри Node
+        self.structs.insert(name.clone(), Vec::new());
+        
+        self.expect(Token::LBrace)?;
+        let mut fields: Vec<Field> = Vec::new();
+        let mut field_spans = Vec::new();
+        while !matches!(self.peek(), Some(Token::RBrace)) {
+            let doc = self.parse_doc_comment();
+            let var_type = self.parse_type()?;
+            self.check_not_function(&var_type, "Struct fields")?;
+            let field_name = self.parse_name("field name")?;
+            let field_span = self.previous();
+            if let Some(first) = fields.iter().position(|f| f.name == field_name) {
+                return Err(ParseError {
+                    code: "E0304",
+                    message: format!("Field '{}' is declared more than once in struct {}", field_name, name),
+                    span: field_span,
+                    notes: vec![Note::located(format!("'{}' is first declared here", field_name), field_spans[first])],
+                });
+            }
+            self.expect_terminator()?;
+            fields.push(Field { name: field_name, var_type, doc });
+            field_spans.push(field_span);
+        }
+        self.expect(Token::RBrace)?;
+        
+        self.structs.insert(name.clone(), fields.clone());
+        Ok(Stmt::Struct { name, fields, doc: None, attributes: Vec::new() })
+    }
+
+    // enum Shape { Circle(Float), Rect(Float, Float), Empty }
+    fn parse_enum(&mut self) -> Result<Stmt, ParseError> {
+        self.expect(Token::Enum)?;
+        let name = self.parse_name("enum name")?;
+        if self.is_type_name(&name) {
+            return Err(self.duplicate_error("type", &name, format!("Type '{}' is defined more than once", name)));
+        }
+        self.definitions.insert(("type", name.clone()), self.previous());
+        // Как и у структур, имя видно внутри определения: Array<Tree> в варианте Tree
+        self.enums.insert(name.clone(), Vec::new());
+        
+        self.expect(Token::LBrace)?;
+        let mut variants: Vec<Variant> = Vec::new();
+        let mut variant_spans = Vec::new();
+        while !matches!(self.peek(), Some(Token::RBrace)) {
+            let doc = self.parse_doc_comment();
+            let variant_name = self.parse_name("variant name")?;
+            if let Some(first) = variants.iter().position(|v| v.name == variant_name) {
+                return Err(ParseError {
+                    code: "E0304",
+                    message: format!("Variant '{}' is declared more than once in enum {}", variant_name, name),
+                    span: self.previous(),
+                    notes: vec![Note::located(format!("'{}' is first declared here", variant_name), variant_spans[first])],
+                });
+            }
+            variant_spans.push(self.previous());
+            
+            let mut payload = Vec::new();
+            if let Some(Token::LParen) = self.peek() {
+                self.advance();
+                payload.push(self.parse_type()?);
+                while let Some(Token::Comma) = self.peek() {
+                    self.advance();
+                    if let Some(Token::RParen) = self.peek() {
+                        break;
+                    }
+                    payload.push(self.parse_type()?);
+                }
+                for var_type in &payload {
+                    self.check_not_function(var_type, "Enum variants")?;
+                }
+                self.expect(Token::RParen)?;
+            }
+            variants.push(Variant { name: variant_name, payload, doc });
+            
+            match self.peek() {
+                Some(Token::Comma) => {
+                    self.advance();
+                }
+                _ => break,
+            }
+        }
+        self.expect(Token::RBrace)?;
+        
+        if variants.is_empty() {
+            return Err(ParseError {
+                code: "E0299",
+                message: format!("Enum {} must have at least one variant", name),
+                span: self.here(),
+                notes: Vec::new(),
+            });
+        }
+        self.enums.insert(name.clone(), variants.clone());
+        Ok(Stmt::Enum { name, variants, doc: None, attributes: Vec::new() })
+    }
+
+    fn parse_function(&mut self) -> Result<Stmt, ParseError> {
+        self.expect(Token::Func)?;
+        let name = self.parse_name("function name")?;
+        let span = self.previous();
+        self.expect(Token::LParen)?;
+        let params = self.parse_params(false)?;
+        let return_type = self.parse_return_type()?;
+        self.functions.insert(name.clone(), return_type.clone());
+        let body = self.parse_function_body(None, &params)?;
+        
+        Ok(Stmt::Function {
+            name,
+            params,
+            return_type,
+            body,
+            doc: None,
+            attributes: Vec::new(),
+            span,
+        })
+    }
+
+    // Список параметров после открывающей скобки; у метода первым идёт self
+    fn parse_params(&mut self, is_method: bool) -> Result<Vec<Param>, ParseError> {
+        if is_method {
+            let span = self.here();
+            match self.advance() {
+                Some(Token::Ident(name)) if name == "self" => {}
+                _ => {
+                    return Err(ParseError {
+                        code: "E0404",
+                        message: "The first parameter of a method must be 'self'".to_string(),
+                        span,
+                        notes: Vec::new(),
+                    })
+                }
+            }
+            match self.peek() {
+                Some(Token::Comma) => {
+                    self.advance();
+                }
+                _ => {
+                    self.expect(Token::RParen)?;
+                    return Ok(Vec::new());
+                }
+            }
+        }
+        
+        let mut params = Vec::new();
+        if let Some(Token::RParen) = self.peek() {
+            // Нет параметров
+        } else {
+            loop {
+                let var_type = self.parse_param_type()?;
+                let name = self.parse_name("parameter name")?;
+                params.push(Param { name, var_type });
+                
+                match self.peek() {
+                    Some(Token::Comma) => {
+                        self.advance();
+                        if let Some(Token::RParen) = self.peek() {
+                            break;
+                        }
+                    }
+                    _ => break,
+                }
+            }
+        }
+        self.expect(Token::RParen)?;
+        Ok(params)
     }
 
-    fn parse_declaration(&mut self) -> Result<Stmt, ParseError> {
-        let var_type = self.parse_type()?;
-        
-        let name = match self.advance() {
-            Some(Token::Ident(name)) => name.clone(),
-            Some(token) => {
+    // Трейт в качестве типа параметра: функция принимает любой тип, который его реализует
+    fn parse_param_type(&mut self) -> Result<VarType, ParseError> {
+        if let Some(Token::Ident(name)) = self.peek() {
+            if self.traits.contains_key(name) {
+                let name = name.clone();
+                self.advance();
+                return Ok(VarType::Trait(name));
+            }
+        }
+        self.parse_type()
+    }
+
+    fn parse_return_type(&mut self) -> Result<Option<VarType>, ParseError> {
+        match self.peek() {
+            Some(Token::Arrow) => {
+                self.advance();
+                Ok(Some(self.parse_type()?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn parse_function_body(&mut self, self_type: Option<&VarType>, params: &[Param]) -> Result<Vec<Stmt>, ParseError> {
+        // Параметры видны только в теле функции
+        let outer_declared = self.declared.clone();
+        if let Some(self_type) = self_type {
+            self.declared.insert("self".to_string(), Some(self_type.clone()));
+        }
+        for param in params {
+            self.declared.insert(param.name.clone(), Some(param.var_type.clone()));
+        }
+        self.in_function = true;
+        let body = self.parse_block();
+        self.in_function = false;
+        self.declared = outer_declared;
+        body
+    }
+
+    // func show(self) -> String
+    fn parse_method_signature(&mut self) -> Result<MethodSignature, ParseError> {
+        let doc = self.parse_doc_comment();
+        self.expect(Token::Func)?;
+        let mut name = self.parse_name("method name")?;
+        let operator = match (name.as_str(), self.peek().and_then(BinOp::from_token)) {
+            ("operator", Some(op)) => Some(op),
+            _ => None,
+        };
+        if let Some(op) = &operator {
+            self.advance();
+            if !op.is_overloadable() {
                 return Err(ParseError {
-                    message: format!("Expected variable name, got {:?}", token),
-                    line: self.current_line,
-                    column: self.current_column,
-                })
+                    code: "E0404",
+                    message: format!("Operator '{}' cannot be overloaded", op.symbol()),
+                    span: self.here(),
+                    notes: Vec::new(),
+                });
             }
-            None => {
+            name = op.method_name();
+        }
+        self.expect(Token::LParen)?;
+        let params = self.parse_params(true)?;
+        let return_type = self.parse_return_type()?;
+        if let Some(op) = operator {
+            // a + b вызывает a.operator+(b): ровно один параметр, сравнения возвращают Boolean
+            if params.len() != 1 {
                 return Err(ParseError {
-                    message: "Expected variable name".to_string(),
-                    line: self.current_line,
-                    column: self.current_column,
-                })
+                    code: "E0404",
+                    message: format!("Method '{}' must take exactly one parameter besides self", name),
+                    span: self.here(),
+                    notes: Vec::new(),
+                });
             }
-        };
+            if op.is_comparison() && return_type != Some(VarType::Boolean) {
+                return Err(ParseError {
+                    code: "E0404",
+                    message: format!("Method '{}' must return Boolean", name),
+                    span: self.here(),
+                    notes: Vec::new(),
+                });
+            }
+        }
+        Ok(MethodSignature { name, params, return_type, doc })
+    }
+
+    // trait Printable { func show(self) -> String; }
+    fn parse_trait(&mut self) -> Result<Stmt, ParseError> {
+        self.expect(Token::Trait)?;
+        let name = self.parse_name("trait name")?;
+        if self.is_type_name(&name) {
+            return Err(self.duplicate_error("type", &name, format!("Type '{}' is defined more than once", name)));
+        }
+        self.definitions.insert(("type", name.clone()), self.previous());
         
-        self.expect(Token::Equals)?;
+        self.expect(Token::LBrace)?;
+        let mut methods: Vec<MethodSignature> = Vec::new();
+        while !matches!(self.peek(), Some(Token::RBrace)) {
+            let method = self.parse_method_signature()?;
+            self.expect_terminator()?;
+            if methods.iter().any(|m| m.name == method.name) {
+                return Err(ParseError {
+                    code: "E0304",
+                    message: format!("Method '{}' is declared more than once in trait {}", method.name, name),
+                    span: self.here(),
+                    notes: Vec::new(),
+                });
+            }
+            methods.push(method);
+        }
+        self.expect(Token::RBrace)?;
         
-        let value = self.parse_value()?;
+        self.traits.insert(name.clone(), methods.clone());
+        Ok(Stmt::Trait { name, methods, doc: None, attributes: Vec::new() })
+    }
+
+    // impl Printable for Point { func show(self) -> String { ... } }
+    fn parse_impl(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.here();
+        self.expect(Token::Impl)?;
+        let trait_name = self.parse_name("trait name")?;
+        if !self.traits.contains_key(&trait_name) {
+            return Err(ParseError {
+                code: "E0303",
+                message: format!("Unknown trait '{}'", trait_name),
+                span: self.here(),
+                notes: Vec::new(),
+            });
+        }
+        self.expect(Token::For)?;
+        let self_type = self.parse_type()?;
+        if !matches!(self_type, VarType::Struct(_) | VarType::Enum(_)) {
+            return Err(ParseError {
+                code: "E0404",
+                message: format!("Traits can only be implemented for structs and enums, got {:?}", self_type),
+                span: self.here(),
+                notes: Vec::new(),
+            });
+        }
+        let span = self.span_from(start);
         
-        match (&var_type, &value) {
-            (VarType::String, Value::String(_)) => {}
-            (VarType::Integer, Value::Integer(_)) => {}
-            (VarType::Float, Value::Float(_)) => {}
-            (VarType::Boolean, Value::Boolean(_)) => {}
+        self.expect(Token::LBrace)?;
+        let mut methods = Vec::new();
+        while !matches!(self.peek(), Some(Token::RBrace)) {
+            let signature = self.parse_method_signature()?;
+            let body = self.parse_function_body(Some(&self_type), &signature.params)?;
+            methods.push(Method { signature, body });
+        }
+        self.expect(Token::RBrace)?;
+        
+        Ok(Stmt::Impl { trait_name, self_type, methods, span })
+    }
+
+    fn parse_statement(&mut self) -> Result<Stmt, ParseError> {
+        match self.peek() {
+            Some(Token::DocComment(_)) => {
+                let doc = self.parse_doc_comment();
+                let stmt = self.parse_statement()?;
+                self.attach_doc(stmt, doc)
+            }
+            
+            Some(Token::Ident(name))
+                if self.macros.contains_key(name) && matches!(self.tokens.get(self.position + 1), Some(Token::LParen)) =>
+            {
+                self.expand_macro()?;
+                let block = self.parse_statement()?;
+                // Как после вызова функции, после вызова макроса может стоять ;
+                if let Some(Token::Semicolon) = self.peek() {
+                    self.advance();
+                }
+                Ok(block)
+            }
+            
+            Some(Token::Macro) => Err(ParseError {
+                code: "E0203",
+                message: "Macros can only be defined at the top level".to_string(),
+                span: self.here(),
+                notes: Vec::new(),
+            }),
+            
+            _ if self.at_type() => self.parse_declaration(false, false),
+            
+            Some(Token::Const) => {
+                self.advance();
+                self.parse_declaration(true, false)
+            }
+            
+            Some(Token::Mut) => {
+                self.advance();
+                if !self.at_type() {
+                    return Err(ParseError {
+                        code: "E0201",
+                        message: "Expected a type after 'mut', e.g. mut Integer count = 0".to_string(),
+                        span: self.here(),
+                        notes: Vec::new(),
+                    });
+                }
+                self.parse_declaration(false, true)
+            }
+            
+            Some(Token::Let) => self.parse_let(),
+            
+            Some(Token::LBrace) => Ok(Stmt::Block(self.parse_block()?)),
+            
+            Some(Token::Match) => self.parse_match(),
+            Some(Token::Try) => self.parse_try(),
+            Some(Token::For) => self.parse_for(None),
+            Some(Token::While) => self.parse_while(None),
+            Some(Token::Loop) => self.parse_loop(None),
+            Some(Token::Break | Token::Continue) => self.parse_jump(),
+            
+            Some(Token::Return) => self.parse_return(),
+            Some(Token::Defer) => self.parse_defer(),
+            
+            Some(Token::Func) => Err(ParseError {
+                code: "E0203",
+                message: "Functions can only be declared at the top level".to_string(),
+                span: self.here(),
+                notes: Vec::new(),
+            }),
+            
+            Some(Token::Struct) => Err(ParseError {
+                code: "E0203",
+                message: "Structs can only be declared at the top level".to_string(),
+                span: self.here(),
+                notes: Vec::new(),
+            }),
+            
+            Some(Token::Enum) => Err(ParseError {
+                code: "E0203",
+                message: "Enums can only be declared at the top level".to_string(),
+                span: self.here(),
+                notes: Vec::new(),
+            }),
+            
+            Some(Token::Trait | Token::Impl) => Err(ParseError {
+                code: "E0203",
+                message: "Traits and impls can only be declared at the top level".to_string(),
+                span: self.here(),
+                notes: Vec::new(),
+            }),
+            
+            Some(Token::Import) => Err(ParseError {
+                code: "E0203",
+                message: "Imports are only allowed at the top level".to_string(),
+                span: self.here(),
+                notes: Vec::new(),
+            }),
+            
+            Some(Token::Module) => Err(ParseError {
+                code: "E0203",
+                message: "Modules can only be declared at the top level".to_string(),
+                span: self.here(),
+                notes: Vec::new(),
+            }),
+            
+            Some(Token::Ident(name)) => {
+                let name = name.clone();
+                let start = self.here();
+                self.advance();
+                let name = self.parse_qualified_name(name)?;
+                
+                if let Some(Token::Colon) = self.peek() {
+                    self.parse_labeled(name)
+                } else if let Some(Token::PlusPlus) | Some(Token::MinusMinus) = self.peek() {
+                    let stmt = self.parse_increment(name, start)?;
+                    self.expect_terminator()?;
+                    Ok(stmt)
+                } else if let Some(Token::LBracket | Token::Dot | Token::Question) = self.peek() {
+                    self.parse_place_assignment(name, start)
+                } else if let Some(Token::Equals) = self.peek() {
+                    let stmt = self.parse_assignment(name, start)?;
+                    self.expect_terminator()?;
+                    Ok(stmt)
+                } else if let Some(Token::LParen) = self.peek() {
+                    let mut call = self.parse_call(name)?;
+                    // save()?; — вызов ради возможной ошибки
+                    if let Some(Token::Question) = self.peek() {
+                        self.advance();
+                        call = Expr::Try(Box::new(call));
+                    }
+                    self.expect_terminator()?;
+                    Ok(Stmt::Expression(call))
+                } else {
+                    Err(ParseError {
+                        code: "E0301",
+                        message: format!("Unknown function or variable: {}", name),
+                        span: self.previous(),
+                        notes: Vec::new(),
+                    })
+                }
+            }
+            
+            Some(Token::Reserved(word)) => Err(self.reserved_keyword_error(word, self.here())),
+            
+            Some(Token::Illegal(ch)) => Err(ParseError {
+                code: "E0006",
+                message: format!("Invalid character: '{}'", ch),
+                span: self.here(),
+                notes: Vec::new(),
+            }),
+            Some(Token::EOF) | None => Err(ParseError {
+                code: "E0202",
+                message: "Unexpected end of file".to_string(),
+                span: self.here(),
+                notes: Vec::new(),
+            }),
             _ => {
-                return Err(ParseError {
-                    message: format!("Type mismatch: cannot assign {:?} to {:?}", value, var_type),
-                    line: self.current_line,
-                    column: self.current_column,
+                let token = self.advance().unwrap();
+                Err(ParseError {
+                    code: "E0201",
+                    message: format!("Unexpected token: {:?}", token),
+                    span: self.previous(),
+                    notes: Vec::new(),
                 })
             }
         }
-        
-        self.expect(Token::Semicolon)?;
-        
-        Ok(Stmt::Declaration {
-            var_type,
-            name,
-            value,
-        })
     }
 
-    fn parse_call(&mut self, name: String) -> Result<Expr, ParseError> {
-        self.expect(Token::LParen)?;
+    fn parse_item(&mut self) -> Result<Stmt, ParseError> {
+        match self.peek() {
+            Some(Token::DocComment(_)) => {
+                let doc = self.parse_doc_comment();
+                let item = self.parse_item()?;
+                self.attach_doc(item, doc)
+            }
+            Some(Token::Hash) => {
+                let attributes = self.parse_attributes()?;
+                let item = self.parse_item()?;
+                self.attach_attributes(item, attributes)
+            }
+            // func(Integer) -> Integer f = ... — объявление переменной, а не функции
+            Some(Token::Func) if !self.at_type() => self.parse_function(),
+            Some(Token::Struct) => self.parse_struct(),
+            Some(Token::Enum) => self.parse_enum(),
+            Some(Token::Trait) => self.parse_trait(),
+            Some(Token::Impl) => self.parse_impl(),
+            Some(Token::Import) => self.parse_import(),
+            _ => self.parse_statement(),
+        }
+    }
+
+    // module math { func abs(...) ... } — члены модуля получают имена math.abs
+    fn parse_module_block(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        self.expect(Token::Module)?;
+        let name = self.parse_name("module name")?;
+        if self.modules.contains(&name) || self.is_type_name(&name) {
+            return Err(ParseError {
+                code: "E0304",
+                message: format!("Module '{}' is defined more than once", name),
+                span: self.here(),
+                notes: Vec::new(),
+            });
+        }
+        self.modules.insert(name.clone());
         
-        let mut args = Vec::new();
+        // Внутри модуля его переменные доступны без префикса
+        let outer_declared = self.declared.clone();
+        self.expect(Token::LBrace)?;
+        let mut members = Vec::new();
+        while !matches!(self.peek(), Some(Token::RBrace) | Some(Token::EOF) | None) {
+            let mut member = self.parse_item()?;
+            match &mut member {
+                Stmt::Function { name: qualified, .. } | Stmt::Declaration { name: qualified, .. } => {
+                    *qualified = member_name(&name, qualified);
+                }
+                _ => {
+                    return Err(ParseError {
+                        code: "E0203",
+                        message: format!("Only functions and variables can be declared inside module {}", name),
+                        span: self.here(),
+                        notes: Vec::new(),
+                    })
+                }
+            }
+            members.push(member);
+        }
+        self.expect(Token::RBrace)?;
         
-        if let Some(Token::RParen) = self.peek() {
-            // Нет аргументов
+        self.declared = outer_declared;
+        for member in &members {
+            if let Stmt::Declaration { name, var_type, .. } = member {
+                self.declared.insert(name.clone(), var_type.clone());
+            }
+        }
+        Ok(members)
+    }
+
+    // math.abs — обращение к члену модуля, а не к полю переменной
+    fn parse_qualified_name(&mut self, name: String) -> Result<String, ParseError> {
+        if !self.modules.contains(&name) || !matches!(self.peek(), Some(Token::Dot)) {
+            return Ok(name);
+        }
+        self.advance(); // .
+        let member = self.parse_name("module member name")?;
+        Ok(member_name(&name, &member))
+    }
+
+    fn parse_import(&mut self) -> Result<Stmt, ParseError> {
+        self.expect(Token::Import)?;
+        let span = self.here();
+        let path = match self.advance() {
+            Some(Token::StringLiteral(path)) => path.clone(),
+            token => {
+                return Err(ParseError {
+                    code: "E0201",
+                    message: format!("Expected a file path in quotes after import, got {:?}", token),
+                    span,
+                    notes: Vec::new(),
+                })
+            }
+        };
+        self.expect_terminator()?;
+        Ok(Stmt::Import(path))
+    }
+
+    // Пути из import в начале файла; CLI загружает эти модули раньше самого файла
+    pub fn parse_imports(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut paths = Vec::new();
+        while let Some(Token::Import) = self.peek() {
+            if let Stmt::Import(path) = self.parse_import()? {
+                paths.push(path);
+            }
+        }
+        Ok(paths)
+    }
+
+    // Следующий модуль программы: типы и глобальные переменные прошлых модулей остаются видны
+    pub fn parse_module(&mut self, tokens: Vec<SpannedToken>) -> Result<Program, Vec<ParseError>> {
+        let (tokens, line_breaks, spans) = split_line_breaks(tokens);
+        self.tokens = tokens;
+        self.line_breaks = line_breaks;
+        self.spans = spans;
+        self.position = 0;
+        // Места из прошлых модулей относятся к другим файлам
+        self.definitions.clear();
+        self.parse()
+    }
+
+    // Подряд идущие строки /// сливаются в один текст
+    fn parse_doc_comment(&mut self) -> Option<String> {
+        let mut lines = Vec::new();
+        while let Some(Token::DocComment(line)) = self.peek() {
+            lines.push(line.clone());
+            self.advance();
+        }
+        if lines.is_empty() {
+            None
         } else {
-            args.push(self.parse_expression()?);
+            Some(lines.join("\n"))
         }
+    }
+
+    // macro log(msg) { echo("[LOG] " + msg); } — тело разбирается только при раскрытии
+    fn parse_macro(&mut self) -> Result<(), ParseError> {
+        self.expect(Token::Macro)?;
+        let name = self.parse_name("macro name")?;
+        if self.macros.contains_key(&name) {
+            return Err(self.duplicate_error("macro", &name, format!("Macro '{}' is defined more than once", name)));
+        }
+        self.definitions.insert(("macro", name.clone()), self.previous());
         
+        self.expect(Token::LParen)?;
+        let mut params: Vec<String> = Vec::new();
+        while !matches!(self.peek(), Some(Token::RParen)) {
+            let param = self.parse_name("macro parameter name")?;
+            if params.contains(&param) {
+                return Err(ParseError {
+                    code: "E0304",
+                    message: format!("Parameter '{}' of macro '{}' is declared more than once", param, name),
+                    span: self.here(),
+                    notes: Vec::new(),
+                });
+            }
+            params.push(param);
+            if let Some(Token::Comma) = self.peek() {
+                self.advance();
+            } else {
+                break;
+            }
+        }
         self.expect(Token::RParen)?;
-        self.expect(Token::Semicolon)?;
         
-        Ok(Expr::Call {
-            name,
-            args,
-        })
+        if !matches!(self.peek(), Some(Token::LBrace)) {
+            return Err(ParseError {
+                code: "E0205",
+                message: format!("Expected the body of macro '{}' in braces", name),
+                span: self.here(),
+                notes: Vec::new(),
+            });
+        }
+        let body = self.parse_macro_body(&name)?;
+        self.macros.insert(name, MacroDef { params, body });
+        Ok(())
     }
 
-    pub fn parse(&mut self) -> Result<Program, ParseError> {
-        let mut statements = Vec::new();
+    // Тело макроса вместе с фигурными скобками
+    fn parse_macro_body(&mut self, name: &str) -> Result<Vec<(Token, bool, Span)>, ParseError> {
+        let mut body = Vec::new();
+        let mut depth = 0usize;
+        loop {
+            let token = self.next_macro_token(name)?;
+            match token.0 {
+                Token::LBrace => depth += 1,
+                Token::RBrace => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+            if depth == 0 {
+                return Ok(body);
+            }
+        }
+    }
+
+    fn next_macro_token(&mut self, name: &str) -> Result<(Token, bool, Span), ParseError> {
+        let line_break = self.at_line_start();
+        let span = self.here();
+        match self.advance() {
+            Some(Token::EOF) | None => Err(ParseError {
+                code: "E0205",
+                message: format!("Unterminated macro '{}'", name),
+                span,
+                notes: Vec::new(),
+            }),
+            Some(token) => Ok((token.clone(), line_break, span)),
+        }
+    }
+
+    // log("x") на месте инструкции заменяется блоком из тела макроса, где каждый параметр —
+    // аргумент в скобках, чтобы сохранить приоритет операторов
+    fn expand_macro(&mut self) -> Result<(), ParseError> {
+        let start = self.position;
+        let name = self.parse_name("macro name")?;
+        self.expect(Token::LParen)?;
+        // Аргументы разделяются запятыми вне вложенных скобок
+        let mut args: Vec<Vec<(Token, bool, Span)>> = vec![Vec::new()];
+        let mut depth = 0usize;
+        loop {
+            let token = self.next_macro_token(&name)?;
+            match token.0 {
+                Token::RParen if depth == 0 => break,
+                Token::Comma if depth == 0 => {
+                    args.push(Vec::new());
+                    continue;
+                }
+                Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+                Token::RParen | Token::RBracket | Token::RBrace => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            args.last_mut().unwrap().push(token);
+        }
+        // log() — без аргументов, log(a,) — запятая в конце отбрасывается
+        if args.last().is_some_and(|arg| arg.is_empty()) {
+            args.pop();
+        }
         
-        while let Some(token) = self.peek() {
-            match token {
-                Token::StringType | Token::IntegerType | Token::FloatType | Token::BooleanType => {
-                    statements.push(self.parse_declaration()?);
+        let definition = self.macros[&name].clone();
+        if args.len() != definition.params.len() || args.iter().any(|arg| arg.is_empty()) {
+            return Err(ParseError {
+                code: "E0205",
+                message: format!("Macro '{}' takes {} argument(s), got {}", name, definition.params.len(), args.len()),
+                span: self.here(),
+                notes: Vec::new(),
+            });
+        }
+        
+        // Раскрытия, которые уже разобраны, больше не считаются охватывающими
+        self.expansions.retain(|&end| end > start);
+        if self.expansions.len() >= MAX_MACRO_DEPTH {
+            return Err(ParseError {
+                code: "E0205",
+                message: format!(
+                    "Macro '{}' is expanded more than {} levels deep; is it recursive?", name, MAX_MACRO_DEPTH
+                ),
+                span: self.here(),
+                notes: Vec::new(),
+            });
+        }
+        
+        // Токены тела указывают на определение макроса, аргументы — на место вызова
+        let mut expanded: Vec<(Token, bool, Span)> = Vec::new();
+        for (token, line_break, span) in definition.body {
+            match &token {
+                Token::Ident(param) if definition.params.contains(param) => {
+                    let index = definition.params.iter().position(|p| p == param).unwrap();
+                    expanded.push((Token::LParen, line_break, span));
+                    expanded.extend(args[index].iter().cloned());
+                    expanded.push((Token::RParen, false, span));
                 }
-                
-                Token::Ident(name) => {
-                    let name = name.clone();
-                    self.advance();
-                    
-                    if name == "echo" {
-                        statements.push(Stmt::Expression(self.parse_call(name)?));
+                _ => expanded.push((token, line_break, span)),
+            }
+        }
+        // Раскрытие начинает инструкцию там же, где стоял вызов
+        expanded[0].1 = self.line_breaks[start];
+        
+        let replaced = self.position - start;
+        let added = expanded.len();
+        let mut tokens = Vec::with_capacity(added);
+        let mut line_breaks = Vec::with_capacity(added);
+        let mut spans = Vec::with_capacity(added);
+        for (token, line_break, span) in expanded {
+            tokens.push(token);
+            line_breaks.push(line_break);
+            spans.push(span);
+        }
+        self.tokens.splice(start..self.position, tokens);
+        self.line_breaks.splice(start..self.position, line_breaks);
+        self.spans.splice(start..self.position, spans);
+        for end in &mut self.expansions {
+            *end = *end + added - replaced;
+        }
+        self.expansions.push(start + added);
+        self.position = start;
+        Ok(())
+    }
+
+    // #[inline] #[deprecated("use g")] — подряд перед объявлением
+    fn parse_attributes(&mut self) -> Result<Vec<Attribute>, ParseError> {
+        let mut attributes: Vec<Attribute> = Vec::new();
+        while let Some(Token::Hash) = self.peek() {
+            self.advance();
+            self.expect(Token::LBracket)?;
+            let name = self.parse_name("attribute name")?;
+            let mut args = Vec::new();
+            if let Some(Token::LParen) = self.peek() {
+                self.advance();
+                while !matches!(self.peek(), Some(Token::RParen)) {
+                    let span = self.here();
+                    match self.advance() {
+                        Some(Token::StringLiteral(arg)) => args.push(arg.clone()),
+                        _ => {
+                            return Err(ParseError {
+                                code: "E0204",
+                                message: format!("Arguments of attribute '{}' must be string literals", name),
+                                span,
+                                notes: Vec::new(),
+                            })
+                        }
+                    }
+                    if let Some(Token::Comma) = self.peek() {
+                        self.advance();
                     } else {
-                        return Err(ParseError {
-                            message: format!("Unknown function or variable: {}", name),
-                            line: self.current_line,
-                            column: self.current_column,
-                        });
+                        break;
                     }
                 }
-                
-                Token::EOF => break,
-                Token::Illegal(ch) => {
+                self.expect(Token::RParen)?;
+            }
+            self.expect(Token::RBracket)?;
+            
+            let spec = attributes::lookup(&name).ok_or_else(|| ParseError {
+                code: "E0204",
+                message: format!("Unknown attribute '{}'", name),
+                span: self.here(),
+                notes: Vec::new(),
+            })?;
+            if args.len() > spec.max_args {
+                return Err(ParseError {
+                    code: "E0204",
+                    message: format!("Attribute '{}' takes at most {} argument(s), got {}", name, spec.max_args, args.len()),
+                    span: self.here(),
+                    notes: Vec::new(),
+                });
+            }
+            if attributes.iter().any(|a| a.name == name) {
+                return Err(ParseError {
+                    code: "E0204",
+                    message: format!("Attribute '{}' is given more than once", name),
+                    span: self.here(),
+                    notes: Vec::new(),
+                });
+            }
+            attributes.push(Attribute { name, args });
+        }
+        Ok(attributes)
+    }
+
+    fn attach_attributes(&self, mut stmt: Stmt, mut given: Vec<Attribute>) -> Result<Stmt, ParseError> {
+        match &mut stmt {
+            Stmt::Function { params, return_type, attributes, .. } => {
+                if attributes::has(&given, "test") && (!params.is_empty() || return_type.is_some()) {
                     return Err(ParseError {
-                        message: format!("Invalid character: '{}'", ch),
-                        line: self.current_line,
-                        column: self.current_column,
+                        code: "E0204",
+                        message: "A #[test] function must take no parameters and return nothing".to_string(),
+                        span: self.here(),
+                        notes: Vec::new(),
                     });
                 }
-                _ => {
-                    let token = self.advance().unwrap();
+                attributes.append(&mut given);
+            }
+            Stmt::Struct { attributes, .. } | Stmt::Enum { attributes, .. } | Stmt::Trait { attributes, .. } => {
+                if let Some(attribute) = given.iter().find(|a| attributes::lookup(&a.name).is_some_and(|spec| spec.functions_only)) {
                     return Err(ParseError {
-                        message: format!("Unexpected token: {:?}", token),
-                        line: self.current_line,
-                        column: self.current_column,
+                        code: "E0204",
+                        message: format!("Attribute '{}' can only be applied to functions", attribute.name),
+                        span: self.here(),
+                        notes: Vec::new(),
                     });
                 }
+                attributes.append(&mut given);
+            }
+            _ => {
+                return Err(ParseError {
+                    code: "E0204",
+                    message: "Attributes can only precede declarations of functions, structs, enums and traits".to_string(),
+                    span: self.here(),
+                    notes: Vec::new(),
+                })
+            }
+        }
+        Ok(stmt)
+    }
+
+    fn attach_doc(&self, mut stmt: Stmt, text: Option<String>) -> Result<Stmt, ParseError> {
+        match &mut stmt {
+            Stmt::Declaration { doc, .. }
+            | Stmt::Function { doc, .. }
+            | Stmt::Struct { doc, .. }
+            | Stmt::Enum { doc, .. }
+            | Stmt::Trait { doc, .. } => *doc = text,
+            _ => {
+                return Err(ParseError {
+                    code: "E0203",
+                    message: "Doc comments can only precede declarations of variables, functions, structs, enums and traits".to_string(),
+                    span: self.here(),
+                    notes: Vec::new(),
+                })
+            }
+        }
+        Ok(stmt)
+    }
+
+    pub fn parse(&mut self) -> Result<Program, Vec<ParseError>> {
+        let (program, errors) = self.parse_partial();
+        if errors.is_empty() {
+            Ok(program)
+        } else {
+            Err(errors)
+        }
+    }
+
+    // Ошибка в инструкции не останавливает разбор остальных: в программу попадает
+    // всё, что удалось разобрать, а ошибки возвращаются рядом в порядке исходника
+    pub fn parse_partial(&mut self) -> (Program, Vec<ParseError>) {
+        let mut statements = Vec::new();
+        
+        while !matches!(self.peek(), None | Some(Token::EOF)) {
+            let start = self.position;
+            if let Err(error) = self.parse_top_level(&mut statements) {
+                self.errors.push(error);
+                self.depth = 0;
+                self.loop_depth = 0;
+                self.in_function = false;
+                self.synchronize(start);
             }
         }
         
-        Ok(Program { statements })
+        let mut errors = std::mem::take(&mut self.errors);
+        errors.sort_by_key(|error| error.span.start);
+        (Program { statements }, errors)
+    }
+
+    fn parse_top_level(&mut self, statements: &mut Vec<Stmt>) -> Result<(), ParseError> {
+        if let Some(Token::Module) = self.peek() {
+            statements.extend(self.parse_module_block()?);
+            return Ok(());
+        }
+        if let Some(Token::Macro) = self.peek() {
+            return self.parse_macro();
+        }
+        let start = self.here();
+        let item = self.parse_item()?;
+        if matches!(item, Stmt::Import(_)) && statements.iter().any(|s| !matches!(s, Stmt::Import(_))) {
+            return Err(ParseError {
+                code: "E0203",
+                message: "Imports must come before all other declarations".to_string(),
+                span: self.span_from(start),
+                notes: vec![Note::help("move the import to the top of the file".to_string())],
+            });
+        }
+        statements.push(item);
+        Ok(())
+    }
+
+    // Пропускает инструкцию, начатую в start, дальше места, где разбор остановился.
+    // Следующая начинается вне скобок в начале строки, после ';' или с ключевого слова
+    // инструкции. Внутри блока разбор останавливается и на '}', закрывающей блок, а на
+    // верхнем уровне объявление в начале строки начинает новую инструкцию в любом случае
+    fn synchronize(&mut self, start: usize) {
+        let failed_at = self.position.max(start + 1);
+        let nested = self.depth > 0;
+        
+        let (mut braces, mut parens) = (0usize, 0usize);
+        let mut position = start;
+        while position < self.tokens.len() && self.tokens[position] != Token::EOF {
+            let token = &self.tokens[position];
+            if position >= failed_at {
+                let line_start = self.line_breaks[position];
+                let item = match token {
+                    Token::Func => !matches!(self.tokens.get(position + 1), Some(Token::LParen)),
+                    Token::Struct | Token::Enum | Token::Trait | Token::Impl | Token::Module | Token::Macro => true,
+                    _ => false,
+                };
+                let keyword = matches!(
+                    token,
+                    Token::Let
+                        | Token::Const
+                        | Token::Mut
+                        | Token::Match
+                        | Token::Try
+                        | Token::For
+                        | Token::While
+                        | Token::Loop
+                        | Token::Break
+                        | Token::Continue
+                        | Token::Return
+                        | Token::Defer
+                );
+                let after_semicolon = self.tokens[position - 1] == Token::Semicolon;
+                if (braces == 0 && (line_start || (parens == 0 && (after_semicolon || keyword))))
+                    || (!nested && item && line_start)
+                {
+                    break;
+                }
+            }
+            match token {
+                Token::LBrace => braces += 1,
+                Token::RBrace if braces == 0 && nested => break,
+                Token::RBrace => braces = braces.saturating_sub(1),
+                Token::LParen | Token::LBracket => parens += 1,
+                Token::RParen | Token::RBracket => parens = parens.saturating_sub(1),
+                _ => {}
+            }
+            position += 1;
+        }
+        self.position = position;
+    }
+}
+
+// Токены Newline убираются из потока, остаётся только отметка у следующего токена
+fn split_line_breaks(tokens: Vec<SpannedToken>) -> (Vec<Token>, Vec<bool>, Vec<Span>) {
+    let mut kept = Vec::with_capacity(tokens.len());
+    let mut line_breaks = Vec::with_capacity(tokens.len());
+    let mut spans = Vec::with_capacity(tokens.len());
+    let mut pending = false;
+    for SpannedToken { token, span } in tokens {
+        if token == Token::Newline {
+            pending = true;
+        } else {
+            kept.push(token);
+            line_breaks.push(pending);
+            spans.push(span);
+            pending = false;
+        }
     }
-}
\ No newline at end of file
+    (kept, line_breaks, spans)
+}
+
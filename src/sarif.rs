@@ -0,0 +1,200 @@
+use crate::diagnostics::{Note, NoteKind};
+use crate::error_codes;
+use crate::lexer::Span;
+use crate::warnings;
+
+use std::path::PathBuf;
+
+// Результаты quark check в формате SARIF 2.1.0 для GitHub code scanning и других
+// инструментов. rule — код ошибки (E0101) или имя предупреждения (shadowed-variable)
+pub struct Finding {
+    pub rule: String,
+    pub warning: bool,
+    pub message: String,
+    pub path: PathBuf,
+    pub span: Option<Span>,
+    pub notes: Vec<Note>,
+}
+
+enum Json {
+    Object(Vec<(&'static str, Json)>),
+    Array(Vec<Json>),
+    String(String),
+    Number(usize),
+}
+
+impl Json {
+    fn text(text: impl Into<String>) -> Json {
+        Json::String(text.into())
+    }
+
+    // { "text": ... } — так в SARIF записывается любое сообщение
+    fn message(text: impl Into<String>) -> Json {
+        Json::Object(vec![("text", Json::text(text))])
+    }
+
+    fn write(&self, out: &mut String, indent: usize) {
+        let pad = "  ".repeat(indent + 1);
+        match self {
+            Json::Object(fields) if fields.is_empty() => out.push_str("{}"),
+            Json::Array(items) if items.is_empty() => out.push_str("[]"),
+            Json::Object(fields) => {
+                out.push_str("{\n");
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    out.push_str(&pad);
+                    write_string(out, key);
+                    out.push_str(": ");
+                    value.write(out, indent + 1);
+                    out.push_str(if i + 1 < fields.len() { ",\n" } else { "\n" });
+                }
+                out.push_str(&"  ".repeat(indent));
+                out.push('}');
+            }
+            Json::Array(items) => {
+                out.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    out.push_str(&pad);
+                    item.write(out, indent + 1);
+                    out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+                }
+                out.push_str(&"  ".repeat(indent));
+                out.push(']');
+            }
+            Json::String(text) => write_string(out, text),
+            Json::Number(number) => out.push_str(&number.to_string()),
+        }
+    }
+}
+
+fn write_string(out: &mut String, text: &str) {
+    out.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+}
+
+pub fn document(findings: &[Finding], version: &str) -> String {
+    // Описание каждого правила, которое встретилось в результатах, по одному разу
+    let mut rules: Vec<&str> = Vec::new();
+    for finding in findings {
+        if !rules.contains(&finding.rule.as_str()) {
+            rules.push(&finding.rule);
+        }
+    }
+    let rules = rules.into_iter().map(rule).collect();
+
+    let driver = Json::Object(vec![
+        ("name", Json::text("quark")),
+        ("version", Json::text(version)),
+        ("informationUri", Json::text("https://github.com/folltawn/quark")),
+        ("rules", Json::Array(rules)),
+    ]);
+    let run = Json::Object(vec![
+        ("tool", Json::Object(vec![("driver", driver)])),
+        // Столбцы считаются в символах, как в сообщениях компилятора
+        ("columnKind", Json::text("unicodeCodePoints")),
+        ("results", Json::Array(findings.iter().map(result).collect())),
+    ]);
+    let document = Json::Object(vec![
+        ("$schema", Json::text("https://json.schemastore.org/sarif-2.1.0.json")),
+        ("version", Json::text("2.1.0")),
+        ("runs", Json::Array(vec![run])),
+    ]);
+
+    let mut out = String::new();
+    document.write(&mut out, 0);
+    out
+}
+
+fn rule(id: &str) -> Json {
+    let (short, full) = match (error_codes::lookup(id), warnings::lookup(id)) {
+        (Some(code), _) => (code.title, code.explanation),
+        (None, Some(spec)) => (spec.description, spec.description),
+        (None, None) => (id, id),
+    };
+    Json::Object(vec![
+        ("id", Json::text(id)),
+        ("shortDescription", Json::message(short)),
+        ("fullDescription", Json::message(full)),
+    ])
+}
+
+fn result(finding: &Finding) -> Json {
+    let uri = finding.path.display().to_string().replace('\\', "/");
+    let physical = |span: Option<Span>| {
+        // Без места result относится ко всему файлу, и region не пишется вовсе
+        let mut location = vec![("artifactLocation", Json::Object(vec![("uri", Json::text(uri.clone()))]))];
+        location.extend(span.map(|span| ("region", region(span))));
+        Json::Object(location)
+    };
+
+    // Заметки с местом — связанные места, советы с заменой — исправления,
+    // а заметки без места дописываются к тексту сообщения
+    let mut message = finding.message.clone();
+    let mut related = Vec::new();
+    let mut fixes = Vec::new();
+    for note in &finding.notes {
+        match (note.span, &note.replacement) {
+            (Some(span), Some(replacement)) => fixes.push(Json::Object(vec![
+                ("description", Json::message(note.message.clone())),
+                (
+                    "artifactChanges",
+                    Json::Array(vec![Json::Object(vec![
+                        ("artifactLocation", Json::Object(vec![("uri", Json::text(uri.clone()))])),
+                        (
+                            "replacements",
+                            Json::Array(vec![Json::Object(vec![
+                                ("deletedRegion", region(span)),
+                                ("insertedContent", Json::message(replacement.clone())),
+                            ])]),
+                        ),
+                    ])]),
+                ),
+            ])),
+            (Some(span), None) => related.push(Json::Object(vec![
+                ("id", Json::Number(related.len())),
+                ("message", Json::message(note.message.clone())),
+                ("physicalLocation", physical(Some(span))),
+            ])),
+            (None, _) => {
+                let label = match note.kind {
+                    NoteKind::Note => "note",
+                    NoteKind::Help => "help",
+                };
+                message.push_str(&format!("\n{}: {}", label, note.message));
+            }
+        }
+    }
+
+    let mut fields = vec![
+        ("ruleId", Json::text(finding.rule.clone())),
+        ("level", Json::text(if finding.warning { "warning" } else { "error" })),
+        ("message", Json::message(message)),
+        ("locations", Json::Array(vec![Json::Object(vec![("physicalLocation", physical(finding.span))])])),
+    ];
+    if !related.is_empty() {
+        fields.push(("relatedLocations", Json::Array(related)));
+    }
+    if !fixes.is_empty() {
+        fields.push(("fixes", Json::Array(fixes)));
+    }
+    Json::Object(fields)
+}
+
+fn region(span: Span) -> Json {
+    Json::Object(vec![
+        ("startLine", Json::Number(span.line)),
+        ("startColumn", Json::Number(span.column)),
+        ("endLine", Json::Number(span.end_line)),
+        ("endColumn", Json::Number(span.end_column)),
+    ])
+}
@@ -0,0 +1,1330 @@
+use crate::attributes;
+use crate::diagnostics::Note;
+use crate::lexer::Span;
+use crate::parser::{
+    Expr, Field, LambdaParam, MatchArm, Method, MethodSignature, Param, Pattern, Program, Stmt, StructPattern, Value,
+    VarType, Variant,
+};
+use crate::types::{
+    argument_count_matches, array_result_type, assignable, binary_result_type, builtin_result_type,
+    cast_result_type, check_json_type, comprehension_result_type, field_result_type, index_result_type,
+    inferred_type, is_builtin, iteration_element_type, lambda_param_types, map_result_type, member_name,
+    method_signature, operator_call, operator_method, pattern_bindings, range_result_type, slice_result_type, split_member,
+    spread_element_type, try_result_type, unary_result_type, user_type_name, value_type, with_expected_type, Impls,
+    TypeError, SPREAD_CONTEXT_ERROR,
+};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub struct SemanticError {
+    pub code: &'static str,
+    pub message: String,
+    // Инструкция или выражение, в которых найдена ошибка
+    pub span: Option<Span>,
+    pub notes: Vec<Note>,
+}
+
+impl SemanticError {
+    pub fn new(code: &'static str, message: impl Into<String>) -> Self {
+        SemanticError { code, message: message.into(), span: None, notes: Vec::new() }
+    }
+
+    // Повторное объявление показывает, где имя объявлено впервые
+    fn first_declared(mut self, name: &str, span: Option<Span>) -> Self {
+        if let Some(span) = span.filter(|span| *span != Span::default()) {
+            self.notes.push(Note::located(format!("'{}' is first declared here", name), span));
+        }
+        self
+    }
+
+    // Место берётся от самого вложенного узла: внешние узлы его не перезаписывают,
+    // а синтезированные узлы без позиции (Span::default()) пропускаются
+    fn at(mut self, span: Option<Span>) -> Self {
+        if self.span.is_none() {
+            self.span = span.filter(|span| *span != Span::default());
+        }
+        self
+    }
+}
+
+impl From<TypeError> for SemanticError {
+    fn from(error: TypeError) -> Self {
+        SemanticError::new(error.code, error.message)
+    }
+}
+
+impl std::fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Warning {
+    // Имя из warnings::WARNINGS, по нему предупреждение отключают флагом -A
+    pub name: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} [{}]", self.message, self.name)
+    }
+}
+
+pub struct Analyzer {
+    warnings: Vec<Warning>,
+    // Метки охватывающих циклов, от внешнего к внутреннему
+    labels: Vec<String>,
+    functions: HashMap<String, Signature>,
+    scopes: Vec<HashMap<String, Variable>>,
+    structs: HashMap<String, Vec<Field>>,
+    enums: HashMap<String, Vec<Variant>>,
+    traits: HashMap<String, Vec<MethodSignature>>,
+    impls: Impls,
+    // Тип ошибки Result, который возвращает текущая функция; только в такой функции работает ?
+    error_type: Option<VarType>,
+    // Тип результата текущей функции: по нему выводятся типы параметров возвращаемой лямбды
+    return_type: Option<VarType>,
+    // Модуль, функция которого сейчас проверяется, и индекс первой области видимости этой функции
+    module: Option<String>,
+    function_scope: usize,
+    // Проверяется отложенная инструкция: выход из функции в ней уже идёт, и ? запрещён
+    deferring: bool,
+    // Функции и типы с #[deprecated] и хвост предупреждения о них
+    deprecated: HashMap<String, String>,
+    // Функция или метод (Point::show), тело которых проверяется: им глобальные переменные видны
+    // независимо от места объявления, а порядок проверяется по вызовам
+    function: Option<String>,
+    // Какие глобальные переменные функции читают и какие функции вызывают
+    uses: HashMap<String, FunctionUses>,
+    // Вызовы из кода верхнего уровня: функция, сколько глобальных к этому месту уже объявлено
+    // и инструкция вызова
+    top_level_calls: Vec<(String, usize, Option<Span>)>,
+    // Номер глобальной переменной в порядке объявлений верхнего уровня
+    global_order: HashMap<String, usize>,
+    // Ближайшая проверяемая инструкция с известным местом: к ней привязываются предупреждения
+    span: Option<Span>,
+}
+
+#[derive(Default)]
+struct FunctionUses {
+    globals: Vec<String>,
+    calls: Vec<String>,
+}
+
+struct Variable {
+    var_type: VarType,
+    binding: Binding,
+    // Инструкция объявления; у параметров и привязок образцов её нет
+    span: Option<Span>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Binding {
+    // Объявлена через mut; параметры и счётчик for изменяемы всегда
+    Mutable,
+    Immutable,
+    Constant,
+}
+
+impl Binding {
+    fn of(constant: bool, mutable: bool) -> Self {
+        match (constant, mutable) {
+            (true, _) => Binding::Constant,
+            (false, true) => Binding::Mutable,
+            (false, false) => Binding::Immutable,
+        }
+    }
+}
+
+struct Signature {
+    params: Vec<VarType>,
+    return_type: Option<VarType>,
+    span: Span,
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Analyzer {
+    pub fn new() -> Self {
+        Self {
+            warnings: Vec::new(),
+            labels: Vec::new(),
+            functions: HashMap::new(),
+            scopes: vec![HashMap::new()],
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            traits: HashMap::new(),
+            impls: Impls::new(),
+            error_type: None,
+            return_type: None,
+            module: None,
+            function_scope: 0,
+            deferring: false,
+            deprecated: HashMap::new(),
+            function: None,
+            uses: HashMap::new(),
+            top_level_calls: Vec::new(),
+            global_order: HashMap::new(),
+            span: None,
+        }
+    }
+
+    pub fn analyze(&mut self, program: &Program) -> Result<Vec<Warning>, SemanticError> {
+        for stmt in &program.statements {
+            if let (Some(name), Some(note)) = (declared_name(stmt), attributes::deprecation_note(attributes::attributes_of(stmt))) {
+                self.deprecated.insert(name.to_string(), note);
+            }
+            match stmt {
+                Stmt::Struct { name, fields, .. } => {
+                    self.structs.insert(name.clone(), fields.clone());
+                }
+                Stmt::Enum { name, variants, .. } => {
+                    self.enums.insert(name.clone(), variants.clone());
+                }
+                Stmt::Trait { name, methods, .. } => {
+                    self.traits.insert(name.clone(), methods.clone());
+                }
+                _ => {}
+            }
+        }
+        // Как и функции, реализации трейтов видны во всей программе
+        for stmt in &program.statements {
+            if let Stmt::Impl { trait_name, self_type, methods, span } = stmt {
+                self.collect_impl(trait_name, self_type, methods).map_err(|error| error.at(Some(*span)))?;
+            }
+        }
+        self.collect_functions(&program.statements)?;
+        // Объявления верхнего уровня — глобальные переменные, они видны везде
+        for stmt in &program.statements {
+            self.span = stmt.span();
+            self.declare_global(stmt).map_err(|error| error.at(stmt.span()))?;
+        }
+        self.span = None;
+        self.check_block(&program.statements)?;
+        self.check_initialization_order()?;
+        Ok(std::mem::take(&mut self.warnings))
+    }
+
+    fn declare_global(&mut self, stmt: &Stmt) -> Result<(), SemanticError> {
+        match stmt {
+            Stmt::Declaration { var_type, name, value, constant, mutable, .. } => {
+                let var_type = match var_type {
+                    Some(var_type) => var_type.clone(),
+                    None => {
+                        let value_type = self.in_member_scope(name, |analyzer| analyzer.expr_type(value))?;
+                        inferred_type(name, &value_type)?
+                    }
+                };
+                self.declare(name, &var_type, Binding::of(*constant, *mutable))?;
+            }
+            Stmt::Destructure { pattern, value, .. } => {
+                for (binding, _, var_type) in self.pattern_bindings(pattern, value)? {
+                    self.declare(&binding, &var_type, Binding::Immutable)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Функция, вызванная до объявления глобальной переменной, которую она (или то, что она вызывает)
+    // читает, получила бы неинициализированное значение
+    fn check_initialization_order(&self) -> Result<(), SemanticError> {
+        for (callee, declared, span) in &self.top_level_calls {
+            let mut visited = vec![callee.as_str()];
+            let mut pending = vec![callee.as_str()];
+            while let Some(function) = pending.pop() {
+                let Some(uses) = self.uses.get(function) else { continue };
+                if let Some(global) = uses.globals.iter().find(|g| self.global_order.get(*g).is_none_or(|i| i >= declared)) {
+                    return Err(SemanticError::new("E0301", format!(
+                        "variable '{}' is used before its declaration: function '{}' reads it and is called before '{}' is declared",
+                        global, function, global
+                    )).at(*span));
+                }
+                for call in &uses.calls {
+                    if !visited.contains(&call.as_str()) {
+                        visited.push(call);
+                        pending.push(call);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Через трейт может вызваться метод любого типа, который его реализует
+    fn record_method_call(&mut self, target_type: &VarType, method: &str) {
+        let types: Vec<String> = match target_type {
+            VarType::Trait(trait_name) => {
+                self.impls.iter().filter(|(_, other)| other == trait_name).map(|(type_name, _)| type_name.clone()).collect()
+            }
+            other => user_type_name(other).map(str::to_string).into_iter().collect(),
+        };
+        for type_name in types {
+            self.record_call(method_key(&type_name, method));
+        }
+    }
+
+    fn record_call(&mut self, callee: String) {
+        match &self.function {
+            Some(function) => self.uses.entry(function.clone()).or_default().calls.push(callee),
+            None => self.top_level_calls.push((callee, self.global_order.len(), self.span)),
+        }
+    }
+
+    fn warn(&mut self, name: &'static str, message: String) {
+        self.warnings.push(Warning { name, message, span: self.span });
+    }
+
+    // Устаревший тип может быть и внутри составного: Array<Point>, Point?
+    fn warn_deprecated_type(&mut self, var_type: &VarType) {
+        let mut names = Vec::new();
+        user_type_names(var_type, &mut names);
+        for name in names {
+            if let Some(note) = self.deprecated.get(&name) {
+                let message = format!("type {} is deprecated{}", name, note);
+                self.warn("deprecated", message);
+            }
+        }
+    }
+
+    fn collect_impl(&mut self, trait_name: &str, self_type: &VarType, methods: &[Method]) -> Result<(), SemanticError> {
+        let type_name = user_type_name(self_type).unwrap().to_string();
+        let error = |code, message| Err(SemanticError::new(code, message));
+        let expected = &self.traits[trait_name];
+        
+        for (index, method) in methods.iter().enumerate() {
+            let name = &method.signature.name;
+            if methods[..index].iter().any(|other| other.signature.name == *name) {
+                return error("E0304", format!("method '{}' is defined more than once in impl {} for {}", name, trait_name, type_name));
+            }
+            match expected.iter().find(|m| m.name == *name) {
+                None => return error("E0404", format!("method '{}' is not a member of trait {}", name, trait_name)),
+                Some(signature) if !same_signature(signature, &method.signature) => {
+                    return error("E0404", format!(
+                        "method '{}' in impl {} for {} does not match the trait: expected {}, got {}",
+                        name, trait_name, type_name, describe(signature), describe(&method.signature)
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+        if let Some(missing) = expected.iter().find(|m| !methods.iter().any(|method| method.signature.name == m.name)) {
+            return error("E0404", format!("impl {} for {} is missing method '{}'", trait_name, type_name, missing.name));
+        }
+        
+        if self.impls.contains(&(type_name.clone(), trait_name.to_string())) {
+            return error("E0404", format!("trait {} is implemented more than once for {}", trait_name, type_name));
+        }
+        // Вызов p.show() должен однозначно определять метод
+        for (other_type, other_trait) in &self.impls {
+            if *other_type != type_name {
+                continue;
+            }
+            if let Some(method) = expected.iter().find(|m| self.traits[other_trait].iter().any(|o| o.name == m.name)) {
+                return error("E0404", format!(
+                    "method '{}' of {} is defined by both trait {} and trait {}",
+                    method.name, type_name, other_trait, trait_name
+                ));
+            }
+        }
+        self.impls.insert((type_name, trait_name.to_string()));
+        Ok(())
+    }
+
+    // Функции видны во всей программе, независимо от места объявления
+    fn collect_functions(&mut self, statements: &[Stmt]) -> Result<(), SemanticError> {
+        for stmt in statements {
+            if let Stmt::Function { name, params, return_type, span, .. } = stmt {
+                // Член модуля (math::abs) вызывается только с префиксом и встроенную функцию не закрывает
+                if split_member(name).is_none() && is_builtin(name) {
+                    return Err(SemanticError::new("E0304", format!("cannot redefine the built-in function '{}'", name)).at(Some(*span)));
+                }
+                let signature = Signature {
+                    params: params.iter().map(|p| p.var_type.clone()).collect(),
+                    return_type: return_type.clone(),
+                    span: *span,
+                };
+                if let Some(previous) = self.functions.insert(name.clone(), signature) {
+                    return Err(SemanticError::new("E0304", format!("function '{}' is defined more than once", name))
+                        .at(Some(*span))
+                        .first_declared(name, Some(previous.span)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_block(&mut self, statements: &[Stmt]) -> Result<(), SemanticError> {
+        self.scopes.push(HashMap::new());
+        let result = statements.iter().try_for_each(|stmt| self.check_statement(stmt));
+        self.scopes.pop();
+        result
+    }
+
+    // Во вложенной области имя может перекрыть внешнее, но не повториться в той же
+    fn declare(&mut self, name: &str, var_type: &VarType, binding: Binding) -> Result<(), SemanticError> {
+        // Вторая область — код верхнего уровня, объявление в ней инициализирует глобальную переменную
+        if self.function.is_none() && self.scopes.len() == 2 && self.scopes[0].contains_key(name) {
+            let order = self.global_order.len();
+            self.global_order.entry(name.to_string()).or_insert(order);
+        }
+        let variable = Variable { var_type: var_type.clone(), binding, span: self.span };
+        if let Some(previous) = self.scopes.last_mut().unwrap().insert(name.to_string(), variable) {
+            // В одной инструкции (два параметра, две привязки образца) заметка указала бы на ту же строку
+            let first = previous.span.filter(|span| Some(*span) != self.span);
+            return Err(SemanticError::new("E0304", format!("variable '{}' is already declared in this scope", name))
+                .first_declared(name, first));
+        }
+        Ok(())
+    }
+
+    // В функции модуля собственные переменные важнее членов модуля, а те — глобальных имён
+    fn lookup(&self, name: &str) -> Option<&Variable> {
+        fn find<'a>(scopes: &'a [HashMap<String, Variable>], name: &str) -> Option<&'a Variable> {
+            scopes.iter().rev().find_map(|scope| scope.get(name))
+        }
+        match &self.module {
+            Some(module) => {
+                let (outer, locals) = self.scopes.split_at(self.function_scope);
+                find(locals, name)
+                    .or_else(|| find(outer, &member_name(module, name)))
+                    .or_else(|| find(outer, name))
+            }
+            None => find(&self.scopes, name),
+        }
+    }
+
+    // Глобальные переменные объявлены заранее в самой внешней области, а код верхнего
+    // уровня видит их только после инструкции объявления
+    fn check_variable(&mut self, name: &str, undeclared: &str) -> Result<(), SemanticError> {
+        if self.lookup(name).is_none() {
+            let mut error = SemanticError::new("E0301", undeclared.to_string());
+            error.notes.extend(self.similar_names_help(name));
+            return Err(error);
+        }
+        if let Some(function) = self.function.clone() {
+            if let Some(global) = self.global_name(name) {
+                self.uses.entry(function).or_default().globals.push(global);
+            }
+            return Ok(());
+        }
+        let declared_later = self.scopes[0].contains_key(name) && !self.scopes[1..].iter().any(|scope| scope.contains_key(name));
+        if declared_later {
+            return Err(SemanticError::new("E0301", format!("variable '{}' is used before its declaration", name)));
+        }
+        Ok(())
+    }
+
+    // Глобальная переменная, к которой в теле функции относится имя, если его не перекрывают локальные
+    fn global_name(&self, name: &str) -> Option<String> {
+        if self.scopes[self.function_scope..].iter().any(|scope| scope.contains_key(name)) {
+            return None;
+        }
+        let member = self.module.as_ref().map(|module| member_name(module, name));
+        member.filter(|member| self.scopes[0].contains_key(member))
+            .or_else(|| self.scopes[0].contains_key(name).then(|| name.to_string()))
+    }
+
+    // Совет "did you mean 'count'?" для видимых сейчас переменных с похожими именами
+    fn similar_names_help(&self, name: &str) -> Option<Note> {
+        let limit = (name.chars().count() / 3).max(1);
+        let mut candidates: Vec<(usize, &str)> = Vec::new();
+        for scope in &self.scopes {
+            for visible in scope.keys() {
+                let visible = match (split_member(visible), &self.module) {
+                    (Some((module, member)), Some(current)) if module == current => member,
+                    (Some(_), _) => continue,
+                    (None, _) => visible.as_str(),
+                };
+                let distance = edit_distance(name, visible);
+                if distance <= limit && !candidates.iter().any(|(_, c)| *c == visible) {
+                    candidates.push((distance, visible));
+                }
+            }
+        }
+        candidates.sort();
+        let names: Vec<String> = candidates.iter().take(3).map(|(_, c)| format!("'{}'", c)).collect();
+        match names.as_slice() {
+            [] => None,
+            [single] => Some(Note::help(format!("did you mean {}?", single))),
+            [rest @ .., last] => Some(Note::help(format!("did you mean {} or {}?", rest.join(", "), last))),
+        }
+    }
+
+    fn resolve_function(&self, name: &str) -> String {
+        if let Some(module) = &self.module {
+            let member = member_name(module, name);
+            if self.functions.contains_key(&member) {
+                return member;
+            }
+        }
+        name.to_string()
+    }
+
+    fn check_statement(&mut self, stmt: &Stmt) -> Result<(), SemanticError> {
+        let outer = self.span;
+        self.span = stmt.span().filter(|span| *span != Span::default()).or(outer);
+        let result = self.check_statement_kind(stmt);
+        self.span = outer;
+        result.map_err(|error| error.at(stmt.span()))
+    }
+
+    fn check_statement_kind(&mut self, stmt: &Stmt) -> Result<(), SemanticError> {
+        match stmt {
+            Stmt::Match { value, arms, .. } => {
+                self.check_expr(value)?;
+                let match_type = self.expr_type(value)?;
+                self.check_match(&match_type, arms);
+                for arm in arms {
+                    self.check_arm(&match_type, arm)?;
+                }
+                Ok(())
+            }
+            Stmt::For { label, init, condition, update, body, .. } => {
+                // Переменная цикла видна только внутри for
+                self.scopes.push(HashMap::new());
+                let result = self.check_statement(init).and_then(|_| {
+                    self.check_expr(condition)?;
+                    self.check_statement(update)?;
+                    self.check_loop(label.as_deref(), body)
+                });
+                self.scopes.pop();
+                result
+            }
+            Stmt::ForIn { label, variable, iterable, body, .. } => {
+                self.check_expr(iterable)?;
+                let iterable_type = self.expr_type(iterable)?;
+                let element_type = iteration_element_type(&iterable_type)?;
+                self.scopes.push(HashMap::new());
+                let result = self
+                    .declare(variable, &element_type, Binding::Immutable)
+                    .and_then(|_| self.check_loop(label.as_deref(), body));
+                self.scopes.pop();
+                result
+            }
+            Stmt::While { label, condition, body, .. } => {
+                self.check_expr(condition)?;
+                self.check_loop(label.as_deref(), body)
+            }
+            Stmt::Loop { label, body } => self.check_loop(label.as_deref(), body),
+            Stmt::Function { name, params, return_type, body, .. } => {
+                Self::check_params(name, params)?;
+                for var_type in params.iter().map(|p| &p.var_type).chain(return_type) {
+                    self.warn_deprecated_type(var_type);
+                }
+                // Параметры и тело функции находятся в одной области видимости
+                self.scopes.push(HashMap::new());
+                self.error_type = error_type(return_type.as_ref());
+                self.return_type = return_type.clone();
+                self.module = split_member(name).map(|(module, _)| module.to_string());
+                self.function_scope = self.scopes.len() - 1;
+                self.function = Some(name.clone());
+                let result = params
+                    .iter()
+                    .try_for_each(|param| self.declare(&param.name, &param.var_type, Binding::Mutable))
+                    .and_then(|_| body.iter().try_for_each(|stmt| self.check_statement(stmt)));
+                self.error_type = None;
+                self.return_type = None;
+                self.module = None;
+                self.function = None;
+                self.scopes.pop();
+                result
+            }
+            Stmt::Assignment { name, value, .. } => self.check_assignment(name, value),
+            Stmt::Destructure { pattern, value, .. } => {
+                self.check_expr(value)?;
+                for (binding, _, var_type) in self.pattern_bindings(pattern, value)? {
+                    self.declare(&binding, &var_type, Binding::Immutable)?;
+                }
+                Ok(())
+            }
+            Stmt::FieldAssignment { target, field, value, .. } => {
+                self.check_expr(target)?;
+                self.check_expr(value)?;
+                
+                // Структура хранится по значению, изменить поле — изменить переменную.
+                // Элементы массивов и словарей меняются и через неизменяемую переменную
+                if let Some(name) = root_variable(target) {
+                    let message = match self.lookup(name).map(|v| v.binding) {
+                        Some(Binding::Constant) => format!("cannot assign to a field of constant '{}'", name),
+                        Some(Binding::Immutable) => format!(
+                            "cannot assign to a field of '{}': it is not declared with `mut`", name
+                        ),
+                        _ => String::new(),
+                    };
+                    if !message.is_empty() {
+                        return Err(SemanticError::new("E0401", message));
+                    }
+                }
+                
+                let target_type = self.expr_type(target)?;
+                let field_type = field_result_type(&self.structs, &target_type, field)?;
+                let value_type = self.expr_type(value)?;
+                if !assignable(&self.impls, &field_type, &value_type) {
+                    return Err(SemanticError::new("E0101", format!("cannot assign {:?} to field '{}' of type {:?}", value_type, field, field_type)));
+                }
+                Ok(())
+            }
+            Stmt::IndexAssignment { target, index, value, .. } => {
+                self.check_expr(target)?;
+                self.check_expr(index)?;
+                self.check_expr(value)?;
+                
+                let target_type = self.expr_type(target)?;
+                if target_type == VarType::String {
+                    return Err(SemanticError::new("E0401", "strings cannot be modified by index".to_string()));
+                }
+                if target_type == VarType::Bytes {
+                    return Err(SemanticError::new("E0401", "Bytes values cannot be modified by index".to_string()));
+                }
+                let index_type = self.expr_type(index)?;
+                let element_type = index_result_type(&target_type, &index_type)?;
+                let value_type = self.expr_type(value)?;
+                if !assignable(&self.impls, &element_type, &value_type) {
+                    return Err(SemanticError::new("E0101", format!("cannot assign {:?} to an element of type {:?}", value_type, element_type)));
+                }
+                Ok(())
+            }
+            Stmt::Return(Some(value)) => {
+                let value = match self.return_type.clone() {
+                    Some(return_type) => with_expected_type(value, &return_type).into_owned(),
+                    None => value.clone(),
+                };
+                self.check_expr(&value)
+            }
+            Stmt::Expression(value) => self.check_expr(value),
+            Stmt::Break(Some(label)) => self.resolve_label("break", label),
+            Stmt::Continue(Some(label)) => self.resolve_label("continue", label),
+            Stmt::Declaration { var_type, name, value, constant, mutable, .. } => {
+                if let Some(var_type) = var_type {
+                    self.warn_deprecated_type(var_type);
+                }
+                let var_type = self.check_initializer(var_type.as_ref(), name, value)?;
+                self.declare(name, &var_type, Binding::of(*constant, *mutable))
+            }
+            Stmt::Block(statements) => self.check_block(statements),
+            Stmt::Defer(deferred) => {
+                if let Some(jump) = deferred_jump(std::slice::from_ref(deferred.as_ref()), 0, &mut Vec::new()) {
+                    return Err(SemanticError::new("E0402", format!("'{}' cannot leave a deferred statement; it runs when the scope is already exiting", jump)));
+                }
+                let outer = std::mem::replace(&mut self.deferring, true);
+                let result = self.check_statement(deferred);
+                self.deferring = outer;
+                result
+            }
+            Stmt::Try { body, error, handler } => {
+                self.check_block(body)?;
+                // Переменная с текстом ошибки видна только в catch
+                self.scopes.push(HashMap::new());
+                let result = self
+                    .declare(error, &VarType::String, Binding::Immutable)
+                    .and_then(|_| self.check_block(handler));
+                self.scopes.pop();
+                result
+            }
+            Stmt::Impl { trait_name, self_type, methods, .. } => {
+                for method in methods {
+                    let name = format!("{}.{}", trait_name, method.signature.name);
+                    Self::check_params(&name, &method.signature.params)?;
+                    self.scopes.push(HashMap::new());
+                    self.error_type = error_type(method.signature.return_type.as_ref());
+                    self.return_type = method.signature.return_type.clone();
+                    self.function_scope = self.scopes.len() - 1;
+                    self.function = Some(method_key(user_type_name(self_type).unwrap(), &method.signature.name));
+                    let result = self
+                        .declare("self", self_type, Binding::Mutable)
+                        .and_then(|_| {
+                            method
+                                .signature
+                                .params
+                                .iter()
+                                .try_for_each(|param| self.declare(&param.name, &param.var_type, Binding::Mutable))
+                        })
+                        .and_then(|_| method.body.iter().try_for_each(|stmt| self.check_statement(stmt)));
+                    self.error_type = None;
+                    self.return_type = None;
+                    self.function = None;
+                    self.scopes.pop();
+                    result?;
+                }
+                Ok(())
+            }
+            Stmt::Struct { .. } | Stmt::Enum { .. } | Stmt::Trait { .. } | Stmt::Import(_) => Ok(()),
+            Stmt::Break(None)
+            | Stmt::Continue(None)
+            | Stmt::Return(None) => Ok(()),
+        }
+    }
+
+    fn pattern_bindings(&mut self, pattern: &StructPattern,
+                        value: &Expr) -> Result<Vec<(String, String, VarType)>, SemanticError> {
+        let value_type = self.expr_type(value)?;
+        pattern_bindings(&self.structs, pattern, &value_type).map_err(SemanticError::from)
+    }
+
+    // Переменная модуля инициализируется в контексте своего модуля:
+    // её значение может ссылаться на другие члены без префикса
+    // Значение переменной модуля видит другие его члены без префикса
+    fn in_member_scope<T>(&mut self, name: &str, check: impl FnOnce(&mut Self) -> T) -> T {
+        let outer_module = self.module.clone();
+        let outer_scope = self.function_scope;
+        if let Some((module, _)) = split_member(name) {
+            self.module = Some(module.to_string());
+            self.function_scope = self.scopes.len();
+        }
+        let result = check(self);
+        self.module = outer_module;
+        self.function_scope = outer_scope;
+        result
+    }
+
+    // Без объявленного типа (let) переменная получает тип значения
+    fn check_initializer(&mut self, var_type: Option<&VarType>, name: &str, value: &Expr) -> Result<VarType, SemanticError> {
+        let value = match var_type {
+            Some(var_type) => with_expected_type(value, var_type),
+            None => Cow::Borrowed(value),
+        };
+        let value_type = self.in_member_scope(name, |analyzer| {
+            analyzer.check_expr(&value).and_then(|_| analyzer.expr_type(&value))
+        })?;
+        let var_type = match var_type {
+            Some(var_type) => var_type,
+            None => return inferred_type(name, &value_type).map_err(SemanticError::from),
+        };
+        if !assignable(&self.impls, var_type, &value_type) {
+            return Err(SemanticError::new("E0101", format!(
+                "cannot initialize '{}' of type {:?} with a value of type {:?}",
+                name, var_type, value_type
+            )));
+        }
+        Ok(var_type.clone())
+    }
+
+    fn check_assignment(&mut self, name: &str, value: &Expr) -> Result<(), SemanticError> {
+        self.check_variable(name, &format!("cannot assign to undeclared variable '{}'", name))?;
+        let variable = self.lookup(name).unwrap();
+        let var_type = variable.var_type.clone();
+        let binding = variable.binding;
+        let value = with_expected_type(value, &var_type);
+        self.check_expr(&value)?;
+        
+        match binding {
+            Binding::Constant => {
+                return Err(SemanticError::new("E0401", format!("cannot assign to constant '{}'", name)))
+            }
+            Binding::Immutable => {
+                return Err(SemanticError::new("E0401", format!(
+                    "cannot assign twice to immutable variable '{}'; declare it with `mut` to allow reassignment",
+                    name
+                )))
+            }
+            Binding::Mutable => {}
+        }
+        let value_type = self.expr_type(&value)?;
+        if !assignable(&self.impls, &var_type, &value_type) {
+            return Err(SemanticError::new("E0101", format!(
+                "cannot assign {:?} to variable '{}' of type {:?}",
+                value_type, name, var_type
+            )));
+        }
+        Ok(())
+    }
+
+    fn expr_type(&mut self, expr: &Expr) -> Result<VarType, SemanticError> {
+        self.expr_kind_type(expr).map_err(|error| error.at(expr.span()))
+    }
+
+    fn expr_kind_type(&mut self, expr: &Expr) -> Result<VarType, SemanticError> {
+        match expr {
+            Expr::Literal(value) => Ok(value_type(value)),
+            Expr::Array(elements) => {
+                let mut element_types = Vec::new();
+                for element in elements {
+                    element_types.push(match element {
+                        Expr::Spread(array) => (true, self.expr_type(array)?),
+                        _ => (false, self.expr_type(element)?),
+                    });
+                }
+                array_result_type(&element_types).map_err(SemanticError::from)
+            }
+            Expr::Struct { name, .. } => Ok(VarType::Struct(name.clone())),
+            Expr::Enum { name, .. } => Ok(VarType::Enum(name.clone())),
+            Expr::Map(entries) => {
+                let mut entry_types = Vec::new();
+                for (key, value) in entries {
+                    entry_types.push((self.expr_type(key)?, self.expr_type(value)?));
+                }
+                map_result_type(&entry_types).map_err(SemanticError::from)
+            }
+            Expr::Spread(_) => Err(SemanticError::new("E0499", SPREAD_CONTEXT_ERROR.to_string())),
+            Expr::JsonParse { target, .. } => Ok(VarType::Result(Box::new(target.clone()), Box::new(VarType::String))),
+            Expr::Comprehension { element, variable, iterable, condition } => {
+                self.enter_comprehension(variable, iterable)?;
+                let element_type = self.expr_type(element);
+                let condition_type = condition.as_deref().map(|condition| self.expr_type(condition)).transpose();
+                self.scopes.pop();
+                comprehension_result_type(&element_type?, condition_type?.as_ref()).map_err(SemanticError::from)
+            }
+            // Как и компилятор, неизвестную переменную считаем строкой
+            Expr::Variable(name) => Ok(self.lookup(name).map_or(VarType::String, |v| v.var_type.clone())),
+            Expr::BinaryOp { left, op, right, span } => {
+                let left_type = self.expr_type(left)?;
+                if let Some((method, _)) = operator_method(&self.traits, &self.impls, op, &left_type) {
+                    return self.expr_type(&operator_call(left, method, right, *span));
+                }
+                let right_type = self.expr_type(right)?;
+                binary_result_type(op, &left_type, &right_type).map_err(SemanticError::from)
+            }
+            Expr::UnaryOp { op, operand, .. } => {
+                let operand_type = self.expr_type(operand)?;
+                unary_result_type(op, &operand_type).map_err(SemanticError::from)
+            }
+            Expr::Index { target, index } => {
+                let target_type = self.expr_type(target)?;
+                let index_type = self.expr_type(index)?;
+                index_result_type(&target_type, &index_type).map_err(SemanticError::from)
+            }
+            Expr::Cast { value, target } => {
+                let value_type = self.expr_type(value)?;
+                cast_result_type(&value_type, target).map_err(SemanticError::from)
+            }
+            Expr::Slice { target, start, end } => {
+                let target_type = self.expr_type(target)?;
+                let bounds = start.iter().chain(end).map(|bound| self.expr_type(bound)).collect::<Result<Vec<_>, _>>()?;
+                slice_result_type(&target_type, &bounds).map_err(SemanticError::from)
+            }
+            Expr::Range { start, end } => {
+                let start_type = self.expr_type(start)?;
+                let end_type = self.expr_type(end)?;
+                range_result_type(&start_type, &end_type).map_err(SemanticError::from)
+            }
+            Expr::Field { target, field } => {
+                let target_type = self.expr_type(target)?;
+                field_result_type(&self.structs, &target_type, field).map_err(SemanticError::from)
+            }
+            Expr::Call { name, .. } => self
+                .call_result_type(expr)?
+                .ok_or_else(|| SemanticError::new("E0403", format!("'{}' does not return a value", name))),
+            Expr::MethodCall { method, .. } => self
+                .call_result_type(expr)?
+                .ok_or_else(|| SemanticError::new("E0403", format!("method '{}' does not return a value", method))),
+            Expr::Lambda { params, body } => {
+                let param_types = lambda_param_types(params)?;
+                self.enter_lambda(params);
+                let result = self.call_result_type(body);
+                self.scopes.pop();
+                Ok(VarType::Function(param_types, result?.map(Box::new)))
+            }
+            Expr::Try(value) => {
+                let value_type = self.expr_type(value)?;
+                try_result_type(&value_type).map(|(ok_type, _)| ok_type).map_err(SemanticError::from)
+            }
+        }
+    }
+
+    // Результат вызова, если он есть; любое другое выражение всегда даёт значение
+    fn call_result_type(&mut self, expr: &Expr) -> Result<Option<VarType>, SemanticError> {
+        match expr {
+            Expr::Call { name, args, .. } if is_builtin(name) => {
+                let arg_types = args.iter().map(|arg| self.expr_type(arg)).collect::<Result<Vec<_>, _>>()?;
+                builtin_result_type(name, &arg_types).map_err(SemanticError::from)
+            }
+            Expr::Call { name, .. } => Ok(match self.closure_type(name) {
+                Some(VarType::Function(_, return_type)) => return_type.map(|t| *t),
+                _ => self.functions.get(&self.resolve_function(name)).and_then(|f| f.return_type.clone()),
+            }),
+            Expr::MethodCall { target, method, .. } => {
+                let target_type = self.expr_type(target)?;
+                Ok(method_signature(&self.traits, &self.impls, &target_type, method)?
+                    .return_type
+                    .clone())
+            }
+            _ => self.expr_type(expr).map(Some),
+        }
+    }
+
+    // f(x), где f — переменная с функцией, вызывает замыкание, а не функцию с тем же именем
+    fn closure_type(&self, name: &str) -> Option<VarType> {
+        self.lookup(name)
+            .map(|variable| variable.var_type.clone())
+            .filter(|var_type| matches!(var_type, VarType::Function(..)))
+    }
+
+    // Параметры лямбды видны только в её теле, поверх захваченных переменных
+    fn enter_lambda(&mut self, params: &[LambdaParam]) {
+        let scope = params
+            .iter()
+            .filter_map(|param| {
+                let var_type = param.var_type.clone()?;
+                Some((param.name.clone(), Variable { var_type, binding: Binding::Mutable, span: None }))
+            })
+            .collect();
+        self.scopes.push(scope);
+    }
+
+    // Переменная цикла видна только в элементе и условии
+    fn enter_comprehension(&mut self, variable: &str, iterable: &Expr) -> Result<(), SemanticError> {
+        let iterable_type = self.expr_type(iterable)?;
+        let var_type = iteration_element_type(&iterable_type)?;
+        self.scopes.push(HashMap::from([(variable.to_string(), Variable { var_type, binding: Binding::Immutable, span: None })]));
+        Ok(())
+    }
+
+    fn check_expr(&mut self, expr: &Expr) -> Result<(), SemanticError> {
+        self.check_expr_kind(expr).map_err(|error| error.at(expr.span()))
+    }
+
+    fn check_expr_kind(&mut self, expr: &Expr) -> Result<(), SemanticError> {
+        match expr {
+            Expr::Call { name, args, .. } => {
+                let param_types = match self.closure_type(name) {
+                    Some(VarType::Function(params, _)) => Some(params),
+                    _ => self.functions.get(&self.resolve_function(name)).map(|f| f.params.clone()),
+                };
+                for (index, arg) in args.iter().enumerate() {
+                    match param_types.as_ref().and_then(|params| params.get(index)) {
+                        Some(param_type) => self.check_expr(&with_expected_type(arg, param_type))?,
+                        None => self.check_expr(arg)?,
+                    }
+                }
+                self.check_call(name, args)
+            }
+            Expr::Lambda { params, body } => {
+                lambda_param_types(params)?;
+                // '?' в теле лямбды вышел бы из неё, а не из функции вокруг
+                let outer_error = self.error_type.take();
+                self.enter_lambda(params);
+                let result = self.check_expr(body);
+                self.scopes.pop();
+                self.error_type = outer_error;
+                result
+            }
+            Expr::Index { target, index } => {
+                self.check_expr(target)?;
+                self.check_expr(index)?;
+                self.expr_type(expr).map(|_| ())
+            }
+            Expr::Cast { value, .. } => {
+                self.check_expr(value)?;
+                self.expr_type(expr).map(|_| ())
+            }
+            Expr::Slice { target, start, end } => {
+                self.check_expr(target)?;
+                start.iter().chain(end).try_for_each(|bound| self.check_expr(bound))?;
+                self.expr_type(expr).map(|_| ())
+            }
+            Expr::Range { start, end } => {
+                self.check_expr(start)?;
+                self.check_expr(end)?;
+                self.expr_type(expr).map(|_| ())
+            }
+            Expr::Field { target, .. } => {
+                self.check_expr(target)?;
+                self.expr_type(expr).map(|_| ())
+            }
+            Expr::MethodCall { target, method, args, .. } => {
+                self.check_expr(target)?;
+                args.iter().try_for_each(|arg| self.check_expr(arg))?;
+                let target_type = self.expr_type(target)?;
+                let signature = method_signature(&self.traits, &self.impls, &target_type, method)?;
+                if !argument_count_matches(signature.params.len(), args) {
+                    return Err(SemanticError::new("E0102", format!(
+                        "method '{}' takes {} argument(s), got {}",
+                        method, signature.params.len(), args.len()
+                    )));
+                }
+                let params: Vec<VarType> = signature.params.iter().map(|p| p.var_type.clone()).collect();
+                self.record_method_call(&target_type, method);
+                self.check_arguments(method, &params, args)
+            }
+            // Типы операндов проверяются и там, где значение никуда не сохраняется
+            Expr::BinaryOp { left, op, right, .. } => {
+                self.check_expr(left)?;
+                self.check_expr(right)?;
+                let left_type = self.expr_type(left)?;
+                if let Some((method, _)) = operator_method(&self.traits, &self.impls, op, &left_type) {
+                    self.record_method_call(&left_type, &method);
+                }
+                self.expr_type(expr).map(|_| ())
+            }
+            Expr::UnaryOp { operand, .. } => {
+                self.check_expr(operand)?;
+                self.expr_type(expr).map(|_| ())
+            }
+            Expr::Array(elements) => elements.iter().try_for_each(|element| self.check_expr(element)),
+            Expr::Struct { name, fields } => {
+                let struct_type = VarType::Struct(name.clone());
+                for (field, value) in fields {
+                    let field_type = field_result_type(&self.structs, &struct_type, field)?;
+                    let value = with_expected_type(value, &field_type);
+                    self.check_expr(&value)?;
+                    let value_type = self.expr_type(&value)?;
+                    if !assignable(&self.impls, &field_type, &value_type) {
+                        return Err(SemanticError::new("E0101", format!(
+                            "field '{}' of {} must be {:?}, got {:?}",
+                            field, name, field_type, value_type
+                        )));
+                    }
+                }
+                Ok(())
+            }
+            Expr::Enum { name, variant, payload } => {
+                let types = self.enums[name].iter().find(|v| v.name == *variant).unwrap().payload.clone();
+                for (index, (value, value_type)) in payload.iter().zip(&types).enumerate() {
+                    let value = with_expected_type(value, value_type);
+                    self.check_expr(&value)?;
+                    let actual = self.expr_type(&value)?;
+                    if !assignable(&self.impls, value_type, &actual) {
+                        return Err(SemanticError::new("E0101", format!(
+                            "value {} of {}::{} must be {:?}, got {:?}",
+                            index + 1, name, variant, value_type, actual
+                        )));
+                    }
+                }
+                Ok(())
+            }
+            Expr::Map(entries) => {
+                for (key, value) in entries {
+                    self.check_expr(key)?;
+                    self.check_expr(value)?;
+                }
+                self.expr_type(expr).map(|_| ())
+            }
+            Expr::Comprehension { element, variable, iterable, condition } => {
+                self.check_expr(iterable)?;
+                self.enter_comprehension(variable, iterable)?;
+                let result = self
+                    .check_expr(element)
+                    .and_then(|_| condition.as_deref().map_or(Ok(()), |condition| self.check_expr(condition)));
+                self.scopes.pop();
+                result?;
+                self.expr_type(expr).map(|_| ())
+            }
+            Expr::JsonParse { text, target } => {
+                self.check_expr(text)?;
+                let text_type = self.expr_type(text)?;
+                if text_type != VarType::String {
+                    return Err(SemanticError::new("E0101", format!("'json_parse' expects a String, got {:?}", text_type)));
+                }
+                check_json_type(&self.structs, target).map_err(SemanticError::from)
+            }
+            Expr::Spread(array) => {
+                self.check_expr(array)?;
+                let array_type = self.expr_type(array)?;
+                spread_element_type(&array_type).map(|_| ()).map_err(SemanticError::from)
+            }
+            Expr::Try(value) => {
+                if self.deferring {
+                    return Err(SemanticError::new("E0402", "'?' cannot be used in a deferred statement: it would return while the function is already exiting".to_string()));
+                }
+                self.check_expr(value)?;
+                let value_type = self.expr_type(value)?;
+                let (_, value_error) = try_result_type(&value_type)?;
+                match &self.error_type {
+                    None => Err(SemanticError::new("E0499", "'?' can only be used inside a function that returns a Result".to_string())),
+                    Some(error_type) if *error_type != value_error => Err(SemanticError::new("E0101", format!(
+                        "'?' cannot return an error of type {:?} from a function whose errors are {:?}",
+                        value_error, error_type
+                    ))),
+                    Some(_) => Ok(()),
+                }
+            }
+            Expr::Variable(name) => self.check_variable(name, &format!("undeclared variable '{}'", name)),
+            Expr::Literal(_) => Ok(()),
+        }
+    }
+
+    fn check_params(function: &str, params: &[Param]) -> Result<(), SemanticError> {
+        for (index, param) in params.iter().enumerate() {
+            if params[..index].iter().any(|other| other.name == param.name) {
+                return Err(SemanticError::new("E0304", format!("parameter '{}' of function '{}' is declared more than once", param.name, function)));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_call(&mut self, name: &str, args: &[Expr]) -> Result<(), SemanticError> {
+        if is_builtin(name) {
+            if args.iter().any(|arg| matches!(arg, Expr::Spread(_))) {
+                return Err(SemanticError::new("E0104", format!("cannot spread an array into the arguments of builtin '{}'", name)));
+            }
+            let arg_types = args.iter().map(|arg| self.expr_type(arg)).collect::<Result<Vec<_>, _>>()?;
+            builtin_result_type(name, &arg_types)?;
+            if let ("json_stringify", [value_type]) = (name, arg_types.as_slice()) {
+                check_json_type(&self.structs, value_type)?;
+            }
+            return Ok(());
+        }
+
+        let arg_count = args.len();
+        if let Some(VarType::Function(params, _)) = self.closure_type(name) {
+            if !argument_count_matches(params.len(), args) {
+                return Err(SemanticError::new("E0102", format!("'{}' takes {} argument(s), got {}", name, params.len(), arg_count)));
+            }
+            return self.check_arguments(name, &params, args);
+        }
+        let resolved = self.resolve_function(name);
+        if let Some(note) = self.deprecated.get(&resolved) {
+            self.warn("deprecated", format!("function '{}' is deprecated{}", resolved, note));
+        }
+        if self.functions.contains_key(&resolved) {
+            self.record_call(resolved.clone());
+        }
+        match self.functions.get(&resolved).map(|f| f.params.clone()) {
+            Some(params) if argument_count_matches(params.len(), args) => self.check_arguments(name, &params, args),
+            Some(params) => Err(SemanticError::new("E0102", format!("function '{}' takes {} argument(s), got {}", name, params.len(), arg_count))),
+            None => Err(SemanticError::new("E0302", format!("unknown function '{}'", name))),
+        }
+    }
+
+    // Те же сообщения, что и у компилятора: ошибка находится до генерации кода
+    fn check_arguments(&mut self, name: &str, params: &[VarType], args: &[Expr]) -> Result<(), SemanticError> {
+        let (args, spread) = match args.split_last() {
+            Some((Expr::Spread(array), fixed)) => (fixed, Some(array)),
+            _ => (args, None),
+        };
+        for (index, (arg, param_type)) in args.iter().zip(params).enumerate() {
+            let arg_type = self.expr_type(&with_expected_type(arg, param_type))?;
+            if !assignable(&self.impls, param_type, &arg_type) {
+                return Err(SemanticError::new("E0101", format!(
+                    "argument {} of '{}' must be {:?}, got {:?}",
+                    index + 1, name, param_type, arg_type
+                )));
+            }
+        }
+        if let Some(array) = spread {
+            let rest = &params[args.len().min(params.len())..];
+            let array_type = self.expr_type(array)?;
+            let element_type = spread_element_type(&array_type)?;
+            if let Some(index) = rest.iter().position(|param| !assignable(&self.impls, param, &element_type)) {
+                return Err(SemanticError::new("E0101", format!(
+                    "cannot spread {:?} elements into argument {} of '{}', which must be {:?}",
+                    element_type, args.len() + index + 1, name, rest[index]
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_loop(&mut self, label: Option<&str>, body: &[Stmt]) -> Result<(), SemanticError> {
+        let label = match label {
+            Some(label) => label,
+            None => return self.check_block(body),
+        };
+
+        if self.labels.iter().any(|outer| outer == label) {
+            return Err(SemanticError::new("E0304", format!("label '{}' is already used by an enclosing loop", label)));
+        }
+
+        self.labels.push(label.to_string());
+        let result = self.check_block(body);
+        self.labels.pop();
+        result
+    }
+
+    fn resolve_label(&self, keyword: &str, label: &str) -> Result<(), SemanticError> {
+        if self.labels.iter().any(|outer| outer == label) {
+            Ok(())
+        } else {
+            Err(SemanticError::new("E0402", format!("'{} {}' does not refer to an enclosing loop label", keyword, label)))
+        }
+    }
+
+    // Значения, связанные образцом варианта, видны только в теле ветки
+    fn check_arm(&mut self, match_type: &VarType, arm: &MatchArm) -> Result<(), SemanticError> {
+        let (enum_name, variant, bindings) = match &arm.pattern {
+            Pattern::Variant { enum_name, variant, bindings } => (enum_name, variant, bindings),
+            Pattern::Struct(pattern) => {
+                let bindings = pattern_bindings(&self.structs, pattern, match_type)?;
+                self.scopes.push(HashMap::new());
+                let result = bindings
+                    .iter()
+                    .try_for_each(|(binding, _, var_type)| self.declare(binding, var_type, Binding::Immutable))
+                    .and_then(|_| self.check_block(&arm.body));
+                self.scopes.pop();
+                return result;
+            }
+            _ => return self.check_block(&arm.body),
+        };
+        if *match_type != VarType::Enum(enum_name.clone()) {
+            return Err(SemanticError::new("E0101", format!("pattern {}::{} cannot match a value of type {:?}", enum_name, variant, match_type)));
+        }
+        
+        let payload = self.enums[enum_name].iter().find(|v| v.name == *variant).unwrap().payload.clone();
+        self.scopes.push(HashMap::new());
+        let result = bindings
+            .iter()
+            .zip(&payload)
+            .filter(|(binding, _)| binding.as_str() != "_")
+            .try_for_each(|(binding, var_type)| self.declare(binding, var_type, Binding::Immutable))
+            .and_then(|_| self.check_block(&arm.body));
+        self.scopes.pop();
+        result
+    }
+
+    fn check_match(&mut self, match_type: &VarType, arms: &[MatchArm]) {
+        // Разбор структуры, как и _, подходит под любое значение
+        if let Some(index) = arms.iter().position(|arm| matches!(arm.pattern, Pattern::Wildcard | Pattern::Struct(_))) {
+            if index + 1 < arms.len() {
+                let arm = if matches!(arms[index].pattern, Pattern::Wildcard) { "the default arm `_`" } else { "a struct pattern" };
+                self.warn("unreachable-arm", format!(
+                    "unreachable match arm: {} arm(s) after {} are never executed",
+                    arms.len() - index - 1, arm
+                ));
+            }
+            return;
+        }
+
+        // Без ветки _ сопоставление перечисления должно покрывать все варианты
+        if let VarType::Enum(name) = match_type {
+            let missing: Vec<&str> = self.enums[name]
+                .iter()
+                .filter(|v| !arms.iter().any(|arm| matches!(&arm.pattern, Pattern::Variant { variant, .. } if *variant == v.name)))
+                .map(|v| v.name.as_str())
+                .collect();
+            if !missing.is_empty() {
+                self.warn("non-exhaustive-match", format!(
+                    "match on {} has no default arm `_` and does not handle `{}`",
+                    name,
+                    missing.join("`, `")
+                ));
+            }
+            return;
+        }
+
+        // Без ветки _ сопоставление Boolean должно покрывать оба значения
+        let covers = |expected: bool| {
+            arms.iter().any(|arm| matches!(arm.pattern, Pattern::Literal(Value::Boolean(b)) if b == expected))
+        };
+        let is_boolean = arms.iter().any(|arm| matches!(arm.pattern, Pattern::Literal(Value::Boolean(_))));
+
+        if is_boolean {
+            let missing: Vec<&str> = [(true, "true"), (false, "false")]
+                .iter()
+                .filter(|(value, _)| !covers(*value))
+                .map(|(_, name)| *name)
+                .collect();
+            if !missing.is_empty() {
+                self.warn("non-exhaustive-match", format!(
+                    "match on Boolean has no default arm `_` and does not handle `{}`",
+                    missing.join("`, `")
+                ));
+            }
+        }
+    }
+}
+
+// return, а также break и continue к циклу снаружи не могут выйти из отложенной инструкции
+fn deferred_jump<'a>(statements: &'a [Stmt], loops: usize, labels: &mut Vec<&'a str>) -> Option<&'static str> {
+    statements.iter().find_map(|stmt| match stmt {
+        Stmt::Return(_) => Some("return"),
+        Stmt::Break(None) if loops == 0 => Some("break"),
+        Stmt::Continue(None) if loops == 0 => Some("continue"),
+        Stmt::Break(Some(label)) if !labels.contains(&label.as_str()) => Some("break"),
+        Stmt::Continue(Some(label)) if !labels.contains(&label.as_str()) => Some("continue"),
+        Stmt::For { label, body, .. }
+        | Stmt::ForIn { label, body, .. }
+        | Stmt::While { label, body, .. }
+        | Stmt::Loop { label, body } => {
+            labels.extend(label.as_deref());
+            let jump = deferred_jump(body, loops + 1, labels);
+            if label.is_some() {
+                labels.pop();
+            }
+            jump
+        }
+        Stmt::Block(body) => deferred_jump(body, loops, labels),
+        Stmt::Try { body, handler, .. } => {
+            deferred_jump(body, loops, labels).or_else(|| deferred_jump(handler, loops, labels))
+        }
+        Stmt::Match { arms, .. } => arms.iter().find_map(|arm| deferred_jump(&arm.body, loops, labels)),
+        _ => None,
+    })
+}
+
+fn error_type(return_type: Option<&VarType>) -> Option<VarType> {
+    match return_type {
+        Some(VarType::Result(_, err_type)) => Some((**err_type).clone()),
+        _ => None,
+    }
+}
+
+fn method_key(type_name: &str, method: &str) -> String {
+    format!("{}::{}", type_name, method)
+}
+
+// Имена параметров в реализации могут отличаться от объявления в трейте
+fn same_signature(expected: &MethodSignature, actual: &MethodSignature) -> bool {
+    expected.return_type == actual.return_type
+        && expected.params.len() == actual.params.len()
+        && expected.params.iter().zip(&actual.params).all(|(a, b)| a.var_type == b.var_type)
+}
+
+fn describe(signature: &MethodSignature) -> String {
+    let mut params = vec!["self".to_string()];
+    params.extend(signature.params.iter().map(|p| format!("{:?}", p.var_type)));
+    match &signature.return_type {
+        Some(return_type) => format!("({}) -> {:?}", params.join(", "), return_type),
+        None => format!("({})", params.join(", ")),
+    }
+}
+
+// Расстояние Левенштейна по символам
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb { diagonal } else { 1 + diagonal.min(above).min(row[j]) };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+// Переменная, с которой начинается цепочка p.x[i].y
+fn root_variable(expr: &Expr) -> Option<&str> {
+    match expr {
+        Expr::Variable(name) => Some(name),
+        Expr::Index { target, .. } | Expr::Field { target, .. } => root_variable(target),
+        _ => None,
+    }
+}
+
+fn declared_name(stmt: &Stmt) -> Option<&str> {
+    match stmt {
+        Stmt::Function { name, .. } | Stmt::Struct { name, .. } | Stmt::Enum { name, .. } | Stmt::Trait { name, .. } => {
+            Some(name)
+        }
+        _ => None,
+    }
+}
+
+fn user_type_names(var_type: &VarType, names: &mut Vec<String>) {
+    match var_type {
+        VarType::Struct(name) | VarType::Enum(name) | VarType::Trait(name) => names.push(name.clone()),
+        VarType::Array(inner) | VarType::Option(inner) | VarType::Ok(inner) | VarType::Err(inner) => {
+            user_type_names(inner, names)
+        }
+        VarType::Map(first, second) | VarType::Result(first, second) => {
+            user_type_names(first, names);
+            user_type_names(second, names);
+        }
+        VarType::Function(params, result) => {
+            params.iter().chain(result.as_deref()).for_each(|param| user_type_names(param, names));
+        }
+        _ => {}
+    }
+}
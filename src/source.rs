@@ -0,0 +1,182 @@
+use std::fs::{self, File};
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+
+// Файлы больше этого размера отображаются в память, а не читаются целиком
+pub const MMAP_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+pub enum SourceText {
+    Owned(String),
+    Mapped(MappedFile),
+}
+
+impl SourceText {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+
+        if len < MMAP_THRESHOLD || !MappedFile::SUPPORTED {
+            return fs::read_to_string(path).map(SourceText::Owned);
+        }
+
+        let len = match usize::try_from(len) {
+            Ok(len) => len,
+            Err(_) => return fs::read_to_string(path).map(SourceText::Owned),
+        };
+
+        let mapped = MappedFile::map(&file, len)?;
+        if std::str::from_utf8(mapped.as_bytes()).is_err() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "stream did not contain valid UTF-8",
+            ));
+        }
+
+        Ok(SourceText::Mapped(mapped))
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        matches!(self, SourceText::Mapped(_))
+    }
+}
+
+impl Deref for SourceText {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match self {
+            SourceText::Owned(text) => text,
+            // UTF-8 проверен в SourceText::read
+            SourceText::Mapped(mapped) => unsafe { std::str::from_utf8_unchecked(mapped.as_bytes()) },
+        }
+    }
+}
+
+// Отображение файла только для чтения. Изменение файла другим процессом
+// во время компиляции приводит к неопределённому поведению, как и в любом mmap.
+pub struct MappedFile {
+    ptr: *const u8,
+    len: usize,
+    #[cfg(windows)]
+    mapping: sys::Handle,
+}
+
+impl MappedFile {
+    pub const SUPPORTED: bool = cfg!(any(unix, windows));
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::{c_int, c_void};
+
+    pub const PROT_READ: c_int = 1;
+    pub const MAP_PRIVATE: c_int = 2;
+    pub const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+
+    extern "C" {
+        pub fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: i64) -> *mut c_void;
+        pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+}
+
+#[cfg(unix)]
+impl MappedFile {
+    fn map(file: &File, len: usize) -> io::Result<Self> {
+        use std::os::unix::io::AsRawFd;
+
+        let ptr = unsafe {
+            sys::mmap(std::ptr::null_mut(), len, sys::PROT_READ, sys::MAP_PRIVATE, file.as_raw_fd(), 0)
+        };
+        if ptr == sys::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { ptr: ptr as *const u8, len })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        unsafe {
+            sys::munmap(self.ptr as *mut _, self.len);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::ffi::c_void;
+
+    pub type Handle = *mut c_void;
+
+    pub const PAGE_READONLY: u32 = 0x02;
+    pub const FILE_MAP_READ: u32 = 0x04;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub fn CreateFileMappingW(
+            file: Handle,
+            attributes: *mut c_void,
+            protect: u32,
+            size_high: u32,
+            size_low: u32,
+            name: *const u16,
+        ) -> Handle;
+        pub fn MapViewOfFile(mapping: Handle, access: u32, offset_high: u32, offset_low: u32, bytes: usize) -> *mut c_void;
+        pub fn UnmapViewOfFile(base: *const c_void) -> i32;
+        pub fn CloseHandle(handle: Handle) -> i32;
+    }
+}
+
+#[cfg(windows)]
+impl MappedFile {
+    fn map(file: &File, len: usize) -> io::Result<Self> {
+        use std::os::windows::io::AsRawHandle;
+
+        unsafe {
+            let mapping = sys::CreateFileMappingW(
+                file.as_raw_handle() as sys::Handle,
+                std::ptr::null_mut(),
+                sys::PAGE_READONLY,
+                0,
+                0,
+                std::ptr::null(),
+            );
+            if mapping.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+
+            let ptr = sys::MapViewOfFile(mapping, sys::FILE_MAP_READ, 0, 0, len);
+            if ptr.is_null() {
+                let err = io::Error::last_os_error();
+                sys::CloseHandle(mapping);
+                return Err(err);
+            }
+
+            Ok(Self { ptr: ptr as *const u8, len, mapping })
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        unsafe {
+            sys::UnmapViewOfFile(self.ptr as *const _);
+            sys::CloseHandle(self.mapping);
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+impl MappedFile {
+    fn map(_file: &File, _len: usize) -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "memory mapping is not supported"))
+    }
+}
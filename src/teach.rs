@@ -0,0 +1,192 @@
+use std::sync::OnceLock;
+
+// Пояснения для --teach: не что сломалось в конкретной строке (это делают сообщение
+// и quark explain), а простыми словами о понятии языка, на котором споткнулись.
+// Одно пояснение покрывает несколько кодов ошибок
+pub struct Lesson {
+    pub concept: &'static str,
+    pub codes: &'static [&'static str],
+    pub text: &'static str,
+}
+
+pub const LESSONS: &[Lesson] = &[
+    Lesson {
+        concept: "tokens and literals",
+        codes: &["E0001", "E0002", "E0003", "E0004", "E0005", "E0006", "E0007", "E0099"],
+        text: "\
+Before Quark understands your program, it cuts the text into small pieces
+called tokens: names like count, numbers like 42, strings like \"hi\",
+and symbols like + or {. A value written directly in the code, such as 42
+or \"hi\", is called a literal.
+
+This error means one piece could not be read at all: a string is missing
+its closing quote, a number has a digit that does not belong, or there is a
+character that Quark does not know. Look at the marked spot and the few
+characters before it.",
+    },
+    Lesson {
+        concept: "types",
+        codes: &["E0101", "E0103", "E0104", "E0105"],
+        text: "\
+Every value in Quark has a type that says what kind of value it is:
+Integer for whole numbers, Float for numbers with a fraction, String for
+text, Boolean for true and false. A variable keeps the type it was declared
+with, so Integer count can only ever hold whole numbers.
+
+A type mismatch means a value of one type was put where another type was
+expected, like storing the text \"three\" in an Integer. Quark does not guess
+what you meant; convert the value yourself, for example with to_string(n)
+or parse_int(text).",
+    },
+    Lesson {
+        concept: "functions and arguments",
+        codes: &["E0102", "E0302"],
+        text: "\
+A function is a named piece of code you can run again and again. The names
+in parentheses after func are its parameters; the values you pass when you
+call it are the arguments:
+
+    func add(Integer a, Integer b) -> Integer { return a + b; }
+    Integer sum = add(1, 2);
+
+Each call must pass exactly one argument for every parameter, in the same
+order, and the function must exist: either you declared it with func, or it
+is a builtin such as println.",
+    },
+    Lesson {
+        concept: "statements and punctuation",
+        codes: &["E0201", "E0202", "E0206", "E0299"],
+        text: "\
+A program is a list of statements, and each statement is one step such as
+declaring a variable or calling a function. A statement ends with ; or at
+the end of the line. Brackets always come in pairs: every ( needs a ),
+every [ a ] and every { a }.
+
+When Quark says it expected something else, it read your code up to the
+marked spot and found a piece that cannot come next. Often the real mistake
+is just before it: a missing ), a missing comma or an unfinished
+expression.",
+    },
+    Lesson {
+        concept: "declarations and names",
+        codes: &["E0203", "E0301", "E0303", "E0304", "E0305"],
+        text: "\
+A declaration introduces a new name and says what it is:
+
+    Integer total = 0;
+
+From that line on, total can be used until the end of the block { ... }
+it was declared in; this region is its scope. Using a name before its
+declaration, or outside its scope, is an error, and so is declaring the
+same name twice in one scope. Fields and methods work the same way: only
+those written in the struct or impl exist. Keywords such as match or loop
+are taken by the language and cannot be used as names.",
+    },
+    Lesson {
+        concept: "attributes and macros",
+        codes: &["E0204", "E0205"],
+        text: "\
+An attribute like #[test] is a note placed above a declaration that changes
+how Quark treats it. Only the attributes Quark knows exist, and each one
+fits only certain declarations.
+
+A macro is a named group of statements that is copied into the program
+wherever it is called. It must be defined with macro at the top level and
+called with as many arguments as it has parameters.",
+    },
+    Lesson {
+        concept: "constants and mutable variables",
+        codes: &["E0401"],
+        text: "\
+A variable declared with const can never change after it gets its value.
+A variable declared with mut can be given a new value later:
+
+    mut Integer score = 0;
+    score = 10;
+
+Decide when declaring a variable whether it needs to change. If Quark
+refuses an assignment, the target is something fixed: a constant, a part
+of a String, or the result of a call.",
+    },
+    Lesson {
+        concept: "loops",
+        codes: &["E0402"],
+        text: "\
+A loop repeats a block of code: loop repeats forever, while repeats as long
+as a condition is true, and for walks over the items of an array. Inside a
+loop, break leaves it and continue jumps to the next round.
+
+break and continue only make sense inside a loop, because they say what the
+loop should do next. Outside a loop there is nothing for them to stop.",
+    },
+    Lesson {
+        concept: "return values",
+        codes: &["E0403"],
+        text: "\
+A function can give a value back to the code that called it. The type after
+-> says what kind of value, and return hands it back:
+
+    func double(Integer n) -> Integer { return n * 2; }
+
+A function without -> only does something, like printing, and gives
+nothing back, so its call cannot be stored in a variable.",
+    },
+    Lesson {
+        concept: "traits",
+        codes: &["E0404"],
+        text: "\
+A trait is a list of methods that a type promises to have. impl Trait for
+Type is where the type keeps that promise by writing each of the methods.
+
+An impl has to contain every method of the trait, with the same name and
+parameters, and nothing the trait does not list.",
+    },
+    Lesson {
+        concept: "arithmetic limits",
+        codes: &["E0501", "E0502", "E0503"],
+        text: "\
+Computers store an Integer in a fixed number of bits, so there is a largest
+and a smallest Integer, and some operations have no answer at all, such as
+dividing by zero. Quark checks calculations made only of numbers written in
+the code while compiling, and reports the ones that would always fail when
+the program runs.",
+    },
+    Lesson {
+        concept: "building the program",
+        codes: &["E0499", "E0504", "E0599"],
+        text: "\
+Quark turns your program into C code and then asks a C compiler such as gcc
+to produce the executable. Errors at this stage usually come from the
+computer setup rather than from your code: make sure a C compiler is
+installed and can be found in PATH.",
+    },
+];
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+// --teach включается один раз при запуске и действует на все сообщения об ошибках
+pub fn enable() {
+    let _ = ENABLED.set(true);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.get().copied().unwrap_or(false)
+}
+
+pub fn lesson(code: &str) -> Option<&'static Lesson> {
+    LESSONS.iter().find(|lesson| lesson.codes.iter().any(|known| known.eq_ignore_ascii_case(code)))
+}
+
+// Пояснение выводится под сообщением об ошибке, с отступом, чтобы не сливаться с ним
+pub fn render(code: &str) -> Option<String> {
+    let lesson = lesson(code)?;
+    let mut rendered = format!("teach: {}", lesson.concept);
+    for line in lesson.text.lines() {
+        rendered.push('\n');
+        if !line.is_empty() {
+            rendered.push_str("    ");
+            rendered.push_str(line);
+        }
+    }
+    Some(rendered)
+}
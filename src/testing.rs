@@ -0,0 +1,207 @@
+use crate::compiler::Compiler;
+use crate::lexer::{LexError, Lexer, Span};
+use crate::lints;
+use crate::optimize;
+use crate::parser::Parser;
+use crate::semantic::Analyzer;
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Переменная окружения, при которой ожидаемые снимки перезаписываются
+pub const BLESS_ENV: &str = "QUARK_BLESS";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SnapshotKind {
+    Tokens,
+    Ast,
+    Diagnostics,
+}
+
+impl SnapshotKind {
+    pub const ALL: [SnapshotKind; 3] = [SnapshotKind::Tokens, SnapshotKind::Ast, SnapshotKind::Diagnostics];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SnapshotKind::Tokens => "tokens",
+            SnapshotKind::Ast => "ast",
+            SnapshotKind::Diagnostics => "diagnostics",
+        }
+    }
+
+    pub fn render(&self, source: &str) -> String {
+        match self {
+            SnapshotKind::Tokens => snapshot_tokens(source),
+            SnapshotKind::Ast => snapshot_ast(source),
+            SnapshotKind::Diagnostics => snapshot_diagnostics(source),
+        }
+    }
+}
+
+// Строка и столбец, к которым привязана диагностика
+fn location(span: Option<Span>) -> String {
+    span.map(|span| format!(" at {}:{}", span.line, span.column)).unwrap_or_default()
+}
+
+fn lexer_errors(errors: &[LexError]) -> String {
+    errors.iter().map(|e| format!("lexer error[{}]: {} at offset {}\n", e.code, e.message, e.position)).collect()
+}
+
+pub fn snapshot_tokens(source: &str) -> String {
+    let mut lexer = Lexer::new(source);
+    match lexer.tokenize() {
+        Ok(tokens) => tokens.iter().map(|t| format!("{:?} @ {}:{}\n", t.token, t.span.line, t.span.column)).collect(),
+        Err(errors) => lexer_errors(&errors),
+    }
+}
+
+pub fn snapshot_ast(source: &str) -> String {
+    let mut lexer = Lexer::new(source);
+    let tokens = match lexer.tokenize() {
+        Ok(tokens) => tokens,
+        Err(_) => return "<no ast: lexer error>\n".to_string(),
+    };
+
+    let mut parser = Parser::new(tokens);
+    match parser.parse() {
+        Ok(program) => format!("{:#?}\n", program),
+        Err(_) => "<no ast: parser error>\n".to_string(),
+    }
+}
+
+pub fn snapshot_diagnostics(source: &str) -> String {
+    let mut lexer = Lexer::new(source);
+    let tokens = match lexer.tokenize() {
+        Ok(tokens) => tokens,
+        Err(errors) => return lexer_errors(&errors),
+    };
+
+    let mut parser = Parser::new(tokens);
+    let mut program = match parser.parse() {
+        Ok(program) => program,
+        Err(errors) => return errors.iter().map(|e| format!("parser error[{}]: {}\n", e.code, e)).collect(),
+    };
+
+    // Стилевые проверки идут после предупреждений анализатора, как в quark check
+    let mut diagnostics: String = match Analyzer::new().analyze(&program) {
+        Ok(warnings) => warnings
+            .iter()
+            .chain(&lints::run(&program))
+            .map(|w| format!("warning: {}{}\n", w, location(w.span)))
+            .collect(),
+        Err(e) => {
+            let notes: String = e.notes.iter().map(|note| format!("  {}: {}{}\n", note.kind.label(), note.message, location(note.span))).collect();
+            return format!("semantic error[{}]: {}{}\n{}", e.code, e, location(e.span), notes);
+        }
+    };
+
+    // Деление на ноль и переполнение констант видны только после свёртки, их сообщает генерация C
+    optimize::optimize(&mut program);
+    if let Err(errors) = Compiler::new().check(&program) {
+        diagnostics.extend(errors.iter().map(|e| format!("compile error[{}]: {}\n", e.code(), e)));
+    }
+    diagnostics
+}
+
+#[derive(Debug)]
+pub struct Mismatch {
+    pub fixture: PathBuf,
+    pub kind: SnapshotKind,
+    pub expected: Option<String>,
+    pub actual: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expected = match &self.expected {
+            Some(expected) => expected,
+            None => return write!(f, "{}: missing .{} snapshot", self.fixture.display(), self.kind.extension()),
+        };
+
+        let expected_lines: Vec<&str> = expected.lines().collect();
+        let actual_lines: Vec<&str> = self.actual.lines().collect();
+        let line = expected_lines
+            .iter()
+            .zip(actual_lines.iter())
+            .position(|(e, a)| e != a)
+            .unwrap_or(expected_lines.len().min(actual_lines.len()));
+
+        writeln!(f, "{}: .{} snapshot differs at line {}", self.fixture.display(), self.kind.extension(), line + 1)?;
+        writeln!(f, "  expected: {}", expected_lines.get(line).unwrap_or(&"<end of snapshot>"))?;
+        write!(f, "  actual:   {}", actual_lines.get(line).unwrap_or(&"<end of snapshot>"))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub fixtures: usize,
+    pub blessed: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+
+    // Удобно вызывать из #[test]: падает со списком всех расхождений
+    pub fn assert_ok(&self) {
+        if !self.is_ok() {
+            let details: Vec<String> = self.mismatches.iter().map(|m| m.to_string()).collect();
+            panic!(
+                "{} of {} fixtures do not match their snapshots (rerun with {}=1 to update):\n{}",
+                self.mismatches.len(),
+                self.fixtures,
+                BLESS_ENV,
+                details.join("\n")
+            );
+        }
+    }
+}
+
+// Для каждого name.qrk в каталоге сравнивает name.tokens, name.ast и name.diagnostics
+pub fn run_fixtures(dir: &Path) -> io::Result<Report> {
+    let bless = std::env::var_os(BLESS_ENV).is_some_and(|v| v != "0");
+    let mut report = Report::default();
+
+    let mut fixtures: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("qrk"))
+        .collect();
+    fixtures.sort();
+
+    for fixture in fixtures {
+        let source = fs::read_to_string(&fixture)?;
+        report.fixtures += 1;
+
+        for kind in SnapshotKind::ALL {
+            let snapshot_path = fixture.with_extension(kind.extension());
+            let actual = kind.render(&source);
+            let expected = match fs::read_to_string(&snapshot_path) {
+                Ok(text) => Some(text.replace("\r\n", "\n")),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e),
+            };
+
+            if expected.as_deref() == Some(actual.as_str()) {
+                continue;
+            }
+
+            if bless {
+                fs::write(&snapshot_path, &actual)?;
+                report.blessed += 1;
+            } else {
+                report.mismatches.push(Mismatch {
+                    fixture: fixture.clone(),
+                    kind,
+                    expected,
+                    actual,
+                });
+            }
+        }
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,735 @@
+use crate::lexer::Span;
+use crate::parser::{BinOp, Expr, Field, LambdaParam, MethodSignature, StructPattern, UnaryOp, Value, VarType};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+// Правила типов общие для семантического анализа и генерации кода
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+    // Код из error_codes::CODES
+    pub code: &'static str,
+    pub message: String,
+}
+
+fn type_error<T>(code: &'static str, message: String) -> Result<T, TypeError> {
+    Err(TypeError { code, message })
+}
+
+pub fn value_type(value: &Value) -> VarType {
+    match value {
+        Value::String(_) => VarType::String,
+        Value::Integer(_) => VarType::Integer,
+        Value::Float(_) => VarType::Float,
+        Value::Boolean(_) => VarType::Boolean,
+        Value::BigInt(_) => VarType::BigInt,
+        Value::Bytes(_) => VarType::Bytes,
+        Value::Array { element_type, .. } => VarType::Array(Box::new(element_type.clone())),
+        Value::Map { key_type, value_type, .. } => {
+            VarType::Map(Box::new(key_type.clone()), Box::new(value_type.clone()))
+        }
+        Value::Struct { name, .. } => VarType::Struct(name.clone()),
+        Value::Enum { name, .. } => VarType::Enum(name.clone()),
+        Value::Some(value) => VarType::Option(Box::new(value_type(value))),
+        Value::None => VarType::None,
+        Value::Result { ok_type, err_type, .. } => {
+            VarType::Result(Box::new(ok_type.clone()), Box::new(err_type.clone()))
+        }
+    }
+}
+
+// Значения этих типов можно напечатать и приклеить к строке
+pub fn is_scalar(var_type: &VarType) -> bool {
+    !matches!(
+        var_type,
+        VarType::Bytes
+            | VarType::Array(_)
+            | VarType::Map(..)
+            | VarType::Struct(_)
+            | VarType::Enum(_)
+            | VarType::Trait(_)
+            | VarType::Option(_)
+            | VarType::None
+            | VarType::Result(..)
+            | VarType::Ok(_)
+            | VarType::Err(_)
+            | VarType::Function(..)
+    )
+}
+
+pub fn unary_result_type(op: &UnaryOp, operand: &VarType) -> Result<VarType, TypeError> {
+    match (op, operand) {
+        (UnaryOp::Neg, VarType::Integer) => Ok(VarType::Integer),
+        (UnaryOp::Neg, VarType::Float) => Ok(VarType::Float),
+        (UnaryOp::BitNot, VarType::Integer) => Ok(VarType::Integer),
+        (UnaryOp::BitNot, _) => type_error("E0104", format!(
+            "bitwise '~' requires an Integer operand, got {:?}",
+            operand
+        )),
+        _ => type_error("E0104", format!(
+            "cannot apply unary '{}' to {:?}",
+            op.symbol(), operand
+        )),
+    }
+}
+
+pub fn binary_result_type(op: &BinOp, left: &VarType, right: &VarType) -> Result<VarType, TypeError> {
+    if *op == BinOp::In {
+        return membership_result_type(left, right);
+    }
+    if *op == BinOp::Coalesce {
+        return coalesce_result_type(left, right);
+    }
+    // Методы операторов ищутся только среди реализаций трейтов: у типа нет методов вне impl Trait for
+    if let (true, VarType::Struct(name) | VarType::Enum(name)) = (op.is_overloadable(), left) {
+        return type_error("E0104", format!(
+            "cannot apply '{}' to {:?} and {:?}: an operator on a user type is a trait method, declare \
+             'func {}(self, {} other)' in a trait and implement it with 'impl <Trait> for {}'",
+            op.symbol(), left, right, op.method_name(), type_name(right), name
+        ));
+    }
+    if op.is_comparison() {
+        return match (op, left, right) {
+            (_, VarType::Integer, VarType::Integer) | (_, VarType::Float, VarType::Float) => Ok(VarType::Boolean),
+            (_, VarType::Integer, VarType::Float) | (_, VarType::Float, VarType::Integer) => Ok(VarType::Boolean),
+            (BinOp::Eq | BinOp::Ne, VarType::Boolean, VarType::Boolean) => Ok(VarType::Boolean),
+            // Строки сравниваются по содержимому, порядок — побайтовый, как у strcmp
+            (_, VarType::String, VarType::String) => Ok(VarType::Boolean),
+            (_, VarType::String, other) | (_, other, VarType::String) => type_error("E0104", format!(
+                "cannot compare String and {:?} with '{}'; a String compares only with another String",
+                other, op.symbol()
+            )),
+            // x == none — проверка на отсутствие значения
+            (BinOp::Eq | BinOp::Ne, VarType::Option(_), VarType::None)
+            | (BinOp::Eq | BinOp::Ne, VarType::None, VarType::Option(_)) => Ok(VarType::Boolean),
+            _ => type_error("E0104", format!(
+                "cannot compare {:?} and {:?} with '{}'",
+                left, right, op.symbol()
+            )),
+        };
+    }
+    
+    if op.is_bitwise() {
+        return match (left, right) {
+            (VarType::Integer, VarType::Integer) => Ok(VarType::Integer),
+            _ => type_error("E0104", format!(
+                "bitwise '{}' requires Integer operands, got {:?} and {:?}",
+                op.symbol(), left, right
+            )),
+        };
+    }
+    
+    match (op, left, right) {
+        // У Float остатка нет: % в C определён только для целых
+        (BinOp::Rem, VarType::Integer, VarType::Integer) => Ok(VarType::Integer),
+        (BinOp::Rem, _, _) => type_error("E0104", format!("'%' requires Integer operands, got {:?} and {:?}", left, right)),
+        // Сложение со строкой — конкатенация
+        (BinOp::Add, VarType::String, other) | (BinOp::Add, other, VarType::String) if is_scalar(other) => {
+            Ok(VarType::String)
+        }
+        (_, VarType::Integer, VarType::Integer) => Ok(VarType::Integer),
+        // Integer рядом с Float повышается до Float
+        (_, VarType::Float, VarType::Float) | (_, VarType::Integer, VarType::Float) | (_, VarType::Float, VarType::Integer) => {
+            Ok(VarType::Float)
+        }
+        _ => type_error("E0104", format!(
+            "cannot apply '{}' to {:?} and {:?}",
+            op.symbol(), left, right
+        )),
+    }
+}
+
+// Тип элементов, которые ...xs подставляет по одному
+pub fn spread_element_type(value: &VarType) -> Result<VarType, TypeError> {
+    match value {
+        VarType::Array(element) => Ok((**element).clone()),
+        _ => type_error("E0104", format!("'...' expects an array, got {:?}", value)),
+    }
+}
+
+// [a, ...rest]: элементы и раскрытые массивы должны давать один и тот же тип
+pub fn array_result_type(elements: &[(bool, VarType)]) -> Result<VarType, TypeError> {
+    let mut element_type: Option<VarType> = None;
+    for (index, (spread, var_type)) in elements.iter().enumerate() {
+        let item = if *spread { spread_element_type(var_type)? } else { var_type.clone() };
+        if !is_storable(&item) {
+            return type_error("E0104", format!("cannot store {:?} in an array", item));
+        }
+        match &element_type {
+            Some(expected) if *expected != item => {
+                return type_error("E0101", format!(
+                    "element {} of the array is {:?}, but the first one is {:?}",
+                    index + 1, item, expected
+                ))
+            }
+            Some(_) => {}
+            None => element_type = Some(item),
+        }
+    }
+    Ok(VarType::Array(Box::new(element_type.expect("an array expression has at least one element"))))
+}
+
+// { key: value, ... } из выражений: тип словаря задаёт первая запись
+pub fn map_result_type(entries: &[(VarType, VarType)]) -> Result<VarType, TypeError> {
+    let (key_type, value_type) = entries.first().expect("a map expression has at least one entry");
+    if !matches!(key_type, VarType::String | VarType::Integer) {
+        return type_error("E0101", format!("map keys must be String or Integer, got {:?}", key_type));
+    }
+    if !is_storable(value_type) {
+        return type_error("E0104", format!("cannot store {:?} in a map", value_type));
+    }
+    for (index, (key, value)) in entries.iter().enumerate().skip(1) {
+        if key != key_type {
+            return type_error("E0101", format!("key {} of the map is {:?}, but the first one is {:?}", index + 1, key, key_type));
+        }
+        if value != value_type {
+            return type_error("E0101", format!("value {} of the map is {:?}, but the first one is {:?}", index + 1, value, value_type));
+        }
+    }
+    Ok(VarType::Map(Box::new(key_type.clone()), Box::new(value_type.clone())))
+}
+
+// [element for x in xs if condition]
+pub fn comprehension_result_type(element: &VarType, condition: Option<&VarType>) -> Result<VarType, TypeError> {
+    if let Some(condition) = condition.filter(|condition| **condition != VarType::Boolean) {
+        return type_error("E0101", format!("the condition of a list comprehension must be Boolean, got {:?}", condition));
+    }
+    if !is_storable(element) {
+        return type_error("E0104", format!("cannot store {:?} in an array", element));
+    }
+    Ok(VarType::Array(Box::new(element.clone())))
+}
+
+pub const SPREAD_CONTEXT_ERROR: &str = "'...' can only be used in call arguments and array expressions";
+
+// f(a, ...rest): массив заполняет все параметры после обычных аргументов
+pub fn argument_count_matches(params: usize, args: &[Expr]) -> bool {
+    match args.last() {
+        Some(Expr::Spread(_)) => args.len() - 1 <= params,
+        _ => args.len() == params,
+    }
+}
+
+// Запасное значение может и само быть необязательным, тогда результат тоже T?
+fn coalesce_result_type(option: &VarType, fallback: &VarType) -> Result<VarType, TypeError> {
+    match (option, fallback) {
+        (VarType::Option(inner), fallback) if **inner == *fallback => Ok((**inner).clone()),
+        (VarType::Option(_), VarType::None) => Ok(option.clone()),
+        (VarType::Option(_), fallback) if option == fallback => Ok(option.clone()),
+        (VarType::Option(inner), fallback) => type_error("E0101", format!(
+            "the fallback of '??' must be {:?}, got {:?}",
+            inner, fallback
+        )),
+        _ => type_error("E0104", format!("'??' expects an optional value on the left, got {:?}", option)),
+    }
+}
+
+// Элементы массива сравниваются по значению, поэтому искать можно только скаляры
+fn membership_result_type(item: &VarType, collection: &VarType) -> Result<VarType, TypeError> {
+    match collection {
+        VarType::Array(element) if **element == *item && is_scalar(item) => Ok(VarType::Boolean),
+        VarType::Array(element) if **element == *item => {
+            type_error("E0104", format!("'in' cannot compare {:?} elements; only scalar values can be searched for", element))
+        }
+        VarType::Array(element) => type_error("E0101", format!("cannot search for {:?} in an array of {:?}", item, element)),
+        VarType::Map(key, _) if **key == *item => Ok(VarType::Boolean),
+        VarType::Map(key, _) => type_error("E0101", format!("cannot look up a {:?} key in a map with {:?} keys", item, key)),
+        VarType::String if *item == VarType::String => Ok(VarType::Boolean),
+        VarType::String => type_error("E0101", format!("'in' on a String looks for a substring, got {:?}", item)),
+        _ => type_error("E0104", format!("'in' expects an array, a map or a String on the right, got {:?}", collection)),
+    }
+}
+
+pub const BUILTINS: &[&str] = &[
+    "echo", "print", "println", "eprint", "eprintln", "len", "push", "pop", "is_some", "is_none", "unwrap",
+    "ok", "err", "is_ok", "is_err", "unwrap_err", "read_bytes", "write_bytes",
+    "abs", "sqrt", "pow", "min", "max", "floor", "ceil", "round", "sleep", "exit", "assert", "args", "type_of",
+    "to_string", "to_int", "to_float", "parse_int", "parse_float", "exec",
+    "json_parse", "json_stringify", "join", "http_get", "tcp_send",
+];
+
+// Члены module math { ... } хранятся под полными именами вида math.pi
+pub fn member_name(module: &str, name: &str) -> String {
+    format!("{}.{}", module, name)
+}
+
+pub fn split_member(name: &str) -> Option<(&str, &str)> {
+    name.split_once('.')
+}
+
+// Тип в том виде, в каком он пишется в исходном коде
+pub fn type_name(var_type: &VarType) -> String {
+    match var_type {
+        VarType::String => "String".to_string(),
+        VarType::Integer => "Integer".to_string(),
+        VarType::Float => "Float".to_string(),
+        VarType::Boolean => "Boolean".to_string(),
+        VarType::BigInt => "BigInt".to_string(),
+        VarType::Bytes => "Bytes".to_string(),
+        VarType::Array(element) => format!("Array<{}>", type_name(element)),
+        VarType::Map(key, value) => format!("Map<{}, {}>", type_name(key), type_name(value)),
+        VarType::Struct(name) | VarType::Enum(name) | VarType::Trait(name) => name.clone(),
+        VarType::Option(inner) => format!("{}?", type_name(inner)),
+        VarType::None => "none".to_string(),
+        VarType::Result(ok_type, err_type) => format!("Result<{}, {}>", type_name(ok_type), type_name(err_type)),
+        VarType::Ok(value) => format!("ok({})", type_name(value)),
+        VarType::Err(value) => format!("err({})", type_name(value)),
+        VarType::Function(params, return_type) => {
+            let params: Vec<String> = params.iter().map(type_name).collect();
+            match return_type {
+                Some(return_type) => format!("func({}) -> {}", params.join(", "), type_name(return_type)),
+                None => format!("func({})", params.join(", ")),
+            }
+        }
+    }
+}
+
+pub fn is_builtin(name: &str) -> bool {
+    BUILTINS.contains(&name)
+}
+
+// Тип результата встроенной функции, None — функция ничего не возвращает
+pub fn builtin_result_type(name: &str, args: &[VarType]) -> Result<Option<VarType>, TypeError> {
+    match (name, args) {
+        // print не переводит строку, println — синоним echo; eprint и eprintln пишут в stderr
+        ("echo" | "print" | "println" | "eprint" | "eprintln", _) => {
+            match args.iter().find(|arg| !is_scalar(arg) && **arg != VarType::Bytes) {
+                Some(other) => type_error("E0104", format!("cannot print {:?} values with {}", other, name)),
+                None => Ok(None),
+            }
+        }
+        // Длина строки считается в символах, а не в байтах UTF-8
+        ("len", [VarType::Array(_) | VarType::Map(..) | VarType::String | VarType::Bytes]) => Ok(Some(VarType::Integer)),
+        ("len", [other]) => type_error("E0101", format!("'len' expects an Array, a Map, a String or Bytes, got {:?}", other)),
+        ("push", [VarType::Array(element), value]) if **element == *value => Ok(None),
+        ("push", [VarType::Array(element), value]) => type_error("E0101", format!(
+            "cannot push {:?} onto an array of {:?}",
+            value, element
+        )),
+        ("push", [other, _]) => type_error("E0101", format!("'push' expects an Array as its first argument, got {:?}", other)),
+        ("pop", [VarType::Array(element)]) => Ok(Some((**element).clone())),
+        ("pop", [other]) => type_error("E0101", format!("'pop' expects an Array, got {:?}", other)),
+        ("is_some" | "is_none", [VarType::Option(_)]) => Ok(Some(VarType::Boolean)),
+        ("unwrap", [VarType::Option(inner)]) => Ok(Some((**inner).clone())),
+        ("is_some" | "is_none", [other]) => type_error("E0101", format!(
+            "'{}' expects an optional value, got {:?}",
+            name, other
+        )),
+        ("ok", [value]) if is_storable(value) => Ok(Some(VarType::Ok(Box::new(value.clone())))),
+        ("err", [value]) if is_storable(value) => Ok(Some(VarType::Err(Box::new(value.clone())))),
+        ("ok" | "err", [other]) => type_error("E0104", format!("cannot wrap {:?} into a Result", other)),
+        ("is_ok" | "is_err", [VarType::Result(..)]) => Ok(Some(VarType::Boolean)),
+        ("unwrap", [VarType::Result(ok_type, _)]) => Ok(Some((**ok_type).clone())),
+        ("unwrap_err", [VarType::Result(_, err_type)]) => Ok(Some((**err_type).clone())),
+        ("unwrap", [other]) => type_error("E0101", format!(
+            "'unwrap' expects an optional value or a Result, got {:?}",
+            other
+        )),
+        ("is_ok" | "is_err" | "unwrap_err", [other]) => type_error("E0101", format!(
+            "'{}' expects a Result, got {:?}",
+            name, other
+        )),
+        // Файл читается и пишется целиком; ошибка ввода-вывода — ошибка выполнения
+        ("read_bytes", [VarType::String]) => Ok(Some(VarType::Bytes)),
+        ("write_bytes", [VarType::String, VarType::Bytes]) => Ok(None),
+        ("read_bytes", [other]) => type_error("E0101", format!("'read_bytes' expects a String path, got {:?}", other)),
+        ("write_bytes", [path, data]) => type_error("E0101", format!(
+            "'write_bytes' expects a String path and Bytes, got {:?} and {:?}",
+            path, data
+        )),
+        ("abs", [VarType::Integer | VarType::Float]) => Ok(Some(args[0].clone())),
+        ("sqrt", [VarType::Integer | VarType::Float]) => Ok(Some(VarType::Float)),
+        // Округление сразу даёт Integer; целое число возвращается как есть
+        ("floor" | "ceil" | "round", [VarType::Integer | VarType::Float]) => Ok(Some(VarType::Integer)),
+        ("abs" | "sqrt" | "floor" | "ceil" | "round", [other]) => type_error("E0101", format!(
+            "'{}' expects an Integer or a Float, got {:?}",
+            name, other
+        )),
+        // Два Integer дают Integer, иначе аргументы приводятся к Float, как в арифметике
+        ("pow" | "min" | "max", [VarType::Integer, VarType::Integer]) => Ok(Some(VarType::Integer)),
+        ("pow" | "min" | "max", [VarType::Integer | VarType::Float, VarType::Integer | VarType::Float]) => {
+            Ok(Some(VarType::Float))
+        }
+        ("pow" | "min" | "max", [left, right]) => type_error("E0101", format!(
+            "'{}' expects two numbers, got {:?} and {:?}",
+            name, left, right
+        )),
+        ("sleep", [VarType::Integer]) => Ok(None),
+        ("sleep", [other]) => type_error("E0101", format!("'sleep' expects an Integer number of milliseconds, got {:?}", other)),
+        ("type_of", [_]) => Ok(Some(VarType::String)),
+        ("to_string", [VarType::String | VarType::Integer | VarType::Float | VarType::Boolean | VarType::BigInt]) => {
+            Ok(Some(VarType::String))
+        }
+        ("to_string", [other]) => type_error("E0104", format!("cannot convert {:?} to String", other)),
+        // Строка, которая не является числом целиком, — ошибка выполнения
+        ("to_int", [VarType::String]) => Ok(Some(VarType::Integer)),
+        ("to_float", [VarType::String]) => Ok(Some(VarType::Float)),
+        // Вместо ошибки выполнения — err с сообщением
+        ("parse_int", [VarType::String]) => Ok(Some(VarType::Result(Box::new(VarType::Integer), Box::new(VarType::String)))),
+        ("parse_float", [VarType::String]) => Ok(Some(VarType::Result(Box::new(VarType::Float), Box::new(VarType::String)))),
+        ("to_int" | "to_float" | "parse_int" | "parse_float", [other]) => type_error("E0101", format!(
+            "'{}' expects a String, got {:?}; numbers are converted with 'as'",
+            name, other
+        )),
+        // Аргументы командной строки без имени самой программы
+        ("args", []) => Ok(Some(VarType::Array(Box::new(VarType::String)))),
+        // exec("ls", ["-l"]) запускает программу, ищет её в PATH и возвращает код завершения
+        ("exec", [VarType::String, VarType::Array(element)]) if **element == VarType::String => Ok(Some(VarType::Integer)),
+        ("exec", [command, arguments]) => type_error("E0101", format!(
+            "'exec' expects a String command and an Array<String> of arguments, got {:?} and {:?}",
+            command, arguments
+        )),
+        // Строку по частям собирают push в Array<String> и один join в конце: так
+        // длина результата считается один раз, а не при каждой конкатенации
+        ("join", [VarType::Array(element), VarType::String]) if **element == VarType::String => Ok(Some(VarType::String)),
+        ("join", [array, separator]) => type_error("E0101", format!(
+            "'join' expects an Array<String> and a String separator, got {:?} and {:?}",
+            array, separator
+        )),
+        // Сетевые ошибки не прерывают программу, а возвращаются как err
+        ("http_get", [VarType::String]) => Ok(Some(VarType::Result(Box::new(VarType::String), Box::new(VarType::String)))),
+        ("http_get", [other]) => type_error("E0101", format!("'http_get' expects a String URL, got {:?}", other)),
+        // tcp_send("localhost", 7, "ping") отправляет строку и ждёт ответ до закрытия соединения
+        ("tcp_send", [VarType::String, VarType::Integer, VarType::String]) => {
+            Ok(Some(VarType::Result(Box::new(VarType::String), Box::new(VarType::String))))
+        }
+        ("tcp_send", [host, port, data]) => type_error("E0101", format!(
+            "'tcp_send' expects a String host, an Integer port and a String, got {:?}, {:?} and {:?}",
+            host, port, data
+        )),
+        // Какие типы можно записать в JSON, проверяет check_json_type
+        ("json_stringify", [_]) => Ok(Some(VarType::String)),
+        ("json_parse", [VarType::String]) => type_error("E0103",
+            "cannot infer the type 'json_parse' produces; use it where a Result<T, String> is expected".to_string()
+        ),
+        ("json_parse", [other]) => type_error("E0101", format!("'json_parse' expects a String, got {:?}", other)),
+        ("exit", [VarType::Integer]) => Ok(None),
+        ("exit", [other]) => type_error("E0101", format!("'exit' expects an Integer status code, got {:?}", other)),
+        ("assert", [VarType::Boolean]) => Ok(None),
+        ("assert", [other]) => type_error("E0101", format!("'assert' expects a Boolean condition, got {:?}", other)),
+        _ => type_error("E0102", format!(
+            "'{}' takes {} argument(s), got {}",
+            name,
+            match name {
+                "args" => 0,
+                "tcp_send" => 3,
+                "push" | "write_bytes" | "pow" | "min" | "max" | "exec" | "join" => 2,
+                _ => 1,
+            },
+            args.len()
+        )),
+    }
+}
+
+// |x| x + 1 без типов параметров берёт их из типа функции, ожидаемого на этом месте
+pub fn with_expected_type<'e>(expr: &'e Expr, expected: &VarType) -> Cow<'e, Expr> {
+    match (expr, expected) {
+        (Expr::Lambda { params, body }, VarType::Function(types, _))
+            if params.len() == types.len() && params.iter().any(|p| p.var_type.is_none()) =>
+        {
+            let params = params
+                .iter()
+                .zip(types)
+                .map(|(param, var_type)| LambdaParam {
+                    name: param.name.clone(),
+                    var_type: Some(param.var_type.clone().unwrap_or_else(|| var_type.clone())),
+                })
+                .collect();
+            Cow::Owned(Expr::Lambda { params, body: body.clone() })
+        }
+        (Expr::Call { name, args, .. }, VarType::Result(ok_type, err_type))
+            if name == "json_parse" && args.len() == 1 && **err_type == VarType::String =>
+        {
+            Cow::Owned(Expr::JsonParse { text: Box::new(args[0].clone()), target: (**ok_type).clone() })
+        }
+        _ => Cow::Borrowed(expr),
+    }
+}
+
+// В JSON записываются числа, строки, Boolean, массивы, словари со строковыми ключами,
+// T? (none — это null) и структуры, поля которых тоже можно записать. BigInt — число
+pub fn check_json_type(structs: &HashMap<String, Vec<Field>>, var_type: &VarType) -> Result<(), TypeError> {
+    check_json_type_in(structs, var_type, &mut Vec::new())
+}
+
+fn check_json_type_in<'t>(structs: &'t HashMap<String, Vec<Field>>, var_type: &'t VarType,
+                          visiting: &mut Vec<&'t str>) -> Result<(), TypeError> {
+    match var_type {
+        VarType::String | VarType::Integer | VarType::Float | VarType::Boolean | VarType::BigInt => Ok(()),
+        VarType::Array(element) | VarType::Option(element) => check_json_type_in(structs, element, visiting),
+        VarType::Map(key_type, value_type) if **key_type == VarType::String => {
+            check_json_type_in(structs, value_type, visiting)
+        }
+        VarType::Map(key_type, _) => type_error("E0101", format!("JSON object keys must be Strings, got {:?}", key_type)),
+        // Структура может содержать массив из самой себя
+        VarType::Struct(name) if visiting.contains(&name.as_str()) => Ok(()),
+        VarType::Struct(name) => {
+            visiting.push(name);
+            for field in structs.get(name).into_iter().flatten() {
+                check_json_type_in(structs, &field.var_type, visiting)
+                    .map_err(|error| TypeError {
+                        message: format!("{} (in field '{}' of struct {})", error.message, field.name, name),
+                        ..error
+                    })?;
+            }
+            visiting.pop();
+            Ok(())
+        }
+        other => type_error("E0104", format!("{:?} values cannot be converted to or from JSON", other)),
+    }
+}
+
+pub fn lambda_param_types(params: &[LambdaParam]) -> Result<Vec<VarType>, TypeError> {
+    params
+        .iter()
+        .map(|param| {
+            param.var_type.clone().ok_or_else(|| TypeError {
+                code: "E0103",
+                message: format!("cannot infer the type of lambda parameter '{}'; write it as |Integer {}|", param.name, param.name),
+            })
+        })
+        .collect()
+}
+
+// Тип, который может храниться в переменной; none, ok(..) и err(..) сами по себе — нет
+pub fn is_storable(var_type: &VarType) -> bool {
+    !matches!(var_type, VarType::None | VarType::Ok(_) | VarType::Err(_) | VarType::Trait(_))
+}
+
+// Тип переменной из let — тип её значения: по none, ok(..) и err(..) он не ясен целиком
+pub fn inferred_type(name: &str, value: &VarType) -> Result<VarType, TypeError> {
+    if !is_storable(value) {
+        return type_error("E0103", format!("cannot infer the type of '{}' from {:?}; declare it with an explicit type", name, value));
+    }
+    Ok(value.clone())
+}
+
+// Тип значения, которое x? отдаёт дальше, и тип ошибки, которую он возвращает
+pub fn try_result_type(value: &VarType) -> Result<(VarType, VarType), TypeError> {
+    match value {
+        VarType::Result(ok_type, err_type) => Ok(((**ok_type).clone(), (**err_type).clone())),
+        other => type_error("E0104", format!("'?' expects a Result, got {:?}", other)),
+    }
+}
+
+pub fn index_result_type(target: &VarType, index: &VarType) -> Result<VarType, TypeError> {
+    match (target, index) {
+        (VarType::Array(element), VarType::Integer) => Ok((**element).clone()),
+        (VarType::Array(_), _) => type_error("E0101", format!("array index must be Integer, got {:?}", index)),
+        // Символ строки — тоже строка
+        (VarType::String, VarType::Integer) => Ok(VarType::String),
+        (VarType::String, _) => type_error("E0101", format!("string index must be Integer, got {:?}", index)),
+        // Байт читается как число от 0 до 255
+        (VarType::Bytes, VarType::Integer) => Ok(VarType::Integer),
+        (VarType::Bytes, _) => type_error("E0101", format!("bytes index must be Integer, got {:?}", index)),
+        (VarType::Map(key, value), index) if **key == *index => Ok((**value).clone()),
+        (VarType::Map(key, _), _) => type_error("E0101", format!("map key must be {:?}, got {:?}", key, index)),
+        _ => type_error("E0104", format!("cannot index into a value of type {:?}", target)),
+    }
+}
+
+// as меняет только представление числа; всё остальное делается явными операциями
+pub fn cast_result_type(from: &VarType, to: &VarType) -> Result<VarType, TypeError> {
+    match (from, to) {
+        _ if from == to => Ok(to.clone()),
+        (VarType::Integer, VarType::Float) | (VarType::Float, VarType::Integer) | (VarType::Boolean, VarType::Integer) => {
+            Ok(to.clone())
+        }
+        (VarType::Integer | VarType::Float, VarType::Boolean) => type_error("E0105", format!(
+            "cannot cast {:?} to Boolean; compare with zero instead, e.g. x != 0", from
+        )),
+        (_, VarType::String) if is_scalar(from) => type_error("E0105", format!(
+            "cannot cast {:?} to String; concatenate it with a string instead, e.g. \"\" + x", from
+        )),
+        (VarType::String, VarType::Integer | VarType::Float) => type_error("E0105", format!(
+            "cannot cast String to {:?}: 'as' does not parse text", to
+        )),
+        _ => type_error("E0105", format!("cannot cast {:?} to {:?}: 'as' converts Integer and Float into each other and Boolean into Integer", from, to)),
+    }
+}
+
+// Срез массива — массив, срез строки — строка
+pub fn slice_result_type(target: &VarType, bounds: &[VarType]) -> Result<VarType, TypeError> {
+    if let Some(bound) = bounds.iter().find(|bound| **bound != VarType::Integer) {
+        return type_error("E0101", format!("slice bounds must be Integer, got {:?}", bound));
+    }
+    match target {
+        VarType::Array(_) | VarType::String => Ok(target.clone()),
+        _ => type_error("E0104", format!("cannot slice a value of type {:?}", target)),
+    }
+}
+
+// Тип переменной цикла for x in value
+pub fn iteration_element_type(value: &VarType) -> Result<VarType, TypeError> {
+    match value {
+        VarType::Array(element) => Ok((**element).clone()),
+        VarType::String => Ok(VarType::String),
+        _ => type_error("E0104", format!("cannot iterate over a value of type {:?}", value)),
+    }
+}
+
+// Диапазон нигде не хранится как значение: for-in и генератор списка просто считают от start до end
+pub const RANGE_CONTEXT_ERROR: &str = "a range 'start..end' can only follow 'in' in a for loop or a list comprehension";
+
+pub fn range_result_type(start: &VarType, end: &VarType) -> Result<VarType, TypeError> {
+    match (start, end) {
+        (VarType::Integer, VarType::Integer) => Ok(VarType::Array(Box::new(VarType::Integer))),
+        _ => type_error("E0101", format!("range bounds must be Integer, got {:?} and {:?}", start, end)),
+    }
+}
+
+pub fn field_result_type(structs: &HashMap<String, Vec<Field>>, target: &VarType, field: &str) -> Result<VarType, TypeError> {
+    let name = match target {
+        VarType::Struct(name) => name,
+        _ => return type_error("E0104", format!("cannot access field '{}' on a value of type {:?}", field, target)),
+    };
+    structs
+        .get(name)
+        .and_then(|fields| fields.iter().find(|f| f.name == field))
+        .map(|f| f.var_type.clone())
+        .ok_or_else(|| TypeError {
+            code: "E0303",
+            message: format!("struct {} has no field '{}'", name, field),
+        })
+}
+
+// Переменные, которые образец связывает при разборе значения типа value: имя, поле и тип.
+// Без .. образец должен назвать каждое поле, пропущенные поля — явно через _
+pub fn pattern_bindings(structs: &HashMap<String, Vec<Field>>, pattern: &StructPattern,
+                        value: &VarType) -> Result<Vec<(String, String, VarType)>, TypeError> {
+    let (name, fields) = match value {
+        VarType::Struct(name) => (name, &structs[name]),
+        _ => return type_error("E0104", format!("cannot destructure a value of type {:?}: only structs can be destructured", value)),
+    };
+    
+    let (entries, rest): (Vec<(String, String)>, bool) = match pattern {
+        StructPattern::Named { name: expected, fields: entries, rest } => {
+            if expected != name {
+                return type_error("E0101", format!("pattern {} {{ .. }} cannot match a value of type {:?}", expected, value));
+            }
+            (entries.clone(), *rest)
+        }
+        StructPattern::Positional { bindings, rest } => {
+            if bindings.len() > fields.len() {
+                return type_error("E0303", format!(
+                    "struct {} has {} field(s), but the pattern binds {}",
+                    name, fields.len(), bindings.len()
+                ));
+            }
+            let entries = fields.iter().zip(bindings).map(|(f, binding)| (f.name.clone(), binding.clone())).collect();
+            (entries, *rest)
+        }
+    };
+    
+    let mut bindings = Vec::new();
+    for (index, (field, binding)) in entries.iter().enumerate() {
+        if entries[..index].iter().any(|(other, _)| other == field) {
+            return type_error("E0304", format!("field '{}' of {} appears more than once in the pattern", field, name));
+        }
+        if binding != "_" && entries[..index].iter().any(|(_, other)| other == binding) {
+            return type_error("E0304", format!("'{}' is bound more than once in the pattern", binding));
+        }
+        let var_type = field_result_type(structs, value, field)?;
+        if binding != "_" {
+            bindings.push((binding.clone(), field.clone(), var_type));
+        }
+    }
+    
+    let missing: Vec<&str> = fields
+        .iter()
+        .filter(|f| !entries.iter().any(|(field, _)| *field == f.name))
+        .map(|f| f.name.as_str())
+        .collect();
+    if !rest && !missing.is_empty() {
+        return type_error("E0303", format!(
+            "pattern for {} does not bind `{}`; bind every field, skip it with `_` or end the pattern with `..`",
+            name,
+            missing.join("`, `")
+        ));
+    }
+    Ok(bindings)
+}
+
+// Пары (тип, трейт) из всех impl в программе
+pub type Impls = HashSet<(String, String)>;
+
+// Имя структуры или перечисления, для которых можно реализовать трейт
+pub fn user_type_name(var_type: &VarType) -> Option<&str> {
+    match var_type {
+        VarType::Struct(name) | VarType::Enum(name) => Some(name),
+        _ => None,
+    }
+}
+
+// Можно ли сохранить значение типа value там, где ожидается target.
+// Трейт принимает любой тип, который его реализует, T? — значение T или none,
+// а Result<T, E> — ok(T) или err(E); обычный тип none не принимает никогда
+pub fn assignable(impls: &Impls, target: &VarType, value: &VarType) -> bool {
+    match (target, value) {
+        _ if target == value => true,
+        (VarType::Trait(trait_name), value) => match user_type_name(value) {
+            Some(name) => impls.contains(&(name.to_string(), trait_name.clone())),
+            None => false,
+        },
+        (VarType::Option(_), VarType::None) => true,
+        (VarType::Option(inner), value) => **inner == *value,
+        (VarType::Result(ok_type, _), VarType::Ok(value)) => ok_type == value,
+        (VarType::Result(_, err_type), VarType::Err(value)) => err_type == value,
+        _ => false,
+    }
+}
+
+pub fn method_signature<'a>(
+    traits: &'a HashMap<String, Vec<MethodSignature>>,
+    impls: &Impls,
+    target: &VarType,
+    method: &str,
+) -> Result<&'a MethodSignature, TypeError> {
+    if let VarType::Trait(trait_name) = target {
+        return traits[trait_name]
+            .iter()
+            .find(|m| m.name == method)
+            .ok_or_else(|| TypeError {
+                code: "E0303",
+                message: format!("trait {} has no method '{}'", trait_name, method),
+            });
+    }
+    
+    let name = user_type_name(target)
+        .ok_or_else(|| TypeError {
+            code: "E0104",
+            message: format!("cannot call method '{}' on a value of type {:?}", method, target),
+        })?;
+    impls
+        .iter()
+        .filter(|(type_name, _)| type_name == name)
+        .find_map(|(_, trait_name)| traits[trait_name].iter().find(|m| m.name == method))
+        .ok_or_else(|| TypeError {
+            code: "E0303",
+            message: format!("type {} has no method '{}'", name, method),
+        })
+}
+
+// a + b для структур, перечислений и трейтов — вызов a.operator+(b).
+// Без своего operator!= выражение a != b считается как !a.operator==(b); второе значение — нужно ли отрицание
+pub fn operator_method(
+    traits: &HashMap<String, Vec<MethodSignature>>,
+    impls: &Impls,
+    op: &BinOp,
+    left: &VarType,
+) -> Option<(String, bool)> {
+    if !op.is_overloadable() || !matches!(left, VarType::Struct(_) | VarType::Enum(_) | VarType::Trait(_)) {
+        return None;
+    }
+    let defines = |method: &str| method_signature(traits, impls, left, method).is_ok();
+    if defines(&op.method_name()) {
+        return Some((op.method_name(), false));
+    }
+    if *op == BinOp::Ne && defines(&BinOp::Eq.method_name()) {
+        return Some((BinOp::Eq.method_name(), true));
+    }
+    None
+}
+
+pub fn operator_call(left: &Expr, method: String, right: &Expr, span: Span) -> Expr {
+    Expr::MethodCall { target: Box::new(left.clone()), method, args: vec![right.clone()], span }
+}
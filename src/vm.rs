@@ -0,0 +1,683 @@
+use crate::bytecode::{method_key, Constant, Module, Op};
+use crate::parser::{BinOp, UnaryOp};
+
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+// Стековая машина для байткода .qbc. Значения помечены типом во время выполнения,
+// проверки типов уже сделал компилятор. Массивы, как и в C, передаются по ссылке,
+// структуры и варианты перечислений копируются
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Unit,
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    String(Rc<str>),
+    Array(Rc<RefCell<Vec<Value>>>),
+    // Поля в порядке объявления структуры
+    Struct { name: Rc<str>, fields: Vec<(Rc<str>, Value)> },
+    Enum { name: Rc<str>, variant: Rc<str>, payload: Vec<Value> },
+}
+
+type Builtin = fn(&mut Vm, Vec<Value>) -> Result<Value, String>;
+
+pub const BUILTINS: &[(&str, Builtin)] = &[
+    ("echo", |vm, args| vm.print(false, true, &args)),
+    ("print", |vm, args| vm.print(false, false, &args)),
+    ("println", |vm, args| vm.print(false, true, &args)),
+    ("eprint", |vm, args| vm.print(true, false, &args)),
+    ("eprintln", |vm, args| vm.print(true, true, &args)),
+    ("len", |_, args| match &args[0] {
+        Value::String(text) => Ok(Value::Integer(text.chars().count() as i64)),
+        value => Ok(Value::Integer(array(value).borrow().len() as i64)),
+    }),
+    ("push", |_, args| {
+        array(&args[0]).borrow_mut().push(args[1].clone());
+        Ok(Value::Unit)
+    }),
+    ("pop", |_, args| array(&args[0]).borrow_mut().pop().ok_or_else(|| "pop from an empty array".to_string())),
+    ("to_string", |_, args| Ok(Value::String(to_text(&args[0]).into()))),
+    ("to_int", |_, args| {
+        let text = string(&args[0]);
+        text.parse().map(Value::Integer).map_err(|_| format!("cannot convert '{}' to Integer", text))
+    }),
+    ("to_float", |_, args| {
+        let text = string(&args[0]);
+        text.parse().map(Value::Float).map_err(|_| format!("cannot convert '{}' to Float", text))
+    }),
+    ("abs", |_, args| match args[0] {
+        Value::Integer(value) => value.checked_abs().map(Value::Integer).ok_or_else(|| "integer overflow in abs".to_string()),
+        _ => Ok(Value::Float(float(&args[0]).abs())),
+    }),
+    ("sqrt", |_, args| {
+        let value = float(&args[0]);
+        if value < 0.0 {
+            return Err(format!("cannot take the square root of a negative number {}", format_float(value)));
+        }
+        Ok(Value::Float(value.sqrt()))
+    }),
+    ("floor", |_, args| rounded(&args[0], f64::floor)),
+    ("ceil", |_, args| rounded(&args[0], f64::ceil)),
+    ("round", |_, args| rounded(&args[0], f64::round)),
+    ("pow", |_, args| match (&args[0], &args[1]) {
+        (Value::Integer(_), Value::Integer(exponent)) if *exponent < 0 => {
+            Err(format!("negative exponent {} in integer pow", exponent))
+        }
+        (Value::Integer(base), Value::Integer(exponent)) => u32::try_from(*exponent)
+            .ok()
+            .and_then(|exponent| base.checked_pow(exponent))
+            .map(Value::Integer)
+            .ok_or_else(|| "integer overflow in pow".to_string()),
+        (base, exponent) => Ok(Value::Float(float(base).powf(float(exponent)))),
+    }),
+    ("min", |_, args| match (&args[0], &args[1]) {
+        (Value::Integer(a), Value::Integer(b)) => Ok(Value::Integer(*a.min(b))),
+        (a, b) => Ok(Value::Float(if float(a) < float(b) { float(a) } else { float(b) })),
+    }),
+    ("max", |_, args| match (&args[0], &args[1]) {
+        (Value::Integer(a), Value::Integer(b)) => Ok(Value::Integer(*a.max(b))),
+        (a, b) => Ok(Value::Float(if float(a) > float(b) { float(a) } else { float(b) })),
+    }),
+    ("join", |_, args| {
+        let items: Vec<String> = array(&args[0]).borrow().iter().map(to_text).collect();
+        Ok(Value::String(items.join(&string(&args[1])).into()))
+    }),
+    ("args", |vm, _| {
+        let items = vm.args.iter().map(|arg| Value::String(arg.as_str().into())).collect();
+        Ok(Value::Array(Rc::new(RefCell::new(items))))
+    }),
+    ("sleep", |_, args| match args[0] {
+        Value::Integer(ms) if ms < 0 => Err(format!("cannot sleep for a negative duration of {} ms", ms)),
+        Value::Integer(ms) => {
+            std::thread::sleep(std::time::Duration::from_millis(ms as u64));
+            Ok(Value::Unit)
+        }
+        _ => unreachable!(),
+    }),
+    // Отложенных инструкций в байткоде нет, поэтому выход — просто конец выполнения
+    ("exit", |vm, args| {
+        vm.exit = Some(integer(&args[0]) as i32);
+        Ok(Value::Unit)
+    }),
+    ("assert", |_, args| match args[0] {
+        Value::Boolean(true) => Ok(Value::Unit),
+        _ => Err("assertion failed".to_string()),
+    }),
+];
+
+pub fn is_builtin(name: &str) -> bool {
+    BUILTINS.iter().any(|(known, _)| *known == name)
+}
+
+const MAX_CALL_DEPTH: usize = 5000;
+
+struct Frame {
+    function: usize,
+    ip: usize,
+    // Начало локальных переменных функции на стеке
+    base: usize,
+    // a != b через operator==: результат отрицается при возврате
+    negate: bool,
+}
+
+// На сколько кадров и значений откатиться при ошибке и куда передать управление
+struct Handler {
+    frames: usize,
+    stack: usize,
+    address: usize,
+}
+
+pub struct Vm<'m> {
+    module: &'m Module,
+    builtins: Vec<Builtin>,
+    constants: Vec<Value>,
+    globals: Vec<Value>,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    handlers: Vec<Handler>,
+    args: Vec<String>,
+    exit: Option<i32>,
+}
+
+impl<'m> Vm<'m> {
+    // Файл мог прийти откуда угодно, поэтому все номера в нём проверяются до запуска
+    pub fn new(module: &'m Module, args: Vec<String>) -> Result<Vm<'m>, String> {
+        let builtins = module
+            .builtins
+            .iter()
+            .map(|name| {
+                BUILTINS
+                    .iter()
+                    .find(|(known, _)| known == name)
+                    .map(|(_, builtin)| *builtin)
+                    .ok_or_else(|| format!("the bytecode uses an unknown builtin '{}'", name))
+            })
+            .collect::<Result<_, _>>()?;
+        validate(module)?;
+        Ok(Vm {
+            module,
+            builtins,
+            constants: module.constants.iter().map(value).collect(),
+            globals: vec![Value::Unit; module.globals as usize],
+            stack: Vec::new(),
+            frames: Vec::new(),
+            handlers: Vec::new(),
+            args,
+            exit: None,
+        })
+    }
+
+    // Код завершения программы; необработанная ошибка выполнения возвращается текстом
+    pub fn run(&mut self) -> Result<i32, String> {
+        self.call(self.module.entry as usize, "main")?;
+        loop {
+            match self.step() {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(message) => self.recover(message)?,
+            }
+            if let Some(code) = self.exit {
+                return Ok(code);
+            }
+        }
+        let _ = std::io::stdout().flush();
+        Ok(0)
+    }
+
+    // Ошибка внутри try передаёт управление обработчику, как qk_fail в рантайме C
+    fn recover(&mut self, message: String) -> Result<(), String> {
+        let handler = self.handlers.pop().ok_or(message.clone())?;
+        self.frames.truncate(handler.frames);
+        self.stack.truncate(handler.stack);
+        self.stack.push(Value::String(message.into()));
+        self.frames.last_mut().unwrap().ip = handler.address;
+        Ok(())
+    }
+
+    fn call(&mut self, function: usize, name: &str) -> Result<(), String> {
+        if self.frames.len() >= MAX_CALL_DEPTH {
+            return Err(format!(
+                "call depth limit of {} exceeded in function '{}' (infinite recursion?)",
+                MAX_CALL_DEPTH, name
+            ));
+        }
+        let target = &self.module.functions[function];
+        let base = self.stack.len() - target.arity as usize;
+        self.stack.resize(base + target.locals as usize, Value::Unit);
+        self.frames.push(Frame { function, ip: 0, base, negate: false });
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap()
+    }
+
+    fn pop_many(&mut self, count: usize) -> Vec<Value> {
+        self.stack.split_off(self.stack.len() - count)
+    }
+
+    // false — программа закончилась
+    fn step(&mut self) -> Result<bool, String> {
+        let frame = self.frames.last_mut().unwrap();
+        let function = &self.module.functions[frame.function];
+        let op = &function.code[frame.ip];
+        frame.ip += 1;
+        let base = frame.base;
+
+        match op {
+            Op::Constant(index) => {
+                let value = fresh(&self.constants[*index as usize]);
+                self.stack.push(value);
+            }
+            Op::Unit => self.stack.push(Value::Unit),
+            Op::Pop => {
+                self.pop();
+            }
+            Op::Load(index) => self.stack.push(self.stack[base + *index as usize].clone()),
+            Op::Store(index) => {
+                let value = self.pop();
+                self.stack[base + *index as usize] = value;
+            }
+            Op::LoadGlobal(index) => self.stack.push(self.globals[*index as usize].clone()),
+            Op::StoreGlobal(index) => self.globals[*index as usize] = self.pop(),
+            Op::Binary(op) => {
+                let right = self.pop();
+                let left = self.pop();
+                match self.operator(&left, op) {
+                    // a + b для структур и перечислений — вызов a.operator+(b)
+                    Some((index, negate)) => {
+                        self.stack.push(left);
+                        self.stack.push(right);
+                        self.call(index, &op.method_name())?;
+                        self.frames.last_mut().unwrap().negate = negate;
+                    }
+                    None => self.stack.push(binary(op, left, right)?),
+                }
+            }
+            Op::Unary(op) => {
+                let operand = self.pop();
+                self.stack.push(match (op, operand) {
+                    (UnaryOp::Neg, Value::Integer(value)) => Value::Integer(value.wrapping_neg()),
+                    (UnaryOp::Neg, Value::Float(value)) => Value::Float(-value),
+                    (UnaryOp::BitNot, Value::Integer(value)) => Value::Integer(!value),
+                    (op, value) => return Err(format!("cannot apply '{}' to {:?}", op.symbol(), value)),
+                });
+            }
+            Op::Jump(address) => self.frames.last_mut().unwrap().ip = *address as usize,
+            Op::JumpIfFalse(address) => {
+                if self.pop() == Value::Boolean(false) {
+                    self.frames.last_mut().unwrap().ip = *address as usize;
+                }
+            }
+            Op::Call(index) => {
+                let index = *index as usize;
+                self.call(index, &self.module.functions[index].name)?;
+            }
+            Op::Builtin(index, count) => {
+                let args = self.pop_many(*count as usize);
+                let result = (self.builtins[*index as usize])(self, args)?;
+                self.stack.push(result);
+            }
+            Op::CallMethod(name, count) => {
+                let method = string(&self.constants[*name as usize]);
+                let target = &self.stack[self.stack.len() - 1 - *count as usize];
+                let type_name = match target {
+                    Value::Struct { name, .. } | Value::Enum { name, .. } => name.clone(),
+                    other => return Err(format!("cannot call method '{}' on {:?}", method, other)),
+                };
+                let index = self.method(&type_name, &method).ok_or_else(|| format!("type {} has no method '{}'", type_name, method))?;
+                self.call(index, &method)?;
+            }
+            Op::Return => {
+                let result = self.pop();
+                let frame = self.frames.pop().unwrap();
+                self.stack.truncate(frame.base);
+                // Обработчики try, открытые в этой функции, закрываются вместе с ней
+                let depth = self.frames.len();
+                self.handlers.retain(|handler| handler.frames <= depth);
+                if self.frames.is_empty() {
+                    return Ok(false);
+                }
+                self.stack.push(match (frame.negate, result) {
+                    (true, Value::Boolean(value)) => Value::Boolean(!value),
+                    (_, result) => result,
+                });
+            }
+            Op::Array(count) => {
+                let items = self.pop_many(*count as usize);
+                self.stack.push(Value::Array(Rc::new(RefCell::new(items))));
+            }
+            Op::Index => {
+                let index = integer(&self.pop());
+                let target = self.pop();
+                self.stack.push(index_value(&target, index)?);
+            }
+            Op::StoreIndex => {
+                let value = self.pop();
+                let index = integer(&self.pop());
+                let target = self.pop();
+                let mut items = array(&target).borrow_mut();
+                let len = items.len();
+                let slot = usize::try_from(index)
+                    .ok()
+                    .and_then(|index| items.get_mut(index))
+                    .ok_or_else(|| format!("index {} is out of bounds for an array of length {}", index, len))?;
+                *slot = value;
+            }
+            Op::Field(name) => {
+                let name = string(&self.constants[*name as usize]);
+                let target = self.pop();
+                let Value::Struct { fields, .. } = target else { unreachable!() };
+                let (_, value) = fields.into_iter().find(|(field, _)| **field == *name).unwrap();
+                self.stack.push(value);
+            }
+            Op::SetField(name) => {
+                let name = string(&self.constants[*name as usize]);
+                let value = self.pop();
+                let mut target = self.pop();
+                if let Value::Struct { fields, .. } = &mut target {
+                    if let Some((_, slot)) = fields.iter_mut().find(|(field, _)| **field == *name) {
+                        *slot = value;
+                    }
+                }
+                self.stack.push(target);
+            }
+            Op::Slice => {
+                let end = self.pop();
+                let start = integer(&self.pop());
+                let target = self.pop();
+                self.stack.push(slice(&target, start, end)?);
+            }
+            Op::ToInteger => {
+                let value = self.pop();
+                self.stack.push(match value {
+                    Value::Float(value) => Value::Integer(float_to_integer(value)?),
+                    Value::Boolean(value) => Value::Integer(value as i64),
+                    value => value,
+                });
+            }
+            Op::ToFloat => {
+                let value = self.pop();
+                self.stack.push(Value::Float(float(&value)));
+            }
+            Op::IsVariant(name) => {
+                let name = string(&self.constants[*name as usize]);
+                let value = self.pop();
+                self.stack.push(Value::Boolean(matches!(value, Value::Enum { variant, .. } if *variant == *name)));
+            }
+            Op::Payload(index) => {
+                let value = self.pop();
+                let item = match value {
+                    Value::Enum { mut payload, .. } => payload.swap_remove(*index as usize),
+                    Value::Struct { mut fields, .. } => fields.swap_remove(*index as usize).1,
+                    _ => unreachable!(),
+                };
+                self.stack.push(item);
+            }
+            Op::Items => {
+                let value = self.pop();
+                self.stack.push(match value {
+                    Value::String(text) => {
+                        let chars = text.chars().map(|ch| Value::String(ch.to_string().into())).collect();
+                        Value::Array(Rc::new(RefCell::new(chars)))
+                    }
+                    value => value,
+                });
+            }
+            Op::Len => {
+                let value = self.pop();
+                let len = array(&value).borrow().len();
+                self.stack.push(Value::Integer(len as i64));
+            }
+            Op::TryBegin(address) => {
+                let handler = Handler { frames: self.frames.len(), stack: self.stack.len(), address: *address as usize };
+                self.handlers.push(handler);
+            }
+            Op::TryEnd => {
+                self.handlers.pop();
+            }
+        }
+        Ok(true)
+    }
+
+    // Без своего operator!= выражение a != b считается как !a.operator==(b)
+    fn operator(&self, left: &Value, op: &BinOp) -> Option<(usize, bool)> {
+        let (Value::Struct { name, .. } | Value::Enum { name, .. }) = left else { return None };
+        if !op.is_overloadable() {
+            return None;
+        }
+        if let Some(index) = self.method(name, &op.method_name()) {
+            return Some((index, false));
+        }
+        if *op == BinOp::Ne {
+            return self.method(name, &BinOp::Eq.method_name()).map(|index| (index, true));
+        }
+        None
+    }
+
+    fn method(&self, type_name: &str, method: &str) -> Option<usize> {
+        let key = method_key(type_name, method);
+        self.module.functions.iter().position(|function| function.name == key)
+    }
+
+    // Аргументы разделяются пробелом, как в printf со строкой формата из них
+    fn print(&mut self, stderr: bool, newline: bool, args: &[Value]) -> Result<Value, String> {
+        let mut text = args.iter().map(to_text).collect::<Vec<_>>().join(" ");
+        if newline {
+            text.push('\n');
+        }
+        let result = if stderr {
+            std::io::stderr().write_all(text.as_bytes())
+        } else {
+            std::io::stdout().write_all(text.as_bytes())
+        };
+        result.map_err(|e| format!("cannot write output: {}", e))?;
+        Ok(Value::Unit)
+    }
+}
+
+pub fn run(module: &Module, args: Vec<String>) -> Result<i32, String> {
+    Vm::new(module, args)?.run()
+}
+
+fn validate(module: &Module) -> Result<(), String> {
+    let invalid = |what: &str| Err(format!("invalid bytecode: {}", what));
+    if module.entry as usize >= module.functions.len() {
+        return invalid("the entry function does not exist");
+    }
+    for function in &module.functions {
+        if function.arity > function.locals {
+            return invalid(&format!("function '{}' has more parameters than locals", function.name));
+        }
+        // Код заканчивается возвратом, поэтому выполнение не выходит за его конец
+        if function.code.last() != Some(&Op::Return) {
+            return invalid(&format!("function '{}' does not end with a return", function.name));
+        }
+        for op in &function.code {
+            let valid = match op {
+                Op::Constant(index) | Op::Field(index) | Op::SetField(index) | Op::IsVariant(index) => {
+                    (*index as usize) < module.constants.len()
+                }
+                Op::CallMethod(index, _) => (*index as usize) < module.constants.len(),
+                Op::Load(index) | Op::Store(index) => *index < function.locals,
+                Op::LoadGlobal(index) | Op::StoreGlobal(index) => *index < module.globals,
+                Op::Jump(address) | Op::JumpIfFalse(address) | Op::TryBegin(address) => {
+                    (*address as usize) < function.code.len()
+                }
+                Op::Call(index) => (*index as usize) < module.functions.len(),
+                Op::Builtin(index, _) => (*index as usize) < module.builtins.len(),
+                _ => true,
+            };
+            if !valid {
+                return invalid(&format!("instruction {:?} in '{}' refers to nothing", op, function.name));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn value(constant: &Constant) -> Value {
+    match constant {
+        Constant::Integer(value) => Value::Integer(*value),
+        Constant::Float(value) => Value::Float(*value),
+        Constant::Boolean(value) => Value::Boolean(*value),
+        Constant::String(value) => Value::String(value.as_str().into()),
+        Constant::Array(items) => Value::Array(Rc::new(RefCell::new(items.iter().map(value).collect()))),
+        Constant::Struct { name, fields } => Value::Struct {
+            name: name.as_str().into(),
+            fields: fields.iter().map(|(field, item)| (field.as_str().into(), value(item))).collect(),
+        },
+        Constant::Enum { name, variant, payload } => Value::Enum {
+            name: name.as_str().into(),
+            variant: variant.as_str().into(),
+            payload: payload.iter().map(value).collect(),
+        },
+    }
+}
+
+// Литерал массива при каждом вычислении даёт новый массив, иначе push менял бы константу
+fn fresh(constant: &Value) -> Value {
+    match constant {
+        Value::Array(items) => Value::Array(Rc::new(RefCell::new(items.borrow().iter().map(fresh).collect()))),
+        Value::Struct { name, fields } => Value::Struct {
+            name: name.clone(),
+            fields: fields.iter().map(|(field, item)| (field.clone(), fresh(item))).collect(),
+        },
+        Value::Enum { name, variant, payload } => Value::Enum {
+            name: name.clone(),
+            variant: variant.clone(),
+            payload: payload.iter().map(fresh).collect(),
+        },
+        value => value.clone(),
+    }
+}
+
+fn array(value: &Value) -> &Rc<RefCell<Vec<Value>>> {
+    match value {
+        Value::Array(items) => items,
+        _ => unreachable!(),
+    }
+}
+
+fn string(value: &Value) -> Rc<str> {
+    match value {
+        Value::String(text) => text.clone(),
+        _ => unreachable!(),
+    }
+}
+
+fn integer(value: &Value) -> i64 {
+    match value {
+        Value::Integer(value) => *value,
+        _ => unreachable!(),
+    }
+}
+
+fn float(value: &Value) -> f64 {
+    match value {
+        Value::Integer(value) => *value as f64,
+        Value::Float(value) => *value,
+        _ => unreachable!(),
+    }
+}
+
+fn rounded(value: &Value, round: fn(f64) -> f64) -> Result<Value, String> {
+    match value {
+        Value::Integer(_) => Ok(value.clone()),
+        _ => Ok(Value::Integer(float_to_integer(round(float(value)))?)),
+    }
+}
+
+// Дробная часть отбрасывается, как qk_float_to_int
+fn float_to_integer(value: f64) -> Result<i64, String> {
+    if !(-9223372036854775808.0..9223372036854775808.0).contains(&value) {
+        return Err(format!("cannot convert {} to Integer: the value is out of range", format_float(value)));
+    }
+    Ok(value as i64)
+}
+
+// Как %f в printf: шесть знаков после точки
+fn format_float(value: f64) -> String {
+    match value {
+        value if value.is_nan() => "nan".to_string(),
+        value if value.is_infinite() => if value > 0.0 { "inf" } else { "-inf" }.to_string(),
+        value => format!("{:.6}", value),
+    }
+}
+
+fn to_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.to_string(),
+        Value::Integer(value) => value.to_string(),
+        Value::Float(value) => format_float(*value),
+        Value::Boolean(value) => value.to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+fn binary(op: &BinOp, left: Value, right: Value) -> Result<Value, String> {
+    use Value::{Boolean, Float, Integer};
+    Ok(match (op, left, right) {
+        // Строка с чем угодно даёт строку: второй операнд приводится к тексту
+        (BinOp::Add, Value::String(a), b) => Value::String(format!("{}{}", a, to_text(&b)).into()),
+        (BinOp::Add, a, Value::String(b)) => Value::String(format!("{}{}", to_text(&a), b).into()),
+        (BinOp::Add, Integer(a), Integer(b)) => Integer(a.wrapping_add(b)),
+        (BinOp::Sub, Integer(a), Integer(b)) => Integer(a.wrapping_sub(b)),
+        (BinOp::Mul, Integer(a), Integer(b)) => Integer(a.wrapping_mul(b)),
+        (BinOp::Div, Integer(_), Integer(0)) => return Err("division by zero".to_string()),
+        (BinOp::Div, Integer(a), Integer(b)) => {
+            Integer(a.checked_div(b).ok_or_else(|| "integer overflow in division".to_string())?)
+        }
+        (BinOp::Rem, Integer(_), Integer(0)) => return Err("division by zero".to_string()),
+        (BinOp::Rem, Integer(a), Integer(b)) => {
+            Integer(a.checked_rem(b).ok_or_else(|| "integer overflow in remainder".to_string())?)
+        }
+        (BinOp::BitAnd, Integer(a), Integer(b)) => Integer(a & b),
+        (BinOp::BitOr, Integer(a), Integer(b)) => Integer(a | b),
+        (BinOp::BitXor, Integer(a), Integer(b)) => Integer(a ^ b),
+        (BinOp::BitAnd, Boolean(a), Boolean(b)) => Boolean(a & b),
+        (BinOp::BitOr, Boolean(a), Boolean(b)) => Boolean(a | b),
+        (BinOp::BitXor, Boolean(a), Boolean(b)) => Boolean(a ^ b),
+        (BinOp::Shl, Integer(a), Integer(b)) => Integer(((a as u64).wrapping_shl(b as u32)) as i64),
+        (BinOp::Shr, Integer(a), Integer(b)) => Integer(a.wrapping_shr(b as u32)),
+        (BinOp::In, item, Value::String(text)) => Boolean(text.contains(&*string(&item))),
+        (BinOp::In, item, Value::Array(items)) => Boolean(items.borrow().contains(&item)),
+        (op, a @ (Integer(_) | Float(_)), b @ (Integer(_) | Float(_))) if !op.is_bitwise() => {
+            // Integer с Float повышается до Float, как в сгенерированном C
+            if let (Integer(a), Integer(b)) = (&a, &b) {
+                return Ok(Boolean(compare(op, a.cmp(b))));
+            }
+            let (a, b) = (float(&a), float(&b));
+            match op {
+                BinOp::Add => Float(a + b),
+                BinOp::Sub => Float(a - b),
+                BinOp::Mul => Float(a * b),
+                BinOp::Div => Float(a / b),
+                // NaN не равен ничему, в том числе себе
+                BinOp::Ne => Boolean(a != b),
+                _ => match a.partial_cmp(&b) {
+                    Some(ordering) => Boolean(compare(op, ordering)),
+                    None => Boolean(false),
+                },
+            }
+        }
+        (BinOp::Lt | BinOp::Gt | BinOp::Le | BinOp::Ge, Value::String(a), Value::String(b)) => {
+            Boolean(compare(op, a.as_bytes().cmp(b.as_bytes())))
+        }
+        (BinOp::Eq, a, b) => Boolean(a == b),
+        (BinOp::Ne, a, b) => Boolean(a != b),
+        (op, a, b) => return Err(format!("cannot apply '{}' to {:?} and {:?}", op.symbol(), a, b)),
+    })
+}
+
+fn compare(op: &BinOp, ordering: std::cmp::Ordering) -> bool {
+    match op {
+        BinOp::Eq => ordering.is_eq(),
+        BinOp::Ne => ordering.is_ne(),
+        BinOp::Lt => ordering.is_lt(),
+        BinOp::Gt => ordering.is_gt(),
+        BinOp::Le => ordering.is_le(),
+        BinOp::Ge => ordering.is_ge(),
+        _ => unreachable!(),
+    }
+}
+
+// Индексы строк, как и в C, считаются в символах
+fn index_value(target: &Value, index: i64) -> Result<Value, String> {
+    match target {
+        Value::String(text) => {
+            let len = text.chars().count();
+            usize::try_from(index)
+                .ok()
+                .and_then(|index| text.chars().nth(index))
+                .map(|ch| Value::String(ch.to_string().into()))
+                .ok_or_else(|| format!("index {} is out of bounds for a string of length {}", index, len))
+        }
+        _ => {
+            let items = array(target).borrow();
+            usize::try_from(index)
+                .ok()
+                .and_then(|index| items.get(index))
+                .cloned()
+                .ok_or_else(|| format!("index {} is out of bounds for an array of length {}", index, items.len()))
+        }
+    }
+}
+
+fn slice(target: &Value, start: i64, end: Value) -> Result<Value, String> {
+    let (len, kind) = match target {
+        Value::String(text) => (text.chars().count() as i64, "a string"),
+        _ => (array(target).borrow().len() as i64, "an array"),
+    };
+    let end = if end == Value::Unit { len } else { integer(&end) };
+    if start < 0 || start > end || end > len {
+        return Err(format!("slice {}..{} is out of bounds for {} of length {}", start, end, kind, len));
+    }
+    let (start, end) = (start as usize, end as usize);
+    Ok(match target {
+        Value::String(text) => Value::String(text.chars().skip(start).take(end - start).collect::<String>().into()),
+        _ => Value::Array(Rc::new(RefCell::new(array(target).borrow()[start..end].to_vec()))),
+    })
+}
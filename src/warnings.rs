@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+// Предупреждения, которые можно отключить (-A) или включить (-W) по имени
+pub struct WarningSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub default: Level,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Allow,
+    Warn,
+}
+
+pub const WARNINGS: &[WarningSpec] = &[
+    WarningSpec {
+        name: "deprecated",
+        description: "use of a function or type marked #[deprecated]",
+        default: Level::Warn,
+    },
+    WarningSpec {
+        name: "unreachable-arm",
+        description: "match arms after `_` or a struct pattern",
+        default: Level::Warn,
+    },
+    WarningSpec {
+        name: "non-exhaustive-match",
+        description: "match without `_` that misses an enum variant or a Boolean value",
+        default: Level::Warn,
+    },
+    // Стилевые проверки из lints.rs; их запускает только quark check
+    WarningSpec {
+        name: "non-snake-case",
+        description: "variable, parameter or function name that is not snake_case",
+        default: Level::Warn,
+    },
+    WarningSpec {
+        name: "shadowed-variable",
+        description: "name that hides a variable from an outer scope",
+        default: Level::Warn,
+    },
+    WarningSpec {
+        name: "constant-condition",
+        description: "loop condition or match value that never changes",
+        default: Level::Warn,
+    },
+    WarningSpec {
+        name: "empty-block",
+        description: "function, loop or block with an empty body",
+        default: Level::Warn,
+    },
+];
+
+pub fn lookup(name: &str) -> Option<&'static WarningSpec> {
+    WARNINGS.iter().find(|spec| spec.name == name)
+}
+
+// Уровни из командной строки поверх уровней по умолчанию
+#[derive(Debug, Default)]
+pub struct WarningLevels {
+    levels: HashMap<&'static str, Level>,
+    // --deny-warnings: любое выведенное предупреждение делает сборку неудачной
+    pub deny: bool,
+}
+
+impl WarningLevels {
+    pub fn set(&mut self, name: &str, level: Level) -> Result<(), String> {
+        let spec = lookup(name).ok_or_else(|| {
+            let known: Vec<&str> = WARNINGS.iter().map(|spec| spec.name).collect();
+            format!("unknown warning '{}'; known warnings: {}", name, known.join(", "))
+        })?;
+        self.levels.insert(spec.name, level);
+        Ok(())
+    }
+
+    pub fn level(&self, name: &str) -> Level {
+        match self.levels.get(name) {
+            Some(level) => *level,
+            None => lookup(name).map_or(Level::Warn, |spec| spec.default),
+        }
+    }
+}
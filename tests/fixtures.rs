@@ -0,0 +1,50 @@
+use quark::{error_codes, testing};
+use std::path::Path;
+
+// Снимки обновляются запуском с QUARK_BLESS=1
+#[test]
+fn fixtures_match_snapshots() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    testing::run_fixtures(&dir).expect("cannot read the fixtures").assert_ok();
+}
+
+// У каждого кода из снимков есть объяснение для --explain и SARIF
+#[test]
+fn fixture_error_codes_are_documented() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    for entry in dir.read_dir().unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|ext| ext != "diagnostics") {
+            continue;
+        }
+        let snapshot = std::fs::read_to_string(&path).unwrap();
+        for code in snapshot.split('[').skip(1).filter_map(|rest| rest.split_once(']')).map(|(code, _)| code) {
+            if code.starts_with('E') {
+                assert!(error_codes::lookup(code).is_some(), "{} in {} has no explanation", code, path.display());
+            }
+        }
+    }
+}
+
+// Длинная цепочка операторов строится без рекурсии парсера, но дерево всё равно глубокое:
+// её должен остановить предел вложенности, а не переполнение стека в следующих проходах
+#[test]
+fn long_operator_chain_hits_the_nesting_limit() {
+    let chain = |terms: usize| format!("Integer x = 1\nInteger total = {}\nprintln(total)\n", vec!["x"; terms].join(" + "));
+    let diagnostics = testing::snapshot_diagnostics(&chain(5000));
+    assert!(diagnostics.starts_with("parser error[E0206]: Program too deeply nested"), "{}", diagnostics);
+
+    // Цепочка у самого предела проходит все проходы на стеке главного потока quark (8 МиБ)
+    let under_limit = std::thread::Builder::new()
+        .stack_size(8 << 20)
+        .spawn(move || testing::snapshot_diagnostics(&chain(120)))
+        .unwrap();
+    assert_eq!(under_limit.join().unwrap(), "");
+}
+
+#[test]
+fn long_method_chain_hits_the_nesting_limit() {
+    let source = format!("String s = \"a\"\nprintln(s{})\n", ".trim()".repeat(5000));
+    let diagnostics = testing::snapshot_diagnostics(&source);
+    assert!(diagnostics.starts_with("parser error[E0206]: Program too deeply nested"), "{}", diagnostics);
+}
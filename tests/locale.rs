@@ -0,0 +1,59 @@
+use quark::locale::{self, Locale, CATALOG};
+
+// Каждая запись каталога: английское сообщение с подставленными аргументами узнаётся
+// и переводится в русский шаблон с теми же аргументами на их местах
+#[test]
+fn every_catalog_entry_translates() {
+    let mut failures = Vec::new();
+    for (id, english, russian) in CATALOG {
+        let mut names = locale::placeholders(english);
+        let mut translated = locale::placeholders(russian);
+        names.sort();
+        translated.sort();
+        if names != translated {
+            failures.push(format!("{}: arguments {:?} in english, {:?} in russian", id, names, translated));
+            continue;
+        }
+        let values: Vec<String> = names.iter().map(|name| format!("<{}>", name)).collect();
+        let args: Vec<(&str, &str)> = names.iter().copied().zip(values.iter().map(String::as_str)).collect();
+        let message = locale::message(Locale::English, id, &args).unwrap();
+        let expected = locale::message(Locale::Russian, id, &args).unwrap();
+        let actual = locale::translate_to(Locale::Russian, &message);
+        if actual != expected {
+            failures.push(format!("{}: {:?} became {:?}, expected {:?}", id, message, actual, expected));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn catalog_ids_are_unique() {
+    let mut ids: Vec<&str> = CATALOG.iter().map(|(id, ..)| *id).collect();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), CATALOG.len());
+}
+
+#[test]
+fn arguments_keep_their_meaning_when_reordered() {
+    let message = "unreachable match arm: 2 arm(s) after `_` are never executed";
+    assert_eq!(
+        locale::translate_to(Locale::Russian, message),
+        "недостижимые ветви match: после `_` не выполняется ни одна из ветвей (2 шт.)"
+    );
+}
+
+#[test]
+fn suggestions_are_translated_separately_from_the_error() {
+    assert_eq!(locale::translate_to(Locale::Russian, "undeclared variable 'countr'"), "необъявленная переменная 'countr'");
+    assert_eq!(
+        locale::translate_to(Locale::Russian, "did you mean 'a', 'b' or 'c'?"),
+        "может быть, имелось в виду 'a', 'b' или 'c'?"
+    );
+}
+
+#[test]
+fn unknown_messages_stay_in_english() {
+    assert_eq!(locale::translate_to(Locale::Russian, "something new"), "something new");
+    assert_eq!(locale::translate_to(Locale::English, "undeclared variable 'x'"), "undeclared variable 'x'");
+}
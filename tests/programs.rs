@@ -0,0 +1,126 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// Программы собираются настоящим компилятором C; без него проверка пропускается
+fn quark(args: &[&str]) -> Option<Output> {
+    quark_in(Locale::English, args)
+}
+
+enum Locale {
+    English,
+    Russian,
+}
+
+fn quark_in(locale: Locale, args: &[&str]) -> Option<Output> {
+    let locale = match locale {
+        Locale::English => "en",
+        Locale::Russian => "ru",
+    };
+    let output = Command::new(env!("CARGO_BIN_EXE_quark"))
+        .args(args)
+        .env("QUARK_LOCALE", locale)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("cannot start quark");
+    if String::from_utf8_lossy(&output.stderr).contains("no C compiler found") {
+        eprintln!("skipped: no C compiler found");
+        return None;
+    }
+    Some(output)
+}
+
+fn programs(dir: &str) -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(dir);
+    let mut programs: Vec<_> = dir
+        .read_dir()
+        .unwrap_or_else(|e| panic!("cannot read {}: {}", dir.display(), e))
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "qrk"))
+        .collect();
+    programs.sort();
+    programs
+}
+
+#[test]
+fn test_functions_pass() {
+    for program in programs("tests/programs") {
+        let Some(output) = quark(&["test", program.to_str().unwrap()]) else { return };
+        assert!(
+            output.status.success(),
+            "quark test {} failed:\n{}",
+            program.display(),
+            String::from_utf8_lossy(&output.stdout)
+        );
+    }
+}
+
+// tests/run/name.qrk должна напечатать ровно name.out
+#[test]
+fn programs_print_expected_output() {
+    for program in programs("tests/run") {
+        let expected = std::fs::read_to_string(program.with_extension("out")).expect("missing .out file");
+        let Some(output) = quark(&["run", program.to_str().unwrap()]) else { return };
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "quark run {} failed:\n{}", program.display(), stdout);
+        let printed = stdout.split("------------------\n").nth(1).unwrap_or_default();
+        assert_eq!(printed, expected, "unexpected output of {}", program.display());
+    }
+}
+
+#[test]
+fn failed_assert_fails_the_test() {
+    let program = "tests/failing/assert_false.qrk";
+    let Some(output) = quark(&["test", program]) else { return };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "quark test {} succeeded:\n{}", program, stdout);
+    assert!(stdout.contains("0 passed, 1 failed"), "unexpected output:\n{}", stdout);
+    assert!(stdout.contains("assertion failed") || stderr.contains("assertion failed"), "no assertion message:\n{}{}", stdout, stderr);
+}
+
+#[test]
+fn operator_example_runs() {
+    let Some(output) = quark(&["run", "examples/operators.qrk"]) else { return };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "quark run examples/operators.qrk failed:\n{}", stdout);
+    assert!(stdout.contains("11 22\nfalse true true\n"), "unexpected output:\n{}", stdout);
+}
+
+#[test]
+fn sarif_results_point_at_semantic_errors() {
+    let fixture = "tests/fixtures/global_read_before_declaration.qrk";
+    let Some(output) = quark(&["check", fixture, "--message-format=sarif"]) else { return };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!output.status.success(), "quark check {} succeeded", fixture);
+    assert!(stdout.contains("\"ruleId\": \"E0301\""), "unexpected SARIF:\n{}", stdout);
+    assert!(stdout.contains("\"startLine\": 7,\n"), "missing region of the call:\n{}", stdout);
+}
+
+#[test]
+fn sarif_results_point_at_warnings() {
+    let fixture = "tests/fixtures/shadowed_variable.qrk";
+    let Some(output) = quark(&["check", fixture, "--message-format=sarif"]) else { return };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\"ruleId\": \"shadowed-variable\""), "unexpected SARIF:\n{}", stdout);
+    assert!(stdout.contains("\"level\": \"warning\""), "unexpected SARIF:\n{}", stdout);
+    assert!(stdout.contains("\"startLine\": 3,\n"), "missing region of the declaration:\n{}", stdout);
+}
+
+#[test]
+fn notes_point_at_the_first_declaration() {
+    let fixture = "tests/fixtures/duplicate_variable.qrk";
+    let Some(output) = quark(&["check", fixture]) else { return };
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "quark check {} succeeded", fixture);
+    assert!(stderr.contains("error[E0304]: variable 'count' is already declared in this scope"), "{}", stderr);
+    assert!(stderr.contains("note: 'count' is first declared here\n --> tests/fixtures/duplicate_variable.qrk:1:9"), "{}", stderr);
+}
+
+#[test]
+fn messages_follow_the_locale() {
+    let fixture = "tests/fixtures/undeclared_variable_suggestion.qrk";
+    let Some(output) = quark_in(Locale::Russian, &["check", fixture]) else { return };
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("ошибка[E0301]: необъявленная переменная 'countr'"), "{}", stderr);
+    assert!(stderr.contains("подсказка: может быть, имелось в виду 'counter'?"), "{}", stderr);
+}